@@ -3,7 +3,7 @@ use std::{fmt, mem::MaybeUninit};
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Index(pub u8);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Position(pub u8, pub u8);
 
 impl fmt::Display for Position {
@@ -169,6 +169,47 @@ impl RawBitboard {
         self.data[pos.1 as usize] |= 1u64 << pos.0;
     }
 
+    pub fn unset(&mut self, pos: Position) {
+        debug_assert!(pos.0 < 64 && pos.1 < 64, "position out of bounds");
+        self.data[pos.1 as usize] &= !(1u64 << pos.0);
+    }
+
+    /// The squares of the given row, one bit per column.
+    pub fn row(&self, y: usize) -> u64 {
+        self.data[y]
+    }
+
+    /// The first set square in row-major order, if any.
+    pub fn top_left(&self) -> Option<Position> {
+        let (y, row) = self.data.iter().enumerate().find(|(_, row)| **row != 0)?;
+        Some(Position(row.trailing_zeros() as u8, y as u8))
+    }
+
+    /// The rows of the bitboard as little-endian bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data.iter().flat_map(|row| row.to_le_bytes()).collect()
+    }
+
+    /// Read a bitboard written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 64 * 8 {
+            return None;
+        }
+        let mut result = RawBitboard::new();
+        for (row, chunk) in result.data.iter_mut().zip(bytes.chunks_exact(8)) {
+            *row = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        Some(result)
+    }
+
+    /// Whether every square set in the other bitboard is also set in this one.
+    pub fn contains_all(&self, other: &RawBitboard) -> bool {
+        self.data
+            .iter()
+            .zip(&other.data)
+            .all(|(&bits, &other_bits)| bits & other_bits == other_bits)
+    }
+
     pub fn invert(&self) -> RawBitboard {
         let mut result = RawBitboard::new();
         for i in 0..64 {
@@ -178,6 +219,12 @@ impl RawBitboard {
     }
 }
 
+impl Default for RawBitboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Bitboard for RawBitboard {
     fn get(&self, pos: Position) -> bool {
         RawBitboard::get(self, pos)
@@ -234,13 +281,50 @@ impl LazyBitboard {
         }
     }
 
+    pub fn unset(&mut self, pos: Position) {
+        assert!(pos.0 < 64 && pos.1 < 64, "position out of bounds");
+        let y = pos.1 as usize;
+        if (self.initialized & (1u64 << y)) == 0 {
+            return;
+        }
+        let row = unsafe { &mut *self.data[y].as_mut_ptr() };
+        *row &= !(1u64 << pos.0);
+        if *row == 0 {
+            self.initialized &= !(1u64 << y);
+        }
+    }
+
+    /// The first `height` rows of the bitboard, for storing it compactly.
+    pub fn rows(&self, height: u8) -> impl Iterator<Item = u64> + '_ {
+        (0..height as usize).map(|y| {
+            if (self.initialized & (1u64 << y)) == 0 {
+                0
+            } else {
+                unsafe { *self.data[y].as_ptr() }
+            }
+        })
+    }
+
+    /// Rebuild a bitboard from rows returned by `rows`.
+    pub fn from_rows(rows: &[u64]) -> Self {
+        let mut bitboard = Self::new();
+        for (y, &row) in rows.iter().enumerate() {
+            if row != 0 {
+                bitboard.data[y].write(row);
+                bitboard.initialized |= 1u64 << y;
+            }
+        }
+        bitboard
+    }
+
     /// Returns the lexicographically smallest position that is set in the bitboard.
     /// Returns None if no position is set.
     pub fn top_left(&self) -> Option<Position> {
         if self.initialized == 0 {
             return None;
         }
-        // Every initialized row must have at least one bit set (no unset operation exists)
+        // Every initialized row must have at least one bit set (unset
+        // uninitializes rows it empties)
         let y = self.initialized.trailing_zeros() as u8;
         let row_data = unsafe { *self.data[y as usize].as_ptr() };
         let x = row_data.trailing_zeros() as u8;
@@ -271,6 +355,12 @@ impl LazyBitboard {
     }
 }
 
+impl Default for LazyBitboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Bitboard for LazyBitboard {
     fn get(&self, pos: Position) -> bool {
         LazyBitboard::get(self, pos)
@@ -489,4 +579,40 @@ mod tests {
         assert!(bb1.get(Position(5, 5)));
         assert_eq!(bb1.top_left(), Some(Position(5, 5)));
     }
+
+    #[test]
+    fn test_lazy_bitboard_unset() {
+        let mut bb = LazyBitboard::new();
+        bb.set(Position(2, 1));
+        bb.set(Position(4, 1));
+        bb.set(Position(3, 5));
+
+        bb.unset(Position(2, 1));
+        assert!(!bb.get(Position(2, 1)));
+        assert_eq!(bb.top_left(), Some(Position(4, 1)));
+
+        // Emptying a row leaves top_left to look at later rows
+        bb.unset(Position(4, 1));
+        assert_eq!(bb.top_left(), Some(Position(3, 5)));
+
+        // Unsetting an unset position does nothing
+        bb.unset(Position(0, 0));
+        assert_eq!(bb.top_left(), Some(Position(3, 5)));
+    }
+
+    #[test]
+    fn test_lazy_bitboard_rows() {
+        let mut bb = LazyBitboard::new();
+        bb.set(Position(1, 0));
+        bb.set(Position(3, 2));
+
+        let rows: Vec<u64> = bb.rows(4).collect();
+        assert_eq!(rows, [0b10, 0, 0b1000, 0]);
+
+        let bb = LazyBitboard::from_rows(&rows);
+        assert!(bb.get(Position(1, 0)));
+        assert!(bb.get(Position(3, 2)));
+        assert!(!bb.get(Position(1, 1)));
+        assert_eq!(bb.top_left(), Some(Position(1, 0)));
+    }
 }
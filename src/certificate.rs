@@ -0,0 +1,385 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use crate::{
+    bits::{Bitvector, Index},
+    explain::{Unsolvability, explain_unsolvable},
+    frozen::compute_frozen_boxes,
+    game::{Game, Move, Position, Tile},
+    heuristic::compute_push_distances,
+};
+
+/// First line of every certificate.
+const HEADER: &str = "; sisyphus unsolvability certificate";
+
+/// A machine-checkable proof that a level is unsolvable. Checking one only
+/// relies on push generation, dead squares and the freeze analysis, rather
+/// than on the search and its many pruning techniques.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Certificate {
+    /// The given boxes start on dead squares.
+    DeadSquares(Vec<Position>),
+    /// The given boxes start frozen off their goals.
+    Frozen(Vec<Position>),
+    /// The given boxes can only reach fewer goals than there are boxes.
+    Matching(Vec<Position>),
+    /// Every position reachable from the start, up to those with a box on a
+    /// dead square or frozen off its goal, none of which is solved.
+    States(Vec<State>),
+}
+
+/// A position, with the player at the top-left square of its region.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct State {
+    pub player: Position,
+    /// Box positions, sorted by row and then column.
+    pub boxes: Vec<Position>,
+}
+
+impl State {
+    fn from_game(game: &Game) -> Self {
+        let mut boxes = game.box_positions().to_vec();
+        boxes.sort_by_key(|pos| (pos.1, pos.0));
+        Self {
+            player: game.canonical_player_pos(),
+            boxes,
+        }
+    }
+}
+
+/// The game with its labels cleared, as states don't record which box is
+/// which.
+fn without_labels(game: &Game) -> Game {
+    let mut game = game.clone();
+    game.clear_labels();
+    game
+}
+
+/// Build a certificate for the given level, exploring at most `max_states`
+/// positions if the start isn't already a deadlock. Labels are ignored, so
+/// a labelled level only gets one if it is unsolvable without them.
+pub fn build_certificate(game: &Game, max_states: usize) -> Result<Certificate, String> {
+    let game = &without_labels(game);
+    match explain_unsolvable(game) {
+        Unsolvability::DeadSquares(positions) => return Ok(Certificate::DeadSquares(positions)),
+        Unsolvability::Frozen(positions) => return Ok(Certificate::Frozen(positions)),
+        Unsolvability::Matching(positions) => return Ok(Certificate::Matching(positions)),
+        Unsolvability::Exhausted => {}
+    }
+
+    let mut game = game.clone();
+    game.set_player(game.canonical_player_pos());
+    let mut states = vec![State::from_game(&game)];
+    let mut seen: HashSet<State> = states.iter().cloned().collect();
+    let mut queue = VecDeque::from([game.checkpoint()]);
+    while let Some(checkpoint) = queue.pop_front() {
+        game.restore(&checkpoint);
+        for push in &game.compute_pushes().moves {
+            game.push(push);
+            if game.is_solved() {
+                return Err("level is solvable".to_string());
+            }
+            if !is_dead(&game) {
+                game.set_player(game.canonical_player_pos());
+                let state = State::from_game(&game);
+                if seen.insert(state.clone()) {
+                    if states.len() >= max_states {
+                        return Err(format!("more than {} reachable states", max_states));
+                    }
+                    states.push(state);
+                    queue.push_back(game.checkpoint());
+                }
+            }
+            game.restore(&checkpoint);
+        }
+    }
+    Ok(Certificate::States(states))
+}
+
+/// Check that the certificate proves the given level unsolvable, ignoring its
+/// labels: a level unsolvable without them is unsolvable with them too.
+pub fn check_certificate(game: &Game, certificate: &Certificate) -> Result<(), String> {
+    let game = &without_labels(game);
+    match certificate {
+        Certificate::DeadSquares(positions) => {
+            check_boxes(game, positions)?;
+            match positions
+                .iter()
+                .find(|&&pos| !game.is_push_dead_square(pos))
+            {
+                Some(pos) => Err(format!("box at {} is not on a dead square", pos)),
+                None => Ok(()),
+            }
+        }
+        Certificate::Frozen(positions) => {
+            check_boxes(game, positions)?;
+            let frozen_boxes = compute_frozen_boxes(game);
+            for &pos in positions {
+                if game.get_tile(pos) == Tile::Goal {
+                    return Err(format!("box at {} is on a goal", pos));
+                }
+                if !frozen_boxes.contains(game.box_index(pos).unwrap()) {
+                    return Err(format!("box at {} is not frozen", pos));
+                }
+            }
+            Ok(())
+        }
+        Certificate::Matching(positions) => {
+            check_boxes(game, positions)?;
+            let frozen_boxes = compute_frozen_boxes(game);
+            let distances = compute_push_distances(game, &frozen_boxes);
+            let mut goals = Bitvector::new();
+            for (goal_idx, goal_distances) in distances.iter().enumerate() {
+                if positions
+                    .iter()
+                    .any(|&pos| goal_distances.get(game, pos) != u16::MAX)
+                {
+                    goals.add(Index(goal_idx as u8));
+                }
+            }
+            if goals.len() >= positions.len() {
+                return Err(format!(
+                    "{} boxes can reach {} goals",
+                    positions.len(),
+                    goals.len()
+                ));
+            }
+            Ok(())
+        }
+        Certificate::States(states) => check_states(game, states),
+    }
+}
+
+/// Check that the given positions hold distinct boxes.
+fn check_boxes(game: &Game, positions: &[Position]) -> Result<(), String> {
+    if positions.is_empty() {
+        return Err("no boxes given".to_string());
+    }
+    let mut seen = HashSet::new();
+    for &pos in positions {
+        if pos.0 >= game.width() || pos.1 >= game.height() || game.box_index(pos).is_none() {
+            return Err(format!("no box at {}", pos));
+        }
+        if !seen.insert(pos) {
+            return Err(format!("box at {} given twice", pos));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the states include the start and are closed under pushes, up
+/// to dead positions, and that none of them is solved.
+fn check_states(game: &Game, states: &[State]) -> Result<(), String> {
+    let set: HashSet<&State> = states.iter().collect();
+    if !set.contains(&State::from_game(game)) {
+        return Err("the starting position is missing".to_string());
+    }
+
+    let mut game = game.clone();
+    for (i, state) in states.iter().enumerate() {
+        let line = i + 1;
+        game.set_boxes(&state.boxes)
+            .map_err(|e| format!("state {}: {}", line, e))?;
+        if state.player.0 >= game.width()
+            || state.player.1 >= game.height()
+            || game.get_tile(state.player) == Tile::Wall
+            || game.box_index(state.player).is_some()
+        {
+            return Err(format!("state {}: player is not on an empty floor", line));
+        }
+        game.set_player(state.player);
+        if game.canonical_player_pos() != state.player {
+            return Err(format!("state {}: player position is not canonical", line));
+        }
+        if game.is_solved() {
+            return Err(format!("state {}: position is solved", line));
+        }
+
+        let checkpoint = game.checkpoint();
+        for push in &game.compute_pushes().moves {
+            game.push(push);
+            if !is_dead(&game) && !set.contains(&State::from_game(&game)) {
+                return Err(format!(
+                    "state {}: pushing box at {} {} leads to a missing state",
+                    line,
+                    game.box_position(push.box_index()) + push.direction().reverse(),
+                    push.direction()
+                ));
+            }
+            game.restore(&checkpoint);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the position has a box on a dead square or frozen off its goal.
+fn is_dead(game: &Game) -> bool {
+    game.box_positions()
+        .iter()
+        .any(|&pos| game.is_push_dead_square(pos))
+        || game
+            .unsolved_boxes()
+            .contains_any(&compute_frozen_boxes(game))
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        let (kind, positions) = match self {
+            Certificate::DeadSquares(positions) => ("dead-squares", positions),
+            Certificate::Frozen(positions) => ("frozen", positions),
+            Certificate::Matching(positions) => ("matching", positions),
+            Certificate::States(states) => {
+                writeln!(f, "states {}", states.len())?;
+                for state in states {
+                    write!(f, "@{}", format_position(state.player))?;
+                    for &pos in &state.boxes {
+                        write!(f, " {}", format_position(pos))?;
+                    }
+                    writeln!(f)?;
+                }
+                return Ok(());
+            }
+        };
+        write!(f, "{}", kind)?;
+        for &pos in positions {
+            write!(f, " {}", format_position(pos))?;
+        }
+        writeln!(f)
+    }
+}
+
+impl Certificate {
+    /// Name of the kind of proof.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Certificate::DeadSquares(_) => "dead squares",
+            Certificate::Frozen(_) => "frozen boxes",
+            Certificate::Matching(_) => "matching",
+            Certificate::States(_) => "exhausted states",
+        }
+    }
+
+    /// Parse a certificate written by `Display`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().filter(|line| !line.starts_with(';'));
+        let first = lines.next().ok_or("empty certificate")?;
+        let mut words = first.split_whitespace();
+        let kind = words.next().ok_or("empty certificate")?;
+        let positions = || -> Result<Vec<Position>, String> {
+            first
+                .split_whitespace()
+                .skip(1)
+                .map(parse_position)
+                .collect()
+        };
+        match kind {
+            "dead-squares" => Ok(Certificate::DeadSquares(positions()?)),
+            "frozen" => Ok(Certificate::Frozen(positions()?)),
+            "matching" => Ok(Certificate::Matching(positions()?)),
+            "states" => {
+                let count: usize = words
+                    .next()
+                    .and_then(|count| count.parse().ok())
+                    .ok_or("invalid state count")?;
+                let states = lines.map(parse_state).collect::<Result<Vec<_>, _>>()?;
+                if states.len() != count {
+                    return Err(format!("expected {} states, got {}", count, states.len()));
+                }
+                Ok(Certificate::States(states))
+            }
+            _ => Err(format!("unknown certificate kind: {}", kind)),
+        }
+    }
+}
+
+fn format_position(pos: Position) -> String {
+    format!("{},{}", pos.0, pos.1)
+}
+
+fn parse_position(text: &str) -> Result<Position, String> {
+    let invalid = || format!("invalid position: {}", text);
+    let (x, y) = text.split_once(',').ok_or_else(invalid)?;
+    Ok(Position(
+        x.parse().map_err(|_| invalid())?,
+        y.parse().map_err(|_| invalid())?,
+    ))
+}
+
+fn parse_state(line: &str) -> Result<State, String> {
+    let mut words = line.split_whitespace();
+    let player = words
+        .next()
+        .and_then(|word| word.strip_prefix('@'))
+        .ok_or_else(|| format!("missing player in state: {}", line))?;
+    let mut boxes = words.map(parse_position).collect::<Result<Vec<_>, _>>()?;
+    boxes.sort_by_key(|pos| (pos.1, pos.0));
+    Ok(State {
+        player: parse_position(player)?,
+        boxes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn certify(text: &str) -> (Game, Certificate) {
+        let game = Game::from_text(text).unwrap();
+        let certificate = build_certificate(&game, 1000).unwrap();
+        (game, certificate)
+    }
+
+    #[test]
+    fn test_deadlock_certificate() {
+        let (game, certificate) = certify(
+            "#####\n\
+             #$  #\n\
+             #  .#\n\
+             # @ #\n\
+             #####",
+        );
+        assert_eq!(certificate, Certificate::DeadSquares(vec![Position(1, 1)]));
+        assert_eq!(
+            certificate.to_string(),
+            format!("{}\ndead-squares 1,1\n", HEADER)
+        );
+        assert_eq!(
+            Certificate::parse(&certificate.to_string()),
+            Ok(certificate.clone())
+        );
+        assert_eq!(check_certificate(&game, &certificate), Ok(()));
+
+        // A box which isn't on a dead square proves nothing
+        let wrong = Certificate::DeadSquares(vec![Position(2, 2)]);
+        assert!(check_certificate(&game, &wrong).is_err());
+    }
+
+    #[test]
+    fn test_states_certificate() {
+        // The player can only push the box away from the goal
+        let (game, certificate) = certify(
+            "#########\n\
+             #  .@$  #\n\
+             #########",
+        );
+        let Certificate::States(states) = &certificate else {
+            panic!("expected states, got {:?}", certificate);
+        };
+        assert_eq!(states.len(), 2);
+        let parsed = Certificate::parse(&certificate.to_string()).unwrap();
+        assert_eq!(parsed, certificate);
+        assert_eq!(check_certificate(&game, &parsed), Ok(()));
+
+        // Dropping a state breaks the proof
+        let mut states = states.clone();
+        states.pop();
+        assert!(check_certificate(&game, &Certificate::States(states)).is_err());
+    }
+
+    #[test]
+    fn test_solvable_level() {
+        let game = Game::from_text("#####\n#@$.#\n#####").unwrap();
+        assert!(build_certificate(&game, 1000).is_err());
+    }
+}
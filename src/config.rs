@@ -0,0 +1,135 @@
+use serde::Deserialize;
+use std::fs;
+
+/// Default options read from a TOML config file. Every field is optional;
+/// options given on the command line take precedence.
+///
+/// ```toml
+/// [solver]
+/// heuristic = "greedy"
+/// max_nodes = 1000000
+/// pi_corrals = false
+///
+/// [output]
+/// format = "compact"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub solver: SolverConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+/// Solver options. Enum-valued options use their command-line names.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SolverConfig {
+    pub heuristic: Option<String>,
+    /// Heuristics to take the maximum of along with `heuristic`.
+    pub max_with: Option<Vec<String>>,
+    pub direction: Option<String>,
+    pub balance: Option<String>,
+    /// Forward to reverse effort, as "F:R".
+    pub ratio: Option<String>,
+    /// Number of reverse nodes to expand before searching forwards.
+    pub perimeter: Option<usize>,
+    pub max_nodes: Option<usize>,
+    /// Maximum time per level, in seconds.
+    pub max_time: Option<f64>,
+    /// Approximate memory limit in megabytes.
+    pub max_memory: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub freeze_deadlocks: Option<bool>,
+    pub deadlock_patterns: Option<bool>,
+    pub dead_squares: Option<bool>,
+    pub matching_deadlocks: Option<bool>,
+    pub pi_corrals: Option<bool>,
+    pub deadlock_max_nodes: Option<usize>,
+    pub conflict_deadlocks: Option<bool>,
+    pub conflict_max_nodes: Option<usize>,
+    pub corral_safety: Option<bool>,
+    pub pull_corral_pruning: Option<bool>,
+    pub optimal: Option<bool>,
+    pub cost: Option<String>,
+    pub weight: Option<f64>,
+    pub algorithm: Option<String>,
+    pub anytime: Option<bool>,
+    pub reopen: Option<bool>,
+    pub adaptive: Option<bool>,
+    pub player_tie_break: Option<bool>,
+    pub tie_break: Option<String>,
+    pub penalties: Option<bool>,
+    /// Portfolio configurations, each as `HEURISTIC[:DIRECTION[:MAX_NODES]]`.
+    pub portfolio: Option<Vec<String>>,
+    /// Number of threads to search with.
+    pub threads: Option<usize>,
+    pub paranoid_hash: Option<bool>,
+    pub zobrist_seed: Option<u64>,
+    pub symmetry: Option<bool>,
+    pub share_deadlocks: Option<bool>,
+    pub hash_diagnostics: Option<bool>,
+}
+
+/// Options controlling what is printed or written out for each level.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub print_solution: Option<bool>,
+    pub format: Option<String>,
+    pub events: Option<String>,
+    pub stats_db: Option<String>,
+    pub deadlock_cache: Option<String>,
+    pub certificates: Option<String>,
+}
+
+impl Config {
+    /// Parse a config from TOML text.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// Read and parse a TOML config file.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::from_text(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_text() {
+        let config = Config::from_text(
+            r#"
+[solver]
+heuristic = "greedy"
+max_with = ["simple", "hungarian"]
+max_nodes = 1000
+max_time = 2.5
+pi_corrals = false
+
+[output]
+format = "compact"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.solver.heuristic.as_deref(), Some("greedy"));
+        assert_eq!(
+            config.solver.max_with,
+            Some(vec!["simple".to_string(), "hungarian".to_string()])
+        );
+        assert_eq!(config.solver.max_nodes, Some(1000));
+        assert_eq!(config.solver.max_time, Some(2.5));
+        assert_eq!(config.solver.pi_corrals, Some(false));
+        assert_eq!(config.solver.direction, None);
+        assert_eq!(config.output.format.as_deref(), Some("compact"));
+        assert_eq!(config.output.print_solution, None);
+
+        assert!(Config::from_text("").is_ok());
+        assert!(Config::from_text("[solver]\nmax_node = 5").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use arrayvec::ArrayVec;
+
+use crate::{
+    bits::{Bitvector, Index, Position},
+    game::{Game, Move},
+    zobrist::Zobrist,
+};
+
+/// Maximum number of boxes near the pushed box which are probed with it.
+const MAX_NEIGHBORS: usize = 4;
+
+/// Maximum number of probe results kept, after which new results are no
+/// longer remembered.
+const MAX_RESULTS: usize = 100_000;
+
+/// Probes small groups of interacting boxes for deadlocks which the freeze and
+/// corral tests miss. After each push, the pushed box is taken together with
+/// each box around it, and with each pair of boxes around it, and every other
+/// box is removed (see `Game::project`). Removing boxes never makes a position
+/// harder to solve, so if a tiny exhaustive search can't get the group onto
+/// goals, the full position is deadlocked too.
+pub struct ConflictProber {
+    zobrist: Rc<Zobrist>,
+    /// Maximum number of positions searched for each group, after which the
+    /// group is assumed not to be deadlocked.
+    max_nodes: usize,
+    /// Whether each group probed is deadlocked, by the hash of its boxes and
+    /// canonical player position.
+    results: HashMap<u64, bool>,
+}
+
+impl ConflictProber {
+    pub fn new(zobrist: Rc<Zobrist>, max_nodes: usize) -> Self {
+        Self {
+            zobrist,
+            max_nodes,
+            results: HashMap::new(),
+        }
+    }
+
+    /// Check whether the box just pushed is deadlocked together with one or
+    /// two of the boxes around it.
+    pub fn is_deadlocked(&mut self, game: &Game, box_idx: Index) -> bool {
+        let neighbors = neighbor_boxes(game, game.box_position(box_idx));
+
+        for (i, &first) in neighbors.iter().enumerate() {
+            if self.probe(game, &[box_idx, first]) {
+                return true;
+            }
+            for &second in &neighbors[i + 1..] {
+                if self.probe(game, &[box_idx, first, second]) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Check whether the given boxes can't be put on goals on their own.
+    fn probe(&mut self, game: &Game, boxes: &[Index]) -> bool {
+        let mut boxes_to_keep = Bitvector::new();
+        for &box_idx in boxes {
+            boxes_to_keep.add(box_idx);
+        }
+        let mut game = game.clone();
+        game.project(boxes_to_keep);
+
+        let reachable = game.compute_pushes();
+        let hash = self.zobrist.compute_boxes_hash(&game)
+            ^ self
+                .zobrist
+                .player_hash(reachable.squares.top_left().unwrap());
+        if let Some(&deadlocked) = self.results.get(&hash) {
+            return deadlocked;
+        }
+
+        let deadlocked = self.search(&mut game);
+        if self.results.len() < MAX_RESULTS {
+            self.results.insert(hash, deadlocked);
+        }
+        deadlocked
+    }
+
+    /// Breadth-first search for a position with every box on a goal. Returns
+    /// whether the search was exhausted without finding one.
+    fn search(&self, game: &mut Game) -> bool {
+        let mut queue = VecDeque::new();
+        queue.push_back(game.checkpoint());
+
+        let mut visited = HashSet::new();
+        while let Some(checkpoint) = queue.pop_front() {
+            game.restore(&checkpoint);
+            if game.is_solved() {
+                return false;
+            }
+
+            let reachable = game.compute_pushes();
+            let hash = self.zobrist.compute_boxes_hash(game)
+                ^ self
+                    .zobrist
+                    .player_hash(reachable.squares.top_left().unwrap());
+            if !visited.insert(hash) {
+                continue;
+            }
+            if visited.len() > self.max_nodes {
+                return false;
+            }
+
+            for push in &reachable.moves {
+                let new_pos = game.box_position(push.box_index()) + push.direction();
+                if game.is_push_dead_square(new_pos) {
+                    continue;
+                }
+                game.push(push);
+                queue.push_back(game.checkpoint());
+                game.restore(&checkpoint);
+            }
+        }
+
+        true
+    }
+}
+
+/// Boxes in the eight squares around the given position.
+fn neighbor_boxes(game: &Game, pos: Position) -> ArrayVec<Index, MAX_NEIGHBORS> {
+    let mut neighbors = ArrayVec::new();
+    for dy in -1i8..=1 {
+        for dx in -1i8..=1 {
+            let (Some(x), Some(y)) = (pos.0.checked_add_signed(dx), pos.1.checked_add_signed(dy))
+            else {
+                continue;
+            };
+            if (dx, dy) == (0, 0) || x >= game.width() || y >= game.height() {
+                continue;
+            }
+            if let Some(box_idx) = game.box_index(Position(x, y)) {
+                neighbors.push(box_idx);
+                if neighbors.is_full() {
+                    return neighbors;
+                }
+            }
+        }
+    }
+    neighbors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_prober() -> ConflictProber {
+        ConflictProber::new(Rc::new(Zobrist::new()), 100)
+    }
+
+    #[test]
+    fn test_conflict_deadlock() {
+        // The player can't get past the first box to push the second
+        let game = Game::from_text(
+            r#"
+########
+#@ $$..#
+########
+"#,
+        )
+        .unwrap();
+        let box_idx = game.box_index(Position(3, 2)).unwrap();
+        let mut prober = new_prober();
+        assert!(prober.is_deadlocked(&game, box_idx));
+    }
+
+    #[test]
+    fn test_no_conflict_deadlock() {
+        let game = Game::from_text(
+            r#"
+#######
+#@    #
+#  $$ #
+#  .. #
+#     #
+#######
+"#,
+        )
+        .unwrap();
+        let box_idx = game.box_index(Position(3, 3)).unwrap();
+        let mut prober = new_prober();
+        assert!(!prober.is_deadlocked(&game, box_idx));
+        assert_eq!(prober.results.len(), 1);
+
+        // The result is remembered
+        assert!(!prober.is_deadlocked(&game, box_idx));
+        assert_eq!(prober.results.len(), 1);
+    }
+}
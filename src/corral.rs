@@ -1,16 +1,38 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
 use arrayvec::ArrayVec;
 
 use crate::{
-    bits::{Bitvector, LazyBitboard, Position},
-    game::{ALL_DIRECTIONS, Game, MAX_SIZE, Move, Moves, Push, ReachableSet, Tile},
+    bits::{Bitvector, Index, LazyBitboard, Position, RawBitboard},
+    frozen::{compute_frozen_boxes, compute_new_frozen_boxes},
+    game::{
+        ALL_DIRECTIONS, Game, MAX_SIZE, Move, Moves, Pull, Push, PushByPos, ReachableSet, Tile,
+    },
     zobrist::Zobrist,
 };
 
 pub struct CorralSearcher {
     deadlocks: DeadlockSearcher,
+    /// Whether to avoid pruning with corrals which exclude every goal push.
+    safety: bool,
+    /// How pruning was downgraded during the last search, if at all.
+    downgrade: Option<CorralDowngrade>,
+    /// Boxes of the corrals found not to be deadlocked during the last search.
+    patterns: Vec<Bitvector>,
+}
+
+/// Describes how PI-corral pruning was downgraded because the cheapest corral
+/// would have discarded every push onto a goal while empty goals remain
+/// outside the corral.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorralDowngrade {
+    /// A more expensive corral was used instead (`chosen` is its 0-indexed
+    /// rank among the candidate PI-corrals, ordered by number of pushes).
+    NextBest { candidates: usize, chosen: usize },
+    /// No candidate corral was safe, so pruning was disabled for the node.
+    Disabled { candidates: usize },
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -25,12 +47,52 @@ pub enum CorralResult<T> {
 }
 
 impl CorralSearcher {
-    pub fn new(zobrist: Rc<Zobrist>, max_nodes_explored: usize) -> Self {
+    pub fn new(zobrist: Rc<Zobrist>, max_nodes_explored: usize, safety: bool) -> Self {
         Self {
             deadlocks: DeadlockSearcher::new(zobrist, max_nodes_explored),
+            safety,
+            downgrade: None,
+            patterns: Vec::new(),
         }
     }
 
+    /// Add the corral deadlock search results saved from earlier searches of
+    /// the same level.
+    pub fn load_tables(&mut self, tables: &DeadlockTables) {
+        self.deadlocks.load_tables(tables);
+    }
+
+    /// The corral deadlock search results so far, to be saved for later
+    /// searches of the same level.
+    pub fn tables(&self) -> DeadlockTables {
+        self.deadlocks.tables()
+    }
+
+    /// Takes the downgrade decision made by the last search, if any.
+    pub fn take_downgrade(&mut self) -> Option<CorralDowngrade> {
+        self.downgrade.take()
+    }
+
+    /// Record the positions found deadlocked by corral deadlock searches, with
+    /// the given Zobrist hashes for their check hashes, so that the main
+    /// search can mark them dead in its transposition table.
+    pub fn record_dead_positions(&mut self, check_zobrist: Zobrist) {
+        self.deadlocks.check_zobrist = Some(check_zobrist);
+    }
+
+    /// Takes the positions found deadlocked since this was last called, as
+    /// hashes and check hashes with the exact player position.
+    pub fn take_dead_positions(&mut self) -> Vec<(u64, u64)> {
+        std::mem::take(&mut self.deadlocks.dead_positions)
+    }
+
+    /// Takes the boxes of the corrals found not to be deadlocked by the last
+    /// search. Their boxes may still need more pushes than the heuristic
+    /// expects, so they are candidates for penalty patterns.
+    pub fn take_patterns(&mut self) -> Vec<Bitvector> {
+        std::mem::take(&mut self.patterns)
+    }
+
     /// Performs a corral-level search for PI-corral pruning and corral
     /// deadlocks.
     pub fn search(
@@ -38,100 +100,366 @@ impl CorralSearcher {
         game: &mut Game,
         reachable: &ReachableSet<Push>,
     ) -> CorralResult<Push> {
-        let mut result = CorralResult::None;
-        let mut min_cost = usize::MAX;
-        let mut visited = LazyBitboard::new();
+        let mut candidates = Vec::new();
+        self.downgrade = None;
+        self.patterns.clear();
 
-        for push in &reachable.moves {
-            let box_pos = game.box_position(push.box_index());
-            let new_pos = game.move_position(box_pos, push.direction()).unwrap();
-            // Look for a corral by examining the other side of a push.
-            if !reachable.squares.get(new_pos) && !visited.get(new_pos) {
-                if let Some(corral) = compute_corral(game, new_pos, reachable) {
-                    visited.set_all(&corral.extent);
-                    if corral.i_condition {
-                        // Check for corral deadlocks
-                        if self.deadlocks.search(game, &corral) == DeadlockResult::Deadlocked {
-                            return CorralResult::Deadlocked;
-                        }
-
-                        // This is PI-corral, so it is eligible for pruning
-                        if corral.p_condition {
-                            let cost = corral.pushes.len();
-                            if cost < min_cost {
-                                result = CorralResult::Prune(corral.pushes);
-                                min_cost = cost;
-                            }
-                        }
-                    }
+        // Check the deadlocks proven by earlier searches
+        if self.deadlocks.learned.matches(game) {
+            return CorralResult::Deadlocked;
+        }
+
+        for extent in compute_push_corral_extents(game, reachable) {
+            let corral = compute_pi_conditions(game, extent, reachable);
+            if corral.i_condition {
+                // Check for corral deadlocks
+                match self.deadlocks.search(game, &corral) {
+                    DeadlockResult::Deadlocked => return CorralResult::Deadlocked,
+                    DeadlockResult::Ok => self.patterns.push(corral.boxes),
+                    DeadlockResult::CutOff => {}
+                }
+
+                // This is PI-corral, so it is eligible for pruning
+                if corral.p_condition {
+                    candidates.push(corral);
                 }
             }
         }
 
-        result
+        if candidates.is_empty() {
+            return CorralResult::None;
+        }
+
+        // Prefer the corral with the fewest pushes, skipping corrals which
+        // are unsafe to prune with
+        candidates.sort_by_key(|corral| corral.pushes.len());
+        let chosen = if self.safety {
+            let goal_pushes = goal_pushes(game, reachable);
+            candidates
+                .iter()
+                .position(|corral| is_safe_to_prune(game, corral, &goal_pushes))
+        } else {
+            Some(0)
+        };
+        let num_candidates = candidates.len();
+        match chosen {
+            Some(chosen) => {
+                if chosen > 0 {
+                    self.downgrade = Some(CorralDowngrade::NextBest {
+                        candidates: num_candidates,
+                        chosen,
+                    });
+                }
+                CorralResult::Prune(candidates.swap_remove(chosen).pushes)
+            }
+            None => {
+                self.downgrade = Some(CorralDowngrade::Disabled {
+                    candidates: num_candidates,
+                });
+                CorralResult::None
+            }
+        }
     }
 }
 
-struct Corral {
-    /// The boxes in the corral, including boxes on the edge of the corral.
-    boxes: Bitvector,
-    /// The extent of the corral. This includes all boxes within the corral,
-    /// including its edge.
-    extent: LazyBitboard,
-    /// Valid corral pushes.
-    pushes: Moves<Push>,
-    i_condition: bool,
-    p_condition: bool,
+/// A corral found by `analyze_corrals`, for debugging and tuning corral code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorralReport {
+    /// Squares of the corral, including the boxes on its edge.
+    pub extent: Vec<Position>,
+    /// The boxes in the corral, including the boxes on its edge.
+    pub boxes: Vec<Position>,
+    /// The boxes on the edge of the corral, which the player can reach.
+    pub edge_boxes: Vec<Position>,
+    /// Whether every push of an edge box leads into the corral.
+    pub i_condition: bool,
+    /// Whether the player can make every such push right now.
+    pub p_condition: bool,
+    /// The pushes into the corral the player can make right now, which
+    /// PI-corral pruning would restrict the search to.
+    pub pushes: Vec<PushByPos>,
+    /// The result of the corral deadlock search, which is only run for
+    /// corrals meeting the I condition.
+    pub deadlock: Option<DeadlockResult>,
 }
 
-fn compute_corral(game: &Game, pos: Position, reachable: &ReachableSet<Push>) -> Option<Corral> {
-    assert!(!reachable.squares.get(pos));
-
-    let mut stack: ArrayVec<Position, { MAX_SIZE * MAX_SIZE }> = ArrayVec::new();
-    let mut extent = LazyBitboard::new();
-    let mut boxes = Bitvector::new();
-    let mut boxes_on_edge = Bitvector::new();
-    let mut requires_push = false;
+impl CorralReport {
+    /// Render the board with the corral's empty floor squares drawn as '~'.
+    pub fn render(&self, game: &Game) -> String {
+        let mut text = String::new();
+        for y in 0..game.height() {
+            let mut line = String::new();
+            for x in 0..game.width() {
+                let pos = Position(x, y);
+                let c = game.square_char(pos);
+                line.push(if c == ' ' && self.extent.contains(&pos) {
+                    '~'
+                } else {
+                    c
+                });
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
 
-    // Start DFS from the given position
-    stack.push(pos);
-    extent.set(pos);
+/// Find every corral which needs pushes in the given position, along with
+/// its I and P conditions, pushes and deadlock search result.
+pub fn analyze_corrals(game: &Game, deadlock_max_nodes: usize) -> Vec<CorralReport> {
+    let mut game = game.clone();
+    let reachable = game.compute_pushes();
+    let mut deadlocks = DeadlockSearcher::new(Rc::new(Zobrist::new()), deadlock_max_nodes);
+    let positions = |game: &Game, boxes: Bitvector| {
+        boxes
+            .iter()
+            .map(|box_idx| game.box_position(box_idx))
+            .collect()
+    };
+
+    let mut reports = Vec::new();
+    for extent in compute_push_corral_extents(&game, &reachable) {
+        let edge_boxes = positions(&game, extent.boxes_on_edge);
+        let corral = compute_pi_conditions(&game, extent, &reachable);
+        let deadlock = corral
+            .i_condition
+            .then(|| deadlocks.search(&mut game, &corral));
+        let mut squares = Vec::new();
+        for y in 0..game.height() {
+            for x in 0..game.width() {
+                if corral.extent.get(Position(x, y)) {
+                    squares.push(Position(x, y));
+                }
+            }
+        }
+        let pushes = corral
+            .pushes
+            .iter()
+            .map(|push| PushByPos {
+                box_pos: game.box_position(push.box_index()),
+                direction: push.direction(),
+            })
+            .collect();
+        reports.push(CorralReport {
+            extent: squares,
+            boxes: positions(&game, corral.boxes),
+            edge_boxes,
+            i_condition: corral.i_condition,
+            p_condition: corral.p_condition,
+            pushes,
+            deadlock,
+        });
+    }
+    reports
+}
 
-    // Perform DFS to find full extent of corral
-    while let Some(curr_pos) = stack.pop() {
-        let is_goal = game.get_tile(curr_pos) == Tile::Goal;
+/// Pushes which move a box onto a goal.
+fn goal_pushes(game: &Game, reachable: &ReachableSet<Push>) -> Vec<Push> {
+    let mut pushes = Vec::new();
+    for push in &reachable.moves {
+        let new_pos = game.box_position(push.box_index()) + push.direction();
+        if game.get_tile(new_pos) == Tile::Goal {
+            pushes.push(push);
+        }
+    }
+    pushes
+}
 
-        // We've hit a box
-        if let Some(box_idx) = game.box_index(curr_pos) {
-            boxes.add(box_idx);
-            // Box not on goal: corral requires pushes to solve the puzzle
-            if !is_goal {
-                requires_push = true;
+/// A corral is unsafe to prune with if it contains no empty goals of its own
+/// and its pushes exclude every push onto a goal, while empty goals remain
+/// outside the corral. Pruning is still sound in that case, but on rare levels
+/// it badly delays finding a solution.
+fn is_safe_to_prune(game: &Game, corral: &Corral, goal_pushes: &[Push]) -> bool {
+    if goal_pushes.is_empty() || goal_pushes.iter().any(|&push| corral.pushes.contains(push)) {
+        return true;
+    }
+    let mut has_internal_goal = false;
+    let mut has_external_goal = false;
+    for &goal in game.goal_positions() {
+        if game.box_index(goal).is_none() {
+            if corral.extent.get(goal) {
+                has_internal_goal = true;
+            } else {
+                has_external_goal = true;
             }
-            // If we've hit the edge of the corral, stop exploring further
-            if reachable.boxes.contains(box_idx) {
-                boxes_on_edge.add(box_idx);
-                continue;
+        }
+    }
+    has_internal_goal || !has_external_goal
+}
+
+/// Performs a corral-level search for pull corral pruning and deadlocks (the
+/// reverse search analogue of PI-corrals).
+///
+/// Pulls always move a box towards the player, so the contents of a corral can
+/// only change after one of the boxes on its edge is pulled out from outside the
+/// corral. If a corral still needs changes (i.e., it contains a box off-target
+/// or an empty target) but none of its edge boxes can ever be pulled out, the
+/// state is deadlocked. If every pull out of a corral is available right now,
+/// searching is restricted to those pulls, preferring the corral with the
+/// fewest.
+///
+/// Unlike pushes into a corral, a box pulled out of a corral lands where the
+/// player walks, so it can get in the way of pulls made elsewhere in the
+/// meantime. Pruning therefore doesn't guarantee that a reverse search which
+/// runs out of positions has proven the level unsolvable, nor that it keeps
+/// the positions another search would meet it at, so the solver only prunes
+/// when asked to (`SolverOpts::pull_corral_pruning`).
+pub fn search_pull_corrals(game: &Game, reachable: &ReachableSet<Pull>) -> CorralResult<Pull> {
+    let mut best: Option<Moves<Pull>> = None;
+
+    for extent in compute_corral_extents(game, reachable) {
+        let corral = compute_pull_corral(game, &extent, reachable);
+        if !corral.can_pull_out {
+            return CorralResult::Deadlocked;
+        }
+        if corral.p_condition
+            && best
+                .as_ref()
+                .is_none_or(|pulls| corral.pulls.len() < pulls.len())
+        {
+            best = Some(corral.pulls);
+        }
+    }
+
+    match best {
+        Some(pulls) => CorralResult::Prune(pulls),
+        None => CorralResult::None,
+    }
+}
+
+/// Checks whether the player is shut in a corral of their own by walls and
+/// frozen boxes, while boxes or goals outside it still need changes (goals
+/// only if none may be left empty). Frozen
+/// boxes never move, so the player can never get out, and no box can be moved
+/// in or out either.
+pub fn is_player_enclosed(game: &Game, frozen_boxes: &Bitvector) -> bool {
+    let mut blocked = LazyBitboard::new();
+    for box_idx in frozen_boxes.iter() {
+        blocked.set(game.box_position(box_idx));
+    }
+
+    let mut stack: ArrayVec<Position, { MAX_SIZE * MAX_SIZE }> = ArrayVec::new();
+    let mut region = LazyBitboard::new();
+    stack.push(game.player());
+    region.set(game.player());
+    while let Some(pos) = stack.pop() {
+        for next_pos in pos.neighbors(game.width(), game.height()) {
+            if game.get_tile(next_pos) != Tile::Wall
+                && !blocked.get(next_pos)
+                && !region.get(next_pos)
+            {
+                stack.push(next_pos);
+                region.set(next_pos);
             }
-        } else if is_goal {
-            // Goal without a box: corral requires pushes to solve the puzzle
-            requires_push = true;
         }
+    }
 
-        // Otherwise, continue searching in all directions
+    game.box_positions()
+        .iter()
+        .any(|&pos| !region.get(pos) && game.get_tile(pos) != Tile::Goal)
+        || game.is_balanced()
+            && game
+                .goal_positions()
+                .iter()
+                .any(|&pos| !region.get(pos) && game.box_index(pos).is_none())
+}
+
+/// The ways out of a pull corral.
+struct PullCorral {
+    /// Pulls of a box on the edge of the corral out of it which the player can
+    /// make right now.
+    pulls: Moves<Pull>,
+    /// Whether any box on the edge could ever be pulled out of the corral.
+    can_pull_out: bool,
+    /// Whether every pull which could ever take a box out of the corral can be
+    /// made right now.
+    p_condition: bool,
+}
+
+fn compute_pull_corral(
+    game: &Game,
+    corral: &CorralExtent,
+    reachable: &ReachableSet<Pull>,
+) -> PullCorral {
+    let mut pulls = Moves::new();
+    let mut can_pull_out = false;
+    let mut p_condition = true;
+
+    for box_idx in corral.boxes_on_edge {
+        let box_pos = game.box_position(box_idx);
         for &dir in &ALL_DIRECTIONS {
-            if let Some(next_pos) = game.move_position(curr_pos, dir) {
-                if game.get_tile(next_pos) != Tile::Wall && !extent.get(next_pos) {
-                    stack.push(next_pos);
-                    extent.set(next_pos);
+            // The player stands on the square the box is pulled to, and steps
+            // back one further square
+            if let Some(new_box_pos) = game.move_position(box_pos, dir)
+                && let Some(player_pos) = game.move_position(new_box_pos, dir)
+                && !corral.extent.get(new_box_pos)
+                && game.get_tile(new_box_pos) != Tile::Wall
+                && game.get_tile(player_pos) != Tile::Wall
+                && !game.is_pull_dead_square(new_box_pos)
+            {
+                can_pull_out = true;
+                let pull = Pull::new(box_idx, dir);
+                if reachable.moves.contains(pull) {
+                    pulls.add(box_idx, dir);
+                } else {
+                    p_condition = false;
                 }
             }
         }
     }
 
-    if !requires_push {
-        return None;
+    PullCorral {
+        pulls,
+        can_pull_out,
+        p_condition,
     }
+}
+
+struct Corral {
+    /// The boxes in the corral, including boxes on the edge of the corral.
+    boxes: Bitvector,
+    /// The extent of the corral. This includes all boxes within the corral,
+    /// including its edge.
+    extent: LazyBitboard,
+    /// Valid corral pushes.
+    pushes: Moves<Push>,
+    i_condition: bool,
+    p_condition: bool,
+}
+
+/// The region enclosed by a corral, independent of the move direction.
+struct CorralExtent {
+    /// The boxes in the corral, including boxes on the edge of the corral.
+    boxes: Bitvector,
+    /// The boxes on the edge of the corral (i.e., reachable by the player).
+    boxes_on_edge: Bitvector,
+    /// The extent of the corral, including boxes on its edge.
+    extent: LazyBitboard,
+    /// Whether the corral contains a box off-target or an empty target, so
+    /// that it needs moves to solve the puzzle.
+    requires_push: bool,
+}
+
+#[cfg(test)]
+fn compute_corral(game: &Game, pos: Position, reachable: &ReachableSet<Push>) -> Option<Corral> {
+    let extent = compute_corral_extent(game, pos, reachable);
+    extent
+        .requires_push
+        .then(|| compute_pi_conditions(game, extent, reachable))
+}
+
+fn compute_pi_conditions(
+    game: &Game,
+    extent: CorralExtent,
+    reachable: &ReachableSet<Push>,
+) -> Corral {
+    let CorralExtent {
+        boxes,
+        boxes_on_edge,
+        extent,
+        ..
+    } = extent;
 
     let mut i_condition = true;
     let mut p_condition = true;
@@ -176,50 +504,424 @@ fn compute_corral(game: &Game, pos: Position, reachable: &ReachableSet<Push>) ->
         }
     }
 
-    Some(Corral {
+    Corral {
         boxes,
         extent,
         pushes,
         i_condition,
         p_condition,
-    })
+    }
+}
+
+/// Computes the corrals which a push the player can make right now leads
+/// into, and which require moves to solve the puzzle.
+fn compute_push_corral_extents(game: &Game, reachable: &ReachableSet<Push>) -> Vec<CorralExtent> {
+    let mut extents = compute_corral_extents(game, reachable);
+    extents.retain(|corral| {
+        reachable.moves.iter().any(|push| {
+            let box_pos = game.box_position(push.box_index());
+            corral.extent.get(box_pos + push.direction())
+        })
+    });
+    extents
+}
+
+/// Computes the corrals which require moves to solve the puzzle, in a single
+/// pass over the squares the player can't reach. Each connected component of
+/// those squares is labeled once, extending through boxes up to the boxes on
+/// its edge (which neighbouring corrals can share). Components shut off by
+/// walls alone, with no reachable box on their edge, are left out.
+fn compute_corral_extents<T>(game: &Game, reachable: &ReachableSet<T>) -> Vec<CorralExtent> {
+    let mut extents = Vec::new();
+    let mut labeled = LazyBitboard::new();
+    for y in 0..game.height() {
+        for x in 0..game.width() {
+            let pos = Position(x, y);
+            if game.get_tile(pos) == Tile::Wall
+                || reachable.squares.get(pos)
+                || labeled.get(pos)
+                || game
+                    .box_index(pos)
+                    .is_some_and(|idx| reachable.boxes.contains(idx))
+            {
+                continue;
+            }
+            let extent = compute_corral_extent(game, pos, reachable);
+            labeled.set_all(&extent.extent);
+            if extent.requires_push && !extent.boxes_on_edge.is_empty() {
+                extents.push(extent);
+            }
+        }
+    }
+    extents
+}
+
+/// Computes the extent of the corral containing the given (unreachable)
+/// position.
+fn compute_corral_extent<T>(
+    game: &Game,
+    pos: Position,
+    reachable: &ReachableSet<T>,
+) -> CorralExtent {
+    assert!(!reachable.squares.get(pos));
+
+    let mut stack: ArrayVec<Position, { MAX_SIZE * MAX_SIZE }> = ArrayVec::new();
+    let mut extent = LazyBitboard::new();
+    let mut boxes = Bitvector::new();
+    let mut boxes_on_edge = Bitvector::new();
+    let mut requires_push = false;
+
+    // Start DFS from the given position
+    stack.push(pos);
+    extent.set(pos);
+
+    // Perform DFS to find full extent of corral
+    while let Some(curr_pos) = stack.pop() {
+        let is_goal = game.get_tile(curr_pos) == Tile::Goal;
+
+        // We've hit a box
+        if let Some(box_idx) = game.box_index(curr_pos) {
+            boxes.add(box_idx);
+            // Box not on goal: corral requires pushes to solve the puzzle
+            if !is_goal {
+                requires_push = true;
+            }
+            // If we've hit the edge of the corral, stop exploring further
+            if reachable.boxes.contains(box_idx) {
+                boxes_on_edge.add(box_idx);
+                continue;
+            }
+        } else if is_goal && game.is_balanced() {
+            // Goal without a box: corral requires pushes to solve the puzzle,
+            // unless goals outnumber boxes and it may stay empty
+            requires_push = true;
+        }
+
+        // Otherwise, continue searching in all directions
+        for next_pos in curr_pos.neighbors(game.width(), game.height()) {
+            if game.get_tile(next_pos) != Tile::Wall && !extent.get(next_pos) {
+                stack.push(next_pos);
+                extent.set(next_pos);
+            }
+        }
+    }
+
+    CorralExtent {
+        boxes,
+        boxes_on_edge,
+        extent,
+        requires_push,
+    }
 }
 
+/// The result of a corral deadlock search.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum DeadlockResult {
+pub enum DeadlockResult {
+    /// A box can be pushed out of the corral, or its boxes put on goals.
     Ok,
+    /// The corral can never be solved.
     Deadlocked,
+    /// The search ran out of nodes.
     CutOff,
 }
 
+impl fmt::Display for DeadlockResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeadlockResult::Ok => write!(f, "ok"),
+            DeadlockResult::Deadlocked => write!(f, "deadlocked"),
+            DeadlockResult::CutOff => write!(f, "cut off"),
+        }
+    }
+}
+
+/// Corral deadlock search results for a level, which can be saved and given to
+/// later searches of the same level so that they don't redo the same proofs.
+/// Cut off searches aren't kept, since a larger node limit may finish them.
+#[derive(Debug, Clone, Default)]
+pub struct DeadlockTables {
+    /// Whether each projected corral position, by hash, is deadlocked.
+    pub corrals: HashMap<u64, bool>,
+    /// Learned deadlocks, as the squares of their boxes and the region of the
+    /// player. Those of labelled boxes aren't kept, as their labels aren't.
+    pub learned: Vec<(RawBitboard, RawBitboard)>,
+}
+
+/// Maximum number of times the node budget of a corral deadlock search is
+/// doubled after being cut off.
+const MAX_BUDGET_DOUBLINGS: u32 = 4;
+
+/// Maximum number of learned deadlocks kept, after which no more are learned.
+const MAX_LEARNED_DEADLOCKS: usize = 1024;
+
+/// Box sets proven deadlocked by the corral deadlock search, with every other
+/// box removed. Extra boxes only get in the way, so any later position
+/// containing one of these box sets (with the player in the same region) is
+/// also deadlocked, whichever corral it comes from. In a Sokoban+ level, the
+/// boxes must have the same labels too. The corral table only catches exact
+/// repeats of the projected corral.
+#[derive(Default)]
+struct LearnedDeadlocks {
+    deadlocks: Vec<LearnedDeadlock>,
+    /// Indices of the deadlocks by the position of their first box.
+    by_anchor: HashMap<Position, Vec<usize>>,
+}
+
+struct LearnedDeadlock {
+    boxes: RawBitboard,
+    /// Squares the player can reach with only the deadlocked boxes on the
+    /// board, from any of which the boxes are deadlocked.
+    region: RawBitboard,
+    /// Label of the box on each square, or empty if unlabelled.
+    labels: Box<[(Position, u8)]>,
+}
+
+impl LearnedDeadlocks {
+    fn is_full(&self) -> bool {
+        self.deadlocks.len() >= MAX_LEARNED_DEADLOCKS
+    }
+
+    /// Learn the deadlock of all the boxes of the given (projected) game.
+    fn add(&mut self, game: &Game) {
+        let squares = game.compute_pushes().squares;
+        let mut region = RawBitboard::new();
+        for y in 0..game.height() {
+            for x in 0..game.width() {
+                if squares.get(Position(x, y)) {
+                    region.set(Position(x, y));
+                }
+            }
+        }
+        let labels = if game.is_labelled() {
+            game.box_positions()
+                .iter()
+                .enumerate()
+                .map(|(i, &pos)| (pos, game.box_label(Index(i as u8))))
+                .collect()
+        } else {
+            Box::default()
+        };
+        self.insert(box_bitboard(game), region, labels);
+    }
+
+    fn insert(&mut self, boxes: RawBitboard, region: RawBitboard, labels: Box<[(Position, u8)]>) {
+        let Some(anchor) = boxes.top_left() else {
+            return;
+        };
+        self.by_anchor
+            .entry(anchor)
+            .or_default()
+            .push(self.deadlocks.len());
+        self.deadlocks.push(LearnedDeadlock {
+            boxes,
+            region,
+            labels,
+        });
+    }
+
+    /// Whether the given position contains a learned deadlock.
+    fn matches(&self, game: &Game) -> bool {
+        if self.deadlocks.is_empty() {
+            return false;
+        }
+        let boxes = box_bitboard(game);
+        game.box_positions()
+            .iter()
+            .filter_map(|pos| self.by_anchor.get(pos))
+            .flatten()
+            .any(|&idx| {
+                let deadlock = &self.deadlocks[idx];
+                deadlock.region.get(game.player())
+                    && boxes.contains_all(&deadlock.boxes)
+                    && deadlock.labels.iter().all(|&(pos, label)| {
+                        game.box_index(pos)
+                            .is_some_and(|idx| game.box_label(idx) == label)
+                    })
+            })
+    }
+}
+
+fn box_bitboard(game: &Game) -> RawBitboard {
+    let mut boxes = RawBitboard::new();
+    for &pos in game.box_positions() {
+        boxes.set(pos);
+    }
+    boxes
+}
+
+/// The boxes left out of a corral deadlock search, which are put back into the
+/// positions it visits to record them as positions of the main search.
+struct OutsideBoxes {
+    hash: u64,
+    check: u64,
+    squares: RawBitboard,
+}
+
 struct DeadlockSearcher {
     /// Transposition table which contains search results for corrals.
     corral_table: HashMap<u64, DeadlockResult>,
+    /// Minimal deadlocked box sets found so far.
+    learned: LearnedDeadlocks,
     /// Transposition table which is cleared and reused on each search.
     search_table: HashMap<u64, usize>,
+    /// Number of times the search of each corral, by hash, has been cut off.
+    cutoffs: HashMap<u64, u32>,
     zobrist: Rc<Zobrist>,
+    /// Node budget for the first search of each corral.
     max_nodes_explored: usize,
+    /// Node budget for the current search.
+    budget: usize,
+    /// Zobrist hashes for the check hashes of the main search, if the
+    /// positions found deadlocked are recorded for it.
+    check_zobrist: Option<Zobrist>,
+    /// Boxes outside the corral being searched, while recording positions.
+    outside: Option<OutsideBoxes>,
+    /// Hashes and check hashes of the positions found deadlocked.
+    dead_positions: Vec<(u64, u64)>,
 }
 
 impl DeadlockSearcher {
     fn new(zobrist: Rc<Zobrist>, max_nodes_explored: usize) -> Self {
         Self {
             corral_table: HashMap::new(),
+            learned: LearnedDeadlocks::default(),
             search_table: HashMap::new(),
+            cutoffs: HashMap::new(),
             zobrist,
             max_nodes_explored,
+            budget: max_nodes_explored,
+            check_zobrist: None,
+            outside: None,
+            dead_positions: Vec::new(),
         }
     }
 
-    /// Search for corral deadlocks.
+    fn load_tables(&mut self, tables: &DeadlockTables) {
+        for (&hash, &deadlocked) in &tables.corrals {
+            let result = if deadlocked {
+                DeadlockResult::Deadlocked
+            } else {
+                DeadlockResult::Ok
+            };
+            self.corral_table.entry(hash).or_insert(result);
+        }
+        for &(boxes, region) in &tables.learned {
+            if self.learned.is_full() {
+                break;
+            }
+            self.learned.insert(boxes, region, Box::default());
+        }
+    }
+
+    fn tables(&self) -> DeadlockTables {
+        let corrals = self
+            .corral_table
+            .iter()
+            .filter_map(|(&hash, &result)| match result {
+                DeadlockResult::Ok => Some((hash, false)),
+                DeadlockResult::Deadlocked => Some((hash, true)),
+                DeadlockResult::CutOff => None,
+            })
+            .collect();
+        let learned = self
+            .learned
+            .deadlocks
+            .iter()
+            .filter(|deadlock| deadlock.labels.is_empty())
+            .map(|deadlock| (deadlock.boxes, deadlock.region))
+            .collect();
+        DeadlockTables { corrals, learned }
+    }
+
+    /// Search for corral deadlocks, learning the boxes needed for any
+    /// deadlock found.
     fn search(&mut self, game: &mut Game, corral: &Corral) -> DeadlockResult {
         if self.max_nodes_explored == 0 {
             return DeadlockResult::Ok;
         }
 
-        // Project the game down to only boxes within the corral
+        // Every position visited by a search which finds a deadlock is
+        // deadlocked too, as its pushes were all searched
+        let recorded = self.dead_positions.len();
+        if let Some(check_zobrist) = &self.check_zobrist {
+            let mut outside_boxes = Bitvector::full(game.box_count() as u8);
+            outside_boxes.remove_all(&corral.boxes);
+            let mut squares = RawBitboard::new();
+            for box_idx in outside_boxes.iter() {
+                squares.set(game.box_position(box_idx));
+            }
+            self.outside = Some(OutsideBoxes {
+                hash: self.zobrist.compute_boxes_hash_subset(game, outside_boxes),
+                check: check_zobrist.compute_boxes_hash_subset(game, outside_boxes),
+                squares,
+            });
+        }
+        let result = self.search_boxes(game, corral, corral.boxes);
+        self.outside = None;
+        if result != DeadlockResult::Deadlocked {
+            self.dead_positions.truncate(recorded);
+        }
+
+        if result == DeadlockResult::Deadlocked && !self.learned.is_full() {
+            self.learn(game, corral);
+        }
+        result
+    }
+
+    /// Record the position just pushed to, with the boxes outside the corral
+    /// put back, unless a box was pushed onto one of them. Extra boxes only
+    /// get in the way, so it is deadlocked if the corral's boxes are.
+    fn record_position(&mut self, game: &Game, boxes_hash: u64) {
+        let (Some(check_zobrist), Some(outside)) = (&self.check_zobrist, &self.outside) else {
+            return;
+        };
+        if game
+            .box_positions()
+            .iter()
+            .any(|&pos| outside.squares.get(pos))
+        {
+            return;
+        }
+        let player = game.player();
+        let hash = boxes_hash ^ outside.hash ^ self.zobrist.player_hash(player);
+        let check = check_zobrist.compute_boxes_hash(game)
+            ^ outside.check
+            ^ check_zobrist.player_hash(player);
+        self.dead_positions.push((hash, check));
+    }
+
+    /// Learn the deadlock of the given corral, after dropping each box whose
+    /// removal still leaves the others deadlocked.
+    fn learn(&mut self, game: &mut Game, corral: &Corral) {
+        let mut boxes = corral.boxes;
+        for box_idx in corral.boxes.iter() {
+            let mut subset = boxes;
+            subset.remove(box_idx);
+            if !subset.is_empty()
+                && self.search_boxes(game, corral, subset) == DeadlockResult::Deadlocked
+            {
+                boxes = subset;
+            }
+        }
+
         let checkpoint = game.checkpoint();
-        game.project(corral.boxes);
+        game.project(boxes);
+        self.learned.add(game);
+        game.restore(&checkpoint);
+    }
+
+    /// Search for a deadlock of the given boxes of the corral, with every
+    /// other box removed. A deadlock holds whatever the corral's extent,
+    /// since it means no box could be pushed out of it.
+    fn search_boxes(
+        &mut self,
+        game: &mut Game,
+        corral: &Corral,
+        boxes: Bitvector,
+    ) -> DeadlockResult {
+        // Project the game down to only the given boxes
+        let checkpoint = game.checkpoint();
+        game.project(boxes);
 
         // Clear the working transposition table
         self.search_table.clear();
@@ -227,7 +929,8 @@ impl DeadlockSearcher {
         // Perform the search
         let mut nodes_explored = 0;
         let partial_hash = self.zobrist.compute_boxes_hash(game);
-        let result = self.search_helper(game, corral, 0, &mut nodes_explored, partial_hash);
+        let frozen = compute_frozen_boxes(game);
+        let result = self.search_helper(game, corral, 0, &mut nodes_explored, partial_hash, frozen);
 
         // Undo projection
         game.restore(&checkpoint);
@@ -242,6 +945,7 @@ impl DeadlockSearcher {
         depth: usize,
         nodes_explored: &mut usize,
         partial_hash: u64,
+        frozen: Bitvector,
     ) -> DeadlockResult {
         *nodes_explored += 1;
 
@@ -273,8 +977,15 @@ impl DeadlockSearcher {
         // Mark this state as visited at this depth
         self.search_table.insert(hash, depth);
 
+        // Corrals which keep getting cut off are worth a bigger budget, since
+        // they keep gating the search
+        if depth == 0 {
+            let cutoffs = self.cutoffs.get(&hash).copied().unwrap_or(0);
+            self.budget = self.max_nodes_explored.saturating_mul(1 << cutoffs);
+        }
+
         // Check if we're allowed to explore children
-        if *nodes_explored >= self.max_nodes_explored {
+        if *nodes_explored >= self.budget {
             return DeadlockResult::CutOff;
         }
 
@@ -284,7 +995,7 @@ impl DeadlockSearcher {
         for push in &reachable.moves {
             // Get the old and new box positions
             let old_box_pos = game.box_position(push.box_index());
-            let new_box_pos = game.move_position(old_box_pos, push.direction()).unwrap();
+            let new_box_pos = old_box_pos + push.direction();
 
             // Prune dead square pushes
             if game.is_push_dead_square(new_box_pos) {
@@ -300,15 +1011,31 @@ impl DeadlockSearcher {
             // Make the push
             game.push(push);
 
+            // Prune pushes which freeze a box off its goal, without spending
+            // nodes on them
+            let new_frozen = compute_new_frozen_boxes(frozen, game, push.box_index());
+            if game.unsolved_boxes().contains_any(&new_frozen) {
+                game.pull(push.to_pull());
+                continue;
+            }
+
             // Update partial hash incrementally (unhash old box position, hash
             // new box position)
+            let label = game.box_label(push.box_index());
             let partial_hash = partial_hash
-                ^ self.zobrist.box_hash(old_box_pos)
-                ^ self.zobrist.box_hash(new_box_pos);
+                ^ self.zobrist.labelled_box_hash(old_box_pos, label)
+                ^ self.zobrist.labelled_box_hash(new_box_pos, label);
+            self.record_position(game, partial_hash);
 
             // Recursively search
-            let child_result =
-                self.search_helper(game, corral, depth + 1, nodes_explored, partial_hash);
+            let child_result = self.search_helper(
+                game,
+                corral,
+                depth + 1,
+                nodes_explored,
+                partial_hash,
+                frozen.union(&new_frozen),
+            );
 
             // Undo the push
             game.pull(push.to_pull());
@@ -320,9 +1047,21 @@ impl DeadlockSearcher {
             }
         }
 
-        // Update the corral table if at root
+        // Update the corral table if at root. A cut off search is retried
+        // with double the budget the next time the corral comes up, until
+        // the budget can't grow any further.
         if depth == 0 {
-            self.corral_table.insert(hash, result);
+            if result == DeadlockResult::CutOff {
+                let cutoffs = self.cutoffs.entry(hash).or_insert(0);
+                *cutoffs += 1;
+                if *cutoffs > MAX_BUDGET_DOUBLINGS {
+                    self.cutoffs.remove(&hash);
+                    self.corral_table.insert(hash, result);
+                }
+            } else {
+                self.cutoffs.remove(&hash);
+                self.corral_table.insert(hash, result);
+            }
         }
 
         result
@@ -526,11 +1265,44 @@ mod tests {
         );
 
         let reachable = game.compute_pushes();
-        let mut searcher = CorralSearcher::new(Rc::new(Zobrist::new()), 10000);
+        let mut searcher = CorralSearcher::new(Rc::new(Zobrist::new()), 10000, false);
         let result = searcher.search(&mut game, &reachable);
         assert_eq!(result, CorralResult::Deadlocked);
     }
 
+    #[test]
+    fn test_corral_safety() {
+        // The left corral contains no goals, and its pushes exclude pushing
+        // the bottom box onto its goal
+        let text = r#"
+########
+#  $  .#
+#   $@.#
+#  $# .#
+####   #
+   # $.#
+   #####
+"#;
+        let mut pushes = Moves::new();
+        pushes.add(Index(0), Direction::Left);
+        pushes.add(Index(1), Direction::Left);
+
+        let mut game = parse_game(text);
+        let reachable = game.compute_pushes();
+        let mut searcher = CorralSearcher::new(Rc::new(Zobrist::new()), 10000, false);
+        let result = searcher.search(&mut game, &reachable);
+        assert_eq!(result, CorralResult::Prune(pushes));
+        assert_eq!(searcher.take_downgrade(), None);
+
+        let mut searcher = CorralSearcher::new(Rc::new(Zobrist::new()), 10000, true);
+        let result = searcher.search(&mut game, &reachable);
+        assert_eq!(result, CorralResult::None);
+        assert_eq!(
+            searcher.take_downgrade(),
+            Some(CorralDowngrade::Disabled { candidates: 1 })
+        );
+    }
+
     #[test]
     fn test_deadlock_1() {
         let mut game = parse_game(
@@ -636,6 +1408,301 @@ mod tests {
         check_corral_deadlock(&mut game, Direction::Up, DeadlockResult::Deadlocked);
     }
 
+    #[test]
+    fn test_deadlock_budget() {
+        let mut game = parse_game(
+            r#"
+########
+#.   ###
+#    ###
+#$   ###
+# #@$  #
+#   ## #
+# .*   #
+########
+"#,
+        );
+        let reachable = game.compute_pushes();
+        let corral = compute_corral(&game, Position(5, 4), &reachable).unwrap();
+        let mut searcher = DeadlockSearcher::new(Rc::new(Zobrist::new()), 4);
+        assert_eq!(searcher.search(&mut game, &corral), DeadlockResult::CutOff);
+
+        // The budget is doubled when the corral comes up again
+        assert_eq!(
+            searcher.search(&mut game, &corral),
+            DeadlockResult::Deadlocked
+        );
+        assert!(searcher.cutoffs.is_empty());
+    }
+
+    #[test]
+    fn test_deadlock_freeze_pruning() {
+        // The only push freezes both boxes against each other, which is
+        // pruned without spending any of the (tiny) node budget
+        let mut game = parse_game(
+            r#"
+#########
+#.@$ $ .#
+#########
+"#,
+        );
+        let reachable = game.compute_pushes();
+        let corral = compute_corral(&game, Position(4, 1), &reachable).unwrap();
+        let mut searcher = DeadlockSearcher::new(Rc::new(Zobrist::new()), 2);
+        assert_eq!(
+            searcher.search(&mut game, &corral),
+            DeadlockResult::Deadlocked
+        );
+    }
+
+    #[test]
+    fn test_dead_positions() {
+        let mut game = parse_game(
+            r#"
+#######
+#..$  #
+#*@$  #
+#######
+"#,
+        );
+        let zobrist = Rc::new(Zobrist::new());
+        let check_zobrist = Zobrist::with_seed(1);
+        let mut searcher = CorralSearcher::new(zobrist.clone(), 100, false);
+        searcher.record_dead_positions(Zobrist::with_seed(1));
+        let reachable = game.compute_pushes();
+        assert_eq!(
+            searcher.search(&mut game, &reachable),
+            CorralResult::Deadlocked
+        );
+        let dead = searcher.take_dead_positions();
+        assert!(searcher.take_dead_positions().is_empty());
+
+        // The positions the corral's boxes were pushed to are recorded with
+        // the exact player position and every box on the board
+        let push = reachable
+            .moves
+            .iter()
+            .find(|push| {
+                game.box_position(push.box_index()) == Position(3, 2)
+                    && push.direction() == Direction::Right
+            })
+            .unwrap();
+        game.push(push);
+        let hash = zobrist.compute_boxes_hash(&game) ^ zobrist.player_hash(game.player());
+        let check =
+            check_zobrist.compute_boxes_hash(&game) ^ check_zobrist.player_hash(game.player());
+        assert!(dead.contains(&(hash, check)));
+    }
+
+    #[test]
+    fn test_learned_deadlock() {
+        let mut game = parse_game(
+            r#"
+#######
+#..$  #
+#*@$  #
+#######
+"#,
+        );
+        let reachable = game.compute_pushes();
+        let corral = compute_corral(&game, Position(4, 1), &reachable).unwrap();
+        let mut searcher = DeadlockSearcher::new(Rc::new(Zobrist::new()), 100);
+        assert_eq!(
+            searcher.search(&mut game, &corral),
+            DeadlockResult::Deadlocked
+        );
+        assert_eq!(searcher.learned.deadlocks.len(), 1);
+        assert!(searcher.learned.matches(&game));
+
+        // The deadlock holds wherever the player is in its region, and
+        // wherever the other boxes are
+        game.set_player(Position(2, 1));
+        assert!(searcher.learned.matches(&game));
+        let game = parse_game(
+            r#"
+#######
+#.*$  #
+#.@$  #
+#######
+"#,
+        );
+        assert!(searcher.learned.matches(&game));
+
+        // But not once one of its boxes has moved
+        let game = parse_game(
+            r#"
+#######
+#..$  #
+#*@ $ #
+#######
+"#,
+        );
+        assert!(!searcher.learned.matches(&game));
+    }
+
+    #[test]
+    fn test_corral_extents() {
+        // One corral on each side of the player, while the walled off box on
+        // its goal needs no moves
+        let game = parse_game(
+            r#"
+#########
+#.$@$.#*#
+#########
+"#,
+        );
+        let reachable = game.compute_pushes();
+        let extents = compute_corral_extents(&game, &reachable);
+        assert_eq!(extents.len(), 2);
+        for (extent, box_pos) in extents.iter().zip([Position(2, 1), Position(4, 1)]) {
+            let box_idx = game.box_index(box_pos).unwrap();
+            assert_eq!(extent.boxes_on_edge.iter().collect::<Vec<_>>(), [box_idx]);
+        }
+    }
+
+    #[test]
+    fn test_analyze_corrals() {
+        let game = parse_game(
+            r#"
+######
+#@$ .#
+######
+"#,
+        );
+        let reports = analyze_corrals(&game, 20);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.boxes, vec![Position(2, 1)]);
+        assert_eq!(report.edge_boxes, vec![Position(2, 1)]);
+        assert!(report.i_condition);
+        assert!(report.p_condition);
+        assert_eq!(
+            report.pushes,
+            vec![PushByPos {
+                box_pos: Position(2, 1),
+                direction: Direction::Right,
+            }]
+        );
+        assert_eq!(report.deadlock, Some(DeadlockResult::Ok));
+        assert_eq!(report.render(&game), "######\n#@$~.#\n######\n");
+    }
+
+    #[test]
+    fn test_player_enclosed() {
+        // The boxes in the corridor freeze each other on their goals, shutting
+        // the player off from the rest of the level
+        let game = parse_game(
+            r#"
+#########
+#@ ** $.#
+#########
+"#,
+        );
+        assert!(is_player_enclosed(&game, &compute_frozen_boxes(&game)));
+
+        // Nothing needs to change on the far side of them
+        let game = parse_game(
+            r#"
+#########
+#@$.**  #
+#########
+"#,
+        );
+        assert!(!is_player_enclosed(&game, &compute_frozen_boxes(&game)));
+    }
+
+    #[test]
+    fn test_pull_corral_1() {
+        // The box can be pulled left out of the corral, and that is the only
+        // way out of it
+        let game = parse_game(
+            r#"
+#######
+# .@$ #
+#######
+"#,
+        );
+
+        let reachable = game.compute_pulls();
+        let mut pulls = Moves::new();
+        pulls.add(game.box_index(Position(4, 1)).unwrap(), Direction::Left);
+        assert_eq!(
+            search_pull_corrals(&game, &reachable),
+            CorralResult::Prune(pulls)
+        );
+    }
+
+    #[test]
+    fn test_pull_corral_p_condition() {
+        // The box could be pulled left out of the corral once the other box
+        // is out of the way, so the corral can't be used for pruning yet
+        let game = parse_game(
+            r#"
+#########
+#     ###
+# . $@$.#
+#     ###
+#########
+"#,
+        );
+
+        let reachable = game.compute_pulls();
+        assert_eq!(search_pull_corrals(&game, &reachable), CorralResult::None);
+    }
+
+    #[test]
+    fn test_pull_corral_2() {
+        // There is no room for the player to pull the box out of the corral
+        let game = parse_game(
+            r#"
+#######
+#.#@$ #
+#######
+"#,
+        );
+
+        let reachable = game.compute_pulls();
+        assert_eq!(
+            search_pull_corrals(&game, &reachable),
+            CorralResult::Deadlocked
+        );
+    }
+
+    #[test]
+    fn test_pull_corral_3() {
+        // The corral is sealed, but its boxes are already on goals
+        let game = parse_game(
+            r#"
+########
+#$.#@**#
+########
+"#,
+        );
+
+        let reachable = game.compute_pulls();
+        assert_eq!(search_pull_corrals(&game, &reachable), CorralResult::None);
+    }
+
+    #[test]
+    fn test_pull_corral_4() {
+        // The inner box cannot be reached until the edge box is pulled out,
+        // which requires the player to step back onto a non-wall square
+        let game = parse_game(
+            r#"
+#########
+#  #@$$.#
+#.   ####
+#########
+"#,
+        );
+
+        let reachable = game.compute_pulls();
+        assert_eq!(
+            search_pull_corrals(&game, &reachable),
+            CorralResult::Deadlocked
+        );
+    }
+
     fn parse_game(text: &str) -> Game {
         Game::from_text(text.trim_matches('\n')).unwrap()
     }
@@ -650,8 +1717,8 @@ mod tests {
         expected_result: DeadlockResult,
     ) {
         let reachable = game.compute_pushes();
-        let box_pos = game.move_position(game.player(), direction).unwrap();
-        let corral_pos = game.move_position(box_pos, direction).unwrap();
+        let box_pos = game.player() + direction;
+        let corral_pos = box_pos + direction;
         let corral = compute_corral(game, corral_pos, &reachable).unwrap();
         let zobrist = Rc::new(Zobrist::new());
         let mut searcher = DeadlockSearcher::new(zobrist, 100);
@@ -0,0 +1,151 @@
+use rusqlite::{Connection, params};
+
+use crate::{bits::RawBitboard, corral::DeadlockTables};
+
+/// A SQLite database of corral deadlock search results, keyed by a fingerprint
+/// of each level (see `Game::board_fingerprint`) and the hash of each
+/// projected corral position. Re-running a collection, or re-trying a level
+/// after a cutoff, then starts from the deadlock proofs of earlier runs.
+pub struct DeadlockDb {
+    conn: Connection,
+}
+
+impl DeadlockDb {
+    /// Open (or create) the database at the given path.
+    pub fn open(path: &str) -> Result<Self, String> {
+        Self::init(Connection::open(path).map_err(|e| e.to_string())?)
+    }
+
+    /// Open a temporary in-memory database.
+    pub fn open_in_memory() -> Result<Self, String> {
+        Self::init(Connection::open_in_memory().map_err(|e| e.to_string())?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS corral_results (
+                level INTEGER NOT NULL,
+                hash INTEGER NOT NULL,
+                deadlocked INTEGER NOT NULL,
+                PRIMARY KEY (level, hash)
+            );
+            CREATE TABLE IF NOT EXISTS learned_deadlocks (
+                level INTEGER NOT NULL,
+                boxes BLOB NOT NULL,
+                region BLOB NOT NULL,
+                PRIMARY KEY (level, boxes, region)
+            );",
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(Self { conn })
+    }
+
+    /// Load the results saved for the level with the given fingerprint.
+    pub fn load(&self, fingerprint: u64) -> Result<DeadlockTables, String> {
+        let level = fingerprint as i64;
+        let mut tables = DeadlockTables::default();
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT hash, deadlocked FROM corral_results WHERE level = ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![level], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, bool>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (hash, deadlocked) = row.map_err(|e| e.to_string())?;
+            tables.corrals.insert(hash, deadlocked);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT boxes, region FROM learned_deadlocks WHERE level = ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![level], |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (boxes, region) = row.map_err(|e| e.to_string())?;
+            let (Some(boxes), Some(region)) = (
+                RawBitboard::from_bytes(&boxes),
+                RawBitboard::from_bytes(&region),
+            ) else {
+                return Err("malformed learned deadlock".to_string());
+            };
+            tables.learned.push((boxes, region));
+        }
+
+        Ok(tables)
+    }
+
+    /// Save the given results for the level with the given fingerprint,
+    /// keeping those already saved.
+    pub fn save(&self, fingerprint: u64, tables: &DeadlockTables) -> Result<(), String> {
+        let level = fingerprint as i64;
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| e.to_string())?;
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT OR IGNORE INTO corral_results (level, hash, deadlocked)
+                    VALUES (?1, ?2, ?3)",
+                )
+                .map_err(|e| e.to_string())?;
+            for (&hash, &deadlocked) in &tables.corrals {
+                stmt.execute(params![level, hash as i64, deadlocked])
+                    .map_err(|e| e.to_string())?;
+            }
+
+            let mut stmt = tx
+                .prepare(
+                    "INSERT OR IGNORE INTO learned_deadlocks (level, boxes, region)
+                    VALUES (?1, ?2, ?3)",
+                )
+                .map_err(|e| e.to_string())?;
+            for (boxes, region) in &tables.learned {
+                stmt.execute(params![level, boxes.to_bytes(), region.to_bytes()])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bits::Position;
+
+    #[test]
+    fn test_save_and_load() {
+        let db = DeadlockDb::open_in_memory().unwrap();
+        let mut boxes = RawBitboard::new();
+        boxes.set(Position(3, 1));
+        boxes.set(Position(3, 2));
+        let mut region = RawBitboard::new();
+        region.set(Position(2, 2));
+        let mut tables = DeadlockTables::default();
+        tables.corrals.insert(u64::MAX, true);
+        tables.corrals.insert(7, false);
+        tables.learned.push((boxes, region));
+
+        db.save(1, &tables).unwrap();
+        // Saving again keeps a single copy of each result
+        db.save(1, &tables).unwrap();
+
+        let loaded = db.load(1).unwrap();
+        assert_eq!(loaded.corrals, tables.corrals);
+        assert_eq!(loaded.learned, tables.learned);
+
+        // Results are kept per level
+        let other = db.load(2).unwrap();
+        assert!(other.corrals.is_empty());
+        assert!(other.learned.is_empty());
+    }
+}
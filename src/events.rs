@@ -0,0 +1,231 @@
+use crate::{game::Position, solver::DirectionStats};
+use std::io::Write;
+
+/// A structured search event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A node was expanded.
+    Expanded { hash: u64 },
+    /// PI-corral pruning restricted the moves of the expanded node.
+    CorralPruned { moves: usize, pruned_moves: usize },
+    /// A corral deadlock was detected in the expanded node.
+    CorralDeadlock,
+    /// PI-corral pruning was downgraded because the cheapest corral excluded
+    /// every push onto a goal. `chosen` is the rank of the corral used
+    /// instead, or `None` if pruning was disabled for the node.
+    CorralDowngraded {
+        candidates: usize,
+        chosen: Option<usize>,
+    },
+    /// A child was pruned because the moved box became frozen off-goal.
+    FreezePruned { box_pos: Position },
+    /// A transposition table hit found a different position under the hash.
+    HashCollision { hash: u64 },
+    /// A child with a lower heuristic value than any before was generated.
+    ImprovedBound { cost: usize },
+    /// Anytime search found a solution cheaper than any before, with the
+    /// given number of pushes and cost (pushes or moves).
+    ImprovedSolution { length: usize, cost: usize },
+    /// Bidirectional search stopped expanding the event's direction, as it
+    /// had stalled, or had run out of positions after pruning with pull
+    /// corrals.
+    DirectionDisabled,
+    /// The sizes the event's direction grew to by the end of the search:
+    /// the largest open list and transposition table, the heuristics kept for
+    /// sets of frozen boxes, and the largest estimated number of bytes used.
+    Memory {
+        peak_open: usize,
+        peak_table: usize,
+        heuristic_entries: usize,
+        peak_memory: usize,
+    },
+    /// The search found a solution of the given length.
+    Solved { length: usize },
+    /// The search exceeded its node limit.
+    Cutoff,
+    /// The search exceeded its time limit.
+    TimedOut,
+    /// The search was interrupted (e.g. by Ctrl-C).
+    Interrupted,
+    /// The search proved the puzzle unsolvable.
+    Unsolvable,
+}
+
+impl Event {
+    /// The memory usage recorded in the statistics of a search direction.
+    pub fn memory(stats: &DirectionStats) -> Self {
+        Event::Memory {
+            peak_open: stats.peak_open,
+            peak_table: stats.peak_table,
+            heuristic_entries: stats.heuristic_entries,
+            peak_memory: stats.peak_memory,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Expanded { .. } => "expanded",
+            Event::CorralPruned { .. } => "pruned_by_corral",
+            Event::CorralDeadlock => "corral_deadlock",
+            Event::CorralDowngraded { .. } => "corral_downgraded",
+            Event::FreezePruned { .. } => "pruned_by_freeze",
+            Event::HashCollision { .. } => "hash_collision",
+            Event::ImprovedBound { .. } => "improved_bound",
+            Event::ImprovedSolution { .. } => "improved_solution",
+            Event::DirectionDisabled => "direction_disabled",
+            Event::Memory { .. } => "memory",
+            Event::Solved { .. } => "solved",
+            Event::Cutoff => "cutoff",
+            Event::TimedOut => "timed_out",
+            Event::Interrupted => "interrupted",
+            Event::Unsolvable => "unsolvable",
+        }
+    }
+
+    /// Extra JSON fields specific to the event (including a leading comma).
+    fn fields(&self) -> String {
+        match self {
+            Event::Expanded { hash } | Event::HashCollision { hash } => {
+                format!(r#","hash":"{:016x}""#, hash)
+            }
+            Event::CorralPruned {
+                moves,
+                pruned_moves,
+            } => format!(r#","moves":{},"pruned_moves":{}"#, moves, pruned_moves),
+            Event::CorralDowngraded { candidates, chosen } => match chosen {
+                Some(chosen) => format!(r#","candidates":{},"chosen":{}"#, candidates, chosen),
+                None => format!(r#","candidates":{},"chosen":null"#, candidates),
+            },
+            Event::FreezePruned { box_pos } => {
+                format!(r#","box_x":{},"box_y":{}"#, box_pos.0, box_pos.1)
+            }
+            Event::ImprovedBound { cost } => format!(r#","cost":{}"#, cost),
+            Event::ImprovedSolution { length, cost } => {
+                format!(r#","length":{},"cost":{}"#, length, cost)
+            }
+            Event::Memory {
+                peak_open,
+                peak_table,
+                heuristic_entries,
+                peak_memory,
+            } => format!(
+                r#","peak_open":{},"peak_table":{},"heuristic_entries":{},"peak_memory":{}"#,
+                peak_open, peak_table, heuristic_entries, peak_memory
+            ),
+            Event::Solved { length } => format!(r#","length":{}"#, length),
+            Event::CorralDeadlock
+            | Event::DirectionDisabled
+            | Event::Cutoff
+            | Event::TimedOut
+            | Event::Interrupted
+            | Event::Unsolvable => String::new(),
+        }
+    }
+}
+
+/// Writes search events as JSON Lines (one JSON object per line).
+pub struct EventLog {
+    writer: Option<Box<dyn Write>>,
+    level: usize,
+}
+
+impl EventLog {
+    pub fn new(writer: Box<dyn Write>, level: usize) -> Self {
+        Self {
+            writer: Some(writer),
+            level,
+        }
+    }
+
+    /// Write a single event. Logging stops silently if the writer fails (e.g.,
+    /// if the reading end of a pipe was closed).
+    pub fn write(&mut self, node: usize, direction: Option<&str>, event: &Event) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        let direction = match direction {
+            Some(direction) => format!(r#","direction":"{}""#, direction),
+            None => String::new(),
+        };
+        let result = writeln!(
+            writer,
+            r#"{{"level":{},"node":{}{},"event":"{}"{}}}"#,
+            self.level,
+            node,
+            direction,
+            event.name(),
+            event.fields()
+        );
+        if result.is_err() {
+            self.writer = None;
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = &mut self.writer
+            && writer.flush().is_err()
+        {
+            self.writer = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Writer which appends into a shared buffer.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_event_log() {
+        let buffer = SharedBuffer::default();
+        let mut log = EventLog::new(Box::new(buffer.clone()), 3);
+        log.write(1, Some("forward"), &Event::Expanded { hash: 0xabc });
+        log.write(
+            2,
+            Some("reverse"),
+            &Event::FreezePruned {
+                box_pos: Position(4, 5),
+            },
+        );
+        log.write(
+            2,
+            Some("forward"),
+            &Event::Memory {
+                peak_open: 3,
+                peak_table: 5,
+                heuristic_entries: 1,
+                peak_memory: 512,
+            },
+        );
+        log.write(2, None, &Event::Solved { length: 7 });
+        log.flush();
+
+        let output = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"level":3,"node":1,"direction":"forward","event":"expanded","hash":"0000000000000abc"}"#,
+                r#"{"level":3,"node":2,"direction":"reverse","event":"pruned_by_freeze","box_x":4,"box_y":5}"#,
+                r#"{"level":3,"node":2,"direction":"forward","event":"memory","peak_open":3,"peak_table":5,"heuristic_entries":1,"peak_memory":512}"#,
+                r#"{"level":3,"node":2,"event":"solved","length":7}"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+
+use crate::{
+    bits::{Bitvector, Index},
+    frozen::compute_frozen_boxes,
+    game::{Game, MAX_BOXES, Position},
+    heuristic::{Distances, compute_push_distances},
+};
+
+/// Marks a box or goal which isn't matched.
+const UNMATCHED: usize = usize::MAX;
+
+/// Why a level was found to be unsolvable, with the boxes responsible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unsolvability {
+    /// Boxes start on squares from which no goal can be reached.
+    DeadSquares(Vec<Position>),
+    /// Boxes start frozen off their goals, so can never be moved.
+    Frozen(Vec<Position>),
+    /// A set of boxes can only reach fewer goals than there are boxes in it.
+    Matching(Vec<Position>),
+    /// None of the above holds at the start, but the search examined every
+    /// reachable position without finding a solution.
+    Exhausted,
+}
+
+impl Unsolvability {
+    /// The boxes responsible.
+    pub fn positions(&self) -> &[Position] {
+        match self {
+            Unsolvability::DeadSquares(positions)
+            | Unsolvability::Frozen(positions)
+            | Unsolvability::Matching(positions) => positions,
+            Unsolvability::Exhausted => &[],
+        }
+    }
+
+    /// Render the board with each box responsible drawn as 'X'.
+    pub fn render(&self, game: &Game) -> String {
+        let positions = self.positions();
+        let mut text = String::new();
+        for y in 0..game.height() {
+            let mut line = String::new();
+            for x in 0..game.width() {
+                let pos = Position(x, y);
+                line.push(if positions.contains(&pos) {
+                    'X'
+                } else {
+                    game.square_char(pos)
+                });
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl fmt::Display for Unsolvability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.positions().len();
+        match self {
+            Unsolvability::DeadSquares(_) => {
+                write!(
+                    f,
+                    "{} box(es) on dead squares, from which no goal can be reached",
+                    count
+                )
+            }
+            Unsolvability::Frozen(_) => {
+                write!(f, "{} box(es) frozen off their goals at the start", count)
+            }
+            Unsolvability::Matching(_) => write!(
+                f,
+                "{} boxes can only reach {} goal(s) between them",
+                count,
+                count - 1
+            ),
+            Unsolvability::Exhausted => write!(f, "search exhausted every reachable position"),
+        }
+    }
+}
+
+/// Explain why the given level is unsolvable, by checking the starting
+/// position for a deadlock the search would have pruned at once.
+pub fn explain_unsolvable(game: &Game) -> Unsolvability {
+    let dead: Vec<_> = game
+        .box_positions()
+        .iter()
+        .copied()
+        .filter(|&pos| game.is_push_dead_square(pos))
+        .collect();
+    if !dead.is_empty() {
+        return Unsolvability::DeadSquares(dead);
+    }
+
+    let frozen_boxes = compute_frozen_boxes(game);
+    let frozen: Vec<_> = frozen_boxes
+        .iter()
+        .filter(|&box_idx| game.unsolved_boxes().contains(box_idx))
+        .map(|box_idx| game.box_position(box_idx))
+        .collect();
+    if !frozen.is_empty() {
+        return Unsolvability::Frozen(frozen);
+    }
+
+    let distances = compute_push_distances(game, &frozen_boxes);
+    if let Some(boxes) = find_unmatched_boxes(game, &distances) {
+        return Unsolvability::Matching(boxes);
+    }
+
+    Unsolvability::Exhausted
+}
+
+/// Find a set of boxes which can reach fewer goals than there are boxes in
+/// it, if the boxes can't all be matched to distinct goals.
+fn find_unmatched_boxes(game: &Game, distances: &Distances) -> Option<Vec<Position>> {
+    let positions = game.box_positions();
+    let reaches = |box_idx: usize, goal_idx: usize| {
+        let pos = positions[box_idx];
+        distances[goal_idx].get(game, pos) != u16::MAX
+            && game.box_fits_goal(Index(box_idx as u8), goal_idx)
+    };
+
+    // Maximum matching by augmenting paths
+    let goal_count = game.goal_positions().len();
+    let mut matched_box = [UNMATCHED; MAX_BOXES];
+    let mut unmatched = None;
+    for box_idx in 0..positions.len() {
+        let mut visited = Bitvector::new();
+        if !augment(
+            box_idx,
+            goal_count,
+            &reaches,
+            &mut visited,
+            &mut matched_box,
+        ) {
+            unmatched = Some(box_idx);
+            break;
+        }
+    }
+
+    // The boxes reachable from an unmatched box by alternating paths can
+    // only reach the goals matched to the others
+    let start = unmatched?;
+    let mut boxes = vec![start];
+    let mut visited = Bitvector::new();
+    let mut i = 0;
+    while i < boxes.len() {
+        for (goal_idx, &other) in matched_box[..goal_count].iter().enumerate() {
+            if !visited.contains(Index(goal_idx as u8)) && reaches(boxes[i], goal_idx) {
+                visited.add(Index(goal_idx as u8));
+                boxes.push(other);
+            }
+        }
+        i += 1;
+    }
+    Some(
+        boxes
+            .into_iter()
+            .map(|box_idx| positions[box_idx])
+            .collect(),
+    )
+}
+
+fn augment(
+    box_idx: usize,
+    goal_count: usize,
+    reaches: &impl Fn(usize, usize) -> bool,
+    visited: &mut Bitvector,
+    matched_box: &mut [usize; MAX_BOXES],
+) -> bool {
+    for goal_idx in 0..goal_count {
+        if visited.contains(Index(goal_idx as u8)) || !reaches(box_idx, goal_idx) {
+            continue;
+        }
+        visited.add(Index(goal_idx as u8));
+        let other = matched_box[goal_idx];
+        if other == UNMATCHED || augment(other, goal_count, reaches, visited, matched_box) {
+            matched_box[goal_idx] = box_idx;
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explain(text: &str) -> Unsolvability {
+        explain_unsolvable(&Game::from_text(text).unwrap())
+    }
+
+    #[test]
+    fn test_dead_square() {
+        let text = "#####\n\
+                    #$  #\n\
+                    #  .#\n\
+                    # @ #\n\
+                    #####";
+        let reason = explain(text);
+        assert_eq!(reason, Unsolvability::DeadSquares(vec![Position(1, 1)]));
+        let game = Game::from_text(text).unwrap();
+        assert_eq!(reason.render(&game), "#####\n#X  #\n#  .#\n# @ #\n#####\n");
+    }
+
+    #[test]
+    fn test_frozen() {
+        // No box is on a dead square, but the boxes block each other
+        let text = "######\n\
+                    #    #\n\
+                    # $$ #\n\
+                    # $$ #\n\
+                    # ...#\n\
+                    #  .@#\n\
+                    ######";
+        let reason = explain(text);
+        assert_eq!(
+            reason,
+            Unsolvability::Frozen(vec![
+                Position(2, 2),
+                Position(3, 2),
+                Position(2, 3),
+                Position(3, 3)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_matching() {
+        // Both boxes against the top wall can only reach the top left goal
+        let text = "#########\n\
+                    #.$   $ #\n\
+                    #       #\n\
+                    #@  $  .#\n\
+                    #      .#\n\
+                    #########";
+        let reason = explain(text);
+        let Unsolvability::Matching(mut boxes) = reason else {
+            panic!("expected a matching failure, got {:?}", reason);
+        };
+        boxes.sort_by_key(|pos| (pos.1, pos.0));
+        assert_eq!(boxes, vec![Position(2, 1), Position(6, 1)]);
+    }
+
+    #[test]
+    fn test_exhausted() {
+        // The player is walled off from the box
+        let text = "##########\n\
+                    #@#  $ . #\n\
+                    ##########";
+        assert_eq!(explain(text), Unsolvability::Exhausted);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::bits::Bitvector;
+use crate::game::Game;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// The features of a position used by FESS-style search (after the Festival
+/// solver) to project positions onto a small feature space. Positions which
+/// differ in these features represent different kinds of progress, so the
+/// search spreads its effort across them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Features {
+    /// Number of boxes on goals which are frozen, and so packed for good.
+    pub packed: u8,
+    /// Number of boxes on goals.
+    pub on_goals: u8,
+    /// Number of separate regions of the board the player could be in, i.e.
+    /// how badly the boxes partition the board.
+    pub regions: u8,
+}
+
+impl Features {
+    /// Compute the features of the given position, where `frozen_boxes` are
+    /// the boxes which can no longer be moved.
+    pub fn compute(game: &Game, frozen_boxes: &Bitvector) -> Self {
+        let unsolved = game.unsolved_boxes();
+        let mut solved_frozen = *frozen_boxes;
+        solved_frozen.remove_all(&unsolved);
+        Features {
+            packed: solved_frozen.len() as u8,
+            on_goals: (game.box_count() - unsolved.len()) as u8,
+            regions: game.all_possible_player_positions().len() as u8,
+        }
+    }
+}
+
+/// An open list partitioned into feature-space cells, each ordered by
+/// priority. Popping the minimum cycles through the non-empty cells in the
+/// order they were first seen, taking the best item of each in turn, so that
+/// no single kind of progress starves the others.
+pub struct FeatureQueue<T> {
+    cells: Vec<BTreeMap<usize, VecDeque<T>>>,
+    index: HashMap<Features, usize>,
+    /// The cell to pop from next.
+    cursor: usize,
+    len: usize,
+    /// Whether items of equal priority within a cell are popped last in,
+    /// first out.
+    lifo: bool,
+}
+
+impl<T> FeatureQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            cells: Vec::new(),
+            index: HashMap::new(),
+            cursor: 0,
+            len: 0,
+            lifo: false,
+        }
+    }
+
+    /// Create a queue which pops the most recently pushed of a cell's items
+    /// with the lowest priority first.
+    pub fn lifo() -> Self {
+        Self {
+            lifo: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn push(&mut self, features: Features, priority: usize, item: T) {
+        let cell = *self.index.entry(features).or_insert_with(|| {
+            self.cells.push(BTreeMap::new());
+            self.cells.len() - 1
+        });
+        self.cells[cell]
+            .entry(priority)
+            .or_default()
+            .push_back(item);
+        self.len += 1;
+    }
+
+    /// Pop the item with the lowest priority from the next non-empty cell.
+    pub fn pop_min(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        for offset in 0..self.cells.len() {
+            let cell = (self.cursor + offset) % self.cells.len();
+            let Some(mut bucket) = self.cells[cell].first_entry() else {
+                continue;
+            };
+            let item = if self.lifo {
+                bucket.get_mut().pop_back()
+            } else {
+                bucket.get_mut().pop_front()
+            };
+            if bucket.get().is_empty() {
+                bucket.remove();
+            }
+            self.cursor = cell + 1;
+            self.len -= 1;
+            return item;
+        }
+        None
+    }
+
+    /// Pop an item with the highest priority across all cells (the one its
+    /// cell would pop last), along with its priority.
+    pub fn pop_max(&mut self) -> Option<(usize, T)> {
+        let cell = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cell)| Some((*cell.last_key_value()?.0, i)))
+            .max()?
+            .1;
+        let mut bucket = self.cells[cell].last_entry()?;
+        let priority = *bucket.key();
+        let item = if self.lifo {
+            bucket.get_mut().pop_front()?
+        } else {
+            bucket.get_mut().pop_back()?
+        };
+        if bucket.get().is_empty() {
+            bucket.remove();
+        }
+        self.len -= 1;
+        Some((priority, item))
+    }
+
+    /// The lowest priority of any item across all cells, if any.
+    pub fn min_priority(&self) -> Option<usize> {
+        self.cells
+            .iter()
+            .filter_map(|cell| cell.first_key_value().map(|(&priority, _)| priority))
+            .min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of feature-space cells seen so far.
+    pub fn num_cells(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+impl<T> Default for FeatureQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(on_goals: u8) -> Features {
+        Features {
+            packed: 0,
+            on_goals,
+            regions: 1,
+        }
+    }
+
+    #[test]
+    fn test_pop_min_cycles_cells() {
+        let mut queue = FeatureQueue::new();
+        queue.push(features(0), 5, "a1");
+        queue.push(features(0), 3, "a0");
+        queue.push(features(1), 10, "b0");
+        queue.push(features(2), 7, "c0");
+        queue.push(features(0), 8, "a2");
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.num_cells(), 3);
+
+        // Each cell gets a turn, regardless of the other cells' priorities
+        assert_eq!(queue.pop_min(), Some("a0"));
+        assert_eq!(queue.pop_min(), Some("b0"));
+        assert_eq!(queue.pop_min(), Some("c0"));
+        assert_eq!(queue.pop_min(), Some("a1"));
+        // Empty cells are skipped
+        assert_eq!(queue.pop_min(), Some("a2"));
+        assert_eq!(queue.pop_min(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_pop_max() {
+        let mut queue = FeatureQueue::new();
+        queue.push(features(0), 5, "low");
+        queue.push(features(1), 9, "first");
+        queue.push(features(1), 9, "second");
+        queue.push(features(2), 7, "mid");
+
+        assert_eq!(queue.min_priority(), Some(5));
+        assert_eq!(queue.pop_max(), Some((9, "second")));
+        assert_eq!(queue.pop_max(), Some((9, "first")));
+        assert_eq!(queue.pop_max(), Some((7, "mid")));
+        assert_eq!(queue.pop_min(), Some("low"));
+        assert_eq!(queue.pop_max(), None);
+    }
+
+    #[test]
+    fn test_features() {
+        let game = Game::from_text(
+            r#"
+#######
+#@$.* #
+#######
+"#,
+        )
+        .unwrap();
+        let frozen = Bitvector::new();
+        // The boxes split the corridor into three regions
+        assert_eq!(
+            Features::compute(&game, &frozen),
+            Features {
+                packed: 0,
+                on_goals: 1,
+                regions: 3,
+            }
+        );
+    }
+}
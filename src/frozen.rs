@@ -1,28 +1,55 @@
 use arrayvec::ArrayVec;
 
+use std::iter::successors;
+
 use crate::bits::Bitvector;
 use crate::game::{ALL_DIRECTIONS, Direction, Game, Index, MAX_BOXES, Position, Tile};
 
 /// Computes the set of boxes which are currently effectively frozen.
 pub fn compute_frozen_boxes(game: &Game) -> Bitvector {
+    compute_all_frozen_boxes(game, false)
+}
+
+/// Incrementally compute boxes which are newly frozen after box_idx has been
+/// pushed to its current location.
+pub fn compute_new_frozen_boxes(frozen: Bitvector, game: &Game, box_idx: Index) -> Bitvector {
+    compute_new_frozen_boxes_impl(frozen, game, box_idx, false)
+}
+
+/// Computes the set of boxes which can never be pulled again (or only onto
+/// pull dead squares), for reverse search.
+pub fn compute_pull_frozen_boxes(game: &Game) -> Bitvector {
+    compute_all_frozen_boxes(game, true)
+}
+
+/// Incrementally compute boxes which are newly frozen for pulls after box_idx
+/// has been pulled to its current location.
+pub fn compute_new_pull_frozen_boxes(frozen: Bitvector, game: &Game, box_idx: Index) -> Bitvector {
+    compute_new_frozen_boxes_impl(frozen, game, box_idx, true)
+}
+
+fn compute_all_frozen_boxes(game: &Game, pulls: bool) -> Bitvector {
     let mut result = Bitvector::new();
     for box_idx in 0..game.box_count() {
         let box_idx = Index(box_idx as u8);
         if !result.contains(box_idx) {
-            let frozen = compute_new_frozen_boxes(result, game, box_idx);
+            let frozen = compute_new_frozen_boxes_impl(result, game, box_idx, pulls);
             result.add_all(&frozen);
         }
     }
     result
 }
 
-/// Incrementally compute boxes which are newly frozen after box_idx has been
-/// pushed to its current location.
-pub fn compute_new_frozen_boxes(frozen: Bitvector, game: &Game, box_idx: Index) -> Bitvector {
+fn compute_new_frozen_boxes_impl(
+    frozen: Bitvector,
+    game: &Game,
+    box_idx: Index,
+    pulls: bool,
+) -> Bitvector {
     assert!(!frozen.contains(box_idx));
 
     // Find all boxes which might become frozen
-    let candidates = find_candidates(frozen, game, box_idx);
+    let candidates = find_candidates(frozen, game, box_idx, pulls);
     // Mark all candidate boxes as frozen initially
     let mut candidates_frozen = candidates;
     // Mark all candidates a needing to be checked
@@ -30,18 +57,21 @@ pub fn compute_new_frozen_boxes(frozen: Bitvector, game: &Game, box_idx: Index)
 
     while let Some(box_idx) = to_check.pop() {
         let pos = game.box_position(box_idx);
-        if check_unfrozen(game, pos, &candidates, &candidates_frozen) {
+        let unfrozen = if pulls {
+            check_pullable(game, pos, &frozen, &candidates, &candidates_frozen)
+        } else {
+            check_unfrozen(game, pos, &candidates, &candidates_frozen)
+        };
+        if unfrozen {
             candidates_frozen.remove(box_idx);
 
             // Whenever we unfreeze a box, "wake up" its neighbors to be checked
             // again for unfreezing
-            for &dir in &ALL_DIRECTIONS {
-                if let Some(next_pos) = game.move_position(pos, dir) {
-                    if let Some(next_box_idx) = game.box_index(next_pos) {
-                        if candidates_frozen.contains(next_box_idx) {
-                            to_check.add(next_box_idx);
-                        }
-                    }
+            for next_pos in blocking_positions(game, pos, pulls) {
+                if let Some(next_box_idx) = game.box_index(next_pos)
+                    && candidates_frozen.contains(next_box_idx)
+                {
+                    to_check.add(next_box_idx);
                 }
             }
         }
@@ -50,23 +80,43 @@ pub fn compute_new_frozen_boxes(frozen: Bitvector, game: &Game, box_idx: Index)
     candidates_frozen
 }
 
-fn find_candidates(frozen: Bitvector, game: &Game, box_idx: Index) -> Bitvector {
+/// Positions whose boxes can block the given position's box from moving: the
+/// orthogonal neighbors for pushes, and also the squares two steps away in a
+/// straight line for pulls (where the player steps back).
+fn blocking_positions(game: &Game, pos: Position, pulls: bool) -> impl Iterator<Item = Position> {
+    let reach = if pulls { 2 } else { 1 };
+    ALL_DIRECTIONS.into_iter().flat_map(move |dir| {
+        successors(game.move_position(pos, dir), move |&next_pos| {
+            game.move_position(next_pos, dir)
+        })
+        .take(reach)
+    })
+}
+
+fn find_candidates(frozen: Bitvector, game: &Game, box_idx: Index, pulls: bool) -> Bitvector {
     let mut candidates = Bitvector::new();
     let mut stack: ArrayVec<Index, MAX_BOXES> = ArrayVec::new();
 
+    // Boxes which can be pulled right now without moving any other box never
+    // freeze, so there is no need to check them (or the boxes around them)
+    let is_candidate =
+        |box_idx: Index| !pulls || !is_pullable_now(game, game.box_position(box_idx));
+    if !is_candidate(box_idx) {
+        return candidates;
+    }
     candidates.add(box_idx);
     stack.push(box_idx);
 
     while let Some(box_idx) = stack.pop() {
         let pos = game.box_position(box_idx);
-        for &dir in &ALL_DIRECTIONS {
-            if let Some(next_pos) = game.move_position(pos, dir) {
-                if let Some(next_box_idx) = game.box_index(next_pos) {
-                    if !candidates.contains(next_box_idx) && !frozen.contains(next_box_idx) {
-                        candidates.add(next_box_idx);
-                        stack.push(next_box_idx);
-                    }
-                }
+        for next_pos in blocking_positions(game, pos, pulls) {
+            if let Some(next_box_idx) = game.box_index(next_pos)
+                && !candidates.contains(next_box_idx)
+                && !frozen.contains(next_box_idx)
+                && is_candidate(next_box_idx)
+            {
+                candidates.add(next_box_idx);
+                stack.push(next_box_idx);
             }
         }
     }
@@ -141,3 +191,94 @@ fn check_unfrozen(
     check_unfrozen_horizontal(game, pos, candidates, candidates_frozen)
         || check_unfrozen_vertical(game, pos, candidates, candidates_frozen)
 }
+
+/// Check whether the box at the given position could be pulled one square in
+/// the given direction, given which squares are free: the player needs both
+/// the square next to the box and the one beyond it, and the box mustn't end
+/// up on a pull dead square.
+fn check_pullable_dir(
+    game: &Game,
+    pos: Position,
+    dir: Direction,
+    is_free: impl Fn(Position) -> bool,
+) -> bool {
+    let Some(next_pos) = game.move_position(pos, dir) else {
+        return false;
+    };
+    let Some(player_pos) = game.move_position(next_pos, dir) else {
+        return false;
+    };
+    is_free(next_pos) && is_free(player_pos) && !game.is_pull_dead_square(next_pos)
+}
+
+/// Check whether the box at the given position can be pulled without moving
+/// any other box.
+fn is_pullable_now(game: &Game, pos: Position) -> bool {
+    let is_free = |pos| game.box_index(pos).is_none() && game.get_tile(pos) != Tile::Wall;
+    ALL_DIRECTIONS
+        .into_iter()
+        .any(|dir| check_pullable_dir(game, pos, dir, is_free))
+}
+
+/// Check whether the box at the given position could be pulled once the boxes
+/// which aren't frozen have moved away.
+fn check_pullable(
+    game: &Game,
+    pos: Position,
+    frozen: &Bitvector,
+    candidates: &Bitvector,
+    candidates_frozen: &Bitvector,
+) -> bool {
+    let is_free = |pos| {
+        if let Some(box_idx) = game.box_index(pos) {
+            if candidates.contains(box_idx) {
+                !candidates_frozen.contains(box_idx)
+            } else {
+                // Other boxes are either frozen or can be pulled right now
+                !frozen.contains(box_idx)
+            }
+        } else {
+            game.get_tile(pos) != Tile::Wall
+        }
+    };
+    ALL_DIRECTIONS
+        .into_iter()
+        .any(|dir| check_pullable_dir(game, pos, dir, is_free))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_frozen_boxes() {
+        // Neither box can be pulled: each blocks the square the player needs
+        // to pull the other
+        let game = Game::from_text(
+            "######\n\
+             #$ $ #\n\
+             ####@#\n\
+             #..  #\n\
+             ######",
+        )
+        .unwrap();
+        assert_eq!(compute_pull_frozen_boxes(&game), Bitvector::full(2));
+        assert_eq!(
+            compute_new_pull_frozen_boxes(Bitvector::new(), &game, Index(1)),
+            Bitvector::full(2)
+        );
+
+        // In the open both boxes can be pulled
+        let game = Game::from_text(
+            "########\n\
+             #$ $   #\n\
+             #      #\n\
+             #  ..  #\n\
+             #   @  #\n\
+             #      #\n\
+             ########",
+        )
+        .unwrap();
+        assert!(compute_pull_frozen_boxes(&game).is_empty());
+    }
+}
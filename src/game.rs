@@ -1,12 +1,18 @@
 use crate::bits::{Bitboard, Bitvector, BitvectorIter, LazyBitboard, RawBitboard};
 pub use crate::bits::{Index, Position};
+use crate::solution::Solution;
+use crate::zobrist::Zobrist;
 use arrayvec::ArrayVec;
-use std::{fmt, marker::PhantomData};
+use std::{collections::VecDeque, fmt, marker::PhantomData, ops::Add};
 
 pub const MAX_SIZE: usize = 64;
 pub const MAX_BOXES: usize = 64;
 pub const NO_BOX: Index = Index(255);
 
+/// Maximum number of squares searched for a way around a box moved into the
+/// player's region, after which the region is found again from scratch.
+const MAX_DETOUR_SQUARES: usize = 32;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tile {
     Wall,
@@ -48,7 +54,8 @@ impl Direction {
         }
     }
 
-    fn index(&self) -> usize {
+    /// Index of the direction within `ALL_DIRECTIONS`.
+    pub fn index(&self) -> usize {
         match self {
             Direction::Up => 0,
             Direction::Down => 1,
@@ -68,6 +75,43 @@ impl Direction {
     }
 }
 
+impl Position {
+    /// Move one square in the given direction.
+    /// Returns None if the result would have a negative (or overflowing)
+    /// coordinate. Board bounds are not checked; see `Game::move_position`.
+    pub fn offset(self, dir: Direction) -> Option<Position> {
+        let (dx, dy) = dir.delta();
+        Some(Position(
+            self.0.checked_add_signed(dx)?,
+            self.1.checked_add_signed(dy)?,
+        ))
+    }
+
+    /// Manhattan distance between two positions.
+    pub fn manhattan(self, other: Position) -> usize {
+        self.0.abs_diff(other.0) as usize + self.1.abs_diff(other.1) as usize
+    }
+
+    /// Iterate over the orthogonally adjacent positions within a board of the
+    /// given dimensions.
+    pub fn neighbors(self, width: u8, height: u8) -> impl Iterator<Item = Position> {
+        ALL_DIRECTIONS
+            .into_iter()
+            .filter_map(move |dir| self.offset(dir))
+            .filter(move |pos| pos.0 < width && pos.1 < height)
+    }
+}
+
+/// Move one square in the given direction.
+/// Panics if the result would have a negative coordinate.
+impl Add<Direction> for Position {
+    type Output = Position;
+
+    fn add(self, dir: Direction) -> Position {
+        self.offset(dir).expect("position out of range")
+    }
+}
+
 impl fmt::Display for Direction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -97,6 +141,24 @@ pub struct Pull {
     direction: Direction,
 }
 
+/// A push identified by the board position of the box rather than its index.
+/// Unlike `Push`, this is independent of how boxes are numbered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushByPos {
+    pub box_pos: Position,
+    pub direction: Direction,
+}
+
+impl fmt::Display for PushByPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({},{}) {}",
+            self.box_pos.0, self.box_pos.1, self.direction
+        )
+    }
+}
+
 impl Push {
     pub fn new(box_index: Index, direction: Direction) -> Self {
         Self {
@@ -307,36 +369,56 @@ pub struct ReachableSet<T> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Boxes {
     positions: ArrayVec<Position, MAX_BOXES>,
-    // Maps board position to box index (NO_BOX = no box at this position)
-    index: [[Index; MAX_SIZE]; MAX_SIZE],
+    // Squares with a box on them
+    occupied: RawBitboard,
+    // Maps each floor square (see `Game::square_index`) to the index of the
+    // box on it (NO_BOX = no box on the square)
+    index: Box<[Index]>,
     // Boxes that are not on goal positions
     unsolved: Bitvector,
+    // Zobrist hash of the box positions, from `Zobrist::shared`
+    hash: u64,
 }
 
 impl Boxes {
-    fn new() -> Self {
+    fn new(square_count: usize) -> Self {
         Boxes {
             positions: ArrayVec::new(),
-            index: [[NO_BOX; MAX_SIZE]; MAX_SIZE],
+            occupied: RawBitboard::new(),
+            index: vec![NO_BOX; square_count].into_boxed_slice(),
             unsolved: Bitvector::new(),
+            hash: 0,
         }
     }
 
-    fn add(&mut self, pos: Position, is_goal: bool) -> Index {
+    fn add(&mut self, pos: Position, square: usize, is_goal: bool) -> Index {
         let index = Index(self.positions.len() as u8);
-        self.index[pos.1 as usize][pos.0 as usize] = index;
+        self.index[square] = index;
+        self.occupied.set(pos);
         self.positions.push(pos);
+        self.hash ^= Zobrist::shared().box_hash(pos);
         if !is_goal {
             self.unsolved.add(index);
         }
         index
     }
 
-    fn move_(&mut self, from: Position, to: Position, from_is_goal: bool, to_is_goal: bool) {
-        let idx = self.index[from.1 as usize][from.0 as usize];
+    /// Move the box between the given squares, each given with its index.
+    fn move_(
+        &mut self,
+        (from, from_square): (Position, usize),
+        (to, to_square): (Position, usize),
+        from_is_goal: bool,
+        to_is_goal: bool,
+    ) {
+        let idx = self.index[from_square];
         self.positions[idx.0 as usize] = to;
-        self.index[from.1 as usize][from.0 as usize] = NO_BOX;
-        self.index[to.1 as usize][to.0 as usize] = idx;
+        self.index[from_square] = NO_BOX;
+        self.index[to_square] = idx;
+        self.occupied.unset(from);
+        self.occupied.set(to);
+        let zobrist = Zobrist::shared();
+        self.hash ^= zobrist.box_hash(from) ^ zobrist.box_hash(to);
 
         // Update unsolved boxes
         if from_is_goal {
@@ -348,15 +430,15 @@ impl Boxes {
     }
 
     fn has_box_at(&self, pos: Position) -> bool {
-        self.index[pos.1 as usize][pos.0 as usize] != NO_BOX
+        self.occupied.get(pos)
     }
 
     fn clear(&mut self) {
-        for pos in &self.positions {
-            self.index[pos.1 as usize][pos.0 as usize] = NO_BOX;
-        }
+        self.index.fill(NO_BOX);
+        self.occupied = RawBitboard::new();
         self.positions.clear();
         self.unsolved = Bitvector::new();
+        self.hash = 0;
     }
 }
 
@@ -364,18 +446,65 @@ impl Boxes {
 pub struct Checkpoint {
     player: Position,
     boxes: ArrayVec<Position, MAX_BOXES>,
+    labels: ArrayVec<u8, MAX_BOXES>,
+}
+
+/// A checkpoint with its boxes packed into one bit per floor square, a
+/// fraction of the size of a `Checkpoint` on most boards, followed by one byte
+/// per box for the labels of a Sokoban+ level. Restoring it numbers the boxes
+/// in reading order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedCheckpoint {
+    player: Position,
+    boxes: Box<[u64]>,
+}
+
+/// Pushes made with `Game::push_with_history`, for `undo()` and `redo()`.
+#[derive(Debug, Clone, Default)]
+struct History {
+    /// Pushes made, with the player's position before each.
+    done: Vec<(Push, Position)>,
+    /// Pushes undone since the last push made, the most recently undone last.
+    undone: Vec<(Push, Position)>,
+}
+
+impl History {
+    fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+    }
+}
+
+// Games are compared by their positions alone, however they were reached
+impl PartialEq for History {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
+impl Eq for History {}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Game {
-    tiles: [[Tile; MAX_SIZE]; MAX_SIZE],
     player: Position,
     width: u8,
     height: u8,
     boxes: Boxes,
     goal_positions: ArrayVec<Position, MAX_BOXES>,
+    /// Squares on the board which aren't walls.
+    floor: RawBitboard,
+    /// Goal squares, as a bitboard.
+    goals: RawBitboard,
+    /// Number of floor squares before each row, and in total.
+    floor_offsets: [u16; MAX_SIZE + 1],
     push_dead_squares: RawBitboard,
     pull_dead_squares: RawBitboard,
+    history: History,
+    /// Sokoban+ label of each box, by index, or empty if unlabelled.
+    box_labels: ArrayVec<u8, MAX_BOXES>,
+    /// Sokoban+ label of each goal, in the order of `goal_positions`, or
+    /// empty if unlabelled.
+    goal_labels: ArrayVec<u8, MAX_BOXES>,
 }
 
 impl Game {
@@ -383,14 +512,55 @@ impl Game {
     ///
     /// Characters:
     /// - `#` = Wall
-    /// - ` ` = Floor (empty space)
+    /// - ` ` = Floor (empty space), also written `-` or `_`; tabs are
+    ///   expanded to spaces
     /// - `.` = Goal (target location for boxes)
     /// - `$` = Box
     /// - `@` = Player
     /// - `*` = Box on goal
     /// - `+` = Player on goal
     pub fn from_text(text: &str) -> Result<Self, String> {
-        let lines: Vec<&str> = text.lines().collect();
+        Self::parse(text, false, false)
+    }
+
+    /// Parse a Sokoban board like `from_text`, but only accepting spaces for
+    /// floor.
+    pub fn from_text_strict(text: &str) -> Result<Self, String> {
+        for (y, line) in text.lines().enumerate() {
+            if let Some((x, ch)) = line
+                .chars()
+                .enumerate()
+                .find(|&(_, ch)| matches!(ch, '-' | '_' | '\t'))
+            {
+                return Err(format!(
+                    "Invalid character '{}' at position ({}, {})",
+                    ch.escape_default(),
+                    x,
+                    y
+                ));
+            }
+        }
+        Self::from_text(text)
+    }
+
+    /// Parse a Sokoban board whose goals may outnumber its boxes, as in some
+    /// published collections, where it is solved once every box is on a
+    /// goal. Boards with more boxes than goals are accepted too, but can
+    /// never be solved.
+    pub fn from_text_unbalanced(text: &str) -> Result<Self, String> {
+        Self::parse(text, false, true)
+    }
+
+    /// Parse a Sokoban board which may have no player, e.g. one still being
+    /// edited. Without a player, it is placed in the first region (in reading
+    /// order) from which it can reach a box. See
+    /// `Solver::new_unknown_player` to search from every such region.
+    pub fn from_text_unknown_player(text: &str) -> Result<Self, String> {
+        Self::parse(text, true, false)
+    }
+
+    fn parse(text: &str, unknown_player: bool, unbalanced: bool) -> Result<Self, String> {
+        let lines: Vec<String> = text.lines().map(expand_floor).collect();
 
         if lines.is_empty() {
             return Err("Empty board".to_string());
@@ -414,7 +584,7 @@ impl Game {
 
         let mut tiles = [[Tile::Floor; MAX_SIZE]; MAX_SIZE];
         let mut player = None;
-        let mut boxes = Boxes::new();
+        let mut boxes: ArrayVec<Position, MAX_BOXES> = ArrayVec::new();
         let mut goal_positions = ArrayVec::new();
 
         for (y, line) in lines.iter().enumerate() {
@@ -424,16 +594,20 @@ impl Game {
                     ' ' => tiles[y][x] = Tile::Floor,
                     '.' => {
                         tiles[y][x] = Tile::Goal;
+                        check_capacity(&goal_positions, "Goal")?;
                         goal_positions.push(Position(x as u8, y as u8));
                     }
                     '$' => {
                         tiles[y][x] = Tile::Floor;
-                        boxes.add(Position(x as u8, y as u8), false);
+                        check_capacity(&boxes, "Box")?;
+                        boxes.push(Position(x as u8, y as u8));
                     }
                     '*' => {
                         tiles[y][x] = Tile::Goal;
+                        check_capacity(&goal_positions, "Goal")?;
+                        check_capacity(&boxes, "Box")?;
                         goal_positions.push(Position(x as u8, y as u8));
-                        boxes.add(Position(x as u8, y as u8), true);
+                        boxes.push(Position(x as u8, y as u8));
                     }
                     '@' => {
                         tiles[y][x] = Tile::Floor;
@@ -448,6 +622,7 @@ impl Game {
                             return Err("Multiple players found".to_string());
                         }
                         player = Some(Position(x as u8, y as u8));
+                        check_capacity(&goal_positions, "Goal")?;
                         goal_positions.push(Position(x as u8, y as u8));
                     }
                     _ => {
@@ -460,46 +635,185 @@ impl Game {
             }
         }
 
-        let Some(player) = player else {
+        if player.is_none() && !unknown_player {
             return Err("No player found on board".to_owned());
-        };
+        }
+        Self::from_tiles(
+            tiles,
+            width,
+            height,
+            player,
+            &boxes,
+            goal_positions,
+            unbalanced,
+        )
+    }
 
+    /// Build a game from its tiles, boxes (in index order) and goals (in
+    /// reading order), and player, which is placed in the first region from
+    /// which it can reach a box if not given. Unless `unbalanced` is set,
+    /// there must be as many goals as boxes.
+    fn from_tiles(
+        tiles: [[Tile; MAX_SIZE]; MAX_SIZE],
+        width: usize,
+        height: usize,
+        player: Option<Position>,
+        box_positions: &[Position],
+        goal_positions: ArrayVec<Position, MAX_BOXES>,
+        unbalanced: bool,
+    ) -> Result<Self, String> {
         // Validate that the number of goals matches the number of boxes
-        if goal_positions.len() != boxes.positions.len() {
+        if !unbalanced && goal_positions.len() != box_positions.len() {
             return Err(format!(
                 "Goal count ({}) does not match box count ({})",
                 goal_positions.len(),
-                boxes.positions.len()
+                box_positions.len()
             ));
         }
 
+        let mut floor = RawBitboard::new();
+        let mut goals = RawBitboard::new();
+        for (y, row) in tiles[..height].iter().enumerate() {
+            for (x, &tile) in row[..width].iter().enumerate() {
+                let pos = Position(x as u8, y as u8);
+                if tile != Tile::Wall {
+                    floor.set(pos);
+                }
+                if tile == Tile::Goal {
+                    goals.set(pos);
+                }
+            }
+        }
+        let mut floor_offsets = [0; MAX_SIZE + 1];
+        for y in 0..MAX_SIZE {
+            floor_offsets[y + 1] = floor_offsets[y] + floor.row(y).count_ones() as u16;
+        }
+
         let mut game = Game {
-            tiles,
-            player,
+            player: player.unwrap_or(Position(0, 0)),
             width: width as u8,
             height: height as u8,
-            boxes,
+            boxes: Boxes::new(usize::from(floor_offsets[MAX_SIZE])),
             goal_positions,
+            floor,
+            goals,
+            floor_offsets,
             push_dead_squares: RawBitboard::new(),
             pull_dead_squares: RawBitboard::new(),
+            history: History::default(),
+            box_labels: ArrayVec::new(),
+            goal_labels: ArrayVec::new(),
         };
+        game.set_box_positions(box_positions);
         game.compute_dead_squares();
+        if player.is_none() {
+            game.player = *game
+                .all_possible_player_positions()
+                .first()
+                .ok_or("No square for the player next to a box")?;
+        }
+        Ok(game)
+    }
+
+    /// Normalize the level: floor the player can never reach (ignoring boxes)
+    /// is walled in, and rows and columns outside the walls around the rest
+    /// are dropped. Boxes and goals keep their order and labels. Returns an
+    /// error if the player can walk off the board, i.e. the level isn't
+    /// enclosed.
+    pub fn normalize(&self) -> Result<Game, String> {
+        let inside = self.wall_region(self.player);
+        let keep = |pos: Position| {
+            inside.get(pos) || self.boxes.has_box_at(pos) || self.get_tile(pos) == Tile::Goal
+        };
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (u8::MAX, u8::MAX, 0, 0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pos = Position(x, y);
+                if inside.get(pos)
+                    && (x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1)
+                {
+                    return Err(format!(
+                        "Level is not enclosed: the player can reach {} on the edge of the board",
+                        pos
+                    ));
+                }
+                if keep(pos) {
+                    (min_x, min_y) = (min_x.min(x), min_y.min(y));
+                    (max_x, max_y) = (max_x.max(x), max_y.max(y));
+                }
+            }
+        }
+
+        // Keep one row or column of walls around the squares left
+        let (min_x, min_y) = (min_x.saturating_sub(1), min_y.saturating_sub(1));
+        let max_x = (max_x + 1).min(self.width - 1);
+        let max_y = (max_y + 1).min(self.height - 1);
+        let shift = |pos: Position| Position(pos.0 - min_x, pos.1 - min_y);
+        let mut tiles = [[Tile::Wall; MAX_SIZE]; MAX_SIZE];
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let pos = Position(x, y);
+                if keep(pos) {
+                    let Position(new_x, new_y) = shift(pos);
+                    tiles[new_y as usize][new_x as usize] = self.get_tile(pos);
+                }
+            }
+        }
+        let boxes: ArrayVec<Position, MAX_BOXES> =
+            self.boxes.positions.iter().map(|&pos| shift(pos)).collect();
+        let goal_positions = self.goal_positions.iter().map(|&pos| shift(pos)).collect();
+        let mut game = Self::from_tiles(
+            tiles,
+            (max_x - min_x + 1) as usize,
+            (max_y - min_y + 1) as usize,
+            Some(shift(self.player)),
+            &boxes,
+            goal_positions,
+            !self.is_balanced(),
+        )?;
+        game.set_labels(&self.box_labels, &self.goal_labels)?;
         Ok(game)
     }
 
     /// Compute all dead squares where a box can never reach any goal.
     fn compute_dead_squares(&mut self) {
-        let mut push_reachable = RawBitboard::new();
-        let mut pull_reachable = RawBitboard::new();
+        let no_frozen_boxes = Bitvector::new();
+        self.push_dead_squares = self.compute_push_dead_squares(&no_frozen_boxes);
+        self.pull_dead_squares = self.compute_pull_dead_squares(&no_frozen_boxes);
+    }
+
+    /// Compute the squares from which a box can't be pushed to any goal, with
+    /// the given frozen boxes treated as walls (and so also taking their goals).
+    pub fn compute_push_dead_squares(&self, frozen_boxes: &Bitvector) -> RawBitboard {
+        let blocked = self.frozen_squares(frozen_boxes);
+        let mut reachable = RawBitboard::new();
+        for &goal_pos in &self.goal_positions {
+            if !blocked.get(goal_pos) {
+                self.dfs_push_reachable(goal_pos, &blocked, &mut reachable);
+            }
+        }
+        reachable.invert()
+    }
 
-        // For each goal, find all squares that can reach it via reverse pushes
+    /// Compute the squares from which a box can't be pulled to any goal, with
+    /// the given frozen boxes treated as walls (and so also taking their goals).
+    pub fn compute_pull_dead_squares(&self, frozen_boxes: &Bitvector) -> RawBitboard {
+        let blocked = self.frozen_squares(frozen_boxes);
+        let mut reachable = RawBitboard::new();
         for &goal_pos in &self.goal_positions {
-            self.dfs_push_reachable(goal_pos, &mut push_reachable);
-            self.dfs_pull_reachable(goal_pos, &mut pull_reachable);
+            if !blocked.get(goal_pos) {
+                self.dfs_pull_reachable(goal_pos, &blocked, &mut reachable);
+            }
         }
+        reachable.invert()
+    }
 
-        self.push_dead_squares = push_reachable.invert();
-        self.pull_dead_squares = pull_reachable.invert();
+    fn frozen_squares(&self, frozen_boxes: &Bitvector) -> RawBitboard {
+        let mut squares = RawBitboard::new();
+        for box_idx in frozen_boxes.iter() {
+            squares.set(self.box_position(box_idx));
+        }
+        squares
     }
 
     /// Generic DFS helper that explores positions starting from a given position.
@@ -523,14 +837,13 @@ impl Game {
 
         while let Some(from_pos) = stack.pop() {
             for direction in ALL_DIRECTIONS {
-                if let Some(to_pos) = self.move_position(from_pos, direction) {
-                    if self.get_tile(to_pos) != Tile::Wall
-                        && !visited.get(to_pos)
-                        && should_visit(from_pos, to_pos, direction)
-                    {
-                        visited.set(to_pos);
-                        stack.push(to_pos);
-                    }
+                if let Some(to_pos) = self.move_position(from_pos, direction)
+                    && self.get_tile(to_pos) != Tile::Wall
+                    && !visited.get(to_pos)
+                    && should_visit(from_pos, to_pos, direction)
+                {
+                    visited.set(to_pos);
+                    stack.push(to_pos);
                 }
             }
         }
@@ -538,15 +851,23 @@ impl Game {
 
     /// DFS to find all squares from which a box could be pushed to reach the given position.
     /// Uses reverse pushes (pulls).
-    fn dfs_push_reachable(&self, start_pos: Position, reachable: &mut RawBitboard) {
+    fn dfs_push_reachable(
+        &self,
+        start_pos: Position,
+        blocked: &RawBitboard,
+        reachable: &mut RawBitboard,
+    ) {
         if reachable.get(start_pos) {
             return;
         }
 
         self.dfs(start_pos, reachable, |_from_pos, to_pos, direction| {
-            // Check that there is room for the player
+            // Check that there is room for the box and the player
+            if blocked.get(to_pos) {
+                return false;
+            }
             if let Some(player_pos) = self.move_position(to_pos, direction) {
-                self.get_tile(player_pos) != Tile::Wall
+                self.get_tile(player_pos) != Tile::Wall && !blocked.get(player_pos)
             } else {
                 false
             }
@@ -555,15 +876,23 @@ impl Game {
 
     /// DFS to find all squares from which a box could be pulled to reach the given position.
     /// Uses forward pushes.
-    fn dfs_pull_reachable(&self, start_pos: Position, reachable: &mut RawBitboard) {
+    fn dfs_pull_reachable(
+        &self,
+        start_pos: Position,
+        blocked: &RawBitboard,
+        reachable: &mut RawBitboard,
+    ) {
         if reachable.get(start_pos) {
             return;
         }
 
-        self.dfs(start_pos, reachable, |from_pos, _to_pos, direction| {
-            // Check that there is room for the player
+        self.dfs(start_pos, reachable, |from_pos, to_pos, direction| {
+            // Check that there is room for the box and the player
+            if blocked.get(to_pos) {
+                return false;
+            }
             if let Some(player_pos) = self.move_position(from_pos, direction.reverse()) {
-                self.get_tile(player_pos) != Tile::Wall
+                self.get_tile(player_pos) != Tile::Wall && !blocked.get(player_pos)
             } else {
                 false
             }
@@ -571,7 +900,57 @@ impl Game {
     }
 
     pub fn get_tile(&self, pos: Position) -> Tile {
-        self.tiles[pos.1 as usize][pos.0 as usize]
+        if !self.floor.get(pos) {
+            Tile::Wall
+        } else if self.goals.get(pos) {
+            Tile::Goal
+        } else {
+            Tile::Floor
+        }
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// Get the XSB character describing the contents of a square. Walls with
+    /// no other square around them, such as those filled in outside a
+    /// normalized level, are left blank.
+    pub fn square_char(&self, pos: Position) -> char {
+        let tile = self.get_tile(pos);
+        if pos == self.player {
+            match tile {
+                Tile::Goal => '+',
+                _ => '@',
+            }
+        } else if self.boxes.has_box_at(pos) {
+            match tile {
+                Tile::Goal => '*',
+                _ => '$',
+            }
+        } else {
+            match tile {
+                Tile::Wall if self.is_buried_wall(pos) => ' ',
+                Tile::Wall => '#',
+                Tile::Floor => ' ',
+                Tile::Goal => '.',
+            }
+        }
+    }
+
+    /// Whether the square and all eight squares around it on the board are
+    /// walls.
+    fn is_buried_wall(&self, pos: Position) -> bool {
+        let (x, y) = (pos.0 as usize, pos.1 as usize);
+        let (width, height) = (self.width as usize, self.height as usize);
+        (y.saturating_sub(1)..(y + 2).min(height)).all(|y| {
+            (x.saturating_sub(1)..(x + 2).min(width))
+                .all(|x| !self.floor.get(Position(x as u8, y as u8)))
+        })
     }
 
     pub fn box_count(&self) -> usize {
@@ -582,6 +961,58 @@ impl Game {
         self.player = pos;
     }
 
+    /// Move the player one step in the given direction, without pushing a
+    /// box. Returns an error if the step would leave the board or run into a
+    /// wall or box.
+    pub fn move_player(&mut self, dir: Direction) -> Result<(), String> {
+        let player = self.player;
+        let next = self
+            .move_position(player, dir)
+            .ok_or_else(|| format!("player moves {} out of bounds from {}", dir, player))?;
+        if self.get_tile(next) == Tile::Wall {
+            return Err(format!("player moves {} into a wall at {}", dir, next));
+        }
+        if self.boxes.has_box_at(next) {
+            return Err(format!("move {} pushes the box at {}", dir, next));
+        }
+        self.player = next;
+        Ok(())
+    }
+
+    /// Move the player one step in the given direction, pushing the box in
+    /// front of it. Returns an error if there is no box to push or the box
+    /// would leave the board or run into a wall or another box.
+    pub fn push_player(&mut self, dir: Direction) -> Result<(), String> {
+        let player = self.player;
+        let next = self
+            .move_position(player, dir)
+            .ok_or_else(|| format!("player moves {} out of bounds from {}", dir, player))?;
+        if self.get_tile(next) == Tile::Wall {
+            return Err(format!("player moves {} into a wall at {}", dir, next));
+        }
+        let Some(box_idx) = self.box_index(next) else {
+            return Err(format!("push {} but no box at {}", dir, next));
+        };
+        let dest = self
+            .move_position(next, dir)
+            .ok_or_else(|| format!("box at {} pushed {} out of bounds", next, dir))?;
+        if self.get_tile(dest) == Tile::Wall || self.boxes.has_box_at(dest) {
+            return Err(format!("box at {} pushed {} is blocked", next, dir));
+        }
+        self.push(Push::new(box_idx, dir));
+        Ok(())
+    }
+
+    /// Replay a LURD string (lowercase letters are moves and uppercase
+    /// letters pushes, as written by other Sokoban programs) from this
+    /// position, returning the resulting position. Whitespace is ignored.
+    /// Errors give the 1-indexed step at which the replay failed.
+    pub fn apply_lurd(&self, lurd: &str) -> Result<Game, String> {
+        Solution::parse_lurd(lurd)?
+            .replay(self)
+            .map_err(|err| err.to_string())
+    }
+
     #[allow(dead_code)]
     pub fn player(&self) -> Position {
         self.player
@@ -591,10 +1022,133 @@ impl Game {
         &self.boxes.positions
     }
 
+    /// Zobrist hash of the box positions, kept up to date as boxes move. Equal
+    /// to `Zobrist::new().compute_boxes_hash(game)` unless the game is
+    /// labelled, as labels aren't included.
+    pub fn boxes_hash(&self) -> u64 {
+        self.boxes.hash
+    }
+
     pub fn goal_positions(&self) -> &[Position] {
         &self.goal_positions
     }
 
+    /// Whether the other game has the same walls and goals (in the same
+    /// order), so that box distances ignoring the boxes are the same.
+    pub fn same_layout(&self, other: &Game) -> bool {
+        self.floor == other.floor
+            && self.goals == other.goals
+            && self.goal_positions == other.goal_positions
+    }
+
+    /// A hash of the level which stays the same across runs and ignores how
+    /// it happens to be drawn: squares outside the walls are dropped, the
+    /// board is trimmed to the squares left, and the player only counts by
+    /// the region it can walk in. In a Sokoban+ level, the labels of the
+    /// boxes and goals count too. With `symmetric`, mirror images and
+    /// rotations of the level get the same fingerprint too.
+    pub fn fingerprint(&self, symmetric: bool) -> u64 {
+        self.compute_fingerprint(symmetric, false)
+    }
+
+    /// Like `fingerprint(false)`, but also telling apart levels drawn at
+    /// different offsets, to identify results saved in board coordinates
+    /// (such as Zobrist hashes and bitboards).
+    pub fn board_fingerprint(&self) -> u64 {
+        self.compute_fingerprint(false, true)
+    }
+
+    fn compute_fingerprint(&self, symmetric: bool, with_origin: bool) -> u64 {
+        let inside = self.wall_region(self.player);
+        let player_region = self.player_region(self.player);
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (u8::MAX, u8::MAX, 0, 0);
+        let mut cells = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pos = Position(x, y);
+                let is_goal = self.get_tile(pos) == Tile::Goal;
+                let cell = match (self.boxes.has_box_at(pos), is_goal) {
+                    (true, true) => 4,
+                    (true, false) => 3,
+                    (false, true) => 2,
+                    (false, false) if inside.get(pos) => 1,
+                    (false, false) => 0,
+                };
+                let cell = if player_region.get(pos) {
+                    cell + 4
+                } else {
+                    cell
+                };
+                if cell != 0 {
+                    (min_x, min_y) = (min_x.min(x), min_y.min(y));
+                    (max_x, max_y) = (max_x.max(x), max_y.max(y));
+                }
+                cells.push(cell);
+            }
+        }
+        // Labels of the box and goal on each square, where labelled
+        let labels: Vec<(u8, u8)> = if self.is_labelled() {
+            let mut labels = vec![(0, 0); cells.len()];
+            for (i, &pos) in self.boxes.positions.iter().enumerate() {
+                labels[pos.1 as usize * self.width as usize + pos.0 as usize].0 =
+                    self.box_label(Index(i as u8));
+            }
+            for (i, &pos) in self.goal_positions.iter().enumerate() {
+                labels[pos.1 as usize * self.width as usize + pos.0 as usize].1 =
+                    self.goal_label(i);
+            }
+            labels
+        } else {
+            Vec::new()
+        };
+        let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+        let index =
+            |x: u8, y: u8| (y + min_y) as usize * self.width as usize + (x + min_x) as usize;
+
+        let hash_view = |transpose: bool, flip_x: bool, flip_y: bool| {
+            // FNV-1a
+            let mut hash = 0xcbf29ce484222325u64;
+            let mut add = |byte: u8| {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            };
+            if with_origin {
+                add(min_x);
+                add(min_y);
+            }
+            let (view_width, view_height) = if transpose {
+                (height, width)
+            } else {
+                (width, height)
+            };
+            add(view_width);
+            add(view_height);
+            for y in 0..view_height {
+                for x in 0..view_width {
+                    let x = if flip_x { view_width - 1 - x } else { x };
+                    let y = if flip_y { view_height - 1 - y } else { y };
+                    let i = if transpose { index(y, x) } else { index(x, y) };
+                    add(cells[i]);
+                    if let Some(&(box_label, goal_label)) = labels.get(i) {
+                        add(box_label);
+                        add(goal_label);
+                    }
+                }
+            }
+            hash
+        };
+
+        if !symmetric {
+            return hash_view(false, false, false);
+        }
+        // The smallest hash of the eight mirror images and rotations, which
+        // all of them share
+        (0..8)
+            .map(|i| hash_view(i & 4 != 0, i & 2 != 0, i & 1 != 0))
+            .min()
+            .unwrap()
+    }
+
     pub fn unsolved_boxes(&self) -> Bitvector {
         self.boxes.unsolved
     }
@@ -610,8 +1164,9 @@ impl Game {
     /// Get the box index at the given position, if any.
     /// Returns Some(box_index) if there is a box at the position, None otherwise.
     pub fn box_index(&self, pos: Position) -> Option<Index> {
-        let idx = self.boxes.index[pos.1 as usize][pos.0 as usize];
-        if idx == NO_BOX { None } else { Some(idx) }
+        self.boxes
+            .has_box_at(pos)
+            .then(|| self.boxes.index[self.floor_rank(pos)])
     }
 
     /// Get the position of a box given its index.
@@ -622,21 +1177,28 @@ impl Game {
     /// Move from position in the given direction.
     /// Returns Some(new_position) if the new position is within bounds, None otherwise.
     pub fn move_position(&self, pos: Position, dir: Direction) -> Option<Position> {
-        let (dx, dy) = dir.delta();
-        let new_x = pos.0 as i32 + dx as i32;
-        let new_y = pos.1 as i32 + dy as i32;
+        pos.offset(dir)
+            .filter(|new_pos| new_pos.0 < self.width && new_pos.1 < self.height)
+    }
 
-        if new_x >= 0 && new_y >= 0 && new_x < self.width as i32 && new_y < self.height as i32 {
-            Some(Position(new_x as u8, new_y as u8))
-        } else {
-            None
-        }
+    /// Place the player behind the box of the given push, ready to make it.
+    pub fn set_player_for_push(&mut self, push: Push) {
+        let box_pos = self.box_position(push.box_index);
+        self.player = self
+            .move_position(box_pos, push.direction.reverse())
+            .expect("Push player position out of bounds");
     }
 
     /// Pushes a box.
-    /// Updates the player position to where the box was.
+    /// Updates the player position to where the box was, and forgets the
+    /// pushes to undo and redo.
     /// Panics if the push is invalid (invalid box index, destination blocked, etc.)
     pub fn push(&mut self, push: Push) {
+        self.history.clear();
+        self.push_box(push);
+    }
+
+    fn push_box(&mut self, push: Push) {
         let box_pos = self.box_position(push.box_index);
         let new_pos = self
             .move_position(box_pos, push.direction)
@@ -650,19 +1212,30 @@ impl Game {
             new_pos
         );
 
-        let source_tile = self.get_tile(box_pos);
-        let source_is_goal = source_tile == Tile::Goal;
-        let dest_is_goal = dest_tile == Tile::Goal;
+        let label = self.box_label(push.box_index);
+        let source_is_goal = self.is_goal_for(label, box_pos);
+        let dest_is_goal = self.is_goal_for(label, new_pos);
 
         // Update box position
-        self.boxes
-            .move_(box_pos, new_pos, source_is_goal, dest_is_goal);
+        let (box_square, new_square) = (self.floor_rank(box_pos), self.floor_rank(new_pos));
+        self.boxes.move_(
+            (box_pos, box_square),
+            (new_pos, new_square),
+            source_is_goal,
+            dest_is_goal,
+        );
 
         // Update player position to where the box was
         self.player = box_pos;
     }
 
+    /// Undoes a push, forgetting the pushes to undo and redo.
     pub fn pull(&mut self, pull: Pull) {
+        self.history.clear();
+        self.pull_box(pull);
+    }
+
+    fn pull_box(&mut self, pull: Pull) {
         // Current box position (after the push we're undoing)
         let new_pos = self.box_position(pull.box_index);
 
@@ -676,118 +1249,439 @@ impl Game {
             .move_position(old_pos, pull.direction)
             .expect("Pull player position out of bounds");
 
-        let current_tile = self.get_tile(new_pos);
-        let current_is_goal = current_tile == Tile::Goal;
-        let old_tile = self.get_tile(old_pos);
-        let old_is_goal = old_tile == Tile::Goal;
+        let label = self.box_label(pull.box_index);
+        let current_is_goal = self.is_goal_for(label, new_pos);
+        let old_is_goal = self.is_goal_for(label, old_pos);
 
         // Move box back
-        self.boxes
-            .move_(new_pos, old_pos, current_is_goal, old_is_goal);
+        let (new_square, old_square) = (self.floor_rank(new_pos), self.floor_rank(old_pos));
+        self.boxes.move_(
+            (new_pos, new_square),
+            (old_pos, old_square),
+            current_is_goal,
+            old_is_goal,
+        );
 
         // Restore player position
         self.player = player_old_pos;
     }
 
-    /// Check if all boxes are on goals (win condition)
+    /// Pushes a box like `push()`, recording the push so that it can be
+    /// undone. Any pushes undone since the last one made can no longer be
+    /// redone.
+    pub fn push_with_history(&mut self, push: Push) {
+        self.history.done.push((push, self.player));
+        self.history.undone.clear();
+        self.push_box(push);
+    }
+
+    /// Undo the last push made with `push_with_history()` (and not undone
+    /// yet), putting the player back where it was before the push. Returns
+    /// the push undone, or `None` if there was none.
+    pub fn undo(&mut self) -> Option<Push> {
+        let (push, player) = self.history.done.pop()?;
+        self.pull_box(push.to_pull());
+        self.player = player;
+        self.history.undone.push((push, player));
+        Some(push)
+    }
+
+    /// Make the last push undone by `undo()` again. Returns the push redone,
+    /// or `None` if there was none.
+    pub fn redo(&mut self) -> Option<Push> {
+        let (push, player) = self.history.undone.pop()?;
+        self.push_box(push);
+        self.history.done.push((push, player));
+        Some(push)
+    }
+
+    /// Forget the pushes to undo and redo. Changing the boxes in any other
+    /// way (`push()`, `pull()`, `restore()`, ...) forgets them too, since
+    /// they would no longer apply; walking the player doesn't.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Check if all boxes are on goals (win condition), with the same label
+    /// in a Sokoban+ level. Surplus goals may be left empty, while with
+    /// surplus boxes the game is never solved.
     pub fn is_solved(&self) -> bool {
         self.boxes.unsolved.is_empty()
     }
 
+    /// Whether the game is solved exactly when every goal has a box on it,
+    /// whichever box: balanced and unlabelled. Searching backwards starts from
+    /// that single arrangement of the boxes, so needs one.
+    pub fn has_unique_solved_boxes(&self) -> bool {
+        self.is_balanced() && !self.is_labelled()
+    }
+
+    /// Label the boxes and goals of a Sokoban+ level, in which a box is only
+    /// solved on a goal with the same label. Labels are given by box index and
+    /// in the order of `goal_positions()`, both reading order for a parsed
+    /// level. Missing labels are 0, so plain boxes go on plain goals, and a
+    /// level labelled all 0 is unlabelled. Returns an error if there are more
+    /// labels than boxes or goals, or more boxes than goals with some label.
+    pub fn set_labels(&mut self, box_labels: &[u8], goal_labels: &[u8]) -> Result<(), String> {
+        if box_labels.len() > self.box_count() {
+            return Err(format!(
+                "{} box labels for {} boxes",
+                box_labels.len(),
+                self.box_count()
+            ));
+        }
+        if goal_labels.len() > self.goal_positions.len() {
+            return Err(format!(
+                "{} goal labels for {} goals",
+                goal_labels.len(),
+                self.goal_positions.len()
+            ));
+        }
+        let labelled = box_labels
+            .iter()
+            .chain(goal_labels)
+            .any(|&label| label != 0);
+        if labelled {
+            let mut surplus = [0isize; 256];
+            for &label in box_labels {
+                surplus[label as usize] += 1;
+            }
+            for &label in goal_labels {
+                surplus[label as usize] -= 1;
+            }
+            surplus[0] += (self.box_count() - box_labels.len()) as isize;
+            surplus[0] -= (self.goal_positions.len() - goal_labels.len()) as isize;
+            if let Some(label) = (0..=255).find(|&label| surplus[label as usize] > 0) {
+                return Err(format!("More boxes than goals labelled {}", label));
+            }
+        }
+
+        self.box_labels.clear();
+        self.goal_labels.clear();
+        if labelled {
+            self.box_labels.extend(box_labels.iter().copied());
+            self.box_labels
+                .extend((box_labels.len()..self.box_count()).map(|_| 0));
+            self.goal_labels.extend(goal_labels.iter().copied());
+            self.goal_labels
+                .extend((goal_labels.len()..self.goal_positions.len()).map(|_| 0));
+        }
+        let positions = self.boxes.positions.clone();
+        self.set_box_positions(&positions);
+        Ok(())
+    }
+
+    /// Forget the labels of a Sokoban+ level, making every box fit every goal.
+    pub fn clear_labels(&mut self) {
+        self.set_labels(&[], &[])
+            .expect("no labels are always valid");
+    }
+
+    /// Whether boxes only fit goals with the same label (Sokoban+).
+    pub fn is_labelled(&self) -> bool {
+        !self.goal_labels.is_empty()
+    }
+
+    /// Label of the given box, 0 if unlabelled.
+    pub fn box_label(&self, box_idx: Index) -> u8 {
+        self.box_labels
+            .get(box_idx.0 as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Label of the goal with the given index in `goal_positions()`, 0 if
+    /// unlabelled.
+    pub fn goal_label(&self, goal_idx: usize) -> u8 {
+        self.goal_labels.get(goal_idx).copied().unwrap_or(0)
+    }
+
+    /// Whether the given box may be solved on the goal with the given index in
+    /// `goal_positions()`: always, unless the level is labelled.
+    pub fn box_fits_goal(&self, box_idx: Index, goal_idx: usize) -> bool {
+        self.box_label(box_idx) == self.goal_label(goal_idx)
+    }
+
+    /// Whether a box with the given label is solved on the given square.
+    fn is_goal_for(&self, label: u8, pos: Position) -> bool {
+        self.get_tile(pos) == Tile::Goal
+            && (self.goal_labels.is_empty() || {
+                let goal_idx = self
+                    .goal_positions
+                    .iter()
+                    .position(|&goal| goal == pos)
+                    .unwrap();
+                self.goal_labels[goal_idx] == label
+            })
+    }
+
+    /// Replace the boxes with boxes at the given positions, numbered in the
+    /// given order, keeping the labels of the box indices.
+    fn set_box_positions(&mut self, positions: &[Position]) {
+        self.history.clear();
+        self.boxes.clear();
+        for (i, &pos) in positions.iter().enumerate() {
+            let is_goal = self.is_goal_for(self.box_label(Index(i as u8)), pos);
+            let square = self.floor_rank(pos);
+            self.boxes.add(pos, square, is_goal);
+        }
+    }
+
+    /// Whether there are as many goals as boxes, as required unless parsed
+    /// with `from_text_unbalanced`. Searching backwards from the goals, and
+    /// the analyses assuming every goal gets filled, need a balanced game.
+    pub fn is_balanced(&self) -> bool {
+        self.goal_positions.len() == self.boxes.positions.len()
+    }
+
     /// Create a new game state with boxes and goals swapped.
     /// Boxes are placed at goal positions, and goals become where boxes originally were.
-    /// This is useful for backward search.
+    /// This is useful for backward search. For an unbalanced or labelled game,
+    /// the result has a box on every goal, which is only one of the positions
+    /// the game can be solved in, so it can't be used to search backwards.
+    /// Labels move along with the boxes and goals.
     pub fn swap_boxes_and_goals(&self) -> Self {
         // Build new boxes with positions at goal locations
-        let mut boxes = Boxes::new();
+        let mut boxes = Boxes::new(self.square_count());
         let new_goal_positions = self.boxes.positions.clone();
 
         for &goal_pos in &self.goal_positions {
             // Box is on goal if it's on one of the new goals (original box positions)
             let is_goal = new_goal_positions.contains(&goal_pos);
-            boxes.add(goal_pos, is_goal);
+            boxes.add(goal_pos, self.floor_rank(goal_pos), is_goal);
         }
 
-        // Update tiles: old goals become floor, old box positions become goals
-        let mut tiles = self.tiles;
-        for &old_goal in &self.goal_positions {
-            tiles[old_goal.1 as usize][old_goal.0 as usize] = Tile::Floor;
-        }
+        // The old box positions become the goals
+        let mut goals = RawBitboard::new();
         for &new_goal in &new_goal_positions {
-            tiles[new_goal.1 as usize][new_goal.0 as usize] = Tile::Goal;
+            goals.set(new_goal);
         }
 
         let mut game = Game {
-            tiles,
+            goals,
             boxes,
             goal_positions: new_goal_positions,
             push_dead_squares: RawBitboard::new(),
             pull_dead_squares: RawBitboard::new(),
+            history: History::default(),
+            box_labels: self.goal_labels.clone(),
+            goal_labels: self.box_labels.clone(),
             ..self.clone()
         };
+        if game.is_labelled() {
+            let positions = game.boxes.positions.clone();
+            game.set_box_positions(&positions);
+        }
         game.compute_dead_squares();
         game
     }
 
     /// Compute the canonical (lexicographically smallest reachable) player position.
     pub fn canonical_player_pos(&self) -> Position {
-        let mut visited = LazyBitboard::new();
-        self.player_dfs(self.player, &mut visited, |_pos, _dir, _box_idx| {});
-        visited.top_left().unwrap()
+        self.player_region(self.player).top_left().unwrap()
     }
 
     pub fn compute_pushes(&self) -> ReachableSet<Push> {
+        self.pushes_from_squares(self.player_region(self.player))
+    }
+
+    fn is_blocked(&self, pos: Position) -> bool {
+        self.get_tile(pos) == Tile::Wall || self.boxes.has_box_at(pos)
+    }
+
+    pub fn compute_pulls(&self) -> ReachableSet<Pull> {
+        self.pulls_from_squares(self.player_region(self.player))
+    }
+
+    /// Compute the pushes after the box at `from` was moved to `to`, given the
+    /// squares the player could reach before the move. Only the squares around
+    /// the box change, so the reachable squares are updated rather than found
+    /// again, unless the box's new square might cut them in two.
+    pub fn compute_pushes_after_move(
+        &self,
+        squares: LazyBitboard,
+        from: Position,
+        to: Position,
+    ) -> ReachableSet<Push> {
+        match self.update_reachable_squares(squares, from, to) {
+            Some(squares) => self.pushes_from_squares(squares),
+            None => self.compute_pushes(),
+        }
+    }
+
+    /// Compute the pulls after the box at `from` was moved to `to`, given the
+    /// squares the player could reach before the move (see
+    /// `compute_pushes_after_move`).
+    pub fn compute_pulls_after_move(
+        &self,
+        squares: LazyBitboard,
+        from: Position,
+        to: Position,
+    ) -> ReachableSet<Pull> {
+        match self.update_reachable_squares(squares, from, to) {
+            Some(squares) => self.pulls_from_squares(squares),
+            None => self.compute_pulls(),
+        }
+    }
+
+    /// The pushes the player can make from the given squares.
+    fn pushes_from_squares(&self, squares: LazyBitboard) -> ReachableSet<Push> {
         let mut moves = Moves::new();
-        let mut visited = LazyBitboard::new();
         let mut boxes = Bitvector::new();
-        self.player_dfs(self.player, &mut visited, |_player_pos, dir, box_idx| {
-            boxes.add(box_idx);
-            let box_pos = self.box_position(box_idx);
-            if let Some(dest_pos) = self.move_position(box_pos, dir) {
-                if !self.is_blocked(dest_pos) {
-                    moves.add(box_idx, dir);
+        for (i, &box_pos) in self.boxes.positions.iter().enumerate() {
+            let box_idx = Index(i as u8);
+            for dir in ALL_DIRECTIONS {
+                if let Some(player_pos) = self.move_position(box_pos, dir.reverse())
+                    && squares.get(player_pos)
+                {
+                    boxes.add(box_idx);
+                    if let Some(dest_pos) = self.move_position(box_pos, dir)
+                        && !self.is_blocked(dest_pos)
+                    {
+                        moves.add(box_idx, dir);
+                    }
                 }
             }
-        });
+        }
         ReachableSet {
             moves,
-            squares: visited,
+            squares,
             boxes,
         }
     }
 
-    fn is_blocked(&self, pos: Position) -> bool {
-        self.get_tile(pos) == Tile::Wall || self.boxes.has_box_at(pos)
-    }
-
-    pub fn compute_pulls(&self) -> ReachableSet<Pull> {
+    /// The pulls the player can make from the given squares.
+    fn pulls_from_squares(&self, squares: LazyBitboard) -> ReachableSet<Pull> {
         let mut moves = Moves::new();
-        let mut visited = LazyBitboard::new();
         let mut boxes = Bitvector::new();
-        self.player_dfs(self.player, &mut visited, |player_pos, dir, box_idx| {
-            boxes.add(box_idx);
-            if let Some(dest_pos) = self.move_position(player_pos, dir.reverse()) {
-                if !self.is_blocked(dest_pos) {
-                    moves.add(box_idx, dir.reverse());
+        for (i, &box_pos) in self.boxes.positions.iter().enumerate() {
+            let box_idx = Index(i as u8);
+            for dir in ALL_DIRECTIONS {
+                if let Some(player_pos) = self.move_position(box_pos, dir.reverse())
+                    && squares.get(player_pos)
+                {
+                    boxes.add(box_idx);
+                    if let Some(dest_pos) = self.move_position(player_pos, dir.reverse())
+                        && !self.is_blocked(dest_pos)
+                    {
+                        moves.add(box_idx, dir.reverse());
+                    }
                 }
             }
-        });
+        }
         ReachableSet {
             moves,
-            squares: visited,
+            squares,
             boxes,
         }
     }
 
-    /// Compute all possible canonical player positions (assuming the player's real position is unknown).
-    /// Returns positions for which at least one box is reachable from that connected region.
-    pub fn all_possible_player_positions(&self) -> Vec<Position> {
-        let mut all_visited = LazyBitboard::new();
+    /// Update the squares the player could reach before the box at `from` was
+    /// moved to `to`. Returns None if the box's new square may have split
+    /// them, in which case they must be found again.
+    fn update_reachable_squares(
+        &self,
+        mut squares: LazyBitboard,
+        from: Position,
+        to: Position,
+    ) -> Option<LazyBitboard> {
+        if squares.get(to) {
+            squares.unset(to);
+            if !self.keeps_neighbors_connected(to) && !self.has_short_detour(&squares, to) {
+                return None;
+            }
+        }
+        if !squares.get(from)
+            && ALL_DIRECTIONS.into_iter().any(|dir| {
+                self.move_position(from, dir)
+                    .is_some_and(|pos| squares.get(pos))
+            })
+        {
+            self.dfs(from, &mut squares, |_, to_pos, _| {
+                !self.boxes.has_box_at(to_pos)
+            });
+        }
+        squares.get(self.player).then_some(squares)
+    }
+
+    /// Whether the neighbors of the given square within the squares are
+    /// joined by short paths through them. Gives up after searching
+    /// `MAX_DETOUR_SQUARES` squares.
+    fn has_short_detour(&self, squares: &LazyBitboard, pos: Position) -> bool {
+        let mut targets: ArrayVec<Position, 4> = pos
+            .neighbors(self.width, self.height)
+            .filter(|&neighbor| squares.get(neighbor))
+            .collect();
+        let Some(start) = targets.pop() else {
+            return true;
+        };
+
+        // Breadth-first, as detours around a box are usually short
+        let mut visited = LazyBitboard::new();
+        let mut queue: ArrayVec<Position, MAX_DETOUR_SQUARES> = ArrayVec::new();
+        visited.set(start);
+        queue.push(start);
+        let mut i = 0;
+        while let Some(&from_pos) = queue.get(i) {
+            i += 1;
+            for to_pos in from_pos.neighbors(self.width, self.height) {
+                if !squares.get(to_pos) || visited.get(to_pos) {
+                    continue;
+                }
+                targets.retain(|target| *target != to_pos);
+                if targets.is_empty() {
+                    return true;
+                }
+                if queue.is_full() {
+                    return false;
+                }
+                visited.set(to_pos);
+                queue.push(to_pos);
+            }
+        }
+        false
+    }
+
+    /// Whether the free squares around the given square stay connected
+    /// without it. This holds when its free neighbors all lie in one unbroken
+    /// run of free squares going around it, diagonals included.
+    fn keeps_neighbors_connected(&self, pos: Position) -> bool {
+        const RING: [(i8, i8); 8] = [
+            (0, -1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+            (-1, -1),
+        ];
+        let is_free = |&(dx, dy): &(i8, i8)| {
+            let (Some(x), Some(y)) = (pos.0.checked_add_signed(dx), pos.1.checked_add_signed(dy))
+            else {
+                return false;
+            };
+            x < self.width && y < self.height && !self.is_blocked(Position(x, y))
+        };
+        let free: [bool; 8] = std::array::from_fn(|i| is_free(&RING[i]));
+
+        // Count the runs of free squares holding a free neighbor, by the
+        // neighbor starting each run going clockwise
+        let runs = (0..8)
+            .step_by(2)
+            .filter(|&i| free[i] && (!free[(i + 7) % 8] || !free[(i + 6) % 8]))
+            .count();
+        runs <= 1
+    }
+
+    /// Compute all possible canonical player positions (assuming the player's real position is unknown).
+    /// Returns positions for which at least one box is reachable from that connected region.
+    pub fn all_possible_player_positions(&self) -> Vec<Position> {
+        let mut all_visited = LazyBitboard::new();
         let mut result: Vec<Position> = Vec::new();
 
         for y in 0..self.height {
             for x in 0..self.width {
-                let mut local_visited = LazyBitboard::new();
                 let pos = Position(x, y);
 
                 // Skip if already explored or blocked
@@ -795,67 +1689,543 @@ impl Game {
                     continue;
                 }
 
-                let mut found_box = false;
-                self.player_dfs(pos, &mut local_visited, |_pos, _dir, _box_idx| {
-                    found_box = true
-                });
-                if found_box {
-                    result.push(local_visited.top_left().unwrap());
+                let region = self.player_region(pos);
+                all_visited.set_all(&region);
+                let reachable = self.pushes_from_squares(region);
+                if !reachable.boxes.is_empty() {
+                    result.push(reachable.squares.top_left().unwrap());
                 }
-                all_visited.set_all(&local_visited);
             }
         }
 
         result
     }
 
-    /// Generic DFS helper to find all reachable player positions.
-    /// Calls the `on_box` closure for each box adjacent to a reachable position.
-    /// The closure receives (player_pos, direction, box_idx) and can handle box move logic.
-    fn player_dfs<F>(&self, start_player: Position, visited: &mut LazyBitboard, mut on_box: F)
-    where
-        F: FnMut(Position, Direction, Index),
-    {
-        self.dfs(start_player, visited, |from_pos, to_pos, direction| {
-            // If there's a box, notify the closure but don't visit
-            if let Some(box_idx) = self.box_index(to_pos) {
-                on_box(from_pos, direction, box_idx);
-                false
-            } else {
-                true
+    /// Compute all squares connected to the given position when only walls are
+    /// treated as obstacles. Since walls never move, the player can never
+    /// leave this region regardless of how the boxes are arranged.
+    pub fn wall_region(&self, pos: Position) -> LazyBitboard {
+        let mut free = [0; MAX_SIZE];
+        for (y, row) in free[..self.height as usize].iter_mut().enumerate() {
+            *row = self.floor.row(y);
+        }
+        self.flood_fill(pos, &free)
+    }
+
+    /// Compute the shortest walking distance from the player to every square
+    /// reachable without pushing a box.
+    pub fn player_distances(&self) -> PlayerDistances {
+        let mut distances = PlayerDistances {
+            width: self.width,
+            dist: vec![u16::MAX; self.width as usize * self.height as usize],
+        };
+        let mut queue = VecDeque::new();
+        distances.set(self.player, 0);
+        queue.push_back(self.player);
+
+        while let Some(pos) = queue.pop_front() {
+            let dist = distances.dist[distances.index(pos)];
+            for &dir in &ALL_DIRECTIONS {
+                if let Some(next) = self.move_position(pos, dir)
+                    && !self.is_blocked(next)
+                    && distances.get(next).is_none()
+                {
+                    distances.set(next, dist + 1);
+                    queue.push_back(next);
+                }
             }
-        });
+        }
+
+        distances
+    }
+
+    /// Compute the walking distance from the player to the nearest square
+    /// from which it can push a box, or `None` if no push is possible. Only
+    /// pushes of the box with the given index onto the given square for which
+    /// `can_push` returns true are considered.
+    pub fn player_distance_to_push(
+        &self,
+        can_push: impl Fn(Index, Position) -> bool,
+    ) -> Option<usize> {
+        self.player_distance_to(|pos, dir| {
+            let box_pos = self.move_position(pos, dir)?;
+            let box_idx = self.box_index(box_pos)?;
+            let dest = self.move_position(box_pos, dir)?;
+            (!self.is_blocked(dest) && can_push(box_idx, dest)).then_some(())
+        })
+    }
+
+    /// Compute the walking distance from the player to the nearest square
+    /// from which it can pull a box, or `None` if no pull is possible. Only
+    /// pulls of the box with the given index onto the given square for which
+    /// `can_pull` returns true are considered.
+    pub fn player_distance_to_pull(
+        &self,
+        can_pull: impl Fn(Index, Position) -> bool,
+    ) -> Option<usize> {
+        self.player_distance_to(|pos, dir| {
+            let box_idx = self.box_index(self.move_position(pos, dir)?)?;
+            let next = self.move_position(pos, dir.reverse())?;
+            (!self.is_blocked(next) && can_pull(box_idx, pos)).then_some(())
+        })
+    }
+
+    /// Breadth-first search from the player for the nearest square from
+    /// which `is_move(pos, dir)` holds for some direction.
+    fn player_distance_to(
+        &self,
+        is_move: impl Fn(Position, Direction) -> Option<()>,
+    ) -> Option<usize> {
+        let mut visited = LazyBitboard::new();
+        let mut queue = VecDeque::new();
+        visited.set(self.player);
+        queue.push_back((self.player, 0));
+
+        while let Some((pos, dist)) = queue.pop_front() {
+            for &dir in &ALL_DIRECTIONS {
+                if is_move(pos, dir).is_some() {
+                    return Some(dist);
+                }
+            }
+            for &dir in &ALL_DIRECTIONS {
+                if let Some(next) = self.move_position(pos, dir)
+                    && !self.is_blocked(next)
+                    && !visited.get(next)
+                {
+                    visited.set(next);
+                    queue.push_back((next, dist + 1));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Compute a shortest walk (without pushing boxes) from the player to the
+    /// given square, or `None` if it can't be reached.
+    pub fn player_path_to(&self, to: Position) -> Option<Vec<Direction>> {
+        let distances = self.player_distances();
+        let mut dist = distances.get(to)?;
+        let mut path = Vec::with_capacity(dist);
+        let mut pos = to;
+
+        // Walk backwards from the destination, one square closer each step
+        while dist > 0 {
+            let (prev, dir) = ALL_DIRECTIONS
+                .iter()
+                .filter_map(|&dir| Some((self.move_position(pos, dir.reverse())?, dir)))
+                .find(|&(prev, _)| distances.get(prev) == Some(dist - 1))
+                .unwrap();
+            path.push(dir);
+            pos = prev;
+            dist -= 1;
+        }
+
+        path.reverse();
+        Some(path)
+    }
+
+    /// Compute the squares the player can reach from the given square
+    /// without moving a box.
+    fn player_region(&self, start: Position) -> LazyBitboard {
+        let mut free = [0; MAX_SIZE];
+        for (y, row) in free[..self.height as usize].iter_mut().enumerate() {
+            *row = self.floor.row(y);
+        }
+        for &pos in &self.boxes.positions {
+            free[pos.1 as usize] &= !(1u64 << pos.0);
+        }
+        self.flood_fill(start, &free)
+    }
+
+    /// Compute the squares connected to the given square through the free
+    /// squares, given one row per word. Rather than visiting one square at a
+    /// time, each row is grown along its runs of free squares at once, and
+    /// rows spread into the rows above and below until nothing changes.
+    fn flood_fill(&self, start: Position, free: &[u64; MAX_SIZE]) -> LazyBitboard {
+        let height = self.height as usize;
+        let mut region = [0; MAX_SIZE];
+        let y = start.1 as usize;
+        region[y] = fill_row(1u64 << start.0, free[y]);
+
+        // Sweep down and then up, so that regions winding back and forth
+        // still take few sweeps
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for y in (0..height).chain((0..height).rev()) {
+                let above = if y > 0 { region[y - 1] } else { 0 };
+                let below = if y + 1 < height { region[y + 1] } else { 0 };
+                let seeds = (above | below) & free[y] & !region[y];
+                if seeds != 0 {
+                    region[y] = fill_row(region[y] | seeds, free[y]);
+                    changed = true;
+                }
+            }
+        }
+        LazyBitboard::from_rows(&region[..height])
     }
 
     pub fn checkpoint(&self) -> Checkpoint {
         Checkpoint {
             player: self.player,
             boxes: self.boxes.positions.clone(),
+            labels: self.box_labels.clone(),
         }
     }
 
     pub fn restore(&mut self, checkpoint: &Checkpoint) {
         self.player = checkpoint.player;
+        self.box_labels.clone_from(&checkpoint.labels);
+        self.set_box_positions(&checkpoint.boxes);
+    }
+
+    /// Pack the current state into a `PackedCheckpoint`.
+    pub fn pack(&self) -> PackedCheckpoint {
+        let len = usize::from(self.floor_offsets[MAX_SIZE]).div_ceil(64);
+        let mut boxes = vec![0u64; len + self.box_labels.len().div_ceil(8)].into_boxed_slice();
+        for &pos in &self.boxes.positions {
+            let rank = self.floor_rank(pos);
+            boxes[rank / 64] |= 1u64 << (rank % 64);
+        }
+        if self.is_labelled() {
+            // Labels in reading order, the order the boxes are restored in
+            let mut order: ArrayVec<usize, MAX_BOXES> = (0..self.box_count()).collect();
+            order.sort_by_key(|&i| {
+                let pos = self.boxes.positions[i];
+                (pos.1, pos.0)
+            });
+            for (rank, &i) in order.iter().enumerate() {
+                boxes[len + rank / 8] |= u64::from(self.box_labels[i]) << (rank % 8 * 8);
+            }
+        }
+        PackedCheckpoint {
+            player: self.player,
+            boxes,
+        }
+    }
+
+    /// Restore a packed checkpoint, numbering the boxes in reading order.
+    pub fn restore_packed(&mut self, checkpoint: &PackedCheckpoint) {
+        self.player = checkpoint.player;
+        self.history.clear();
         self.boxes.clear();
-        for &pos in &checkpoint.boxes {
-            self.boxes.add(pos, self.get_tile(pos) == Tile::Goal);
+        let len = usize::from(self.floor_offsets[MAX_SIZE]).div_ceil(64);
+        for (rank, label) in self.box_labels.iter_mut().enumerate() {
+            *label = (checkpoint.boxes[len + rank / 8] >> (rank % 8 * 8)) as u8;
+        }
+        let mut y = 0;
+        for (i, &word) in checkpoint.boxes[..len].iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                let rank = i * 64 + word.trailing_zeros() as usize;
+                word &= word - 1;
+
+                // Ranks only increase, so the rows are found in order
+                while usize::from(self.floor_offsets[y + 1]) <= rank {
+                    y += 1;
+                }
+                let mut row = self.floor.row(y);
+                for _ in usize::from(self.floor_offsets[y])..rank {
+                    row &= row - 1;
+                }
+                let pos = Position(row.trailing_zeros() as u8, y as u8);
+                let label = self.box_label(Index(self.boxes.positions.len() as u8));
+                self.boxes.add(pos, rank, self.is_goal_for(label, pos));
+            }
         }
     }
 
+    /// Renumber the given boxes as `restore_packed` would number them.
+    pub fn packed_box_indices(&self, boxes: &Bitvector) -> Bitvector {
+        let mut result = Bitvector::new();
+        for box_idx in boxes {
+            let pos = self.box_position(box_idx);
+            let rank = self
+                .boxes
+                .positions
+                .iter()
+                .filter(|other| (other.1, other.0) < (pos.1, pos.0))
+                .count();
+            result.add(Index(rank as u8));
+        }
+        result
+    }
+
+    /// Heap bytes used by each packed checkpoint.
+    pub fn packed_size(&self) -> usize {
+        (usize::from(self.floor_offsets[MAX_SIZE]).div_ceil(64) + self.box_labels.len().div_ceil(8))
+            * size_of::<u64>()
+    }
+
+    /// Number of floor squares, the length of tables indexed by
+    /// `square_index`.
+    pub fn square_count(&self) -> usize {
+        usize::from(self.floor_offsets[MAX_SIZE])
+    }
+
+    /// Index of the given square among the floor squares in reading order,
+    /// or `None` for a wall. Per-square data is stored by this index, in
+    /// tables sized for the level rather than for the largest board.
+    pub fn square_index(&self, pos: Position) -> Option<usize> {
+        self.floor.get(pos).then(|| self.floor_rank(pos))
+    }
+
+    /// Index of the given floor square among the floor squares in reading
+    /// order.
+    fn floor_rank(&self, pos: Position) -> usize {
+        let before = self.floor.row(pos.1 as usize) & ((1u64 << pos.0) - 1);
+        usize::from(self.floor_offsets[pos.1 as usize]) + before.count_ones() as usize
+    }
+
     /// Project the game down to a subset of boxes in-place.
     /// Updates the game to only contain the boxes specified in the input bitvector.
-    /// Box indexes may be renumbered after projection.
+    /// Box indexes may be renumbered after projection, keeping their labels.
     pub fn project(&mut self, boxes_to_keep: Bitvector) {
-        let mut new_boxes = Boxes::new();
+        let positions: ArrayVec<Position, MAX_BOXES> = boxes_to_keep
+            .iter()
+            .map(|box_idx| self.boxes.positions[box_idx.0 as usize])
+            .collect();
+        if self.is_labelled() {
+            self.box_labels = boxes_to_keep
+                .iter()
+                .map(|box_idx| self.box_labels[box_idx.0 as usize])
+                .collect();
+        }
+        self.set_box_positions(&positions);
+    }
+
+    /// Replace the boxes with boxes at the given positions, one per goal. Each
+    /// box keeps the label of its index.
+    pub fn set_boxes(&mut self, positions: &[Position]) -> Result<(), String> {
+        if positions.len() != self.goal_positions.len() {
+            return Err(format!(
+                "expected {} boxes, got {}",
+                self.goal_positions.len(),
+                positions.len()
+            ));
+        }
+        let mut boxes = Boxes::new(self.square_count());
+        for &pos in positions {
+            if pos.0 >= self.width || pos.1 >= self.height || self.get_tile(pos) == Tile::Wall {
+                return Err(format!("box at {} is not on the floor", pos));
+            }
+            if boxes.has_box_at(pos) {
+                return Err(format!("two boxes at {}", pos));
+            }
+            let label = self.box_label(Index(boxes.positions.len() as u8));
+            boxes.add(pos, self.floor_rank(pos), self.is_goal_for(label, pos));
+        }
+        self.boxes = boxes;
+        self.history.clear();
+        Ok(())
+    }
+}
+
+/// Shortest walking distances from the player's position, as computed by
+/// `Game::player_distances`.
+pub struct PlayerDistances {
+    width: u8,
+    dist: Vec<u16>,
+}
+
+impl PlayerDistances {
+    /// Distance to the given square, or `None` if the player can't reach it.
+    pub fn get(&self, pos: Position) -> Option<usize> {
+        match self.dist[self.index(pos)] {
+            u16::MAX => None,
+            dist => Some(dist as usize),
+        }
+    }
+
+    fn set(&mut self, pos: Position, dist: u16) {
+        let index = self.index(pos);
+        self.dist[index] = dist;
+    }
+
+    fn index(&self, pos: Position) -> usize {
+        pos.1 as usize * self.width as usize + pos.0 as usize
+    }
+}
+
+/// Check that another box or goal can be added without exceeding MAX_BOXES.
+/// Builds a game square by square, for level editors and generators which
+/// would otherwise have to write XSB text. Squares are floor until set
+/// otherwise, as are the squares past the end of a line of XSB text.
+#[derive(Debug, Clone)]
+pub struct GameBuilder {
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    boxes: Vec<Position>,
+    player: Option<Position>,
+    /// The first position given outside the board, reported by `build()`.
+    out_of_bounds: Option<Position>,
+}
+
+impl GameBuilder {
+    /// Start a board of the given size.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![Tile::Floor; width * height],
+            boxes: Vec::new(),
+            player: None,
+            out_of_bounds: None,
+        }
+    }
+
+    /// Make the square a wall.
+    pub fn wall(self, pos: Position) -> Self {
+        self.set_tile(pos, Tile::Wall)
+    }
+
+    /// Make the square an empty floor square (neither wall nor goal).
+    pub fn floor(self, pos: Position) -> Self {
+        self.set_tile(pos, Tile::Floor)
+    }
+
+    /// Make the square a goal.
+    pub fn goal(self, pos: Position) -> Self {
+        self.set_tile(pos, Tile::Goal)
+    }
+
+    /// Put a box on the square.
+    pub fn add_box(mut self, pos: Position) -> Self {
+        if self.check_bounds(pos) {
+            self.boxes.push(pos);
+        }
+        self
+    }
+
+    /// Put the player on the square, replacing any earlier position.
+    pub fn player(mut self, pos: Position) -> Self {
+        if self.check_bounds(pos) {
+            self.player = Some(pos);
+        }
+        self
+    }
+
+    fn set_tile(mut self, pos: Position, tile: Tile) -> Self {
+        if self.check_bounds(pos) {
+            self.tiles[pos.1 as usize * self.width + pos.0 as usize] = tile;
+        }
+        self
+    }
+
+    /// Whether the position is on the board, remembering it otherwise.
+    fn check_bounds(&mut self, pos: Position) -> bool {
+        let in_bounds = (pos.0 as usize) < self.width && (pos.1 as usize) < self.height;
+        if !in_bounds && self.out_of_bounds.is_none() {
+            self.out_of_bounds = Some(pos);
+        }
+        in_bounds
+    }
+
+    fn tile(&self, pos: Position) -> Tile {
+        self.tiles[pos.1 as usize * self.width + pos.0 as usize]
+    }
+
+    /// Build the game, checking it as `Game::from_text` does: the board must
+    /// fit, boxes and the player must be on distinct floor or goal squares,
+    /// and there must be as many goals as boxes.
+    pub fn build(&self) -> Result<Game, String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("Empty board".to_string());
+        }
+        if self.width > MAX_SIZE {
+            return Err(format!(
+                "Board width {} exceeds maximum size {}",
+                self.width, MAX_SIZE
+            ));
+        }
+        if self.height > MAX_SIZE {
+            return Err(format!(
+                "Board height {} exceeds maximum size {}",
+                self.height, MAX_SIZE
+            ));
+        }
+        if let Some(Position(x, y)) = self.out_of_bounds {
+            return Err(format!(
+                "Position ({}, {}) is outside the {}x{} board",
+                x, y, self.width, self.height
+            ));
+        }
+
+        let mut tiles = [[Tile::Floor; MAX_SIZE]; MAX_SIZE];
+        let mut goal_positions = ArrayVec::new();
+        for (y, row) in tiles[..self.height].iter_mut().enumerate() {
+            for (x, square) in row[..self.width].iter_mut().enumerate() {
+                let pos = Position(x as u8, y as u8);
+                let tile = self.tile(pos);
+                *square = tile;
+                if tile == Tile::Goal {
+                    check_capacity(&goal_positions, "Goal")?;
+                    goal_positions.push(pos);
+                }
+            }
+        }
 
-        // Iterate through boxes to keep and add them to the new game
-        for box_idx in boxes_to_keep {
-            let pos = self.boxes.positions[box_idx.0 as usize];
-            let is_goal = self.get_tile(pos) == Tile::Goal;
-            new_boxes.add(pos, is_goal);
+        // Number the boxes in reading order, as parsing does
+        let mut box_positions = self.boxes.clone();
+        box_positions.sort_by_key(|pos| (pos.1, pos.0));
+        let mut boxes: ArrayVec<Position, MAX_BOXES> = ArrayVec::new();
+        for (i, &pos) in box_positions.iter().enumerate() {
+            if self.tile(pos) == Tile::Wall {
+                return Err(format!("Box on a wall at ({}, {})", pos.0, pos.1));
+            }
+            if i > 0 && box_positions[i - 1] == pos {
+                return Err(format!("Multiple boxes at ({}, {})", pos.0, pos.1));
+            }
+            check_capacity(&boxes, "Box")?;
+            boxes.push(pos);
+        }
+
+        let Some(player) = self.player else {
+            return Err("No player found on board".to_owned());
+        };
+        if self.tile(player) == Tile::Wall {
+            return Err(format!("Player on a wall at ({}, {})", player.0, player.1));
+        }
+        if box_positions.contains(&player) {
+            return Err(format!("Player on a box at ({}, {})", player.0, player.1));
         }
 
-        self.boxes = new_boxes;
+        Game::from_tiles(
+            tiles,
+            self.width,
+            self.height,
+            Some(player),
+            &boxes,
+            goal_positions,
+            false,
+        )
+    }
+}
+
+/// Write the `-` and `_` floor of a row as spaces, and expand its tabs to
+/// spaces up to the next multiple of 8 columns.
+fn expand_floor(line: &str) -> String {
+    let mut expanded = String::with_capacity(line.len());
+    for ch in line.chars() {
+        match ch {
+            '-' | '_' => expanded.push(' '),
+            '\t' => {
+                let spaces = 8 - expanded.chars().count() % 8;
+                expanded.extend(std::iter::repeat_n(' ', spaces));
+            }
+            _ => expanded.push(ch),
+        }
+    }
+    expanded
+}
+
+fn check_capacity(positions: &[Position], what: &str) -> Result<(), String> {
+    if positions.len() >= MAX_BOXES {
+        Err(format!("{} count exceeds maximum {}", what, MAX_BOXES))
+    } else {
+        Ok(())
     }
 }
 
@@ -870,29 +2240,7 @@ impl fmt::Display for Game {
         for y in 0..self.height {
             let mut line = String::new();
             for x in 0..self.width {
-                let pos = Position(x, y);
-                let tile = self.tiles[y as usize][x as usize];
-                let has_box = self.boxes.has_box_at(pos);
-                let is_player = pos == self.player;
-
-                let ch = if is_player {
-                    match tile {
-                        Tile::Goal => '+',
-                        _ => '@',
-                    }
-                } else if has_box {
-                    match tile {
-                        Tile::Goal => '*',
-                        _ => '$',
-                    }
-                } else {
-                    match tile {
-                        Tile::Wall => '#',
-                        Tile::Floor => ' ',
-                        Tile::Goal => '.',
-                    }
-                };
-                line.push(ch);
+                line.push(self.square_char(Position(x, y)));
             }
             // Trim trailing spaces to match original input format
             writeln!(f, "{}", line.trim_end())?;
@@ -901,6 +2249,20 @@ impl fmt::Display for Game {
     }
 }
 
+/// Extend the bits of `seeds` along the runs of bits of `free` holding them,
+/// doubling the distance covered in each step.
+fn fill_row(seeds: u64, free: u64) -> u64 {
+    let (mut left, mut right) = (seeds, seeds);
+    let (mut left_free, mut right_free) = (free, free);
+    for shift in [1, 2, 4, 8, 16, 32] {
+        left |= left_free & (left << shift);
+        left_free &= left_free << shift;
+        right |= right_free & (right >> shift);
+        right_free &= right_free >> shift;
+    }
+    left | right
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
@@ -939,6 +2301,30 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_unknown_player() {
+        let game = Game::from_text_unknown_player("#######\n#.$ $.#\n#######").unwrap();
+        // The player is placed in the first region next to a box
+        assert_eq!(game.player, Position(1, 1));
+        assert_eq!(
+            game.all_possible_player_positions(),
+            vec![Position(1, 1), Position(3, 1), Position(5, 1)]
+        );
+
+        // Boards with a player are parsed as usual
+        let game = Game::from_text_unknown_player("#######\n#.$@$.#\n#######").unwrap();
+        assert_eq!(game.player, Position(3, 1));
+    }
+
+    #[test]
+    fn test_set_player_for_push() {
+        let mut game = Game::from_text_unknown_player("#######\n#.$ $.#\n#######").unwrap();
+        let push = Push::new(game.box_index(Position(2, 1)).unwrap(), Direction::Left);
+        game.set_player_for_push(push);
+        assert_eq!(game.player, Position(3, 1));
+        assert!(game.compute_pushes().moves.contains(push));
+    }
+
     #[test]
     fn test_multiple_players() {
         let result = parse_game(
@@ -951,6 +2337,26 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_alternative_floor() {
+        let game = Game::from_text("#######\n#@-$_.#\n#######").unwrap();
+        assert_eq!(game.to_string(), "#######\n#@ $ .#\n#######\n");
+
+        // Tabs are expanded to the next multiple of 8 columns
+        let game = Game::from_text("\t####\n\t#@$.#\n\t####").unwrap();
+        assert_eq!(game.width(), 13);
+        assert_eq!(game.player(), Position(9, 1));
+
+        assert_eq!(
+            Game::from_text_strict("#####\n#@$.#\n#####").unwrap(),
+            Game::from_text("#####\n#@$.#\n#####").unwrap()
+        );
+        assert_eq!(
+            Game::from_text_strict("######\n#@$-.#\n######").unwrap_err(),
+            "Invalid character '-' at position (3, 1)"
+        );
+    }
+
     #[test]
     fn test_player_on_goal() {
         let game = parse_game(
@@ -1074,6 +2480,16 @@ mod tests {
         );
         assert!(more_boxes.is_err());
 
+        // Both are accepted when parsing unbalanced boards, and only surplus
+        // goals can be left empty
+        let mut game = Game::from_text_unbalanced("#####\n#@$.#\n#  .#\n#####").unwrap();
+        assert!(!game.is_balanced());
+        game.push(Push::new(Index(0), Direction::Right));
+        assert!(game.is_solved());
+        let game = Game::from_text_unbalanced("######\n#@$$.#\n######").unwrap();
+        assert!(!game.is_balanced());
+        assert!(!game.is_solved());
+
         // Equal goals and boxes - should succeed
         let balanced = parse_game(
             r#"
@@ -1087,6 +2503,342 @@ mod tests {
         assert!(balanced.is_ok());
     }
 
+    #[test]
+    fn test_too_many_boxes() {
+        // Split boxes across two rows to stay within the maximum width
+        let half = MAX_BOXES / 2 + 1;
+        let text = format!(
+            "#{boxes}#\n#{boxes}#\n#{goals}#\n#{goals}#\n#@#",
+            boxes = "$".repeat(half),
+            goals = ".".repeat(half)
+        );
+        let result = Game::from_text(&text);
+        assert_eq!(
+            result.unwrap_err(),
+            format!("Box count exceeds maximum {}", MAX_BOXES)
+        );
+    }
+
+    #[test]
+    fn test_game_builder() {
+        // A walled 6x4 board, built in any order
+        let mut builder = GameBuilder::new(6, 4);
+        for x in 0..6 {
+            builder = builder.wall(Position(x, 0)).wall(Position(x, 3));
+        }
+        for y in 1..3 {
+            builder = builder.wall(Position(0, y)).wall(Position(5, y));
+        }
+        let builder = builder
+            .add_box(Position(3, 2))
+            .add_box(Position(2, 1))
+            .goal(Position(4, 1))
+            .goal(Position(4, 2))
+            .player(Position(1, 2));
+        let text = "######\n\
+                    # $ .#\n\
+                    #@ $.#\n\
+                    ######";
+        assert_eq!(builder.build().unwrap(), Game::from_text(text).unwrap());
+
+        // Changing a square back to floor
+        let game = builder.clone().goal(Position(1, 1)).floor(Position(4, 2));
+        assert_eq!(
+            game.build().unwrap(),
+            Game::from_text("######\n#.$ .#\n#@ $ #\n######").unwrap()
+        );
+
+        let errors = [
+            (
+                builder.clone().add_box(Position(6, 1)),
+                "Position (6, 1) is outside the 6x4 board",
+            ),
+            (
+                builder.clone().add_box(Position(0, 1)),
+                "Box on a wall at (0, 1)",
+            ),
+            (
+                builder.clone().add_box(Position(2, 1)),
+                "Multiple boxes at (2, 1)",
+            ),
+            (
+                builder.clone().player(Position(3, 2)),
+                "Player on a box at (3, 2)",
+            ),
+            (
+                builder.clone().player(Position(0, 0)),
+                "Player on a wall at (0, 0)",
+            ),
+            (
+                builder.clone().floor(Position(4, 2)),
+                "Goal count (1) does not match box count (2)",
+            ),
+            (GameBuilder::new(0, 0), "Empty board"),
+            (
+                GameBuilder::new(3, 1).goal(Position(1, 0)),
+                "No player found on board",
+            ),
+        ];
+        for (builder, error) in errors {
+            assert_eq!(builder.build().unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::from_text(
+            "#######\n\
+             #@ $ .#\n\
+             #######",
+        )
+        .unwrap();
+        let initial = game.clone();
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.redo(), None);
+
+        // The player walks up to the box before the first push
+        let right = Push::new(Index(0), Direction::Right);
+        game.push_with_history(right);
+        game.push_with_history(right);
+        assert!(game.is_solved());
+        let solved = game.clone();
+
+        // Undoing puts the player back where it was before each push
+        assert_eq!(game.undo(), Some(right));
+        assert_eq!(game.box_position(Index(0)), Position(4, 1));
+        assert_eq!(game.player(), Position(3, 1));
+        assert_eq!(game.undo(), Some(right));
+        assert_eq!(game.box_position(Index(0)), Position(3, 1));
+        assert_eq!(game.player(), Position(1, 1));
+        assert_eq!(game.undo(), None);
+
+        assert_eq!(game.redo(), Some(right));
+        assert_eq!(game.redo(), Some(right));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game, solved);
+
+        // A new push after undoing discards the pushes undone
+        game.undo();
+        game.undo();
+        game.push_with_history(right);
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.undo(), Some(right));
+        assert_eq!(game.undo(), None);
+
+        // Games are equal whatever their histories, and changing the boxes
+        // other than through the history forgets it
+        assert_eq!(game, initial);
+        game.push_with_history(right);
+        game.push_with_history(right);
+        game.undo();
+        game.restore(&initial.checkpoint());
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.redo(), None);
+        game.push_with_history(right);
+        game.push(right);
+        assert_eq!(game.undo(), None);
+        assert_eq!(game, solved);
+    }
+
+    #[test]
+    fn test_apply_lurd() {
+        let game = Game::from_text(
+            "#######\n\
+             #@ $ .#\n\
+             #  ####\n\
+             #######",
+        )
+        .unwrap();
+
+        // Whitespace, such as line breaks, is skipped
+        let solved = game.apply_lurd("rR\nR").unwrap();
+        assert!(solved.is_solved());
+        assert_eq!(solved.player(), Position(4, 1));
+        assert_eq!(game.apply_lurd("").unwrap(), game);
+
+        let errors = [
+            ("rRX", "step 3: invalid LURD character 'X'"),
+            ("u", "step 1: player moves Up into a wall at (1, 0)"),
+            ("rr", "step 2: move Right pushes the box at (3, 1)"),
+            ("D", "step 1: push Down but no box at (1, 2)"),
+            ("rRRR", "step 4: box at (5, 1) pushed Right is blocked"),
+        ];
+        for (lurd, error) in errors {
+            assert_eq!(game.apply_lurd(lurd).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = |lines: &[&str], symmetric| {
+            Game::from_text(&lines.join("\n"))
+                .unwrap()
+                .fingerprint(symmetric)
+        };
+        let level = ["#######", "#@ $ .#", "#  $ .#", "#######"];
+        let expected = fingerprint(&level, false);
+
+        // Outside squares, padding and where the player stands in its
+        // region make no difference
+        let redrawn = [
+            "",
+            "   #######   ",
+            "   #  $ .#",
+            "   # @$ .#",
+            "  ########",
+        ];
+        assert_eq!(fingerprint(&redrawn, false), expected);
+
+        // Mirror images only match with symmetric fingerprints
+        let mirrored = ["#######", "#. $ @#", "#. $  #", "#######"];
+        assert_ne!(fingerprint(&mirrored, false), expected);
+        assert_eq!(fingerprint(&mirrored, true), fingerprint(&level, true));
+        let rotated = ["####", "#..#", "#  #", "#$$#", "#  #", "#@ #", "####"];
+        assert_eq!(fingerprint(&rotated, true), fingerprint(&level, true));
+
+        // Moving a box or walling the player in changes the level
+        let moved = ["#######", "#@$  .#", "#  $ .#", "#######"];
+        assert_ne!(fingerprint(&moved, false), expected);
+        assert_ne!(fingerprint(&moved, true), fingerprint(&level, true));
+        let walled = ["#######", "#@#$ .#", "###$ .#", "#######"];
+        assert_ne!(fingerprint(&walled, false), expected);
+
+        // Board fingerprints tell apart where the level is drawn
+        let board_fingerprint = |lines: &[&str]| {
+            Game::from_text(&lines.join("\n"))
+                .unwrap()
+                .board_fingerprint()
+        };
+        let padded = ["", "  #######", "  #@ $ .#", "  #  $ .#", "  #######"];
+        assert_eq!(fingerprint(&padded, false), expected);
+        assert_ne!(board_fingerprint(&padded), board_fingerprint(&level));
+        let trailing = ["#######   ", "#@ $ .#", "#  $ .#", "#######", ""];
+        assert_eq!(board_fingerprint(&trailing), board_fingerprint(&level));
+
+        // So do the labels of Sokoban+ levels
+        let labelled = |box_labels: &[u8], goal_labels: &[u8]| {
+            let mut game = Game::from_text(&level.join("\n")).unwrap();
+            game.set_labels(box_labels, goal_labels).unwrap();
+            game
+        };
+        let swapped = labelled(&[1, 2], &[2, 1]);
+        assert_ne!(labelled(&[1, 2], &[1, 2]).fingerprint(false), expected);
+        assert_ne!(
+            swapped.fingerprint(false),
+            labelled(&[1, 2], &[1, 2]).fingerprint(false)
+        );
+        assert_ne!(
+            swapped.board_fingerprint(),
+            labelled(&[1, 2], &[1, 2]).board_fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let text = [
+            "",
+            "  #######",
+            "  #@ $ .#",
+            "  # ### #",
+            "  # # # #",
+            "  # ### #",
+            "  #     #",
+            "  #######",
+        ]
+        .join("\n");
+        let game = Game::from_text(&text).unwrap();
+        let normalized = game.normalize().unwrap();
+        assert_eq!((normalized.width(), normalized.height()), (7, 7));
+        assert_eq!(normalized.player(), Position(1, 1));
+        assert_eq!(normalized.box_positions(), &[Position(3, 1)]);
+        assert_eq!(normalized.goal_positions(), &[Position(5, 1)]);
+
+        // The unreachable square in the middle is walled in, but still drawn
+        // as before
+        assert_eq!(normalized.get_tile(Position(3, 3)), Tile::Wall);
+        assert_eq!(
+            normalized.to_string(),
+            "#######\n#@ $ .#\n# ### #\n# # # #\n# ### #\n#     #\n#######\n"
+        );
+        assert_eq!(normalized.normalize().unwrap(), normalized);
+
+        // The player must not be able to walk off the board
+        let open = Game::from_text("#####\n#@$. \n#####").unwrap();
+        assert_eq!(
+            open.normalize().unwrap_err(),
+            "Level is not enclosed: the player can reach (4, 1) on the edge of the board"
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut game = Game::from_text(
+            "#########\n\
+             #       #\n\
+             #       #\n\
+             # .$@$. #\n\
+             #       #\n\
+             #       #\n\
+             #########",
+        )
+        .unwrap();
+        assert!(!game.is_labelled());
+        game.set_labels(&[1, 2], &[2, 1]).unwrap();
+        assert!(game.is_labelled());
+        assert!(game.box_fits_goal(Index(0), 1));
+        assert!(!game.box_fits_goal(Index(0), 0));
+        assert_eq!(game.normalize().unwrap().box_label(Index(1)), 2);
+        let start = game.clone();
+
+        // The boxes are on goals, but not their own
+        game.push(Push::new(Index(0), Direction::Left));
+        game.push(Push::new(Index(1), Direction::Right));
+        assert_eq!(game.unsolved_boxes().len(), 2);
+        assert!(!game.is_solved());
+        let mut unlabelled = game.clone();
+        unlabelled.clear_labels();
+        assert!(unlabelled.is_solved());
+
+        // Swapping the labels of the boxes changes their hash
+        let mut swapped = game.clone();
+        swapped.set_labels(&[2, 1], &[2, 1]).unwrap();
+        assert!(swapped.is_solved());
+        for zobrist in [Zobrist::new(), Zobrist::with_seed(1)] {
+            assert_ne!(
+                zobrist.compute_boxes_hash(&swapped),
+                zobrist.compute_boxes_hash(&game)
+            );
+        }
+
+        // Labels follow the boxes when packing renumbers them in reading
+        // order, and through projections
+        let mut game = start.clone();
+        game.push(Push::new(Index(1), Direction::Up));
+        let mut restored = start.clone();
+        restored.restore_packed(&game.pack());
+        assert_eq!(restored.box_positions()[0], Position(5, 2));
+        assert_eq!(restored.box_label(Index(0)), 2);
+        let checkpoint = restored.checkpoint();
+        let mut boxes_to_keep = Bitvector::new();
+        boxes_to_keep.add(Index(1));
+        restored.project(boxes_to_keep);
+        assert_eq!(restored.box_label(Index(0)), 1);
+        restored.restore(&checkpoint);
+        assert_eq!(restored.box_label(Index(0)), 2);
+
+        assert_eq!(
+            start.clone().set_labels(&[1, 1], &[1, 2]).unwrap_err(),
+            "More boxes than goals labelled 1"
+        );
+        assert_eq!(
+            start.clone().set_labels(&[1, 2, 3], &[]).unwrap_err(),
+            "3 box labels for 2 boxes"
+        );
+        let mut plain = start.clone();
+        plain.set_labels(&[0], &[0, 0]).unwrap();
+        assert!(!plain.is_labelled());
+    }
+
     #[test]
     fn test_push_basic() {
         // Simple board: player can push box right onto goal
@@ -1100,7 +2852,7 @@ mod tests {
         .unwrap();
 
         // Push box right (box at position (2,1) is box index 0)
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1131,7 +2883,7 @@ mod tests {
 "#,
         )
         .unwrap();
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1150,7 +2902,7 @@ mod tests {
 "#,
         )
         .unwrap();
-        let box_idx = game.boxes.index[2][2];
+        let box_idx = game.box_index(Position(2, 2)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Down,
@@ -1169,7 +2921,7 @@ mod tests {
 "#,
         )
         .unwrap();
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Left,
@@ -1188,7 +2940,7 @@ mod tests {
 "#,
         )
         .unwrap();
-        let box_idx = game.boxes.index[2][2];
+        let box_idx = game.box_index(Position(2, 2)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Up,
@@ -1211,7 +2963,7 @@ mod tests {
         .unwrap();
 
         assert_eq!(game.boxes.unsolved.len(), 1);
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1238,7 +2990,7 @@ mod tests {
         assert_eq!(game.get_tile(Position(2, 1)), Tile::Goal);
         assert!(game.boxes.has_box_at(Position(2, 1)));
 
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1265,7 +3017,7 @@ mod tests {
         .unwrap();
 
         assert_eq!(game.boxes.unsolved.len(), 1);
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1311,7 +3063,7 @@ mod tests {
         .unwrap();
 
         // Try to push box into wall
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1332,7 +3084,7 @@ mod tests {
         .unwrap();
 
         // Try to push box into another box
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         game.push(Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1403,6 +3155,192 @@ mod tests {
         assert_eq!(reachable.squares.top_left(), Some(Position(3, 1)));
     }
 
+    #[test]
+    fn test_boxes_hash() {
+        let mut game = parse_game(
+            r#"
+#######
+# @   #
+# $   #
+#   $.#
+#.  * #
+#######
+"#,
+        )
+        .unwrap();
+        let expected = |game: &Game| {
+            game.box_positions()
+                .iter()
+                .fold(0, |hash, &pos| hash ^ Zobrist::shared().box_hash(pos))
+        };
+        assert_eq!(game.boxes_hash(), expected(&game));
+        let checkpoint = game.checkpoint();
+        let packed = game.pack();
+
+        game.push(Push::new(Index(0), Direction::Down));
+        assert_eq!(game.boxes_hash(), expected(&game));
+        game.pull(Pull::new(Index(0), Direction::Up));
+        assert_eq!(game.boxes_hash(), expected(&game));
+
+        game.restore(&checkpoint);
+        assert_eq!(game.boxes_hash(), expected(&game));
+        game.restore_packed(&packed);
+        assert_eq!(game.boxes_hash(), expected(&game));
+
+        let mut boxes = Bitvector::new();
+        boxes.add(Index(1));
+        game.project(boxes);
+        assert_eq!(game.boxes_hash(), expected(&game));
+    }
+
+    #[test]
+    fn test_square_index() {
+        let game = parse_game(
+            r#"
+#####
+#@$.#
+# # #
+#####
+"#,
+        )
+        .unwrap();
+        assert_eq!(game.square_count(), 5);
+        assert_eq!(game.square_index(Position(0, 0)), None);
+        assert_eq!(game.square_index(Position(2, 2)), None);
+        assert_eq!(game.square_index(Position(1, 1)), Some(0));
+        assert_eq!(game.square_index(Position(3, 1)), Some(2));
+        assert_eq!(game.square_index(Position(1, 2)), Some(3));
+        assert_eq!(game.square_index(Position(3, 2)), Some(4));
+        assert_eq!(game.box_index(Position(2, 1)), Some(Index(0)));
+        assert_eq!(game.box_index(Position(3, 1)), None);
+    }
+
+    #[test]
+    fn test_packed_checkpoint() {
+        let mut game = parse_game(
+            r#"
+#######
+# @   #
+# $   #
+#   $.#
+#.  * #
+#######
+"#,
+        )
+        .unwrap();
+        let start = game.pack();
+
+        // Push the first box past the others, so that it's no longer first in
+        // reading order
+        game.push(Push::new(Index(0), Direction::Down));
+        game.push(Push::new(Index(0), Direction::Down));
+        let mut frozen = Bitvector::new();
+        frozen.add(Index(0));
+        let packed = game.pack();
+        let indices = game.packed_box_indices(&frozen);
+        let box_pos = game.box_position(Index(0));
+
+        let mut restored = game.clone();
+        restored.restore_packed(&start);
+        restored.restore_packed(&packed);
+        assert_eq!(restored.player(), game.player());
+        let mut positions = restored.box_positions().to_vec();
+        positions.sort_by_key(|pos| (pos.1, pos.0));
+        assert_eq!(restored.box_positions(), positions);
+        assert_eq!(restored.pack(), packed);
+        assert_eq!(
+            indices
+                .iter()
+                .map(|idx| restored.box_position(idx))
+                .collect::<Vec<_>>(),
+            vec![box_pos]
+        );
+        assert_eq!(restored.unsolved_boxes().len(), game.unsolved_boxes().len());
+    }
+
+    #[test]
+    fn test_fill_row() {
+        let free = 0b1110_1111_0111;
+        assert_eq!(fill_row(0b100, free), 0b111);
+        assert_eq!(fill_row(0b10_0000, free), 0b1111_0000);
+        assert_eq!(fill_row(1 | 1 << 4 | 1 << 11, free), free);
+        assert_eq!(fill_row(1 << 63, u64::MAX), u64::MAX);
+        assert_eq!(fill_row(0, free), 0);
+    }
+
+    #[test]
+    fn test_compute_moves_after_move() {
+        // Rooms joined by corridors, so that moves both split and join the
+        // player's region
+        let start = parse_game(
+            r#"
+#########
+#  #  . #
+# $ $ # #
+#@ #  $.#
+## ## ###
+#  .    #
+#########
+"#,
+        )
+        .unwrap();
+
+        let mut game = start.clone();
+        let mut queue = VecDeque::from([start.checkpoint()]);
+        let state = |game: &Game| (game.player, game.box_positions().to_vec());
+        let mut seen = HashSet::from([state(&start)]);
+        while let Some(checkpoint) = queue.pop_front() {
+            game.restore(&checkpoint);
+            let reachable = game.compute_pushes();
+            let rows: Vec<u64> = reachable.squares.rows(game.height()).collect();
+            for push in &reachable.moves {
+                let from = game.box_position(push.box_index());
+                game.push(push);
+                let expected = game.compute_pushes();
+                let actual = game.compute_pushes_after_move(
+                    LazyBitboard::from_rows(&rows),
+                    from,
+                    game.box_position(push.box_index()),
+                );
+                assert_eq!(actual.moves, expected.moves);
+                assert_eq!(actual.boxes, expected.boxes);
+                assert!(
+                    actual
+                        .squares
+                        .rows(game.height())
+                        .eq(expected.squares.rows(game.height()))
+                );
+                if seen.len() < 2000 && seen.insert(state(&game)) {
+                    queue.push_back(game.checkpoint());
+                }
+                game.restore(&checkpoint);
+            }
+
+            let reachable = game.compute_pulls();
+            let rows: Vec<u64> = reachable.squares.rows(game.height()).collect();
+            for pull in &reachable.moves {
+                let from = game.box_position(pull.box_index());
+                game.pull(pull);
+                let expected = game.compute_pulls();
+                let actual = game.compute_pulls_after_move(
+                    LazyBitboard::from_rows(&rows),
+                    from,
+                    game.box_position(pull.box_index()),
+                );
+                assert_eq!(actual.moves, expected.moves);
+                assert_eq!(actual.boxes, expected.boxes);
+                assert!(
+                    actual
+                        .squares
+                        .rows(game.height())
+                        .eq(expected.squares.rows(game.height()))
+                );
+                game.restore(&checkpoint);
+            }
+        }
+        assert!(seen.len() > 100);
+    }
+
     #[test]
     fn test_pull() {
         // Test pull restores original state
@@ -1421,7 +3359,7 @@ mod tests {
         let original_goals = game.boxes.unsolved.len();
 
         // Push box right
-        let box_idx = game.boxes.index[1][2];
+        let box_idx = game.box_index(Position(2, 1)).unwrap();
         let push = Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1460,7 +3398,7 @@ mod tests {
         .unwrap();
         let original = game.clone();
         let box_idx = game.boxes.positions[0];
-        let box_idx = game.boxes.index[box_idx.1 as usize][box_idx.0 as usize];
+        let box_idx = game.box_index(box_idx).unwrap();
         let push = Push {
             box_index: box_idx,
             direction: Direction::Right,
@@ -1484,7 +3422,7 @@ mod tests {
         .unwrap();
         let original = game.clone();
         let box_idx = game.boxes.positions[0];
-        let box_idx = game.boxes.index[box_idx.1 as usize][box_idx.0 as usize];
+        let box_idx = game.box_index(box_idx).unwrap();
         let push = Push {
             box_index: box_idx,
             direction: Direction::Down,
@@ -1507,7 +3445,7 @@ mod tests {
         .unwrap();
         let original = game.clone();
         let box_idx = game.boxes.positions[0];
-        let box_idx = game.boxes.index[box_idx.1 as usize][box_idx.0 as usize];
+        let box_idx = game.box_index(box_idx).unwrap();
         let push = Push {
             box_index: box_idx,
             direction: Direction::Left,
@@ -1531,7 +3469,7 @@ mod tests {
         .unwrap();
         let original = game.clone();
         let box_idx = game.boxes.positions[0];
-        let box_idx = game.boxes.index[box_idx.1 as usize][box_idx.0 as usize];
+        let box_idx = game.box_index(box_idx).unwrap();
         let push = Push {
             box_index: box_idx,
             direction: Direction::Up,
@@ -1543,6 +3481,149 @@ mod tests {
         assert_eq!(game.boxes.unsolved.len(), original.boxes.unsolved.len());
     }
 
+    #[test]
+    fn test_position_offset() {
+        assert_eq!(Position(3, 4).offset(Direction::Up), Some(Position(3, 3)));
+        assert_eq!(
+            Position(3, 4).offset(Direction::Right),
+            Some(Position(4, 4))
+        );
+        assert_eq!(Position(0, 4).offset(Direction::Left), None);
+        assert_eq!(Position(3, 0).offset(Direction::Up), None);
+        assert_eq!(Position(3, 4) + Direction::Down, Position(3, 5));
+        assert_eq!(Position(3, 4).manhattan(Position(1, 7)), 5);
+    }
+
+    #[test]
+    fn test_position_neighbors() {
+        let neighbors: Vec<Position> = Position(1, 1).neighbors(3, 3).collect();
+        assert_eq!(
+            neighbors,
+            vec![
+                Position(1, 0),
+                Position(1, 2),
+                Position(0, 1),
+                Position(2, 1)
+            ]
+        );
+        let neighbors: Vec<Position> = Position(0, 2).neighbors(3, 3).collect();
+        assert_eq!(neighbors, vec![Position(0, 1), Position(1, 2)]);
+    }
+
+    #[test]
+    fn test_player_distances() {
+        let game = parse_game(
+            r#"
+#######
+#@ $  #
+# ## .#
+#######
+"#,
+        )
+        .unwrap();
+        let distances = game.player_distances();
+        assert_eq!(distances.get(Position(1, 1)), Some(0));
+        assert_eq!(distances.get(Position(2, 1)), Some(1));
+        assert_eq!(distances.get(Position(1, 2)), Some(1));
+        // Blocked by the box
+        assert_eq!(distances.get(Position(4, 1)), None);
+        assert_eq!(distances.get(Position(0, 0)), None);
+
+        assert_eq!(
+            game.player_path_to(Position(2, 1)),
+            Some(vec![Direction::Right])
+        );
+        assert_eq!(game.player_path_to(Position(1, 1)), Some(vec![]));
+        assert_eq!(game.player_path_to(Position(5, 2)), None);
+
+        // Walking along the path reaches the square, but never past a box
+        let mut walker = game.clone();
+        for dir in game.player_path_to(Position(2, 1)).unwrap() {
+            walker.move_player(dir).unwrap();
+        }
+        assert_eq!(walker.player(), Position(2, 1));
+        assert_eq!(
+            walker.move_player(Direction::Right),
+            Err("move Right pushes the box at (3, 1)".to_string())
+        );
+        assert_eq!(
+            walker.move_player(Direction::Up),
+            Err("player moves Up into a wall at (2, 0)".to_string())
+        );
+        assert_eq!(walker.player(), Position(2, 1));
+        walker.move_player(Direction::Left).unwrap();
+        walker.move_player(Direction::Down).unwrap();
+        assert_eq!(walker.player(), Position(1, 2));
+    }
+
+    #[test]
+    fn test_player_distance_to_push() {
+        let game = parse_game(
+            r#"
+#######
+#@ $  #
+# ## .#
+#######
+"#,
+        )
+        .unwrap();
+        assert_eq!(game.player_distance_to_push(|_, _| true), Some(1));
+        assert_eq!(
+            game.player_distance_to_push(|_, dest| dest != Position(4, 1)),
+            None
+        );
+        assert_eq!(game.player_distance_to_pull(|_, _| true), Some(1));
+        assert_eq!(game.player_distance_to_pull(|_, _| false), None);
+    }
+
+    #[test]
+    fn test_dead_squares_with_frozen_boxes() {
+        let game = parse_game(
+            r#"
+#######
+#. *  #
+#  $ @#
+#######
+"#,
+        )
+        .unwrap();
+        assert!(!game.is_push_dead_square(Position(2, 1)));
+        assert!(!game.is_push_dead_square(Position(4, 1)));
+
+        // With the box on the right goal frozen, boxes along the top wall can
+        // no longer be pushed to the left goal
+        let mut frozen = Bitvector::new();
+        frozen.add(game.box_index(Position(3, 1)).unwrap());
+        let dead = game.compute_push_dead_squares(&frozen);
+        assert!(dead.get(Position(2, 1)));
+        assert!(dead.get(Position(4, 1)));
+        assert!(!dead.get(Position(1, 1)));
+        assert_eq!(
+            game.compute_push_dead_squares(&Bitvector::new()),
+            game.push_dead_squares
+        );
+    }
+
+    #[test]
+    fn test_wall_region() {
+        let game = parse_game(
+            r#"
+##########
+#@$ .#*  #
+##########
+"#,
+        )
+        .unwrap();
+
+        let region = game.wall_region(game.player());
+        assert!(region.get(Position(1, 1)));
+        assert!(region.get(Position(2, 1)));
+        assert!(region.get(Position(4, 1)));
+        assert!(!region.get(Position(5, 1)));
+        assert!(!region.get(Position(6, 1)));
+        assert!(!region.get(Position(8, 1)));
+    }
+
     fn parse_game(text: &str) -> Result<Game, String> {
         Game::from_text(text.trim_matches('\n'))
     }
@@ -3,16 +3,41 @@ use arrayvec::ArrayVec;
 use crate::{
     bits::{Bitvector, Index, RawBitboard},
     game::{ALL_DIRECTIONS, Game, MAX_BOXES, MAX_SIZE, Position, Tile},
-    hungarian::{ArrayMatrix, hungarian_algorithm},
+    hungarian::{ArrayMatrix, Assignment, Matrix},
+    pdb::{GoalRoom, find_goal_rooms},
 };
+use rayon::prelude::*;
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::Arc;
 
 /// Estimated cost returned by heuristic computation.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Cost(u16);
 
 impl Cost {
     pub const INFINITE: Cost = Cost(u16::MAX);
+
+    /// Add `extra` to a finite cost, saturating below `INFINITE`. Infinite
+    /// costs stay infinite.
+    pub fn plus(self, extra: u16) -> Cost {
+        if self == Cost::INFINITE {
+            self
+        } else {
+            Cost(self.0.saturating_add(extra).min(u16::MAX - 1))
+        }
+    }
+
+    /// Raise a finite cost to at least `bound`, saturating below `INFINITE`.
+    /// Infinite costs stay infinite.
+    pub fn at_least(self, bound: u16) -> Cost {
+        if self == Cost::INFINITE {
+            self
+        } else {
+            Cost(self.0.max(bound.min(u16::MAX - 1)))
+        }
+    }
 }
 
 impl From<Cost> for usize {
@@ -24,13 +49,17 @@ impl From<Cost> for usize {
 
 /// Trait for computing heuristics that estimate the number of moves (pushes/pulls) needed.
 pub trait Heuristic {
+    /// Settings chosen at runtime which every heuristic of the type is
+    /// created with, `()` if there are none.
+    type Params: Clone + Send + Sync;
+
     /// Create a push-oriented heuristic for forward search.
-    fn new_push(game: &Game, frozen_boxes: Bitvector) -> Self
+    fn new_push(game: &Game, frozen_boxes: Bitvector, params: &Self::Params) -> Self
     where
         Self: Sized;
 
     /// Create a pull-oriented heuristic for reverse search.
-    fn new_pull(game: &Game, frozen_boxes: Bitvector) -> Self
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, params: &Self::Params) -> Self
     where
         Self: Sized;
 
@@ -43,11 +72,13 @@ pub trait Heuristic {
 pub struct NullHeuristic;
 
 impl Heuristic for NullHeuristic {
-    fn new_push(_game: &Game, _frozen_boxes: Bitvector) -> Self {
+    type Params = ();
+
+    fn new_push(_game: &Game, _frozen_boxes: Bitvector, _params: &()) -> Self {
         NullHeuristic
     }
 
-    fn new_pull(_game: &Game, _frozen_boxes: Bitvector) -> Self {
+    fn new_pull(_game: &Game, _frozen_boxes: Bitvector, _params: &()) -> Self {
         NullHeuristic
     }
 
@@ -59,18 +90,21 @@ impl Heuristic for NullHeuristic {
 /// A heuristic based on simple but fast matching of boxes to goals where
 /// boxes/goals can be re-used within the matching.
 pub struct SimpleHeuristic {
-    /// distances[idx][y][x] = minimum pushes/pulls to get a box from (x, y) to destination idx
-    distances: Box<[[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES]>,
+    /// distances[idx][square] = minimum pushes/pulls to get a box from the
+    /// square to destination idx
+    distances: Distances,
 }
 
 impl Heuristic for SimpleHeuristic {
-    fn new_push(game: &Game, frozen_boxes: Bitvector) -> Self {
-        let distances = Box::new(compute_push_distances(game, &frozen_boxes));
+    type Params = ();
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        let distances = compute_push_distances(game, &frozen_boxes);
         SimpleHeuristic { distances }
     }
 
-    fn new_pull(game: &Game, frozen_boxes: Bitvector) -> Self {
-        let distances = Box::new(compute_pull_distances(game, &frozen_boxes));
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        let distances = compute_pull_distances(game, &frozen_boxes);
         SimpleHeuristic { distances }
     }
 
@@ -79,25 +113,33 @@ impl Heuristic for SimpleHeuristic {
     }
 }
 
-fn compute_simple_heuristic(
-    game: &Game,
-    distances: &[[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES],
-) -> u16 {
+fn compute_simple_heuristic(game: &Game, distances: &Distances) -> u16 {
     // Compute two distances:
     //   box_to_dst_total: total distance from each box to its nearest destination.
     //   dst_to_box_total: total distance from each destination to its nearest box.
     // The simple distance is the maximum between the two.
     // If either distance is u16::MAX, then the game is unsolvable.
+    // Destinations may be left empty when they outnumber the boxes, so then
+    // only the first distance counts. In a labelled game, boxes only count
+    // the destinations they fit.
 
     let mut box_to_dst_total = 0u16;
     let mut dst_to_box = [u16::MAX; MAX_BOXES];
-    let box_count = game.box_count();
+    let dst_count = game.goal_positions().len();
+    if game.box_count() > dst_count {
+        return u16::MAX;
+    }
 
-    for pos in game.box_positions().iter() {
+    let labelled = game.is_labelled();
+    for (box_idx, &pos) in game.box_positions().iter().enumerate() {
         let mut box_to_dst = u16::MAX;
+        let square = box_square(game, pos);
 
-        for (dst_idx, dst_to_box) in dst_to_box.iter_mut().enumerate().take(box_count) {
-            let distance = distances[dst_idx][pos.1 as usize][pos.0 as usize];
+        for (dst_idx, dst_to_box) in dst_to_box.iter_mut().enumerate().take(dst_count) {
+            if labelled && !game.box_fits_goal(Index(box_idx as u8), dst_idx) {
+                continue;
+            }
+            let distance = distances[dst_idx][square];
             box_to_dst = std::cmp::min(box_to_dst, distance);
             *dst_to_box = std::cmp::min(*dst_to_box, distance);
         }
@@ -109,8 +151,11 @@ fn compute_simple_heuristic(
         box_to_dst_total += box_to_dst;
     }
 
+    if !game.is_balanced() {
+        return box_to_dst_total;
+    }
     let mut dst_to_box_total = 0;
-    for &dist in dst_to_box.iter().take(box_count) {
+    for &dist in dst_to_box.iter().take(dst_count) {
         if dist == u16::MAX {
             return u16::MAX;
         } else {
@@ -125,41 +170,91 @@ fn compute_simple_heuristic(
 /// cost matching. Runs in O(n^2) rather than O(n^3) required by the optimal
 /// approach.
 pub struct GreedyHeuristic {
-    /// distances[idx][y][x] = minimum pushes/pulls to get a box from (x, y) to destination idx
-    distances: Box<[[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES]>,
+    /// distances[idx][square] = minimum pushes/pulls to get a box from the
+    /// square to destination idx
+    distances: Distances,
+    /// Improve the greedy matching with pairwise swaps.
+    refine: bool,
 }
 
 impl Heuristic for GreedyHeuristic {
-    fn new_push(game: &Game, frozen_boxes: Bitvector) -> Self {
-        let distances = Box::new(compute_push_distances(game, &frozen_boxes));
-        GreedyHeuristic { distances }
+    type Params = ();
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        let distances = compute_push_distances(game, &frozen_boxes);
+        GreedyHeuristic {
+            distances,
+            refine: false,
+        }
     }
 
-    fn new_pull(game: &Game, frozen_boxes: Bitvector) -> Self {
-        let distances = Box::new(compute_pull_distances(game, &frozen_boxes));
-        GreedyHeuristic { distances }
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        let distances = compute_pull_distances(game, &frozen_boxes);
+        GreedyHeuristic {
+            distances,
+            refine: false,
+        }
     }
 
     fn compute(&self, game: &Game) -> Cost {
-        Cost(compute_greedy_heuristic(game, &self.distances))
+        Cost(compute_greedy_heuristic(game, &self.distances, self.refine))
     }
 }
 
-fn compute_greedy_heuristic(
-    game: &Game,
-    distances: &[[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES],
-) -> u16 {
+/// The greedy heuristic, with the greedy matching improved by up to
+/// `REFINE_PASSES` passes of 2-swaps: whenever exchanging the destinations
+/// of two matched boxes lowers their total distance, they are exchanged.
+/// Each pass is O(n^2), so this stays much cheaper than the Hungarian
+/// heuristic while recovering most of its accuracy. Still not admissible.
+pub struct RefinedGreedyHeuristic(GreedyHeuristic);
+
+impl Heuristic for RefinedGreedyHeuristic {
+    type Params = ();
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        RefinedGreedyHeuristic(GreedyHeuristic {
+            refine: true,
+            ..GreedyHeuristic::new_push(game, frozen_boxes, &())
+        })
+    }
+
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        RefinedGreedyHeuristic(GreedyHeuristic {
+            refine: true,
+            ..GreedyHeuristic::new_pull(game, frozen_boxes, &())
+        })
+    }
+
+    fn compute(&self, game: &Game) -> Cost {
+        self.0.compute(game)
+    }
+}
+
+/// Maximum number of 2-swap passes over the greedy matching.
+const REFINE_PASSES: usize = 3;
+
+fn compute_greedy_heuristic(game: &Game, distances: &Distances, refine: bool) -> u16 {
     const M: usize = MAX_BOXES * MAX_BOXES;
     const N: usize = MAX_SIZE * MAX_SIZE;
     let box_count = game.box_count();
+    let dst_count = game.goal_positions().len();
+    if box_count > dst_count {
+        return u16::MAX;
+    }
 
-    // Compute all pairs of distances between boxes <-> destinations
+    // Compute all pairs of distances between boxes <-> destinations they fit
+    let labelled = game.is_labelled();
+    let fits = |box_idx: Index, dst_idx: usize| !labelled || game.box_fits_goal(box_idx, dst_idx);
     let mut all_pairs: ArrayVec<(u16, Index, Index), M> = ArrayVec::new();
     for (box_idx, &pos) in game.box_positions().iter().enumerate() {
         let box_idx = Index(box_idx as u8);
+        let square = box_square(game, pos);
         #[allow(clippy::needless_range_loop)]
-        for dst_idx in 0..box_count {
-            let distance = distances[dst_idx][pos.1 as usize][pos.0 as usize];
+        for dst_idx in 0..dst_count {
+            if !fits(box_idx, dst_idx) {
+                continue;
+            }
+            let distance = distances[dst_idx][square];
             if distance < u16::MAX {
                 let dst_idx = Index(dst_idx as u8);
                 all_pairs.push((distance, box_idx, dst_idx));
@@ -174,12 +269,46 @@ fn compute_greedy_heuristic(
     // Walk through sorted pairs and start matching things up
     let mut total_distance = 0;
     let mut unmatched_boxes = Bitvector::full(box_count as u8);
-    let mut unmatched_dsts = Bitvector::full(box_count as u8);
+    let mut unmatched_dsts = Bitvector::full(dst_count as u8);
+    let mut matches: ArrayVec<(Index, usize, u16), MAX_BOXES> = ArrayVec::new();
     for (distance, box_idx, dst_idx) in all_pairs {
         if unmatched_boxes.contains(box_idx) && unmatched_dsts.contains(dst_idx) {
             total_distance += distance;
             unmatched_boxes.remove(box_idx);
             unmatched_dsts.remove(dst_idx);
+            if refine {
+                matches.push((box_idx, dst_idx.0 as usize, distance));
+            }
+        }
+    }
+
+    // Exchange the destinations of pairs of matched boxes while that lowers
+    // the total distance
+    for _ in 0..REFINE_PASSES {
+        let mut improved = false;
+        for i in 0..matches.len() {
+            for j in (i + 1)..matches.len() {
+                let (box_i, dst_i, distance_i) = matches[i];
+                let (box_j, dst_j, distance_j) = matches[j];
+                if !fits(box_i, dst_j) || !fits(box_j, dst_i) {
+                    continue;
+                }
+                let (pos_i, pos_j) = (game.box_position(box_i), game.box_position(box_j));
+                let swapped_i = distances[dst_j].get(game, pos_i);
+                let swapped_j = distances[dst_i].get(game, pos_j);
+                if swapped_i == u16::MAX || swapped_j == u16::MAX {
+                    continue;
+                }
+                if swapped_i + swapped_j < distance_i + distance_j {
+                    total_distance -= distance_i + distance_j - swapped_i - swapped_j;
+                    matches[i] = (box_i, dst_j, swapped_i);
+                    matches[j] = (box_j, dst_i, swapped_j);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
         }
     }
 
@@ -187,29 +316,35 @@ fn compute_greedy_heuristic(
     let mut unmatched_box_to_dst = 0;
     for box_idx in unmatched_boxes.iter() {
         let pos = game.box_position(box_idx);
-        let min_distance = (0..box_count)
-            .map(|dst_idx| distances[dst_idx][pos.1 as usize][pos.0 as usize])
+        let min_distance = (0..dst_count)
+            .filter(|&dst_idx| fits(box_idx, dst_idx))
+            .map(|dst_idx| distances[dst_idx].get(game, pos))
             .min()
-            .unwrap();
+            .unwrap_or(u16::MAX);
         if min_distance == u16::MAX {
             return u16::MAX;
         }
         unmatched_box_to_dst += min_distance;
     }
 
-    // Compute distance lower bound for unmatched goals -> boxes
+    // Compute distance lower bound for unmatched goals -> boxes, which
+    // needn't all be filled if they outnumber the boxes
     let mut unmatched_dst_to_box = 0;
-    for dst_idx in unmatched_dsts.iter() {
-        let min_distance = game
-            .box_positions()
-            .iter()
-            .map(|pos| distances[dst_idx.0 as usize][pos.1 as usize][pos.0 as usize])
-            .min()
-            .unwrap();
-        if min_distance == u16::MAX {
-            return u16::MAX;
+    if game.is_balanced() {
+        for dst_idx in unmatched_dsts.iter() {
+            let min_distance = game
+                .box_positions()
+                .iter()
+                .enumerate()
+                .filter(|&(box_idx, _)| fits(Index(box_idx as u8), dst_idx.0 as usize))
+                .map(|(_, &pos)| distances[dst_idx.0 as usize].get(game, pos))
+                .min()
+                .unwrap_or(u16::MAX);
+            if min_distance == u16::MAX {
+                return u16::MAX;
+            }
+            unmatched_dst_to_box += min_distance;
         }
-        unmatched_dst_to_box += min_distance;
     }
 
     // Add distance for unmatched boxes <-> goals (pick whichever lower
@@ -220,42 +355,333 @@ fn compute_greedy_heuristic(
 }
 
 /// Heuristic which computes the optimal minimum cost matching between boxes and goals
-/// using the Hungarian algorithm. Runs in O(n^3) time.
+/// using the Hungarian algorithm. Runs in O(n^3) time, or O(n^2) per moved box
+/// when patching the matching of the previously computed position.
 pub struct HungarianHeuristic {
-    /// distances[idx][y][x] = minimum pushes/pulls to get a box from (x, y) to destination idx
-    distances: Box<[[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES]>,
+    /// distances[idx][square] = minimum pushes/pulls to get a box from the
+    /// square to destination idx
+    distances: Distances,
     frozen_boxes: RawBitboard,
     frozen_goals: Bitvector,
+    cache: RefCell<Option<CachedAssignment>>,
+    /// Add the penalty for a linear conflict within the matching.
+    linear_conflicts: bool,
+    /// Add the pattern database costs of the goal rooms to the matching.
+    rooms: Option<RoomCosts>,
+}
+
+/// The matching found for the position the heuristic was last computed for.
+/// Consecutive positions usually differ by a single push, so only the moved
+/// boxes need to be reassigned.
+struct CachedAssignment {
+    /// Positions of the unfrozen boxes, one per row of the cost matrix.
+    box_positions: ArrayVec<Position, MAX_BOXES>,
+    assignment: Assignment,
 }
 
 impl Heuristic for HungarianHeuristic {
-    fn new_push(game: &Game, frozen_boxes: Bitvector) -> Self {
-        let distances = Box::new(compute_push_distances(game, &frozen_boxes));
+    type Params = ();
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        let distances = compute_push_distances(game, &frozen_boxes);
         let (frozen_boxes, frozen_goals) = compute_frozen_boxes_and_goals(game, &frozen_boxes);
         HungarianHeuristic {
             distances,
             frozen_boxes,
             frozen_goals,
+            cache: RefCell::new(None),
+            linear_conflicts: false,
+            rooms: None,
         }
     }
 
-    fn new_pull(game: &Game, frozen_boxes: Bitvector) -> Self {
-        let distances = Box::new(compute_pull_distances(game, &frozen_boxes));
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        let distances = compute_pull_distances(game, &frozen_boxes);
         let (frozen_boxes, frozen_goals) = compute_frozen_boxes_and_goals(game, &frozen_boxes);
         HungarianHeuristic {
             distances,
             frozen_boxes,
             frozen_goals,
+            cache: RefCell::new(None),
+            linear_conflicts: false,
+            rooms: None,
+        }
+    }
+
+    fn compute(&self, game: &Game) -> Cost {
+        Cost(compute_hungarian_heuristic(game, self))
+    }
+}
+
+/// The Hungarian heuristic, plus an admissible penalty when the optimal
+/// matching contains a linear conflict: two adjacent boxes on a line which
+/// each block the first push of the other along its straight path to its
+/// goal (or which is already on its goal). Whichever of the two is pushed
+/// first must leave its straight path, costing at least 2 more pushes.
+pub struct LinearConflictHeuristic(HungarianHeuristic);
+
+impl Heuristic for LinearConflictHeuristic {
+    type Params = ();
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        LinearConflictHeuristic(HungarianHeuristic {
+            linear_conflicts: true,
+            ..HungarianHeuristic::new_push(game, frozen_boxes, &())
+        })
+    }
+
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        LinearConflictHeuristic(HungarianHeuristic {
+            linear_conflicts: true,
+            ..HungarianHeuristic::new_pull(game, frozen_boxes, &())
+        })
+    }
+
+    fn compute(&self, game: &Game) -> Cost {
+        self.0.compute(game)
+    }
+}
+
+/// The Hungarian heuristic, plus the exact number of pushes into each goal
+/// room from a pattern database. Within the matching, boxes are only charged
+/// for the pushes outside of goal rooms, so the two parts add up admissibly.
+/// Goal rooms are only used for forward search.
+pub struct GoalRoomHeuristic(HungarianHeuristic);
+
+impl Heuristic for GoalRoomHeuristic {
+    type Params = ();
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        GoalRoomHeuristic(HungarianHeuristic {
+            rooms: RoomCosts::new(game, &frozen_boxes),
+            ..HungarianHeuristic::new_push(game, frozen_boxes, &())
+        })
+    }
+
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, _params: &()) -> Self {
+        GoalRoomHeuristic(HungarianHeuristic::new_pull(game, frozen_boxes, &()))
+    }
+
+    fn compute(&self, game: &Game) -> Cost {
+        self.0.compute(game)
+    }
+}
+
+/// The maximum of two heuristics, which is admissible if both are.
+pub struct MaxHeuristic<A, B>(A, B);
+
+impl<A: Heuristic, B: Heuristic> Heuristic for MaxHeuristic<A, B> {
+    type Params = (A::Params, B::Params);
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, params: &Self::Params) -> Self {
+        MaxHeuristic(
+            A::new_push(game, frozen_boxes, &params.0),
+            B::new_push(game, frozen_boxes, &params.1),
+        )
+    }
+
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, params: &Self::Params) -> Self {
+        MaxHeuristic(
+            A::new_pull(game, frozen_boxes, &params.0),
+            B::new_pull(game, frozen_boxes, &params.1),
+        )
+    }
+
+    fn compute(&self, game: &Game) -> Cost {
+        let cost = self.0.compute(game);
+        if cost == Cost::INFINITE {
+            return cost;
+        }
+        cost.max(self.1.compute(game))
+    }
+}
+
+/// The heuristics which can be combined at runtime by `MaxListHeuristic`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeuristicKind {
+    Simple,
+    Greedy,
+    GreedyRefined,
+    Hungarian,
+    LinearConflict,
+    GoalRoom,
+    Null,
+}
+
+/// A heuristic of any kind, chosen at runtime.
+enum AnyHeuristic {
+    Simple(SimpleHeuristic),
+    Greedy(GreedyHeuristic),
+    GreedyRefined(RefinedGreedyHeuristic),
+    Hungarian(HungarianHeuristic),
+    LinearConflict(LinearConflictHeuristic),
+    GoalRoom(GoalRoomHeuristic),
+    Null(NullHeuristic),
+}
+
+impl AnyHeuristic {
+    fn new(kind: HeuristicKind, game: &Game, frozen_boxes: Bitvector, push: bool) -> Self {
+        fn build<H: Heuristic<Params = ()>>(game: &Game, frozen_boxes: Bitvector, push: bool) -> H {
+            if push {
+                H::new_push(game, frozen_boxes, &())
+            } else {
+                H::new_pull(game, frozen_boxes, &())
+            }
+        }
+        match kind {
+            HeuristicKind::Simple => AnyHeuristic::Simple(build(game, frozen_boxes, push)),
+            HeuristicKind::Greedy => AnyHeuristic::Greedy(build(game, frozen_boxes, push)),
+            HeuristicKind::GreedyRefined => {
+                AnyHeuristic::GreedyRefined(build(game, frozen_boxes, push))
+            }
+            HeuristicKind::Hungarian => AnyHeuristic::Hungarian(build(game, frozen_boxes, push)),
+            HeuristicKind::LinearConflict => {
+                AnyHeuristic::LinearConflict(build(game, frozen_boxes, push))
+            }
+            HeuristicKind::GoalRoom => AnyHeuristic::GoalRoom(build(game, frozen_boxes, push)),
+            HeuristicKind::Null => AnyHeuristic::Null(build(game, frozen_boxes, push)),
         }
     }
 
     fn compute(&self, game: &Game) -> Cost {
-        Cost(compute_hungarian_heuristic(
-            game,
-            &self.distances,
-            &self.frozen_boxes,
-            &self.frozen_goals,
-        ))
+        match self {
+            AnyHeuristic::Simple(heuristic) => heuristic.compute(game),
+            AnyHeuristic::Greedy(heuristic) => heuristic.compute(game),
+            AnyHeuristic::GreedyRefined(heuristic) => heuristic.compute(game),
+            AnyHeuristic::Hungarian(heuristic) => heuristic.compute(game),
+            AnyHeuristic::LinearConflict(heuristic) => heuristic.compute(game),
+            AnyHeuristic::GoalRoom(heuristic) => heuristic.compute(game),
+            AnyHeuristic::Null(heuristic) => heuristic.compute(game),
+        }
+    }
+}
+
+/// The maximum of a list of heuristics chosen at runtime, given as its
+/// parameters. An empty list always reports 0.
+pub struct MaxListHeuristic(Vec<AnyHeuristic>);
+
+impl MaxListHeuristic {
+    fn new(game: &Game, frozen_boxes: Bitvector, kinds: &[HeuristicKind], push: bool) -> Self {
+        MaxListHeuristic(
+            kinds
+                .iter()
+                .map(|&kind| AnyHeuristic::new(kind, game, frozen_boxes, push))
+                .collect(),
+        )
+    }
+}
+
+impl Heuristic for MaxListHeuristic {
+    type Params = Vec<HeuristicKind>;
+
+    fn new_push(game: &Game, frozen_boxes: Bitvector, kinds: &Self::Params) -> Self {
+        MaxListHeuristic::new(game, frozen_boxes, kinds, true)
+    }
+
+    fn new_pull(game: &Game, frozen_boxes: Bitvector, kinds: &Self::Params) -> Self {
+        MaxListHeuristic::new(game, frozen_boxes, kinds, false)
+    }
+
+    fn compute(&self, game: &Game) -> Cost {
+        let mut max = Cost(0);
+        for heuristic in &self.0 {
+            max = max.max(heuristic.compute(game));
+            if max == Cost::INFINITE {
+                break;
+            }
+        }
+        max
+    }
+}
+
+const NO_ROOM: u8 = u8::MAX;
+
+/// The goal rooms of a game, along with what is needed to bound the pushes
+/// which end outside of them.
+struct RoomCosts {
+    rooms: Arc<[GoalRoom]>,
+    /// room_of[square] = index of the room containing the square, or NO_ROOM
+    room_of: Box<[u8]>,
+    /// Index of the room containing each goal, or NO_ROOM.
+    goal_rooms: ArrayVec<u8, MAX_BOXES>,
+    /// entrance_distances[room][square] = minimum pushes to get a box from
+    /// the square onto the entrance of the room
+    entrance_distances: Vec<GoalDistances>,
+}
+
+impl RoomCosts {
+    fn new(game: &Game, frozen_boxes: &Bitvector) -> Option<Self> {
+        // Rooms are costed by filling all their goals
+        if !game.is_balanced() {
+            return None;
+        }
+        let rooms = find_goal_rooms(game);
+        if rooms.is_empty() {
+            return None;
+        }
+
+        let mut room_of = vec![NO_ROOM; game.square_count()].into_boxed_slice();
+        let mut entrance_distances = Vec::new();
+        for (room_idx, room) in rooms.iter().enumerate() {
+            for &pos in room.squares() {
+                room_of[box_square(game, pos)] = room_idx as u8;
+            }
+            entrance_distances.push(bfs_goal(game, room.entrance(), frozen_boxes, false));
+        }
+        let goal_rooms = game
+            .goal_positions()
+            .iter()
+            .map(|&pos| room_of[box_square(game, pos)])
+            .collect();
+
+        Some(RoomCosts {
+            rooms,
+            room_of,
+            goal_rooms,
+            entrance_distances,
+        })
+    }
+
+    /// Lower bound on the pushes outside of goal rooms needed to get the box
+    /// at the given position to the given goal.
+    fn distance(
+        &self,
+        game: &Game,
+        distances: &Distances,
+        goal_idx: usize,
+        box_pos: Position,
+    ) -> u16 {
+        let box_room = self.room_of[box_square(game, box_pos)];
+        let goal_room = self.goal_rooms[goal_idx];
+        if box_room != NO_ROOM && box_room == goal_room {
+            return 0;
+        }
+
+        // A box in a room must first be pushed out onto the entrance
+        let (start, leave) = if box_room == NO_ROOM {
+            (box_pos, 0)
+        } else {
+            (self.rooms[box_room as usize].entrance(), 1)
+        };
+        let distance = if goal_room == NO_ROOM {
+            distances[goal_idx].get(game, start)
+        } else {
+            self.entrance_distances[goal_room as usize].get(game, start)
+        };
+        if distance == u16::MAX {
+            u16::MAX
+        } else {
+            distance + leave
+        }
+    }
+
+    /// Total pushes into goal rooms, counting nothing for rooms whose
+    /// placement of boxes isn't in their database.
+    fn cost(&self, game: &Game) -> u16 {
+        self.rooms
+            .iter()
+            .map(|room| room.cost(game).unwrap_or(0))
+            .sum()
     }
 }
 
@@ -267,11 +693,11 @@ fn compute_frozen_boxes_and_goals(
     let mut frozen_goals = Bitvector::new();
 
     for (goal_idx, &goal_pos) in game.goal_positions().iter().enumerate() {
-        if let Some(box_idx) = game.box_index(goal_pos) {
-            if frozen_boxes.contains(box_idx) {
-                frozen_boxes_bitboard.set(goal_pos);
-                frozen_goals.add(Index(goal_idx as u8));
-            }
+        if let Some(box_idx) = game.box_index(goal_pos)
+            && frozen_boxes.contains(box_idx)
+        {
+            frozen_boxes_bitboard.set(goal_pos);
+            frozen_goals.add(Index(goal_idx as u8));
         }
     }
 
@@ -286,120 +712,440 @@ fn compute_frozen_boxes_and_goals(
 
 const MAX_HUNGARIAN_BOXES: usize = 39;
 
-fn compute_hungarian_heuristic(
-    game: &Game,
-    distances: &[[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES],
-    frozen_boxes: &RawBitboard,
-    frozen_goals: &Bitvector,
-) -> u16 {
-    let box_count = game.box_count();
-    let unfrozen_count = box_count - frozen_goals.len();
+fn compute_hungarian_heuristic(game: &Game, heuristic: &HungarianHeuristic) -> u16 {
+    let distances = &heuristic.distances;
+    let frozen_boxes = &heuristic.frozen_boxes;
+    let frozen_goals = &heuristic.frozen_goals;
+    let goal_count = game.goal_positions().len();
+    if game.box_count() > goal_count {
+        return u16::MAX;
+    }
+    let unfrozen_count = game.box_count() - frozen_goals.len();
+    let unfrozen_goal_count = goal_count - frozen_goals.len();
 
     // Somewhat arbitrarily set threshold at which to switch from O(n^3) to
     // O(n^2) algorithm
-    if unfrozen_count > MAX_HUNGARIAN_BOXES {
+    if unfrozen_goal_count > MAX_HUNGARIAN_BOXES {
         return compute_simple_heuristic(game, distances);
     }
 
-    // Build cost matrix: cost[i][j] = distance from unfrozen box i to unfrozen goal j
-    let mut cost_matrix =
-        ArrayMatrix::<u16, { MAX_BOXES * MAX_BOXES }>::new(unfrozen_count, unfrozen_count);
+    // Build cost matrix: cost[i][j] = distance from unfrozen box i to unfrozen
+    // goal j, infinite if the box doesn't fit the goal. Surplus goals are
+    // matched to dummy boxes at no cost, keeping the matrix square.
+    let mut cost_matrix = ArrayMatrix::<u16, { MAX_BOXES * MAX_BOXES }>::new(
+        unfrozen_goal_count,
+        unfrozen_goal_count,
+    );
+    let mut box_positions = ArrayVec::<Position, MAX_BOXES>::new();
 
-    for &box_pos in game.box_positions().iter() {
+    let labelled = game.is_labelled();
+    for (box_idx, &box_pos) in game.box_positions().iter().enumerate() {
         // Skip frozen boxes
         if frozen_boxes.get(box_pos) {
             continue;
         }
+        box_positions.push(box_pos);
+        let square = box_square(game, box_pos);
 
         #[allow(clippy::needless_range_loop)]
-        for goal_idx in 0..box_count {
+        for goal_idx in 0..goal_count {
             // Skip frozen goals
             if frozen_goals.contains(Index(goal_idx as u8)) {
                 continue;
             }
+            if labelled && !game.box_fits_goal(Index(box_idx as u8), goal_idx) {
+                cost_matrix.push(u16::MAX);
+                continue;
+            }
 
-            let distance = distances[goal_idx][box_pos.1 as usize][box_pos.0 as usize];
+            let distance = match &heuristic.rooms {
+                Some(rooms) => rooms.distance(game, distances, goal_idx, box_pos),
+                None => distances[goal_idx][square],
+            };
             cost_matrix.push(distance);
         }
     }
+    for _ in unfrozen_count * unfrozen_goal_count..unfrozen_goal_count * unfrozen_goal_count {
+        cost_matrix.push(0);
+    }
 
-    // Call Hungarian algorithm to find optimal matching
-    hungarian_algorithm(&cost_matrix)
+    // Call Hungarian algorithm to find optimal matching, reassigning only the
+    // boxes which moved since the last position if possible
+    let cache = &mut *heuristic.cache.borrow_mut();
+    let cached = match cache.take() {
+        Some(mut cached) => {
+            let moved_rows: ArrayVec<usize, MAX_BOXES> = (0..unfrozen_count)
+                .filter(|&row| cached.box_positions[row] != box_positions[row])
+                .collect();
+            cached.assignment.reassign(&cost_matrix, &moved_rows);
+            cached.box_positions = box_positions;
+            cached
+        }
+        None => CachedAssignment {
+            box_positions,
+            assignment: Assignment::solve(&cost_matrix),
+        },
+    };
+    let cached = cache.insert(cached);
+    let cost = cached.assignment.cost(&cost_matrix);
+
+    if let Some(rooms) = &heuristic.rooms
+        && cost != u16::MAX
+    {
+        cost.saturating_add(rooms.cost(game))
+    } else if heuristic.linear_conflicts {
+        let goal_positions: ArrayVec<Position, MAX_BOXES> = game
+            .goal_positions()
+            .iter()
+            .enumerate()
+            .filter(|&(goal_idx, _)| !frozen_goals.contains(Index(goal_idx as u8)))
+            .map(|(_, &goal_pos)| goal_pos)
+            .collect();
+        add_linear_conflict(
+            &cost_matrix,
+            &cached.assignment,
+            &cached.box_positions,
+            &goal_positions,
+            cost,
+        )
+    } else {
+        cost
+    }
 }
 
-/// Compute push distances from each goal to all positions using BFS with pulls
-fn compute_push_distances(
-    game: &Game,
-    frozen_boxes: &Bitvector,
-) -> [[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES] {
-    let mut distances = [[[u16::MAX; MAX_SIZE]; MAX_SIZE]; MAX_BOXES];
+/// Add the penalty for a linear conflict within an optimal matching of the
+/// given cost. Matchings without either pair of the conflict don't pay it,
+/// so the result is capped by the cheapest of those to stay admissible.
+fn add_linear_conflict(
+    cost_matrix: &ArrayMatrix<u16, { MAX_BOXES * MAX_BOXES }>,
+    assignment: &Assignment,
+    box_positions: &[Position],
+    goal_positions: &[Position],
+    cost: u16,
+) -> u16 {
+    if cost == u16::MAX {
+        return cost;
+    }
 
-    for (goal_idx, &goal_pos) in game.goal_positions().iter().enumerate() {
-        bfs_pulls(game, goal_pos, frozen_boxes, &mut distances[goal_idx]);
+    // Skip the dummy boxes matched to surplus goals
+    let pairs: ArrayVec<(usize, usize), MAX_BOXES> = assignment
+        .pairs()
+        .filter(|&(row, _)| row < box_positions.len())
+        .collect();
+    for (i, &(box1, goal1)) in pairs.iter().enumerate() {
+        for &(box2, goal2) in &pairs[i + 1..] {
+            let first = (box_positions[box1], goal_positions[goal1]);
+            let second = (box_positions[box2], goal_positions[goal2]);
+            let distances = (cost_matrix.get(box1, goal1), cost_matrix.get(box2, goal2));
+            if !is_linear_conflict(first, second, distances) {
+                continue;
+            }
+
+            let cost_without = |row: usize, col: usize| {
+                let mut cost_matrix = cost_matrix.clone();
+                cost_matrix.set(row, col, u16::MAX);
+                let mut assignment = assignment.clone();
+                assignment.reassign(&cost_matrix, &[row]);
+                assignment.cost(&cost_matrix)
+            };
+            return cost
+                .saturating_add(2)
+                .min(cost_without(box1, goal1))
+                .min(cost_without(box2, goal2));
+        }
     }
 
-    distances
+    cost
 }
 
-/// Compute pull distances from each goal to all positions using BFS with pushes
-fn compute_pull_distances(
+/// Whether two boxes, each given with its matched goal, are in a linear
+/// conflict given their distances to those goals.
+fn is_linear_conflict(
+    (box1, goal1): (Position, Position),
+    (box2, goal2): (Position, Position),
+    (distance1, distance2): (u16, u16),
+) -> bool {
+    if box1.manhattan(box2) != 1 || (distance1 == 0 && distance2 == 0) {
+        return false;
+    }
+
+    // A box is stuck if moving it at all costs extra pushes: it is on its
+    // goal, or its only shortest paths are straight and the other box blocks
+    // the first push
+    let is_stuck = |pos: Position, goal: Position, distance: u16, other: Position| {
+        if distance == 0 {
+            return true;
+        }
+        let straight =
+            (pos.0 == goal.0 || pos.1 == goal.1) && pos.manhattan(goal) == distance as usize;
+        let Some(direction) = ALL_DIRECTIONS.into_iter().find(|&direction| {
+            pos.offset(direction)
+                .is_some_and(|next| next.manhattan(goal) < pos.manhattan(goal))
+        }) else {
+            return false;
+        };
+        straight
+            && (pos.offset(direction) == Some(other)
+                || pos.offset(direction.reverse()) == Some(other))
+    };
+    is_stuck(box1, goal1, distance1, box2) && is_stuck(box2, goal2, distance2, box1)
+}
+
+/// Distances from a single goal: goal_distances[square] = minimum
+/// pushes/pulls to get a box from the square (see `Game::square_index`) to
+/// the goal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoalDistances {
+    distances: Box<[u16]>,
+}
+
+impl GoalDistances {
+    /// A table with no square reaching the goal.
+    fn new(game: &Game) -> Self {
+        GoalDistances {
+            distances: vec![u16::MAX; game.square_count()].into_boxed_slice(),
+        }
+    }
+
+    /// The distance from the given square, `u16::MAX` for a wall or a square
+    /// from which the goal can't be reached.
+    pub fn get(&self, game: &Game, pos: Position) -> u16 {
+        game.square_index(pos)
+            .map_or(u16::MAX, |square| self.distances[square])
+    }
+
+    fn set(&mut self, game: &Game, pos: Position, distance: u16) {
+        let square = game.square_index(pos).expect("distances are set on floor");
+        self.distances[square] = distance;
+    }
+}
+
+impl std::ops::Index<usize> for GoalDistances {
+    type Output = u16;
+
+    fn index(&self, square: usize) -> &u16 {
+        &self.distances[square]
+    }
+}
+
+/// Push or pull distances from each goal to all positions, indexed by goal.
+/// The table of each goal is shared between the distances computed for
+/// different sets of frozen boxes whenever the frozen boxes don't affect it,
+/// so only the tables which differ from those without frozen boxes are
+/// computed and stored for each set.
+#[derive(Clone)]
+pub struct Distances {
+    goals: ArrayVec<Rc<GoalDistances>, MAX_BOXES>,
+}
+
+impl Distances {
+    fn new(game: &Game, frozen_boxes: &Bitvector, pulls: bool) -> Self {
+        let base = base_distances(game, pulls);
+        if frozen_boxes.is_empty() {
+            return base;
+        }
+
+        let frozen_positions: ArrayVec<Position, MAX_BOXES> = frozen_boxes
+            .iter()
+            .map(|box_idx| game.box_position(box_idx))
+            .collect();
+        let mut distances = base;
+        let affected: ArrayVec<usize, MAX_BOXES> = (0..distances.goals.len())
+            .filter(|&goal_idx| {
+                frozen_positions
+                    .iter()
+                    .any(|&pos| is_affected(game, &distances.goals[goal_idx], pos))
+            })
+            .collect();
+        let goal_positions: ArrayVec<Position, MAX_BOXES> = affected
+            .iter()
+            .map(|&goal_idx| game.goal_positions()[goal_idx])
+            .collect();
+        let tables = bfs_goals(game, &goal_positions, frozen_boxes, pulls);
+        for (goal_idx, table) in affected.into_iter().zip(tables) {
+            distances.goals[goal_idx] = Rc::new(table);
+        }
+        distances
+    }
+
+    /// Iterate over the distances from each goal.
+    pub fn iter(&self) -> impl Iterator<Item = &GoalDistances> {
+        self.goals.iter().map(|goal| &**goal)
+    }
+}
+
+impl std::ops::Index<usize> for Distances {
+    type Output = GoalDistances;
+
+    fn index(&self, goal_idx: usize) -> &GoalDistances {
+        &self.goals[goal_idx]
+    }
+}
+
+/// The index of the square of a box, or of another square known to be floor.
+fn box_square(game: &Game, pos: Position) -> usize {
+    game.square_index(pos).expect("boxes are on floor")
+}
+
+/// Fewest goals whose distances are worth computing on the thread pool.
+const MIN_PARALLEL_GOALS: usize = 4;
+
+/// Compute the distances from each of the given goals, on the thread pool if
+/// there are enough of them.
+fn bfs_goals(
     game: &Game,
+    goal_positions: &[Position],
     frozen_boxes: &Bitvector,
-) -> [[[u16; MAX_SIZE]; MAX_SIZE]; MAX_BOXES] {
-    let mut distances = [[[u16::MAX; MAX_SIZE]; MAX_SIZE]; MAX_BOXES];
+    pulls: bool,
+) -> Vec<GoalDistances> {
+    let bfs = |&goal_pos: &Position| bfs_goal(game, goal_pos, frozen_boxes, pulls);
+    if goal_positions.len() >= MIN_PARALLEL_GOALS && rayon::current_num_threads() > 1 {
+        goal_positions.par_iter().map(bfs).collect()
+    } else {
+        goal_positions.iter().map(bfs).collect()
+    }
+}
 
-    for (goal_idx, &goal_pos) in game.goal_positions().iter().enumerate() {
-        bfs_pushes(game, goal_pos, frozen_boxes, &mut distances[goal_idx]);
+/// Compute the distances from a goal with a BFS using pulls (for push
+/// distances) or pushes (for pull distances).
+fn bfs_goal(
+    game: &Game,
+    goal_pos: Position,
+    frozen_boxes: &Bitvector,
+    pulls: bool,
+) -> GoalDistances {
+    // Each thread reuses its queue across searches
+    thread_local! {
+        static QUEUE: RefCell<VecDeque<Position>> = const { RefCell::new(VecDeque::new()) };
     }
 
+    let mut distances = GoalDistances::new(game);
+    QUEUE.with_borrow_mut(|queue| {
+        queue.clear();
+        if pulls {
+            bfs_pushes(game, goal_pos, frozen_boxes, &mut distances, queue);
+        } else {
+            bfs_pulls(game, goal_pos, frozen_boxes, &mut distances, queue);
+        }
+    });
     distances
 }
 
+/// Whether a frozen box at the given position can change the distances from
+/// a goal. The BFS only ever places a box or the player within two squares in
+/// a straight line of a square it reached, so a box further than that from
+/// every reached square is never in its way.
+fn is_affected(game: &Game, distances: &GoalDistances, pos: Position) -> bool {
+    let reached = |pos: Position| distances.get(game, pos) != u16::MAX;
+    reached(pos)
+        || ALL_DIRECTIONS.iter().any(|&dir| {
+            game.move_position(pos, dir).is_some_and(|next| {
+                reached(next) || game.move_position(next, dir).is_some_and(reached)
+            })
+        })
+}
+
+/// The distances without frozen boxes. Those of the last game seen by the
+/// thread in each direction are kept, since distances are computed for every
+/// set of frozen boxes met during the search.
+fn base_distances(game: &Game, pulls: bool) -> Distances {
+    thread_local! {
+        static LAST_DISTANCES: RefCell<[Option<CachedDistances>; 2]> =
+            const { RefCell::new([None, None]) };
+    }
+
+    LAST_DISTANCES.with_borrow_mut(|last| {
+        let last = &mut last[usize::from(pulls)];
+        if let Some((last_game, distances)) = last
+            && last_game.same_layout(game)
+        {
+            return distances.clone();
+        }
+        let distances = Distances {
+            goals: bfs_goals(game, game.goal_positions(), &Bitvector::new(), pulls)
+                .into_iter()
+                .map(Rc::new)
+                .collect(),
+        };
+        *last = Some((game.clone(), distances.clone()));
+        distances
+    })
+}
+
+type CachedDistances = (Game, Distances);
+
+/// Compute push distances from each goal to all positions using BFS with pulls
+pub fn compute_push_distances(game: &Game, frozen_boxes: &Bitvector) -> Distances {
+    Distances::new(game, frozen_boxes, false)
+}
+
+/// Compute the minimum pushes to get a box from each position to its nearest
+/// goal, ignoring all other boxes.
+pub fn compute_min_push_distances(game: &Game) -> GoalDistances {
+    let distances = compute_push_distances(game, &Bitvector::new());
+    let mut min_distances = GoalDistances::new(game);
+    for goal_distances in distances.iter() {
+        for (min, &distance) in min_distances
+            .distances
+            .iter_mut()
+            .zip(&goal_distances.distances)
+        {
+            *min = (*min).min(distance);
+        }
+    }
+    min_distances
+}
+
+/// Compute pull distances from each goal to all positions using BFS with pushes
+pub fn compute_pull_distances(game: &Game, frozen_boxes: &Bitvector) -> Distances {
+    Distances::new(game, frozen_boxes, true)
+}
+
 /// BFS using pulls to compute distances from a goal position
 fn bfs_pulls(
     game: &Game,
     goal_pos: Position,
     frozen_boxes: &Bitvector,
-    distances: &mut [[u16; MAX_SIZE]; MAX_SIZE],
+    distances: &mut GoalDistances,
+    queue: &mut VecDeque<Position>,
 ) {
-    distances[goal_pos.1 as usize][goal_pos.0 as usize] = 0;
+    distances.set(game, goal_pos, 0);
 
     // Check if this goal is frozen
-    if let Some(box_idx) = game.box_index(goal_pos) {
-        if frozen_boxes.contains(box_idx) {
-            return;
-        }
+    if let Some(box_idx) = game.box_index(goal_pos)
+        && frozen_boxes.contains(box_idx)
+    {
+        return;
     }
 
-    let mut queue = VecDeque::new();
     queue.push_back(goal_pos);
 
     while let Some(box_pos) = queue.pop_front() {
-        let dist = distances[box_pos.1 as usize][box_pos.0 as usize];
+        let dist = distances.get(game, box_pos);
 
         for direction in ALL_DIRECTIONS {
-            if let Some(new_box_pos) = game.move_position(box_pos, direction.reverse()) {
-                if let Some(player_pos) = game.move_position(new_box_pos, direction.reverse()) {
-                    let new_box_tile = game.get_tile(new_box_pos);
-                    let player_tile = game.get_tile(player_pos);
-
-                    // Check if new_box_pos or player_pos has a frozen box
-                    let new_box_frozen = game
-                        .box_index(new_box_pos)
-                        .is_some_and(|idx| frozen_boxes.contains(idx));
-                    let player_frozen = game
-                        .box_index(player_pos)
-                        .is_some_and(|idx| frozen_boxes.contains(idx));
-
-                    if (new_box_tile == Tile::Floor || new_box_tile == Tile::Goal)
-                        && (player_tile == Tile::Floor || player_tile == Tile::Goal)
-                        && !new_box_frozen
-                        && !player_frozen
-                        && distances[new_box_pos.1 as usize][new_box_pos.0 as usize] == u16::MAX
-                    {
-                        distances[new_box_pos.1 as usize][new_box_pos.0 as usize] = dist + 1;
-                        queue.push_back(new_box_pos);
-                    }
+            if let Some(new_box_pos) = game.move_position(box_pos, direction.reverse())
+                && let Some(player_pos) = game.move_position(new_box_pos, direction.reverse())
+            {
+                let new_box_tile = game.get_tile(new_box_pos);
+                let player_tile = game.get_tile(player_pos);
+
+                // Check if new_box_pos or player_pos has a frozen box
+                let new_box_frozen = game
+                    .box_index(new_box_pos)
+                    .is_some_and(|idx| frozen_boxes.contains(idx));
+                let player_frozen = game
+                    .box_index(player_pos)
+                    .is_some_and(|idx| frozen_boxes.contains(idx));
+
+                if (new_box_tile == Tile::Floor || new_box_tile == Tile::Goal)
+                    && (player_tile == Tile::Floor || player_tile == Tile::Goal)
+                    && !new_box_frozen
+                    && !player_frozen
+                    && distances.get(game, new_box_pos) == u16::MAX
+                {
+                    distances.set(game, new_box_pos, dist + 1);
+                    queue.push_back(new_box_pos);
                 }
             }
         }
@@ -411,38 +1157,46 @@ fn bfs_pushes(
     game: &Game,
     start_pos: Position,
     frozen_boxes: &Bitvector,
-    distances: &mut [[u16; MAX_SIZE]; MAX_SIZE],
+    distances: &mut GoalDistances,
+    queue: &mut VecDeque<Position>,
 ) {
-    let mut queue = VecDeque::new();
+    distances.set(game, start_pos, 0);
+
+    // Check if this start position is frozen
+    if let Some(box_idx) = game.box_index(start_pos)
+        && frozen_boxes.contains(box_idx)
+    {
+        return;
+    }
+
     queue.push_back(start_pos);
-    distances[start_pos.1 as usize][start_pos.0 as usize] = 0;
 
     while let Some(box_pos) = queue.pop_front() {
-        let dist = distances[box_pos.1 as usize][box_pos.0 as usize];
+        let dist = distances.get(game, box_pos);
 
         for direction in ALL_DIRECTIONS {
-            if let Some(new_box_pos) = game.move_position(box_pos, direction) {
-                if let Some(player_pos) = game.move_position(box_pos, direction.reverse()) {
-                    let new_box_tile = game.get_tile(new_box_pos);
-                    let player_tile = game.get_tile(player_pos);
-
-                    // Check if new_box_pos or player_pos has a frozen box
-                    let new_box_frozen = game
-                        .box_index(new_box_pos)
-                        .is_some_and(|idx| frozen_boxes.contains(idx));
-                    let player_frozen = game
-                        .box_index(player_pos)
-                        .is_some_and(|idx| frozen_boxes.contains(idx));
-
-                    if (new_box_tile == Tile::Floor || new_box_tile == Tile::Goal)
-                        && (player_tile == Tile::Floor || player_tile == Tile::Goal)
-                        && !new_box_frozen
-                        && !player_frozen
-                        && distances[new_box_pos.1 as usize][new_box_pos.0 as usize] == u16::MAX
-                    {
-                        distances[new_box_pos.1 as usize][new_box_pos.0 as usize] = dist + 1;
-                        queue.push_back(new_box_pos);
-                    }
+            if let Some(new_box_pos) = game.move_position(box_pos, direction)
+                && let Some(player_pos) = game.move_position(box_pos, direction.reverse())
+            {
+                let new_box_tile = game.get_tile(new_box_pos);
+                let player_tile = game.get_tile(player_pos);
+
+                // Check if new_box_pos or player_pos has a frozen box
+                let new_box_frozen = game
+                    .box_index(new_box_pos)
+                    .is_some_and(|idx| frozen_boxes.contains(idx));
+                let player_frozen = game
+                    .box_index(player_pos)
+                    .is_some_and(|idx| frozen_boxes.contains(idx));
+
+                if (new_box_tile == Tile::Floor || new_box_tile == Tile::Goal)
+                    && (player_tile == Tile::Floor || player_tile == Tile::Goal)
+                    && !new_box_frozen
+                    && !player_frozen
+                    && distances.get(game, new_box_pos) == u16::MAX
+                {
+                    distances.set(game, new_box_pos, dist + 1);
+                    queue.push_back(new_box_pos);
                 }
             }
         }
@@ -525,7 +1279,7 @@ mod tests {
                      #@*#\n\
                      ####";
         let game = Game::from_text(input).unwrap();
-        let heuristic = SimpleHeuristic::new_push(&game, Bitvector::new());
+        let heuristic = SimpleHeuristic::new_push(&game, Bitvector::new(), &());
 
         assert_eq!(heuristic.compute(&game), Cost(0));
     }
@@ -536,7 +1290,7 @@ mod tests {
                      #@$.#\n\
                      ####";
         let game = Game::from_text(input).unwrap();
-        let heuristic = SimpleHeuristic::new_push(&game, Bitvector::new());
+        let heuristic = SimpleHeuristic::new_push(&game, Bitvector::new(), &());
 
         // Box at (2,1), goal at (3,1), push distance = 1
         assert_eq!(heuristic.compute(&game), Cost(1));
@@ -551,13 +1305,292 @@ mod tests {
                      #  @ #\n\
                      ######";
         let game = Game::from_text(input).unwrap();
-        let heuristic = SimpleHeuristic::new_push(&game, Bitvector::new());
+        let heuristic = SimpleHeuristic::new_push(&game, Bitvector::new(), &());
 
         // Two boxes at (2,2) and (3,2), two goals at (2,3) and (3,3)
         // Simple matching should pair them optimally: each box is 1 away from a goal
         assert_eq!(heuristic.compute(&game), Cost(2));
     }
 
+    #[test]
+    fn test_frozen_boxes_as_walls() {
+        let input = "########\n\
+                     #      #\n\
+                     #      #\n\
+                     #@$ *. #\n\
+                     #      #\n\
+                     #      #\n\
+                     ########";
+        let game = Game::from_text(input).unwrap();
+        let mut frozen_boxes = Bitvector::new();
+        frozen_boxes.add(game.box_index(Position(4, 3)).unwrap());
+
+        // The box can only reach the far goal by going around the packed box
+        let unfrozen = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+        let frozen = HungarianHeuristic::new_push(&game, frozen_boxes, &());
+        assert_eq!(unfrozen.compute(&game), Cost(3));
+        assert_eq!(frozen.compute(&game), Cost(5));
+        let frozen = SimpleHeuristic::new_push(&game, frozen_boxes, &());
+        assert_eq!(frozen.compute(&game), Cost(5));
+    }
+
+    #[test]
+    fn test_frozen_boxes_as_walls_pull() {
+        // In reverse, the packed box sits on the start square of the boxes
+        let input = "########\n\
+                     #      #\n\
+                     #      #\n\
+                     #@. *$ #\n\
+                     #      #\n\
+                     #      #\n\
+                     ########";
+        let game = Game::from_text(input).unwrap().swap_boxes_and_goals();
+        let mut frozen_boxes = Bitvector::new();
+        frozen_boxes.add(game.box_index(Position(4, 3)).unwrap());
+
+        let unfrozen = HungarianHeuristic::new_pull(&game, Bitvector::new(), &());
+        let frozen = HungarianHeuristic::new_pull(&game, frozen_boxes, &());
+        assert_eq!(unfrozen.compute(&game), Cost(3));
+        assert_eq!(frozen.compute(&game), Cost(5));
+        let frozen = SimpleHeuristic::new_pull(&game, frozen_boxes, &());
+        assert_eq!(frozen.compute(&game), Cost(5));
+    }
+
+    #[test]
+    fn test_hungarian_heuristic_incremental() {
+        let input = "#######\n\
+                     #.  . #\n\
+                     # $$$ #\n\
+                     #  @  #\n\
+                     #.    #\n\
+                     #######";
+        let mut game = Game::from_text(input).unwrap();
+        let heuristic = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+        let mut rng = ChaCha8Rng::seed_from_u64(12345);
+
+        // Walk randomly, checking the patched matching against a fresh one
+        for _ in 0..50 {
+            let pushes = game.compute_pushes().moves;
+            if pushes.is_empty() {
+                break;
+            }
+            let push = pushes.iter().nth(rng.gen_range(0..pushes.len())).unwrap();
+            game.push(push);
+            let fresh = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+            assert_eq!(heuristic.compute(&game), fresh.compute(&game));
+        }
+    }
+
+    #[test]
+    fn test_surplus_goals() {
+        // Either box can take the near goal, leaving the far ones empty
+        let game = Game::from_text_unbalanced(
+            "########\n\
+             #.  $ .#\n\
+             #  @$ .#\n\
+             #.     #\n\
+             ########",
+        )
+        .unwrap();
+        assert_eq!(
+            SimpleHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(4)
+        );
+        assert_eq!(
+            GreedyHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(4)
+        );
+        let hungarian = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+        assert_eq!(hungarian.compute(&game), Cost(4));
+        let conflicts = LinearConflictHeuristic::new_push(&game, Bitvector::new(), &());
+        assert_eq!(conflicts.compute(&game), Cost(4));
+
+        // Boxes outnumbering goals can never all be put on them
+        let game = Game::from_text_unbalanced("######\n#@$$.#\n######").unwrap();
+        assert_eq!(
+            HungarianHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(u16::MAX)
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        // Each box has to cross over to the far goal
+        let mut game = Game::from_text(
+            "#########\n\
+             #       #\n\
+             #       #\n\
+             # .$@$. #\n\
+             #       #\n\
+             #       #\n\
+             #########",
+        )
+        .unwrap();
+        assert_eq!(
+            HungarianHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(2)
+        );
+        game.set_labels(&[1, 2], &[2, 1]).unwrap();
+        assert_eq!(
+            SimpleHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(6)
+        );
+        assert_eq!(
+            RefinedGreedyHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(6)
+        );
+        assert_eq!(
+            HungarianHeuristic::new_push(&game, Bitvector::new(), &()).compute(&game),
+            Cost(6)
+        );
+    }
+
+    #[test]
+    fn test_linear_conflict_heuristic() {
+        let input = "#######\n\
+                     #     #\n\
+                     #     #\n\
+                     # .$* #\n\
+                     #  @  #\n\
+                     #     #\n\
+                     #######";
+        let game = Game::from_text(input).unwrap();
+
+        // The packed box must step aside to let the other box past
+        let hungarian = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+        let linear_conflict = LinearConflictHeuristic::new_push(&game, Bitvector::new(), &());
+        assert_eq!(hungarian.compute(&game), Cost(1));
+        assert_eq!(linear_conflict.compute(&game), Cost(3));
+
+        // Without the blocking box there is no conflict
+        let input = "#######\n\
+                     #     #\n\
+                     #     #\n\
+                     # .$ *#\n\
+                     #  @  #\n\
+                     #     #\n\
+                     #######";
+        let game = Game::from_text(input).unwrap();
+        let linear_conflict = LinearConflictHeuristic::new_push(&game, Bitvector::new(), &());
+        assert_eq!(linear_conflict.compute(&game), Cost(1));
+    }
+
+    #[test]
+    fn test_refined_greedy_heuristic() {
+        let input = "#########\n\
+                     #       #\n\
+                     #  .$.  #\n\
+                     #       #\n\
+                     #  $    #\n\
+                     #   @   #\n\
+                     #########";
+        let game = Game::from_text(input).unwrap();
+
+        // Greedy matches the middle box to the left goal first, leaving the
+        // lower box the long way round to the right goal
+        let greedy = GreedyHeuristic::new_push(&game, Bitvector::new(), &());
+        let refined = RefinedGreedyHeuristic::new_push(&game, Bitvector::new(), &());
+        let hungarian = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+        assert_eq!(greedy.compute(&game), Cost(5));
+        assert_eq!(refined.compute(&game), Cost(3));
+        assert_eq!(hungarian.compute(&game), Cost(3));
+    }
+
+    #[test]
+    fn test_is_linear_conflict() {
+        // Boxes moving away from each other need each other's squares
+        let first = (Position(3, 3), Position(2, 3));
+        let second = (Position(4, 3), Position(5, 3));
+        assert!(is_linear_conflict(first, second, (1, 1)));
+        // Boxes on different rows don't get in each other's way
+        let second = (Position(3, 4), Position(4, 4));
+        assert!(!is_linear_conflict(first, second, (1, 1)));
+        // Only a straight path is blocked
+        let second = (Position(4, 3), Position(4, 3));
+        assert!(is_linear_conflict(first, second, (1, 0)));
+        assert!(!is_linear_conflict(first, second, (3, 0)));
+        // Both boxes on their goals is no conflict at all
+        assert!(!is_linear_conflict(
+            (Position(3, 3), Position(3, 3)),
+            second,
+            (0, 0)
+        ));
+    }
+
+    #[test]
+    fn test_goal_room_heuristic() {
+        let input = "##########\n\
+                     #@       #\n\
+                     #  $$$   #\n\
+                     #        #\n\
+                     ##### ####\n  \
+                       #      #\n  \
+                       # ...  #\n  \
+                       #      #\n  \
+                       ########";
+        let game = Game::from_text(input).unwrap();
+
+        // The pattern database sees that a box has to go around the others
+        // once inside the room, which makes the estimate exact here
+        let hungarian = HungarianHeuristic::new_push(&game, Bitvector::new(), &());
+        let goal_room = GoalRoomHeuristic::new_push(&game, Bitvector::new(), &());
+        assert_eq!(hungarian.compute(&game), Cost(17));
+        assert_eq!(goal_room.compute(&game), Cost(19));
+
+        // Goal rooms are only used when pushing
+        let goal_room = GoalRoomHeuristic::new_pull(&game, Bitvector::new(), &());
+        let hungarian = HungarianHeuristic::new_pull(&game, Bitvector::new(), &());
+        assert_eq!(goal_room.compute(&game), hungarian.compute(&game));
+    }
+
+    #[test]
+    fn test_max_heuristic() {
+        let input = "#######\n\
+                     #     #\n\
+                     #     #\n\
+                     # .$* #\n\
+                     #  @  #\n\
+                     #     #\n\
+                     #######";
+        let game = Game::from_text(input).unwrap();
+
+        let heuristic = MaxHeuristic::<NullHeuristic, LinearConflictHeuristic>::new_push(
+            &game,
+            Bitvector::new(),
+            &((), ()),
+        );
+        assert_eq!(heuristic.compute(&game), Cost(3));
+        let heuristic = MaxHeuristic::<HungarianHeuristic, NullHeuristic>::new_push(
+            &game,
+            Bitvector::new(),
+            &((), ()),
+        );
+        assert_eq!(heuristic.compute(&game), Cost(1));
+    }
+
+    #[test]
+    fn test_max_list_heuristic() {
+        let input = "#######\n\
+                     #     #\n\
+                     #     #\n\
+                     # .$* #\n\
+                     #  @  #\n\
+                     #     #\n\
+                     #######";
+        let game = Game::from_text(input).unwrap();
+
+        let kinds = vec![HeuristicKind::Hungarian, HeuristicKind::LinearConflict];
+        let heuristic = MaxListHeuristic::new_push(&game, Bitvector::new(), &kinds);
+        assert_eq!(heuristic.compute(&game), Cost(3));
+        let hungarian = vec![HeuristicKind::Hungarian];
+        let heuristic = MaxListHeuristic::new_push(&game, Bitvector::new(), &hungarian);
+        assert_eq!(heuristic.compute(&game), Cost(1));
+
+        // An empty list combines nothing
+        let heuristic = MaxListHeuristic::new_push(&game, Bitvector::new(), &Vec::new());
+        assert_eq!(heuristic.compute(&game), Cost(0));
+    }
+
     #[test]
     fn test_counting_sort_random() {
         let mut rng = ChaCha8Rng::seed_from_u64(12345);
@@ -570,4 +1603,87 @@ mod tests {
             assert!(data.is_sorted(), "Array not sorted: {:?}", data);
         }
     }
+
+    #[test]
+    fn test_shared_distances() {
+        let game = Game::from_text(
+            r#"
+#########
+#.  #  *#
+# $ #   #
+#@  #   #
+#########
+"#,
+        )
+        .unwrap();
+        let frozen_boxes = {
+            let mut frozen_boxes = Bitvector::new();
+            frozen_boxes.add(game.box_index(Position(7, 2)).unwrap());
+            frozen_boxes
+        };
+
+        for pulls in [false, true] {
+            let base = Distances::new(&game, &Bitvector::new(), pulls);
+            let distances = Distances::new(&game, &frozen_boxes, pulls);
+
+            // Every table matches a search from scratch
+            for (goal_idx, &goal_pos) in game.goal_positions().iter().enumerate() {
+                assert_eq!(
+                    distances[goal_idx],
+                    bfs_goal(&game, goal_pos, &frozen_boxes, pulls)
+                );
+            }
+
+            // The goal on the left is out of reach of the frozen box, so its
+            // table is shared, while the frozen goal's is recomputed
+            let left = game
+                .goal_positions()
+                .iter()
+                .position(|&pos| pos == Position(1, 2));
+            let right = game
+                .goal_positions()
+                .iter()
+                .position(|&pos| pos == Position(7, 2));
+            assert!(Rc::ptr_eq(
+                &base.goals[left.unwrap()],
+                &distances.goals[left.unwrap()]
+            ));
+            assert!(!Rc::ptr_eq(
+                &base.goals[right.unwrap()],
+                &distances.goals[right.unwrap()]
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parallel_distances() {
+        let game = Game::from_text(
+            r#"
+##########
+#..  $   #
+#..  $ $ #
+# @  $   #
+##########
+"#,
+        )
+        .unwrap();
+        let goal_positions = game.goal_positions();
+        assert!(goal_positions.len() >= MIN_PARALLEL_GOALS);
+
+        // The searches give the same tables on the thread pool as on this
+        // thread
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        for pulls in [false, true] {
+            let serial: Vec<_> = goal_positions
+                .iter()
+                .map(|&goal_pos| bfs_goal(&game, goal_pos, &Bitvector::new(), pulls))
+                .collect();
+            let parallel =
+                pool.install(|| bfs_goals(&game, goal_positions, &Bitvector::new(), pulls));
+            assert_eq!(parallel, serial);
+        }
+    }
 }
@@ -17,6 +17,7 @@ impl<T: Copy, const N: usize, const M: usize> Matrix<T> for [[T; M]; N] {
     }
 }
 
+#[derive(Clone)]
 pub struct ArrayMatrix<T, const CAP: usize> {
     data: ArrayVec<T, CAP>,
     rows: usize,
@@ -36,6 +37,11 @@ impl<T: Copy, const CAP: usize> ArrayMatrix<T, CAP> {
         debug_assert!(self.data.len() < self.rows * self.cols);
         self.data.push(item);
     }
+
+    pub fn set(&mut self, row: usize, col: usize, item: T) {
+        debug_assert!(row < self.rows && col < self.cols);
+        self.data[row * self.cols + col] = item;
+    }
 }
 
 impl<T: Copy, const CAP: usize> Matrix<T> for ArrayMatrix<T, CAP> {
@@ -51,18 +57,95 @@ impl<T: Copy, const CAP: usize> Matrix<T> for ArrayMatrix<T, CAP> {
 
 // Reference: Andrey Lopatin (https://cp-algorithms.com/graph/hungarian-algorithm.html).
 pub fn hungarian_algorithm(a: &impl Matrix<u16>) -> u16 {
-    const INF: i32 = u16::MAX as i32 + 1;
-
-    let (n, m) = a.shape();
-    assert!(n == m);
+    Assignment::solve(a).cost(a)
+}
 
+/// A minimum cost assignment found by the Hungarian algorithm, along with the
+/// dual potentials which prove it optimal. Keeping these around allows the
+/// assignment to be patched when only a few rows of the cost matrix change,
+/// re-running augmentation for just those rows in O(n^2) each rather than
+/// solving from scratch in O(n^3).
+#[derive(Clone)]
+pub struct Assignment {
+    n: usize,
     // 1-indexed arrays with dummy 0 element
-    let mut u = new_buffer::<i32>(n, 0);
-    let mut v = new_buffer::<i32>(m, 0);
-    let mut p = new_buffer::<usize>(m, 0);
-    let mut way = new_buffer::<usize>(m, 0);
+    u: ArrayVec<i32, { MAX_BOXES + 1 }>,
+    v: ArrayVec<i32, { MAX_BOXES + 1 }>,
+    /// The row assigned to each column, or 0 if none.
+    p: ArrayVec<usize, { MAX_BOXES + 1 }>,
+}
+
+impl Assignment {
+    /// Solve the assignment problem for the given square cost matrix.
+    pub fn solve(a: &impl Matrix<u16>) -> Self {
+        let (n, m) = a.shape();
+        assert!(n == m);
+
+        let mut assignment = Assignment {
+            n,
+            u: new_buffer(n, 0),
+            v: new_buffer(n, 0),
+            p: new_buffer(n, 0),
+        };
+        for i in 1..=n {
+            assignment.augment(a, i);
+        }
+        assignment
+    }
+
+    /// Patch the assignment after the given rows (0-indexed) of the cost
+    /// matrix changed. All other rows must be the same as when the
+    /// assignment was last solved or patched.
+    pub fn reassign(&mut self, a: &impl Matrix<u16>, rows: &[usize]) {
+        assert!(a.shape() == (self.n, self.n));
+
+        for &row in rows {
+            if let Some(j) = (1..=self.n).find(|&j| self.p[j] == row + 1) {
+                self.p[j] = 0;
+            }
+        }
+        // Lower the potentials of the changed rows until they are feasible
+        // for the new costs
+        for &row in rows {
+            self.u[row + 1] = (1..=self.n)
+                .map(|j| a.get(row, j - 1) as i32 - self.v[j])
+                .min()
+                .unwrap_or(0);
+        }
+        for &row in rows {
+            self.augment(a, row + 1);
+        }
+
+        // Shift the potentials so they don't drift over many patches (only
+        // the sums u[i] + v[j] matter)
+        let shift = self.v[1..].iter().copied().max().unwrap_or(0);
+        for j in 1..=self.n {
+            self.v[j] -= shift;
+            self.u[j] += shift;
+        }
+    }
+
+    /// The (row, column) pairs of the assignment (0-indexed).
+    pub fn pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (1..=self.n).map(|j| (self.p[j] - 1, j - 1))
+    }
+
+    /// Total cost of the assignment, saturating at `u16::MAX`.
+    pub fn cost(&self, a: &impl Matrix<u16>) -> u16 {
+        let total: i32 = (1..=self.n)
+            .map(|j| a.get(self.p[j] - 1, j - 1) as i32)
+            .sum();
+        u16::try_from(total).unwrap_or(u16::MAX)
+    }
+
+    /// Assign row `i` (1-indexed) along a shortest augmenting path.
+    fn augment(&mut self, a: &impl Matrix<u16>, i: usize) {
+        const INF: i32 = u16::MAX as i32 + 1;
+
+        let m = self.n;
+        let (u, v, p) = (&mut self.u, &mut self.v, &mut self.p);
+        let mut way = new_buffer::<usize>(m, 0);
 
-    for i in 1..=n {
         p[0] = i;
         let mut j0 = 0;
         let mut minv = new_buffer::<i32>(m, INF);
@@ -114,8 +197,6 @@ pub fn hungarian_algorithm(a: &impl Matrix<u16>) -> u16 {
             }
         }
     }
-
-    u16::try_from(-v[0]).unwrap_or(u16::MAX)
 }
 
 fn new_buffer<T: Copy>(n: usize, initial_value: T) -> ArrayVec<T, { MAX_BOXES + 1 }> {
@@ -124,6 +205,9 @@ fn new_buffer<T: Copy>(n: usize, initial_value: T) -> ArrayVec<T, { MAX_BOXES +
 
 #[cfg(test)]
 mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
     use super::*;
 
     #[test]
@@ -132,4 +216,49 @@ mod tests {
         let cost = hungarian_algorithm(&a);
         assert_eq!(cost, 15);
     }
+
+    #[test]
+    fn test_reassign_random() {
+        let mut rng = ChaCha8Rng::seed_from_u64(12345);
+
+        for _ in 0..100 {
+            let n = rng.gen_range(1..=10);
+            let random_cost = |rng: &mut ChaCha8Rng| {
+                // Include some unreachable goals
+                if rng.gen_bool(0.1) {
+                    u16::MAX
+                } else {
+                    rng.gen_range(0..20)
+                }
+            };
+            let mut costs: Vec<Vec<u16>> = (0..n)
+                .map(|_| (0..n).map(|_| random_cost(&mut rng)).collect())
+                .collect();
+            let mut assignment = Assignment::solve(&to_matrix(&costs));
+
+            for _ in 0..20 {
+                // Change a few rows, as when boxes are pushed
+                let mut rows: Vec<usize> = (0..rng.gen_range(0..=2))
+                    .map(|_| rng.gen_range(0..n))
+                    .collect();
+                rows.sort();
+                rows.dedup();
+                for &row in &rows {
+                    costs[row] = (0..n).map(|_| random_cost(&mut rng)).collect();
+                }
+
+                let a = to_matrix(&costs);
+                assignment.reassign(&a, &rows);
+                assert_eq!(assignment.cost(&a), hungarian_algorithm(&a));
+            }
+        }
+    }
+
+    fn to_matrix(costs: &[Vec<u16>]) -> ArrayMatrix<u16, { MAX_BOXES * MAX_BOXES }> {
+        let mut a = ArrayMatrix::new(costs.len(), costs.len());
+        for &cost in costs.iter().flatten() {
+            a.push(cost);
+        }
+        a
+    }
 }
@@ -1,7 +1,12 @@
-use crate::game::Game;
+use crate::bits::Index;
+use crate::game::{Game, Position};
 use std::fmt;
 use std::fs;
 use std::io;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Lines;
+use std::sync::OnceLock;
 
 /// Error type for level parsing operations.
 #[derive(Debug)]
@@ -33,76 +38,585 @@ impl From<String> for LevelError {
     }
 }
 
-/// A collection of Sokoban levels in XSB format.
+/// A level of a collection, along with the metadata given for it in the
+/// file. The board is only parsed into a `Game` when it is first needed, so
+/// that loading a large collection to solve a few of its levels is cheap.
+#[derive(Debug)]
+pub struct Level {
+    /// The rows of the board, each ending with a newline.
+    board: String,
+    /// The lines after the board, which may give its Sokoban+ labels.
+    notes: String,
+    unbalanced: bool,
+    /// The parsed level, or the reason it was skipped.
+    game: OnceLock<Result<Game, String>>,
+    /// The last line before the level, without its `;`, which usually names
+    /// or numbers it (e.g. "1" or "1 - Aislinn").
+    pub id: Option<String>,
+    /// From a `Title:` line right after the level.
+    pub title: Option<String>,
+    /// From an `Author:` line right after the level, or else from the header
+    /// of the collection.
+    pub author: Option<String>,
+    /// The LURD moves of a saved game, from a `Snapshot` section after the
+    /// level.
+    pub snapshot: Option<String>,
+    /// Whether the game starts from the position after the snapshot (see
+    /// `Levels::use_snapshots`).
+    from_snapshot: bool,
+}
+
+impl Level {
+    fn new(board: String, notes: String, unbalanced: bool) -> Self {
+        Level {
+            board,
+            notes,
+            unbalanced,
+            game: OnceLock::new(),
+            id: None,
+            title: None,
+            author: None,
+            snapshot: None,
+            from_snapshot: false,
+        }
+    }
+
+    /// A level for the given game, e.g. a normalized or generated one,
+    /// without metadata.
+    pub fn from_game(game: Game) -> Self {
+        let board = game.to_string();
+        let unbalanced = !game.is_balanced();
+        Level {
+            game: OnceLock::from(Ok(game)),
+            ..Level::new(board, String::new(), unbalanced)
+        }
+    }
+
+    /// Append the level in XSB format: its id as a comment, the board (as
+    /// normalized, unless it was skipped) and then its metadata and Sokoban+
+    /// labels.
+    fn write_text(&self, text: &mut String) {
+        if let Some(id) = &self.id {
+            text.push_str(&format!("; {}\n\n", id));
+        }
+        match self.game() {
+            Some(game) => text.push_str(&game.to_string()),
+            None => text.push_str(&self.board),
+        }
+        if let Some(title) = &self.title {
+            text.push_str(&format!("Title: {}\n", title));
+        }
+        if let Some(author) = &self.author {
+            text.push_str(&format!("Author: {}\n", author));
+        }
+        if let Some(snapshot) = self.snapshot.as_ref().filter(|_| !self.from_snapshot) {
+            text.push_str(&format!("Snapshot\n{}\n", snapshot));
+        }
+        match self.game() {
+            Some(game) if game.is_labelled() => {
+                // Boxes are labelled in reading order when parsed
+                let mut boxes: Vec<(Position, u8)> = (0..game.box_positions().len())
+                    .map(|i| {
+                        let box_idx = Index(i as u8);
+                        (game.box_position(box_idx), game.box_label(box_idx))
+                    })
+                    .collect();
+                boxes.sort_by_key(|&(Position(x, y), _)| (y, x));
+                let box_labels = boxes.iter().map(|&(_, label)| label);
+                let goal_labels = (0..game.goal_positions().len()).map(|i| game.goal_label(i));
+                text.push_str(&format!("BoxSequence: {}\n", join_labels(box_labels)));
+                text.push_str(&format!("GoalSequence: {}\n", join_labels(goal_labels)));
+            }
+            Some(_) => {}
+            None => {
+                for line in self.notes.lines().filter(|line| {
+                    key_value(line).is_some_and(|(key, _)| {
+                        key.eq_ignore_ascii_case("BoxSequence")
+                            || key.eq_ignore_ascii_case("GoalSequence")
+                    })
+                }) {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            }
+        }
+        text.push('\n');
+    }
+
+    /// The parsed level, or `None` if it was skipped.
+    pub fn game(&self) -> Option<&Game> {
+        self.parsed().as_ref().ok()
+    }
+
+    /// The reason the level was skipped, if it was.
+    pub fn skip_reason(&self) -> Option<&str> {
+        self.parsed().as_ref().err().map(String::as_str)
+    }
+
+    fn parsed(&self) -> &Result<Game, String> {
+        self.game.get_or_init(|| {
+            let notes: Vec<&str> = self.notes.lines().collect();
+            let game = parse_level(&self.board, &notes, self.unbalanced)?;
+            match &self.snapshot {
+                Some(snapshot) if self.from_snapshot => game
+                    .apply_lurd(snapshot)
+                    .map_err(|reason| format!("Invalid snapshot: {}", reason)),
+                _ => Ok(game),
+            }
+        })
+    }
+}
+
+/// An iterator over the levels of an XSB collection, reading the text only
+/// as far as the levels returned so far (see `Levels::iter_text`).
+pub struct LevelIter<'a> {
+    lines: Peekable<Lines<'a>>,
+    /// The lines after the last level returned, or before the first.
+    gap: Vec<&'a str>,
+    /// The author in the header of the collection, once it has been read.
+    header_author: Option<Option<String>>,
+    unbalanced: bool,
+}
+
+impl<'a> Iterator for LevelIter<'a> {
+    type Item = Level;
+
+    fn next(&mut self) -> Option<Level> {
+        while let Some(line) = self.lines.next_if(|line| !is_board_line(line)) {
+            self.gap.push(line);
+        }
+        self.lines.peek()?;
+        // The first level is led by the whole header, and later ones by the
+        // lines after the first blank line following the previous level
+        let leader = match self.header_author {
+            None => {
+                self.header_author = Some(find_value(&self.gap, "author"));
+                &self.gap[..]
+            }
+            Some(_) => {
+                // Leaving out the previous level's snapshot
+                let leader = split_gap(&self.gap).1;
+                match find_snapshot(&self.gap) {
+                    Some((_, end)) if end > self.gap.len() - leader.len() => &self.gap[end..],
+                    _ => leader,
+                }
+            }
+        };
+        let id = leader
+            .iter()
+            .rev()
+            .map(|line| line.trim().trim_start_matches(';').trim())
+            .find(|line| !line.is_empty())
+            .filter(|line| key_value(line).is_none())
+            .map(str::to_string);
+
+        let mut board = String::new();
+        while let Some(line) = self.lines.next_if(|line| is_board_line(line)) {
+            board.push_str(line);
+            board.push('\n');
+        }
+        self.gap.clear();
+        while let Some(line) = self.lines.next_if(|line| !is_board_line(line)) {
+            self.gap.push(line);
+        }
+        let trailer = split_gap(&self.gap).0;
+        Some(Level {
+            id,
+            title: find_value(trailer, "title"),
+            author: find_value(trailer, "author").or_else(|| self.header_author.clone().flatten()),
+            snapshot: find_snapshot(&self.gap)
+                .map(|(moves, _)| moves)
+                .filter(|moves| !moves.is_empty()),
+            ..Level::new(board, self.gap.join("\n"), self.unbalanced)
+        })
+    }
+}
+
+/// A collection of Sokoban levels in XSB or SLC (XML) format.
+///
+/// Levels which failed to parse are only retained when loading with
+/// `skip_invalid`, in which case they are stored alongside the valid levels so
+/// that level numbering still matches the file.
 #[derive(Debug)]
 pub struct Levels {
-    levels: Vec<Game>,
+    levels: Vec<Level>,
 }
 
 impl Levels {
     /// Parse XSB-formatted Sokoban levels from a string.
     ///
     /// The XSB format uses:
-    /// - Lines starting with `;` as level separators/comments
+    /// - Lines starting with `;` as level separators/comments, the last of
+    ///   which before a level is kept as its id
+    /// - `Title:` and `Author:` lines right after a level, and an `Author:`
+    ///   line (possibly commented out) in the header of the collection
     /// - Standard Sokoban characters (#, @, $, ., *, +, space)
     /// - Empty lines between levels (optional)
+    /// - `BoxSequence:` and `GoalSequence:` lines after a level, giving the
+    ///   Sokoban+ labels of its boxes and goals in reading order (see
+    ///   `Game::set_labels`)
     ///
-    /// Parses and validates each level, returning a Levels struct containing Game instances.
+    /// Parses, validates and normalizes (see `Game::normalize`) each level,
+    /// returning a Levels struct containing Game instances.
     pub fn from_text(contents: &str) -> Result<Self, LevelError> {
-        let mut levels = Vec::new();
-        let mut current_level = String::new();
+        Self::from_text_with(contents, false, false)
+    }
 
-        for line in contents.lines() {
-            // Check if line is part of a level (starts with zero or more spaces followed by '#')
-            let trimmed = line.trim_start();
-            let is_level_line = trimmed.starts_with('#');
-
-            if !is_level_line {
-                // Line is a separator/comment - save current level if any
-                if !current_level.is_empty() {
-                    let level_str = current_level.trim_end();
-                    let game = Game::from_text(level_str)?;
-                    levels.push(game);
-                    current_level.clear();
+    /// Parse XSB-formatted Sokoban levels from a string, recording levels which
+    /// fail to parse (or exceed the solver's limits) as skipped rather than
+    /// failing the whole collection.
+    pub fn from_text_skip_invalid(contents: &str) -> Self {
+        Levels {
+            levels: Self::parse_levels(contents, false),
+        }
+    }
+
+    /// Parse XSB-formatted Sokoban levels from a string, like
+    /// `from_text_skip_invalid` if `skip_invalid` is set and `from_text`
+    /// otherwise. With `unbalanced`, levels may have more goals than boxes or
+    /// vice versa (see `Game::from_text_unbalanced`). Contents starting with
+    /// `<` are parsed as SLC instead (see `from_slc_with`).
+    pub fn from_text_with(
+        contents: &str,
+        skip_invalid: bool,
+        unbalanced: bool,
+    ) -> Result<Self, LevelError> {
+        if contents.trim_start().starts_with('<') {
+            return Self::from_slc_with(contents, skip_invalid, unbalanced);
+        }
+        Self::check_levels(Self::parse_levels(contents, unbalanced), skip_invalid)
+    }
+
+    /// Parse Sokoban levels in the SLC format, the XML format of collections
+    /// exported by Sokoban YASC and other programs:
+    ///
+    /// - Each `<Level>` element has one `<L>` element per row
+    /// - The `Id` attribute of a level names it, and is kept as both its id
+    ///   and its title
+    /// - The `Copyright` attribute of a level, or else of its
+    ///   `<LevelCollection>`, is kept as its author
+    ///
+    /// Invalid levels are handled as in `from_text_with`, but XML which
+    /// fails to parse fails the whole collection.
+    pub fn from_slc_with(
+        contents: &str,
+        skip_invalid: bool,
+        unbalanced: bool,
+    ) -> Result<Self, LevelError> {
+        let document = roxmltree::Document::parse(contents)
+            .map_err(|e| LevelError::InvalidLevel(format!("Invalid SLC file: {}", e)))?;
+        let levels = document
+            .descendants()
+            .filter(|node| node.has_tag_name("Level"))
+            .map(|node| {
+                let board: String = node
+                    .children()
+                    .filter(|row| row.has_tag_name("L"))
+                    .map(|row| format!("{}\n", row.text().unwrap_or("")))
+                    .collect();
+                let id = node
+                    .attribute("Id")
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string);
+                let author = node
+                    .ancestors()
+                    .find_map(|node| node.attribute("Copyright"))
+                    .map(str::trim)
+                    .filter(|author| !author.is_empty())
+                    .map(str::to_string);
+                Level {
+                    title: id.clone(),
+                    id,
+                    author,
+                    ..Level::new(board, String::new(), unbalanced)
                 }
-                continue;
-            }
+            })
+            .collect();
+        Self::check_levels(levels, skip_invalid)
+    }
 
-            // Add line to current level
-            current_level.push_str(line);
-            current_level.push('\n');
+    /// Keep skipped levels if `skip_invalid` is set, and otherwise fail with
+    /// the first reason a level was skipped, and its (1-indexed) number.
+    fn check_levels(levels: Vec<Level>, skip_invalid: bool) -> Result<Self, LevelError> {
+        let levels = Levels { levels };
+        if !skip_invalid {
+            levels.check()?;
         }
+        Ok(levels)
+    }
 
-        // Don't forget the last level if file doesn't end with a separator
-        if !current_level.is_empty() {
-            let level_str = current_level.trim_end();
-            let game = Game::from_text(level_str)?;
-            levels.push(game);
+    /// Fail with the first reason a level was skipped, and its (1-indexed)
+    /// number, e.g. to reject snapshots which don't replay after
+    /// `use_snapshots()`.
+    pub fn check(&self) -> Result<(), LevelError> {
+        match self
+            .levels
+            .iter()
+            .enumerate()
+            .find_map(|(index, level)| Some((index, level.skip_reason()?)))
+        {
+            Some((index, reason)) => Err(LevelError::InvalidLevel(format!(
+                "{} (level {})",
+                reason,
+                index + 1
+            ))),
+            None => Ok(()),
         }
+    }
 
-        Ok(Levels { levels })
+    fn parse_levels(contents: &str, unbalanced: bool) -> Vec<Level> {
+        Self::iter_text(contents, unbalanced).collect()
+    }
+
+    /// Iterate over the XSB-formatted levels of a string, as in `from_text`,
+    /// reading no further than the levels returned and parsing each only
+    /// when its game is first needed (skipping levels with `nth` never
+    /// parses them).
+    pub fn iter_text(contents: &str, unbalanced: bool) -> LevelIter<'_> {
+        LevelIter {
+            lines: contents.lines().peekable(),
+            gap: Vec::new(),
+            header_author: None,
+            unbalanced,
+        }
+    }
+
+    /// The number of levels in a collection, found by scanning the text
+    /// (XSB or SLC) for boards without parsing them. Collections with
+    /// malformed SLC may have fewer levels than this.
+    pub fn len_hint(contents: &str) -> usize {
+        if contents.trim_start().starts_with('<') {
+            return contents.matches("<Level").count()
+                - contents.matches("<LevelCollection").count();
+        }
+        let mut count = 0;
+        let mut in_board = false;
+        for line in contents.lines() {
+            let is_board = is_board_line(line);
+            if is_board && !in_board {
+                count += 1;
+            }
+            in_board = is_board;
+        }
+        count
     }
 
     /// Parse XSB-formatted Sokoban levels from a text file.
     pub fn from_file(path: &str) -> Result<Self, LevelError> {
+        Self::from_file_with(path, false, false)
+    }
+
+    /// Parse Sokoban levels from a file, as SLC if it has a `.slc` extension
+    /// and otherwise as in `from_text_with`.
+    pub fn from_file_with(
+        path: &str,
+        skip_invalid: bool,
+        unbalanced: bool,
+    ) -> Result<Self, LevelError> {
         let contents = fs::read_to_string(path)?;
-        Self::from_text(&contents)
+        let is_slc = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("slc"));
+        if is_slc {
+            Self::from_slc_with(&contents, skip_invalid, unbalanced)
+        } else {
+            Self::from_text_with(&contents, skip_invalid, unbalanced)
+        }
     }
 
-    /// Get the nth level (0-indexed).
+    /// Parse XSB-formatted Sokoban levels from a text file, skipping invalid
+    /// levels (see `from_text_skip_invalid`).
+    pub fn from_file_skip_invalid(path: &str) -> Result<Self, LevelError> {
+        Self::from_file_with(path, true, false)
+    }
+
+    /// Write the levels out in XSB format, in order, so that reading them
+    /// back gives the same levels (as normalized when they were loaded) with
+    /// the same ids, titles, authors and Sokoban+ labels. Skipped levels are
+    /// written as they were read, keeping the level numbering.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for level in &self.levels {
+            level.write_text(&mut text);
+        }
+        text
+    }
+
+    /// Write the levels to a file in XSB format (see `to_text`).
+    pub fn to_file(&self, path: &str) -> Result<(), LevelError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    /// Start each level with a snapshot from the position after its moves,
+    /// so that solving it finishes the saved game. Levels whose moves can't
+    /// be replayed are skipped, which `check()` reports.
+    pub fn use_snapshots(&mut self) {
+        for level in &mut self.levels {
+            if level.snapshot.is_some() {
+                level.from_snapshot = true;
+                level.game.take();
+            }
+        }
+    }
+
+    /// Get the nth level (0-indexed), or `None` if it does not exist or was
+    /// skipped.
     pub fn get(&self, index: usize) -> Option<&Game> {
+        self.levels.get(index).and_then(Level::game)
+    }
+
+    /// Get the nth level (0-indexed) with its metadata, even if it was
+    /// skipped.
+    pub fn level(&self, index: usize) -> Option<&Level> {
         self.levels.get(index)
     }
 
-    /// Get the number of levels.
+    /// Get the reason the nth level (0-indexed) was skipped, if it was.
+    pub fn skip_reason(&self, index: usize) -> Option<&str> {
+        self.levels.get(index).and_then(Level::skip_reason)
+    }
+
+    /// Find the first level (0-indexed) with the given title, ignoring case.
+    pub fn find_title(&self, title: &str) -> Option<usize> {
+        self.levels.iter().position(|level| {
+            level
+                .title
+                .as_ref()
+                .is_some_and(|level_title| level_title.eq_ignore_ascii_case(title.trim()))
+        })
+    }
+
+    /// Get the number of levels, including skipped levels.
     pub fn len(&self) -> usize {
         self.levels.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
+
+/// Build a collection from levels, e.g. deduplicated or generated ones, to
+/// write out with `to_text`.
+impl FromIterator<Level> for Levels {
+    fn from_iter<I: IntoIterator<Item = Level>>(iter: I) -> Self {
+        Levels {
+            levels: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Join Sokoban+ labels with spaces.
+fn join_labels(labels: impl Iterator<Item = u8>) -> String {
+    labels
+        .map(|label| label.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse a level, labelled by the Sokoban+ sequences among the lines after
+/// it, walling in the floor outside it.
+fn parse_level(text: &str, notes: &[&str], unbalanced: bool) -> Result<Game, String> {
+    let text = text.trim_end();
+    let mut game = if unbalanced {
+        Game::from_text_unbalanced(text)?
+    } else {
+        Game::from_text(text)?
+    };
+    let box_labels = parse_sequence(notes, "BoxSequence")?;
+    let goal_labels = parse_sequence(notes, "GoalSequence")?;
+    game.set_labels(&box_labels, &goal_labels)?;
+    game.normalize()
+}
+
+/// Find the saved game among the lines after a level: the LURD lines
+/// following a `Snapshot` line, past any `Key: value` lines (such as its
+/// date) and blank lines. Returns its moves, and the index of the line after
+/// them.
+fn find_snapshot(lines: &[&str]) -> Option<(String, usize)> {
+    let start = lines.iter().position(|line| {
+        line.trim()
+            .trim_end_matches(':')
+            .eq_ignore_ascii_case("snapshot")
+    })?;
+    let mut moves = String::new();
+    let mut end = start + 1;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        let line = line.trim();
+        if !line.is_empty() && line.chars().all(|ch| "lurdLURD".contains(ch)) {
+            moves.push_str(line);
+            end = i + 1;
+        } else if !line.is_empty() && key_value(line).is_none() {
+            break;
+        }
+    }
+    Some((moves, end))
+}
+
+/// Whether a line is a row of a board: zero or more spaces or other floor
+/// followed by a wall.
+fn is_board_line(line: &str) -> bool {
+    line.trim_start_matches([' ', '\t', '-', '_'])
+        .starts_with('#')
+}
+
+/// Split the lines between two boards into those up to the first blank line,
+/// which describe the first board, and the rest, which lead up to the next.
+fn split_gap<'a>(gap: &'a [&'a str]) -> (&'a [&'a str], &'a [&'a str]) {
+    let end = gap
+        .iter()
+        .position(|line| line.trim().is_empty())
+        .unwrap_or(gap.len());
+    gap.split_at(end)
+}
+
+/// Split a `Key: value` line, which may be commented out with `;`, into its
+/// key and value. Keys are single words.
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start().trim_start_matches(';');
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim();
+    (!key.is_empty() && !key.contains(char::is_whitespace)).then_some((key, value.trim()))
+}
+
+/// The value of the first line among the given ones with the given key,
+/// ignoring case, unless it is empty.
+fn find_value(lines: &[&str], key: &str) -> Option<String> {
+    lines
+        .iter()
+        .filter_map(|line| key_value(line))
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Parse the labels of a `key: 1 2 3` line, or none if there is no such line.
+fn parse_sequence(notes: &[&str], key: &str) -> Result<Vec<u8>, String> {
+    let Some(value) = notes
+        .iter()
+        .filter_map(|line| key_value(line))
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+    else {
+        return Ok(Vec::new());
+    };
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|label| !label.is_empty())
+        .map(|label| {
+            label
+                .parse()
+                .map_err(|_| format!("Invalid label '{}' in {}", label, key))
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bits::Index;
 
     #[test]
     fn test_from_text_basic() {
@@ -142,6 +656,11 @@ mod tests {
         assert_eq!(levels.get(0).unwrap().to_string().trim_end(), level1);
         assert_eq!(levels.get(1).unwrap().to_string().trim_end(), level2);
         assert_eq!(levels.get(2).unwrap().to_string().trim_end(), level3);
+
+        // Rows may start with '-' or '_' floor
+        let levels = Levels::from_text(&level3.replace(' ', "-")).unwrap();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels.get(0).unwrap().to_string().trim_end(), level3);
     }
 
     #[test]
@@ -159,6 +678,270 @@ mod tests {
         assert!(matches!(result.unwrap_err(), LevelError::InvalidLevel(_)));
     }
 
+    #[test]
+    fn test_from_text_skip_invalid() {
+        let xsb_content = "; 1
+
+####
+#@@#
+####
+
+; 2
+
+#####
+#@$.#
+#####
+
+; 3
+
+#####
+#@$.
+#####
+";
+
+        let levels = Levels::from_text_skip_invalid(xsb_content);
+        assert_eq!(levels.len(), 3);
+        assert!(levels.get(0).is_none());
+        assert_eq!(levels.skip_reason(0), Some("Multiple players found"));
+        assert!(levels.get(1).is_some());
+        assert_eq!(levels.skip_reason(1), None);
+        assert_eq!(
+            levels.skip_reason(2),
+            Some("Level is not enclosed: the player can reach (4, 1) on the edge of the board")
+        );
+
+        // Without skipping, the first invalid level fails the collection
+        let no_player = xsb_content.replacen("#@@#", "#  #", 1);
+        assert_eq!(
+            Levels::from_text(&no_player).unwrap_err().to_string(),
+            "Invalid level: No player found on board (level 1)"
+        );
+    }
+
+    #[test]
+    fn test_iter_text() {
+        let xsb_content = "; 1\n\n#####\n#@$.#\n#####\n\n; 2\n\n####\n#@@#\n####\n\n\
+                           ; 3\n\n#####\n#.$@#\n#####\nTitle: Last\n";
+        assert_eq!(Levels::len_hint(xsb_content), 3);
+
+        // Levels are only parsed when their game is needed
+        let level = Levels::iter_text(xsb_content, false).nth(2).unwrap();
+        assert_eq!(level.id.as_deref(), Some("3"));
+        assert_eq!(level.title.as_deref(), Some("Last"));
+        assert!(level.game.get().is_none());
+        assert!(level.game().is_some());
+
+        let levels = Levels::from_text_skip_invalid(xsb_content);
+        assert!(levels.level(1).unwrap().game.get().is_none());
+        assert_eq!(levels.skip_reason(1), Some("Multiple players found"));
+    }
+
+    #[test]
+    fn test_from_text_sokoban_plus() {
+        let xsb_content = "#######
+#.$@$.#
+#######
+Title: Swap
+BoxSequence: 1 2
+GoalSequence: 2, 1
+
+#######
+#.$@$.#
+#######
+GoalSequence: 1 x
+";
+
+        let levels = Levels::from_text_skip_invalid(xsb_content);
+        assert_eq!(levels.len(), 2);
+        let game = levels.get(0).unwrap();
+        assert!(game.is_labelled());
+        assert_eq!(game.box_label(Index(1)), 2);
+        assert_eq!(game.goal_label(0), 2);
+        assert_eq!(
+            levels.skip_reason(1),
+            Some("Invalid label 'x' in GoalSequence")
+        );
+    }
+
+    #[test]
+    fn test_from_text_metadata() {
+        let xsb_content = "; author: Somebody
+
+; 1 - Aislinn
+#####
+#@$.#
+#####
+Title: First
+Author: Someone Else
+
+1
+#####
+#.$@#
+#####
+Title: Second
+";
+
+        let levels = Levels::from_text(xsb_content).unwrap();
+        assert_eq!(levels.len(), 2);
+        let first = levels.level(0).unwrap();
+        assert_eq!(first.id.as_deref(), Some("1 - Aislinn"));
+        assert_eq!(first.title.as_deref(), Some("First"));
+        assert_eq!(first.author.as_deref(), Some("Someone Else"));
+        let second = levels.level(1).unwrap();
+        assert_eq!(second.id.as_deref(), Some("1"));
+        assert_eq!(second.title.as_deref(), Some("Second"));
+        assert_eq!(second.author.as_deref(), Some("Somebody"));
+
+        assert_eq!(levels.find_title("second"), Some(1));
+        assert_eq!(levels.find_title("Third"), None);
+    }
+
+    #[test]
+    fn test_from_slc() {
+        let slc_content = r#"<?xml version="1.0" encoding="utf-8"?>
+<SokobanLevels>
+  <Title>Tiny</Title>
+  <LevelCollection Copyright="Somebody">
+    <Level Id="First" Width="5" Height="3">
+      <L>#####</L>
+      <L>#@$.#</L>
+      <L>#####</L>
+    </Level>
+    <Level Id="Second" Width="5" Height="3" Copyright="Someone Else">
+      <L>#####</L>
+      <L>#@$$#</L>
+      <L>#####</L>
+    </Level>
+  </LevelCollection>
+</SokobanLevels>
+"#;
+
+        assert_eq!(Levels::len_hint(slc_content), 2);
+
+        // Detected by content, and invalid levels are skipped as for XSB
+        assert!(Levels::from_text(slc_content).is_err());
+        let levels = Levels::from_text_with(slc_content, true, false).unwrap();
+        assert_eq!(levels.len(), 2);
+        let first = levels.level(0).unwrap();
+        assert_eq!(first.id.as_deref(), Some("First"));
+        assert_eq!(first.title.as_deref(), Some("First"));
+        assert_eq!(first.author.as_deref(), Some("Somebody"));
+        assert_eq!(first.game().unwrap().box_positions().len(), 1);
+        let second = levels.level(1).unwrap();
+        assert_eq!(second.author.as_deref(), Some("Someone Else"));
+        assert!(second.skip_reason().is_some());
+        assert_eq!(levels.find_title("second"), Some(1));
+
+        assert!(matches!(
+            Levels::from_slc_with("<Level>", false, false),
+            Err(LevelError::InvalidLevel(_))
+        ));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let xsb_content = "; 1
+#######
+#@ $ .#
+#######
+Title: First
+
+Snapshot
+Date: 2024-01-01
+rR
+
+; 2
+#####
+#@$.#
+#####
+";
+
+        let mut levels = Levels::from_text(xsb_content).unwrap();
+        assert_eq!(levels.level(0).unwrap().snapshot.as_deref(), Some("rR"));
+        assert_eq!(levels.level(1).unwrap().id.as_deref(), Some("2"));
+        assert_eq!(levels.level(1).unwrap().snapshot, None);
+        let text = levels.to_text();
+        assert_eq!(
+            Levels::from_text(&text)
+                .unwrap()
+                .level(0)
+                .unwrap()
+                .snapshot
+                .as_deref(),
+            Some("rR")
+        );
+
+        // Levels start from the position after their snapshot
+        levels.use_snapshots();
+        assert_eq!(
+            levels.get(0).unwrap().to_string(),
+            "#######\n#  @$.#\n#######\n"
+        );
+        assert_eq!(levels.get(1).unwrap().to_string(), "#####\n#@$.#\n#####\n");
+
+        let mut levels = Levels::from_text(&xsb_content.replace("rR", "L")).unwrap();
+        levels.use_snapshots();
+        assert_eq!(
+            levels.skip_reason(0),
+            Some("Invalid snapshot: step 1: player moves Left into a wall at (0, 1)")
+        );
+        assert_eq!(
+            levels.check().unwrap_err().to_string(),
+            "Invalid level: Invalid snapshot: step 1: player moves Left into a wall at (0, 1) (level 1)"
+        );
+    }
+
+    #[test]
+    fn test_to_text() {
+        let xsb_content = "; author: Somebody
+
+; 1
+   #####
+   #@$.#
+   #####
+Title: First
+
+; 2
+####
+#@@#
+####
+
+#######
+#.$@$.#
+#######
+BoxSequence: 1 2
+GoalSequence: 2 1
+";
+
+        let levels = Levels::from_text_skip_invalid(xsb_content);
+        let text = levels.to_text();
+        let reread = Levels::from_text_skip_invalid(&text);
+        assert_eq!(reread.len(), 3);
+        assert_eq!(reread.to_text(), text);
+        for i in 0..3 {
+            let (level, reread_level) = (levels.level(i).unwrap(), reread.level(i).unwrap());
+            assert_eq!(reread_level.id, level.id);
+            assert_eq!(reread_level.title, level.title);
+            assert_eq!(reread_level.author, level.author);
+            assert_eq!(reread_level.game(), level.game());
+            assert_eq!(reread_level.skip_reason(), level.skip_reason());
+        }
+        assert!(reread.get(2).unwrap().is_labelled());
+        assert!(text.starts_with("; 1\n\n#####\n#@$.#\n#####\nTitle: First\nAuthor: Somebody\n"));
+
+        // Games without metadata, e.g. generated ones
+        let game = Game::from_text("#####\n#@$.#\n#####")
+            .unwrap()
+            .normalize()
+            .unwrap();
+        let levels: Levels = [Level::from_game(game.clone())].into_iter().collect();
+        assert_eq!(levels.to_text(), "#####\n#@$.#\n#####\n\n");
+        assert_eq!(
+            Levels::from_text(&levels.to_text()).unwrap().get(0),
+            Some(&game)
+        );
+    }
+
     #[test]
     fn test_from_file_no_file() {
         let result = Levels::from_file("nonexistent_file.xsb");
@@ -0,0 +1,28 @@
+pub mod bits;
+pub mod certificate;
+pub mod config;
+pub mod conflict;
+pub mod corral;
+pub mod deadlockdb;
+pub mod events;
+pub mod explain;
+pub mod fess;
+pub mod frozen;
+pub mod game;
+pub mod heuristic;
+pub mod hungarian;
+pub mod levels;
+pub mod log;
+pub mod matching;
+pub mod parallel;
+pub mod patterns;
+pub mod pdb;
+pub mod penalty;
+pub mod pqueue;
+pub mod render;
+pub mod solution;
+pub mod solver;
+pub mod statsdb;
+pub mod symmetry;
+pub mod ttable;
+pub mod zobrist;
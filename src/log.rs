@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Output verbosity levels, in increasing order of detail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only summaries and errors (`-q`).
+    Quiet,
+    /// Per-level result lines (the default).
+    Normal,
+    /// Additional statistics, such as pruning breakdowns (`-v`).
+    Verbose,
+    /// Search traces (`-vv`).
+    Trace,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+impl Level {
+    /// Level corresponding to the number of `-v` flags, or `Quiet` if `-q`
+    /// was given.
+    pub fn from_flags(verbose: u8, quiet: bool) -> Level {
+        match (quiet, verbose) {
+            (true, _) => Level::Quiet,
+            (false, 0) => Level::Normal,
+            (false, 1) => Level::Verbose,
+            (false, _) => Level::Trace,
+        }
+    }
+}
+
+/// Set the global verbosity level.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Check whether output at the given level should be printed.
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Print a line at `Normal` verbosity (suppressed by `-q`).
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Print a line at `Verbose` verbosity (`-v`).
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Print a line at `Trace` verbosity (`-vv`).
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Trace) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_flags() {
+        assert_eq!(Level::from_flags(0, false), Level::Normal);
+        assert_eq!(Level::from_flags(1, false), Level::Verbose);
+        assert_eq!(Level::from_flags(3, false), Level::Trace);
+        assert_eq!(Level::from_flags(2, true), Level::Quiet);
+        assert!(Level::Quiet < Level::Normal && Level::Verbose < Level::Trace);
+    }
+}
@@ -1,25 +1,43 @@
-mod bits;
-mod corral;
-mod frozen;
-mod game;
-mod heuristic;
-mod hungarian;
-mod levels;
-mod pqueue;
-mod solver;
-mod zobrist;
-
-use clap::{Parser, ValueEnum};
-use game::Game;
-use heuristic::{Heuristic, NullHeuristic, SimpleHeuristic};
-use levels::Levels;
-use solver::{SearchType, SolveResult, Solver};
-use std::ops::Range;
-use std::time::Instant;
-
-use crate::{
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use sisyphus::certificate::{Certificate, build_certificate, check_certificate};
+use sisyphus::config::{Config, SolverConfig};
+use sisyphus::corral::{DeadlockTables, analyze_corrals};
+use sisyphus::deadlockdb::DeadlockDb;
+use sisyphus::events::EventLog;
+use sisyphus::explain::{Unsolvability, explain_unsolvable};
+use sisyphus::game::{Direction as GameDirection, Game, Position};
+use sisyphus::heuristic::{Heuristic, NullHeuristic, SimpleHeuristic};
+use sisyphus::levels::{LevelError, Levels};
+use sisyphus::log::{self, Level};
+use sisyphus::render;
+use sisyphus::solution::{
+    apply_step, format_compact, format_push_positions, parse_solutions, to_lurd,
+};
+use sisyphus::solver::{
+    AlgorithmType, BalanceType, CostType, SearchType, SolveResult, Solver, SolverStats, TieBreak,
+};
+use sisyphus::statsdb::{RunRecord, StatsDb};
+use sisyphus::{info, verbose};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::ops::{ControlFlow, Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sisyphus::{
     game::{Move, Push},
-    heuristic::{GreedyHeuristic, HungarianHeuristic},
+    heuristic::{
+        GoalRoomHeuristic, GreedyHeuristic, HeuristicKind, HungarianHeuristic,
+        LinearConflictHeuristic, MaxListHeuristic, RefinedGreedyHeuristic,
+    },
     solver::SolverOpts,
 };
 
@@ -27,10 +45,25 @@ use crate::{
 enum HeuristicType {
     Simple,
     Greedy,
+    GreedyRefined,
     Hungarian,
+    LinearConflict,
+    GoalRoom,
     Null,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SolutionFormat {
+    /// Print the board after every push
+    Boards,
+    /// Print runs of pushes grouped by box and direction
+    Compact,
+    /// Print each push as the box's board coordinates and a direction
+    Pushes,
+    /// Print player moves in LURD notation (uppercase for pushes)
+    Lurd,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 enum Direction {
     Forward,
@@ -38,6 +71,88 @@ enum Direction {
     Bidirectional,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Balance {
+    /// Alternate between the two directions
+    Alternate,
+    /// Expand the direction with the smaller open list
+    Frontier,
+    /// Expand the direction whose best open node has the lower cost
+    Cost,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Cost {
+    /// Minimise box pushes
+    Pushes,
+    /// Minimise player moves (walking and pushing)
+    Moves,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Algorithm {
+    /// Best-first search with an open list and transposition table
+    BestFirst,
+    /// Iterative-deepening A*, using memory proportional to the solution length
+    #[value(name = "idastar")]
+    IdaStar,
+    /// Feature-space search, taking turns between positions with different
+    /// packing progress and player connectivity
+    Fess,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TieBreakOrder {
+    /// First in, first out
+    Fifo,
+    /// Last in, first out (most recently generated first)
+    Lifo,
+    /// Lowest heuristic cost first (deepest first under optimal search)
+    LowestCost,
+    /// Most boxes on goals first
+    MostOnGoals,
+}
+
+impl From<TieBreakOrder> for TieBreak {
+    fn from(order: TieBreakOrder) -> Self {
+        match order {
+            TieBreakOrder::Fifo => TieBreak::Fifo,
+            TieBreakOrder::Lifo => TieBreak::Lifo,
+            TieBreakOrder::LowestCost => TieBreak::LowestCost,
+            TieBreakOrder::MostOnGoals => TieBreak::MostOnGoals,
+        }
+    }
+}
+
+impl From<Algorithm> for AlgorithmType {
+    fn from(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::BestFirst => AlgorithmType::BestFirst,
+            Algorithm::IdaStar => AlgorithmType::IdaStar,
+            Algorithm::Fess => AlgorithmType::Fess,
+        }
+    }
+}
+
+impl From<Balance> for BalanceType {
+    fn from(balance: Balance) -> Self {
+        match balance {
+            Balance::Alternate => BalanceType::Alternate,
+            Balance::Frontier => BalanceType::Frontier,
+            Balance::Cost => BalanceType::Cost,
+        }
+    }
+}
+
+impl From<Cost> for CostType {
+    fn from(cost: Cost) -> Self {
+        match cost {
+            Cost::Pushes => CostType::Pushes,
+            Cost::Moves => CostType::Moves,
+        }
+    }
+}
+
 impl From<Direction> for SearchType {
     fn from(dir: Direction) -> Self {
         match dir {
@@ -48,6 +163,30 @@ impl From<Direction> for SearchType {
     }
 }
 
+impl From<HeuristicType> for HeuristicKind {
+    fn from(heuristic: HeuristicType) -> Self {
+        match heuristic {
+            HeuristicType::Simple => HeuristicKind::Simple,
+            HeuristicType::Greedy => HeuristicKind::Greedy,
+            HeuristicType::GreedyRefined => HeuristicKind::GreedyRefined,
+            HeuristicType::Hungarian => HeuristicKind::Hungarian,
+            HeuristicType::LinearConflict => HeuristicKind::LinearConflict,
+            HeuristicType::GoalRoom => HeuristicKind::GoalRoom,
+            HeuristicType::Null => HeuristicKind::Null,
+        }
+    }
+}
+
+impl From<SearchType> for Direction {
+    fn from(search_type: SearchType) -> Self {
+        match search_type {
+            SearchType::Forward => Direction::Forward,
+            SearchType::Reverse => Direction::Reverse,
+            SearchType::Bidirectional => Direction::Bidirectional,
+        }
+    }
+}
+
 fn print_solution(game: &Game, solution: &[Push]) {
     println!("\nStarting position:\n{}", game);
     let mut game = game.clone();
@@ -69,80 +208,642 @@ fn print_solution(game: &Game, solution: &[Push]) {
     }
 }
 
+/// Options controlling what is printed or written out for each level.
+struct OutputOpts {
+    print_solution: bool,
+    format: SolutionFormat,
+    svg_out: Option<String>,
+    svg_frames_dir: Option<String>,
+    html_out: Option<String>,
+    events: Option<EventsOutput>,
+    /// Saved corral deadlock search results, if any.
+    deadlock_cache: Option<DeadlockDb>,
+    /// Directory to write unsolvability certificates into, if any.
+    certificates_dir: Option<String>,
+    certificate_max_states: usize,
+}
+
+/// Destination for the search event stream.
+enum EventsOutput {
+    Stdout,
+    File(fs::File),
+}
+
+impl EventsOutput {
+    fn open(path: &str) -> io::Result<Self> {
+        if path == "-" {
+            Ok(EventsOutput::Stdout)
+        } else {
+            Ok(EventsOutput::File(fs::File::create(path)?))
+        }
+    }
+
+    fn writer(&self) -> Box<dyn Write> {
+        match self {
+            EventsOutput::Stdout => Box::new(io::stdout()),
+            EventsOutput::File(file) => match file.try_clone() {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("Error writing events: {}", e);
+                    Box::new(io::sink())
+                }
+            },
+        }
+    }
+}
+
+fn write_solution(game: &Game, solution: &[Push], output: &OutputOpts, stats: &LevelStats) {
+    if output.print_solution {
+        match output.format {
+            SolutionFormat::Boards => print_solution(game, solution),
+            SolutionFormat::Compact => print!("{}", format_compact(solution)),
+            SolutionFormat::Pushes => print!("{}", format_push_positions(game, solution)),
+            SolutionFormat::Lurd => println!("{}", to_lurd(game, solution)),
+        }
+    }
+
+    if let Some(path) = &output.svg_out
+        && let Err(e) = fs::write(path, render::render_animation(game, solution))
+    {
+        eprintln!("Error writing SVG to {}: {}", path, e);
+    }
+
+    if let Some(dir) = &output.svg_frames_dir {
+        let frames = render::render_frames(game, solution);
+        let result = fs::create_dir_all(dir).and_then(|_| {
+            for (i, frame) in frames.iter().enumerate() {
+                fs::write(Path::new(dir).join(format!("frame_{:04}.svg", i)), frame)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("Error writing SVG frames to {}: {}", dir, e);
+        }
+    }
+
+    if let Some(path) = &output.html_out {
+        let title = format!("Level {}", stats.level_num);
+        let html_stats = [
+            ("steps", stats.steps.to_string()),
+            ("states", stats.states_explored.to_string()),
+            ("elapsed", format!("{} ms", stats.elapsed_ms)),
+        ];
+        if let Err(e) = fs::write(
+            path,
+            render::render_html(&title, game, solution, &html_stats),
+        ) {
+            eprintln!("Error writing HTML to {}: {}", path, e);
+        }
+    }
+}
+
 struct LevelStats {
+    level_num: usize,
     solved: bool,
+    result: &'static str,
     steps: usize,
     states_explored: usize,
+    unique_box_configs: usize,
+    elapsed_ms: u128,
+}
+
+/// Set when the user presses Ctrl-C, stopping any running searches.
+static INTERRUPT: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+
+/// Install a Ctrl-C handler which interrupts the running searches, so that
+/// partial results can still be reported. A second Ctrl-C exits immediately.
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPT.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        eprintln!("\nInterrupted (press Ctrl-C again to exit immediately)");
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: unable to install Ctrl-C handler: {}", e);
+    }
+}
+
+fn interrupted() -> bool {
+    INTERRUPT.load(Ordering::Relaxed)
+}
+
+/// The outcome of running the solver on a single level.
+struct SolveOutcome {
+    result: SolveResult,
+    stats: SolverStats,
     elapsed_ms: u128,
+    /// The portfolio configuration whose result was used, if the level was
+    /// solved with a portfolio.
+    portfolio_winner: Option<PortfolioEntry>,
+    /// The corral deadlock search results, if saved results were given.
+    deadlock_tables: Option<DeadlockTables>,
 }
 
-fn solve_level_helper<H: Heuristic>(
+fn run_solver_helper<H: Heuristic>(
     game: &Game,
-    level_num: usize,
     opts: SolverOpts,
-    print_solution: bool,
-) -> LevelStats {
-    let mut solver = Solver::<H>::new(game, opts);
+    params: H::Params,
+    event_log: Option<EventLog>,
+    deadlock_tables: Option<DeadlockTables>,
+    interrupt: Arc<AtomicBool>,
+) -> SolveOutcome {
+    let mut solver = Solver::<H>::with_params(game, opts, params);
+    if let Some(event_log) = event_log {
+        solver.set_event_log(event_log);
+    }
+    if let Some(tables) = &deadlock_tables {
+        solver.load_deadlock_tables(tables);
+    }
+    solver.set_interrupt(interrupt);
     let start = Instant::now();
-    let (result, nodes_explored) = solver.solve();
-    let elapsed = start.elapsed();
+    let (result, stats) = solver.solve();
+    SolveOutcome {
+        result,
+        stats,
+        elapsed_ms: start.elapsed().as_millis(),
+        portfolio_winner: None,
+        deadlock_tables: deadlock_tables.map(|_| solver.deadlock_tables()),
+    }
+}
 
-    let elapsed_ms = elapsed.as_millis();
+fn run_solver(
+    game: &Game,
+    opts: SolverOpts,
+    heuristic_type: HeuristicType,
+    max_with: &[HeuristicType],
+    event_log: Option<EventLog>,
+    deadlock_tables: Option<DeadlockTables>,
+    interrupt: Arc<AtomicBool>,
+) -> SolveOutcome {
+    if !max_with.is_empty() {
+        let kinds: Vec<HeuristicKind> = std::iter::once(heuristic_type)
+            .chain(max_with.iter().copied())
+            .map(HeuristicKind::from)
+            .collect();
+        return run_solver_helper::<MaxListHeuristic>(
+            game,
+            opts,
+            kinds,
+            event_log,
+            deadlock_tables,
+            interrupt,
+        );
+    }
 
-    let (solved_char, solution_len, solved) = match &result {
-        SolveResult::Solved(solution) => ('Y', solution.len(), true),
-        SolveResult::Cutoff => ('N', 0, false),
-        SolveResult::Unsolvable => ('X', 0, false),
-    };
+    match heuristic_type {
+        HeuristicType::Simple => run_solver_helper::<SimpleHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+        HeuristicType::Greedy => run_solver_helper::<GreedyHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+        HeuristicType::GreedyRefined => run_solver_helper::<RefinedGreedyHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+        HeuristicType::Hungarian => run_solver_helper::<HungarianHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+        HeuristicType::LinearConflict => run_solver_helper::<LinearConflictHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+        HeuristicType::GoalRoom => run_solver_helper::<GoalRoomHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+        HeuristicType::Null => run_solver_helper::<NullHeuristic>(
+            game,
+            opts,
+            (),
+            event_log,
+            deadlock_tables,
+            interrupt,
+        ),
+    }
+}
 
-    println!(
-        "level: {:<3}  solved: {}  steps: {:<5}  states: {:<12}  elapsed: {} ms",
-        level_num, solved_char, solution_len, nodes_explored, elapsed_ms
-    );
+/// One configuration of a portfolio, given as `HEURISTIC[:DIRECTION[:MAX_NODES]]`.
+/// The direction and node limit default to those of the other solver options.
+#[derive(Debug, Clone, Copy)]
+struct PortfolioEntry {
+    heuristic: HeuristicType,
+    direction: Option<Direction>,
+    max_nodes: Option<usize>,
+}
 
-    // if solved_char != 'Y' {
-    //     for (hash, count) in solver.frozen_counts.iter() {
-    //         println!("{:016x}: {}", hash, count);
-    //     }
-    // }
+impl PortfolioEntry {
+    /// The solver options for this configuration, based on the given options.
+    fn opts(&self, base: &SolverOpts) -> SolverOpts {
+        let mut opts = base.clone();
+        if let Some(direction) = self.direction {
+            opts.search_type = direction.into();
+        }
+        if let Some(max_nodes) = self.max_nodes {
+            opts.max_nodes_explored = max_nodes;
+        }
+        opts
+    }
+}
 
-    if print_solution {
-        if let SolveResult::Solved(solution) = result {
-            crate::print_solution(game, &solution);
+impl fmt::Display for PortfolioEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", value_name(self.heuristic))?;
+        if let Some(direction) = self.direction {
+            write!(f, ":{}", value_name(direction))?;
+        }
+        if let Some(max_nodes) = self.max_nodes {
+            if self.direction.is_none() {
+                write!(f, ":")?;
+            }
+            write!(f, ":{}", max_nodes)?;
         }
+        Ok(())
     }
+}
 
-    LevelStats {
-        solved,
-        steps: solution_len,
-        states_explored: nodes_explored,
-        elapsed_ms,
+fn parse_portfolio_entry(s: &str) -> Result<PortfolioEntry, String> {
+    let mut parts = s.split(':');
+    let heuristic = parse_value_enum(parts.next().unwrap_or(""))?;
+    let direction = match parts.next() {
+        Some("") | None => None,
+        Some(direction) => Some(parse_value_enum(direction)?),
+    };
+    let max_nodes = match parts.next() {
+        Some(max_nodes) => Some(
+            max_nodes
+                .parse()
+                .map_err(|_| format!("invalid node limit: {}", max_nodes))?,
+        ),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return Err(format!(
+            "expected HEURISTIC[:DIRECTION[:MAX_NODES]], got: {}",
+            s
+        ));
+    }
+    Ok(PortfolioEntry {
+        heuristic,
+        direction,
+        max_nodes,
+    })
+}
+
+/// Run every configuration of a portfolio on its own thread, returning the
+/// outcome of the first to solve the level (or prove it unsolvable) and
+/// interrupting the others. If none does, the outcome of the first
+/// configuration is returned.
+fn run_portfolio(
+    game: &Game,
+    opts: &SolverOpts,
+    portfolio: &[PortfolioEntry],
+    max_with: &[HeuristicType],
+) -> SolveOutcome {
+    let start = Instant::now();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    let mut outcomes: Vec<Option<SolveOutcome>> = portfolio.iter().map(|_| None).collect();
+    let mut winner = None;
+
+    thread::scope(|scope| {
+        for (i, entry) in portfolio.iter().enumerate() {
+            let opts = entry.opts(opts);
+            let cancel = cancel.clone();
+            let sender = sender.clone();
+            scope.spawn(move || {
+                let outcome = run_solver(game, opts, entry.heuristic, max_with, None, None, cancel);
+                // The receiver outlives the scope, so sending can't fail
+                let _ = sender.send((i, outcome));
+            });
+        }
+        drop(sender);
+
+        // Wait for the configurations to finish, passing on Ctrl-C and
+        // cancelling the rest once one of them is decisive
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(50)) {
+                Ok((i, outcome)) => {
+                    let decisive = matches!(
+                        outcome.result,
+                        SolveResult::Solved(_) | SolveResult::Unsolvable
+                    );
+                    if decisive && winner.is_none() {
+                        winner = Some(i);
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                    outcomes[i] = Some(outcome);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if interrupted() {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+
+    let i = winner.unwrap_or(0);
+    let mut outcome = outcomes[i]
+        .take()
+        .expect("every configuration reports an outcome");
+    outcome.elapsed_ms = start.elapsed().as_millis();
+    outcome.portfolio_winner = winner.map(|i| portfolio[i]);
+    outcome
+}
+
+/// Run the solver on a level, racing the configurations of the portfolio if
+/// one is given.
+fn run_level(
+    game: &Game,
+    opts: SolverOpts,
+    heuristic_type: HeuristicType,
+    max_with: &[HeuristicType],
+    portfolio: &[PortfolioEntry],
+    event_log: Option<EventLog>,
+    deadlock_tables: Option<DeadlockTables>,
+) -> SolveOutcome {
+    if portfolio.is_empty() {
+        run_solver(
+            game,
+            opts,
+            heuristic_type,
+            max_with,
+            event_log,
+            deadlock_tables,
+            INTERRUPT.clone(),
+        )
+    } else {
+        run_portfolio(game, &opts, portfolio, max_with)
     }
 }
 
+/// Report the progress made on a level before the search was interrupted.
+fn print_interrupted(level_num: usize, outcome: &SolveOutcome) {
+    println!(
+        "level: {:<3}  interrupted after {} states  best heuristic cost: {}",
+        level_num, outcome.stats.nodes_explored, outcome.stats.best_cost
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
 fn solve_level(
     game: &Game,
     level_num: usize,
+    title: Option<&str>,
     opts: SolverOpts,
     heuristic_type: HeuristicType,
-    print_solution: bool,
+    max_with: &[HeuristicType],
+    portfolio: &[PortfolioEntry],
+    output: &OutputOpts,
 ) -> LevelStats {
-    match heuristic_type {
-        HeuristicType::Simple => {
-            solve_level_helper::<SimpleHeuristic>(game, level_num, opts, print_solution)
+    let event_log = output
+        .events
+        .as_ref()
+        .map(|events| EventLog::new(events.writer(), level_num));
+    let max_depth = opts.max_depth;
+    let fingerprint = game.board_fingerprint();
+    let deadlock_tables = output.deadlock_cache.as_ref().map(|db| {
+        db.load(fingerprint).unwrap_or_else(|e| {
+            eprintln!("Error reading deadlock cache: {}", e);
+            DeadlockTables::default()
+        })
+    });
+    let outcome = run_level(
+        game,
+        opts,
+        heuristic_type,
+        max_with,
+        portfolio,
+        event_log,
+        deadlock_tables,
+    );
+    if let (Some(db), Some(tables)) = (&output.deadlock_cache, &outcome.deadlock_tables)
+        && let Err(e) = db.save(fingerprint, tables)
+    {
+        eprintln!("Error writing deadlock cache: {}", e);
+    }
+    report_level(game, level_num, title, max_depth, outcome, output)
+}
+
+/// Print the outcome of solving a level, along with its title if it has
+/// one, and write out its solution or certificate.
+fn report_level(
+    game: &Game,
+    level_num: usize,
+    title: Option<&str>,
+    max_depth: Option<usize>,
+    outcome: SolveOutcome,
+    output: &OutputOpts,
+) -> LevelStats {
+    let nodes_explored = outcome.stats.nodes_explored;
+
+    let (solved_char, solution_len, solved, result_name) = match &outcome.result {
+        SolveResult::Solved(solution) => ('Y', solution.len(), true, "solved"),
+        SolveResult::Cutoff => ('N', 0, false, "cutoff"),
+        SolveResult::TimedOut => ('T', 0, false, "timed_out"),
+        SolveResult::Interrupted => ('I', 0, false, "interrupted"),
+        SolveResult::Unsolvable => ('X', 0, false, "unsolvable"),
+    };
+
+    let title = title.map_or(String::new(), |title| format!("  title: {}", title));
+    info!(
+        "level: {:<3}  solved: {}  steps: {:<5}  states: {:<12}  elapsed: {} ms{}",
+        level_num, solved_char, solution_len, nodes_explored, outcome.elapsed_ms, title
+    );
+
+    let unique = outcome.stats.unique_box_configs;
+    verbose!(
+        "  unique box configs: {} ({:.1}% of states)",
+        unique,
+        100.0 * unique as f64 / nodes_explored.max(1) as f64
+    );
+    let pruning = &outcome.stats.pruning;
+    verbose!(
+        "  pruned: corrals: {}  corral deadlocks: {}  dead squares: {}  patterns: {}  freeze: {}  matching: {}  conflicts: {}  transpositions: {}  symmetric: {}  duplicates: {}",
+        pruning.corral_pruned,
+        pruning.corral_deadlocks,
+        pruning.dead_square_pruned,
+        pruning.pattern_pruned,
+        pruning.freeze_pruned,
+        pruning.matching_pruned,
+        pruning.conflict_pruned,
+        pruning.transposition_pruned,
+        pruning.symmetry_pruned,
+        pruning.duplicates_pruned
+    );
+    if outcome.stats.evicted_nodes > 0 || outcome.stats.evicted_entries > 0 {
+        verbose!(
+            "  evicted nodes: {}  evicted entries: {}",
+            outcome.stats.evicted_nodes,
+            outcome.stats.evicted_entries
+        );
+    }
+    if outcome.stats.hash_collisions > 0 {
+        verbose!("  hash collisions: {}", outcome.stats.hash_collisions);
+    }
+    for (name, stats) in [
+        ("forward", &outcome.stats.forward),
+        ("reverse", &outcome.stats.reverse),
+    ] {
+        verbose!(
+            "  {}: states: {}  peak open: {}  peak table: {}  heuristics: {}  peak memory: {:.1} MB",
+            name,
+            stats.nodes_explored,
+            stats.peak_open,
+            stats.peak_table,
+            stats.heuristic_entries,
+            stats.peak_memory as f64 / megabytes(1) as f64
+        );
+    }
+    if let Some(direction) = outcome.stats.meeting_direction {
+        verbose!(
+            "  meeting direction: {}",
+            value_name(Direction::from(direction))
+        );
+    }
+    if let Some(direction) = outcome.stats.disabled_direction {
+        verbose!(
+            "  disabled direction: {}",
+            value_name(Direction::from(direction))
+        );
+    }
+    if let Some(winner) = outcome.portfolio_winner {
+        verbose!("  portfolio winner: {}", winner);
+    }
+
+    if let SolveResult::Interrupted = outcome.result {
+        print_interrupted(level_num, &outcome);
+    }
+
+    let stats = LevelStats {
+        level_num,
+        solved,
+        result: result_name,
+        steps: solution_len,
+        states_explored: nodes_explored,
+        unique_box_configs: outcome.stats.unique_box_configs,
+        elapsed_ms: outcome.elapsed_ms,
+    };
+
+    if let SolveResult::Solved(solution) = outcome.result {
+        if log::enabled(Level::Verbose) {
+            println!("  moves: {}", to_lurd(game, &solution).len());
         }
-        HeuristicType::Greedy => {
-            solve_level_helper::<GreedyHeuristic>(game, level_num, opts, print_solution)
+        write_solution(game, &solution, output, &stats);
+    } else if let SolveResult::Unsolvable = outcome.result {
+        print_unsolvable(game, max_depth);
+        if let Some(dir) = &output.certificates_dir {
+            write_certificate(game, level_num, dir, output.certificate_max_states);
         }
-        HeuristicType::Hungarian => {
-            solve_level_helper::<HungarianHeuristic>(game, level_num, opts, print_solution)
+    }
+
+    stats
+}
+
+/// Path of the certificate for the given level within a certificates
+/// directory.
+fn certificate_path(dir: &str, level_num: usize) -> PathBuf {
+    Path::new(dir).join(format!("level_{:03}.cert", level_num))
+}
+
+/// Write a certificate proving the level unsolvable, if one can be built.
+fn write_certificate(game: &Game, level_num: usize, dir: &str, max_states: usize) {
+    let certificate = match build_certificate(game, max_states) {
+        Ok(certificate) => certificate,
+        Err(e) => {
+            eprintln!("Could not certify level {}: {}", level_num, e);
+            return;
         }
-        HeuristicType::Null => {
-            solve_level_helper::<NullHeuristic>(game, level_num, opts, print_solution)
+    };
+    let path = certificate_path(dir, level_num);
+    let result = fs::create_dir_all(dir).and_then(|_| fs::write(&path, certificate.to_string()));
+    match result {
+        Ok(()) => verbose!("  certificate: {}", path.display()),
+        Err(e) => eprintln!("Error writing certificate to {}: {}", path.display(), e),
+    }
+}
+
+/// Report why a level is unsolvable, drawing the boxes responsible as 'X'.
+fn print_unsolvable(game: &Game, max_depth: Option<usize>) {
+    let reason = explain_unsolvable(game);
+    match (&reason, max_depth) {
+        (Unsolvability::Exhausted, Some(max_depth)) => {
+            info!("  unsolvable: no solution within {} steps", max_depth)
         }
+        _ => info!("  unsolvable: {}", reason),
+    }
+    if !reason.positions().is_empty() {
+        info!("\n{}", reason.render(game));
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|_| format!("invalid number: {}", s))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Convert a size in megabytes to bytes.
+fn megabytes(mb: usize) -> usize {
+    mb.saturating_mul(1 << 20)
+}
+
+fn parse_weight(s: &str) -> Result<f64, String> {
+    let weight: f64 = s.parse().map_err(|_| format!("invalid number: {}", s))?;
+    check_weight(weight)
+}
+
+fn check_weight(weight: f64) -> Result<f64, String> {
+    if weight.is_finite() && weight >= 0.0 {
+        Ok(weight)
+    } else {
+        Err(format!("weight must be a non-negative number: {}", weight))
     }
 }
 
+fn parse_ratio(s: &str) -> Result<(usize, usize), String> {
+    let (forward, reverse) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected F:R, got: {}", s))?;
+    let parse = |n: &str| match n.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "invalid effort (must be a positive integer): {}",
+            n
+        )),
+    };
+    Ok((parse(forward)?, parse(reverse)?))
+}
+
 fn parse_trace_range(s: &str) -> Result<Range<usize>, String> {
     // Try parsing as "start..=end" (inclusive)
     if let Some((start, end)) = s.split_once("..=") {
@@ -176,133 +877,2254 @@ fn parse_trace_range(s: &str) -> Result<Range<usize>, String> {
 #[derive(Parser)]
 #[command(name = "sisyphus")]
 #[command(about = "A Sokoban solver", long_about = None)]
-struct Args {
-    /// Path to the levels file (XSB format)
-    #[arg(value_name = "FILE")]
-    levels_file: String,
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 
-    /// Level number to solve (1-indexed), or start of range
-    #[arg(value_name = "LEVEL")]
-    level_start: usize,
+    /// Increase output verbosity (-v for search statistics, -vv for traces)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
 
-    /// Optional end of level range (inclusive, 1-indexed)
-    #[arg(value_name = "LEVEL_END")]
-    level_end: Option<usize>,
+    /// Only print summaries and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
 
-    /// Print the solution step-by-step
-    #[arg(short, long)]
-    print_solution: bool,
+    /// Read default solver and output options from the given TOML file.
+    /// Options given on the command line take precedence.
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<String>,
+}
 
-    /// Maximum number of nodes to explore before giving up
-    #[arg(short = 'n', long, default_value = "5000000")]
-    max_nodes: usize,
+#[derive(Subcommand)]
+enum Command {
+    /// Solve levels
+    Solve(SolveArgs),
+    /// Verify solutions against levels
+    Verify(VerifyArgs),
+    /// Check certificates proving levels unsolvable
+    Certify(CertifyArgs),
+    /// Benchmark the solver by solving levels repeatedly
+    Bench(BenchArgs),
+    /// Print statistics about levels
+    Stats(StatsArgs),
+    /// Print the corrals in each level's position, for debugging corral code
+    Corrals(CorralsArgs),
+    /// Play a level interactively
+    Play(PlayArgs),
+    /// Run every combination of levels and solver options, writing a CSV
+    Experiment(ExperimentArgs),
+}
 
-    /// Heuristic to use for solving
-    #[arg(short = 'H', long, value_enum, default_value = "hungarian")]
-    heuristic: HeuristicType,
+/// Arguments selecting a range of levels from one or more levels files.
+#[derive(Args)]
+struct LevelArgs {
+    /// Paths or glob patterns of levels files (XSB format), or "-" to read
+    /// from stdin, optionally followed by a level number (1-indexed) or the
+    /// start and end of an inclusive level range. All levels in each file are
+    /// selected if no level is given.
+    #[arg(value_name = "FILE", required_unless_present = "board")]
+    inputs: Vec<String>,
 
-    /// Search type
-    #[arg(short = 'd', long, value_enum, default_value = "bidirectional")]
-    direction: Direction,
+    /// Use the given XSB board instead of a levels file. Rows are separated
+    /// by newlines or '|'.
+    #[arg(long, value_name = "TEXT", conflicts_with = "inputs")]
+    board: Option<String>,
 
-    /// Disable freeze deadlock detection
-    #[arg(long, default_value = "false")]
-    no_freeze_deadlocks: bool,
+    /// Abort on the first level which fails to parse or exceeds solver
+    /// limits, instead of skipping it and solving the rest
+    #[arg(long)]
+    strict: bool,
 
-    /// Disable dead square pruning
-    #[arg(long, default_value = "false")]
-    no_dead_squares: bool,
+    /// Skip invalid levels (now the default, see --strict)
+    #[arg(long, hide = true, conflicts_with = "strict")]
+    skip_invalid: bool,
 
-    /// Disable PI-corral pruning
-    #[arg(long, default_value = "false")]
-    no_pi_corrals: bool,
+    /// Accept levels with more goals than boxes, which are solved once every
+    /// box is on a goal, or more boxes than goals, which are unsolvable
+    #[arg(long)]
+    unbalanced: bool,
 
-    /// Maximum nodes to explore when searching for corral deadlocks
-    #[arg(long, default_value = "20")]
-    deadlock_max_nodes: usize,
+    /// Select the level with the given title (from a "Title:" line after it,
+    /// ignoring case) in each file, instead of giving level numbers
+    #[arg(long, value_name = "TITLE")]
+    level_name: Option<String>,
 
-    /// Range of node counts to trace (e.g., "100..200", "100..=200", or "100")
-    #[arg(short = 't', long, value_parser = parse_trace_range)]
-    trace_range: Option<Range<usize>>,
+    /// Start levels with a saved game (a "Snapshot" section of LURD moves
+    /// after the level) from its position, solving the rest of the level
+    #[arg(long)]
+    snapshot: bool,
 }
 
-fn main() {
-    let args = Args::parse();
+/// The levels loaded from a single levels file, along with the (1-indexed)
+/// selected level numbers.
+struct LevelSource {
+    name: String,
+    levels: Levels,
+    range: RangeInclusive<usize>,
+}
 
-    // Load levels from file
-    let levels = match Levels::from_file(&args.levels_file) {
-        Ok(levels) => levels,
-        Err(e) => {
-            eprintln!("Error loading levels: {}", e);
+impl LevelSource {
+    /// The title of the given (1-indexed) level, if it has one.
+    fn title(&self, level_num: usize) -> Option<&str> {
+        self.levels
+            .level(level_num - 1)
+            .and_then(|level| level.title.as_deref())
+    }
+}
+
+impl LevelArgs {
+    /// Load a single levels file and validate the selected range, exiting on
+    /// error. Returns the levels along with the (1-indexed) selected level
+    /// numbers.
+    fn load(&self) -> (Levels, RangeInclusive<usize>) {
+        let mut sources = self.load_all();
+        if sources.len() > 1 {
+            eprintln!("Error: this command accepts a single levels file");
             std::process::exit(1);
         }
-    };
+        let source = sources.pop().unwrap();
+        (source.levels, source.range)
+    }
+
+    /// Load every selected levels file and validate the selected range in
+    /// each, exiting on error.
+    fn load_all(&self) -> Vec<LevelSource> {
+        let (paths, level_start, level_end) = self.split_inputs();
+        if self.board.is_none() && paths.is_empty() {
+            eprintln!("Error: no levels file given");
+            std::process::exit(1);
+        }
+
+        let names: Vec<String> = if self.board.is_some() {
+            vec!["<board>".to_string()]
+        } else {
+            paths.iter().flat_map(|path| expand_glob(path)).collect()
+        };
+
+        names
+            .into_iter()
+            .map(|name| {
+                let levels = self.load_levels(&name).unwrap_or_else(|e| {
+                    eprintln!("Error loading levels from {}: {}", name, e);
+                    std::process::exit(1);
+                });
+                let range = select_range(
+                    &name,
+                    &levels,
+                    level_start,
+                    level_end,
+                    self.level_name.as_deref(),
+                );
+                LevelSource {
+                    name,
+                    levels,
+                    range,
+                }
+            })
+            .collect()
+    }
+
+    /// Split the positional arguments into file paths and the trailing level
+    /// numbers.
+    fn split_inputs(&self) -> (&[String], Option<usize>, Option<usize>) {
+        let numbers = self
+            .inputs
+            .iter()
+            .rev()
+            .take(2)
+            .take_while(|input| input.parse::<usize>().is_ok())
+            .count();
+        let (paths, numbers) = self.inputs.split_at(self.inputs.len() - numbers);
+        let mut numbers = numbers.iter().map(|n| n.parse().unwrap());
+        (paths, numbers.next(), numbers.next())
+    }
+
+    fn load_levels(&self, name: &str) -> Result<Levels, LevelError> {
+        let (skip_invalid, unbalanced) = (!self.strict, self.unbalanced);
+        let mut levels = match &self.board {
+            Some(board) => {
+                Levels::from_text_with(&board.replace('|', "\n"), skip_invalid, unbalanced)?
+            }
+            None if name == "-" => {
+                let text = io::read_to_string(io::stdin())?;
+                Levels::from_text_with(&text, skip_invalid, unbalanced)?
+            }
+            None => Levels::from_file_with(name, skip_invalid, unbalanced)?,
+        };
+        if self.snapshot {
+            levels.use_snapshots();
+            if self.strict {
+                levels.check()?;
+            }
+        }
+        Ok(levels)
+    }
+}
+
+/// Expand a glob pattern into the matching paths, exiting if nothing matches.
+/// Paths without glob metacharacters are returned as is.
+fn expand_glob(pattern: &str) -> Vec<String> {
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![pattern.to_string()];
+    }
+    let paths = glob::glob(pattern).unwrap_or_else(|e| {
+        eprintln!("Error: invalid pattern {}: {}", pattern, e);
+        std::process::exit(1);
+    });
+    let paths: Vec<String> = paths
+        .filter_map(Result::ok)
+        .map(|path| path.display().to_string())
+        .collect();
+    if paths.is_empty() {
+        eprintln!("Error: no files match {}", pattern);
+        std::process::exit(1);
+    }
+    paths
+}
+
+/// Validate the selected level range against the levels in a file, or find
+/// the level with the given title, exiting on error.
+fn select_range(
+    name: &str,
+    levels: &Levels,
+    level_start: Option<usize>,
+    level_end: Option<usize>,
+    level_name: Option<&str>,
+) -> RangeInclusive<usize> {
+    if let Some(title) = level_name {
+        if level_start.is_some() {
+            eprintln!("Error: give either level numbers or --level-name");
+            std::process::exit(1);
+        }
+        let Some(index) = levels.find_title(title) else {
+            eprintln!("Error: no level titled '{}' in {}", title, name);
+            std::process::exit(1);
+        };
+        return index + 1..=index + 1;
+    }
 
-    // Determine the range of levels to solve
-    let level_end = args.level_end.unwrap_or(args.level_start);
-    let num_levels = level_end - args.level_start + 1;
+    let (level_start, level_end) = match level_start {
+        Some(level_start) => (level_start, level_end.unwrap_or(level_start)),
+        None => (1, levels.len()),
+    };
 
-    // Validate range
-    if args.level_start == 0 {
+    if level_start == 0 {
         eprintln!("Error: level numbers must be at least 1");
         std::process::exit(1);
     }
 
-    if level_end < args.level_start {
+    if level_end < level_start {
         eprintln!("Error: level end must be >= level start");
         std::process::exit(1);
     }
 
     if level_end > levels.len() {
         eprintln!(
-            "Error: level {} not found (file contains {} levels)",
+            "Error: level {} not found ({} contains {} levels)",
             level_end,
+            name,
             levels.len()
         );
         std::process::exit(1);
     }
 
-    if args.print_solution && num_levels > 1 {
-        eprintln!("Error: solution printing only supported when solving a single level");
-        std::process::exit(1);
-    }
+    level_start..=level_end
+}
 
-    // Solve each level in the range
-    let mut total_solved = 0;
-    let mut total_steps = 0;
-    let mut total_states = 0;
-    let mut total_time_ms = 0;
-
-    // Use 0..0 for no tracing
-    let trace_range = args.trace_range.unwrap_or(0..0);
-
-    for level_num in args.level_start..=level_end {
-        let game = levels.get(level_num - 1).unwrap();
-        let opts = SolverOpts {
-            search_type: args.direction.into(),
-            max_nodes_explored: args.max_nodes,
-            freeze_deadlocks: !args.no_freeze_deadlocks,
-            dead_squares: !args.no_dead_squares,
-            pi_corrals: !args.no_pi_corrals,
-            deadlock_max_nodes: args.deadlock_max_nodes,
-            trace_range: trace_range.clone(),
+/// Arguments configuring the solver.
+#[derive(Args)]
+struct SolverArgs {
+    /// Maximum number of nodes to explore before giving up
+    #[arg(short = 'n', long, default_value = "5000000")]
+    max_nodes: usize,
+
+    /// Maximum wall-clock time to spend on each level, in seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    max_time: Option<Duration>,
+
+    /// Approximate memory limit for the search, in megabytes. Once exceeded,
+    /// the worst open nodes are evicted and regenerated later if needed, then
+    /// closed positions no open one was reached from are forgotten. IDA* uses
+    /// it for a fixed-size transposition table.
+    #[arg(long, value_name = "MB")]
+    max_memory: Option<usize>,
+
+    /// Only search for solutions of at most this many pushes (or moves, with
+    /// --cost moves). Levels without one are reported as unsolvable.
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Heuristic to use for solving
+    #[arg(short = 'H', long, value_enum, default_value = "hungarian")]
+    heuristic: HeuristicType,
+
+    /// Also compute these heuristics (comma-separated), using the maximum of
+    /// them and --heuristic. The maximum is admissible if all of them are.
+    #[arg(long, value_name = "HEURISTICS", value_enum, value_delimiter = ',')]
+    max_with: Vec<HeuristicType>,
+
+    /// Search type
+    #[arg(short = 'd', long, value_enum, default_value = "bidirectional")]
+    direction: Direction,
+
+    /// How bidirectional search chooses the direction to expand next
+    #[arg(long, value_enum, default_value = "alternate")]
+    balance: Balance,
+
+    /// Forward to reverse effort in bidirectional search: expansions per
+    /// round when alternating, or open list sizes when balancing frontiers
+    #[arg(long, value_name = "F:R", default_value = "1:1", value_parser = parse_ratio)]
+    ratio: (usize, usize),
+
+    /// Perimeter search: expand this many reverse nodes first, then search
+    /// only forwards until reaching a position the reverse search reached
+    #[arg(long, value_name = "NODES")]
+    perimeter: Option<usize>,
+
+    /// Disable freeze deadlock detection
+    #[arg(long, default_value = "false")]
+    no_freeze_deadlocks: bool,
+
+    /// Disable the fixed deadlock patterns (a box in a corner, two boxes
+    /// against a wall, 2x2 blocks) checked after each push
+    #[arg(long, default_value = "false")]
+    no_deadlock_patterns: bool,
+
+    /// Disable dead square pruning
+    #[arg(long, default_value = "false")]
+    no_dead_squares: bool,
+
+    /// Disable matching deadlock detection, which prunes positions whose
+    /// boxes can't all be matched to distinct goals they can reach
+    #[arg(long, default_value = "false")]
+    no_matching_deadlocks: bool,
+
+    /// Disable PI-corral pruning
+    #[arg(long, default_value = "false")]
+    no_pi_corrals: bool,
+
+    /// Nodes to explore when first searching a corral for deadlocks, doubled
+    /// each time the same corral's search is cut off (up to 4 times, reaching
+    /// 16 times the original budget)
+    #[arg(long, default_value = "20")]
+    deadlock_max_nodes: usize,
+
+    /// After each push, search the pushed box together with each one or two
+    /// boxes around it, with every other box removed, and prune the push if
+    /// they can't be put on goals
+    #[arg(long)]
+    conflict_deadlocks: bool,
+
+    /// Maximum nodes to explore for each group of boxes probed for conflict
+    /// deadlocks
+    #[arg(long, default_value = "50")]
+    conflict_max_nodes: usize,
+
+    /// Avoid PI-corral pruning which would exclude every push onto a goal
+    /// while empty goals remain outside the corral
+    #[arg(long)]
+    corral_safety: bool,
+
+    /// Restrict bidirectional search's pulls to those out of a pull corral
+    /// when they can all be made. Unlike PI-corral pruning this isn't safe, as
+    /// a box pulled out early can block pulls made elsewhere, so it may hide
+    /// positions the forward search would meet
+    #[arg(long)]
+    pull_corral_pruning: bool,
+
+    /// Search for a push-optimal solution using A*. Implies a forward search,
+    /// and requires an admissible heuristic (simple, hungarian,
+    /// linear-conflict, goal-room or null).
+    #[arg(long)]
+    optimal: bool,
+
+    /// Cost to minimise. Counting moves implies --optimal.
+    #[arg(long, value_enum, default_value = "pushes")]
+    cost: Cost,
+
+    /// Weight of the heuristic in optimal search (f = g + W * h). Weights
+    /// above 1 trade optimality for speed.
+    #[arg(long, value_name = "W", default_value = "1", value_parser = parse_weight)]
+    weight: f64,
+
+    /// Search algorithm. IDA* implies an optimal forward search, and trades
+    /// re-expanding nodes for using very little memory. FESS spreads the
+    /// search across kinds of progress, which helps on large open levels.
+    #[arg(long, value_enum, default_value = "best-first")]
+    algorithm: Algorithm,
+
+    /// Keep searching for cheaper solutions after the first is found, until
+    /// the node or time limit is hit, and report the best one.
+    #[arg(long)]
+    anytime: bool,
+
+    /// Reopen positions when a shorter path to them is found, giving shorter
+    /// solutions at the cost of re-expanding positions. Implied by --optimal.
+    #[arg(long)]
+    reopen: bool,
+
+    /// In bidirectional search, stop expanding a direction once it stalls,
+    /// i.e. stops getting closer to the other side while its open list grows
+    /// much larger, and give its budget to the other direction.
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Break ties between positions of equal priority by the player's walking
+    /// distance to the nearest box it can usefully move, so that positions
+    /// where the player is stranded far from useful work are expanded later
+    #[arg(long)]
+    player_tie_break: bool,
+
+    /// Order of positions of equal priority in the open list. Ordering by a
+    /// key (lowest-cost, most-on-goals) only tells apart priorities up to a
+    /// sixteenth of the usual range, and can't be combined with
+    /// --player-tie-break
+    #[arg(long, value_enum, default_value = "fifo")]
+    tie_break: TieBreakOrder,
+
+    /// Learn penalty patterns during search: box subsets found by the
+    /// PI-corral search which need more pushes on their own than their
+    /// distances to the nearest goals. The heuristic of every position
+    /// containing them is raised to at least their pushes plus the other
+    /// boxes' distances to the nearest goals
+    #[arg(long)]
+    penalties: bool,
+
+    /// Race several configurations on their own threads, each given as
+    /// HEURISTIC[:DIRECTION[:MAX_NODES]] (comma-separated), and use the first
+    /// to solve the level. Other options apply to every configuration.
+    #[arg(
+        long,
+        value_name = "CONFIGS",
+        value_delimiter = ',',
+        value_parser = parse_portfolio_entry
+    )]
+    portfolio: Vec<PortfolioEntry>,
+
+    /// Search on this many threads, sharing their open lists and
+    /// transposition table. With more than one, a greedy forward search is
+    /// run, ignoring --direction and --max-memory.
+    #[arg(long, default_value = "1")]
+    threads: usize,
+
+    /// Never let two positions share a transposition table entry unless a
+    /// second, independent 64-bit hash of them matches too, rather than
+    /// trusting the first hash alone. Costs a little time, and isn't
+    /// supported with --threads.
+    #[arg(long)]
+    paranoid_hash: bool,
+
+    /// Don't skip positions which are mirror images or rotations of one
+    /// already expanded, on levels whose walls and goals are symmetric
+    #[arg(long, default_value = "false")]
+    no_symmetry: bool,
+
+    /// Don't mark the positions visited by corral deadlock searches which
+    /// find a deadlock dead in the forward search's transposition table
+    #[arg(long, default_value = "false")]
+    no_share_deadlocks: bool,
+
+    /// Seed of the Zobrist hashes identifying positions. Solving again with
+    /// another seed rules out a hash collision behind a suspect result.
+    #[arg(long, value_name = "SEED")]
+    zobrist_seed: Option<u64>,
+
+    /// Compare a second, independent hash on every transposition table hit,
+    /// printing those made by a different position
+    #[arg(long)]
+    hash_diagnostics: bool,
+
+    /// Range of node counts to trace (e.g., "100..200", "100..=200", or "100").
+    /// Traces are only printed at -vv.
+    #[arg(short = 't', long, value_parser = parse_trace_range)]
+    trace_range: Option<Range<usize>>,
+}
+
+impl SolverArgs {
+    /// Fill in the options not given on the command line from a config file.
+    fn apply_config(&mut self, config: &SolverConfig, matches: &ArgMatches) -> Result<(), String> {
+        if let Some(heuristic) = &config.heuristic
+            && !from_command_line(matches, "heuristic")
+        {
+            self.heuristic = parse_value_enum(heuristic)?;
+        }
+        if let Some(max_with) = &config.max_with
+            && !from_command_line(matches, "max_with")
+        {
+            self.max_with = max_with
+                .iter()
+                .map(|heuristic| parse_value_enum(heuristic))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(direction) = &config.direction
+            && !from_command_line(matches, "direction")
+        {
+            self.direction = parse_value_enum(direction)?;
+        }
+        if let Some(balance) = &config.balance
+            && !from_command_line(matches, "balance")
+        {
+            self.balance = parse_value_enum(balance)?;
+        }
+        if let Some(ratio) = &config.ratio
+            && !from_command_line(matches, "ratio")
+        {
+            self.ratio = parse_ratio(ratio)?;
+        }
+        if let Some(perimeter) = config.perimeter
+            && !from_command_line(matches, "perimeter")
+        {
+            self.perimeter = Some(perimeter);
+        }
+        if let Some(max_nodes) = config.max_nodes
+            && !from_command_line(matches, "max_nodes")
+        {
+            self.max_nodes = max_nodes;
+        }
+        if let Some(max_time) = config.max_time
+            && !from_command_line(matches, "max_time")
+        {
+            self.max_time = Some(Duration::try_from_secs_f64(max_time).map_err(|e| e.to_string())?);
+        }
+        if let Some(max_memory) = config.max_memory
+            && !from_command_line(matches, "max_memory")
+        {
+            self.max_memory = Some(max_memory);
+        }
+        if let Some(max_depth) = config.max_depth
+            && !from_command_line(matches, "max_depth")
+        {
+            self.max_depth = Some(max_depth);
+        }
+        if let Some(freeze_deadlocks) = config.freeze_deadlocks
+            && !from_command_line(matches, "no_freeze_deadlocks")
+        {
+            self.no_freeze_deadlocks = !freeze_deadlocks;
+        }
+        if let Some(deadlock_patterns) = config.deadlock_patterns
+            && !from_command_line(matches, "no_deadlock_patterns")
+        {
+            self.no_deadlock_patterns = !deadlock_patterns;
+        }
+        if let Some(dead_squares) = config.dead_squares
+            && !from_command_line(matches, "no_dead_squares")
+        {
+            self.no_dead_squares = !dead_squares;
+        }
+        if let Some(matching_deadlocks) = config.matching_deadlocks
+            && !from_command_line(matches, "no_matching_deadlocks")
+        {
+            self.no_matching_deadlocks = !matching_deadlocks;
+        }
+        if let Some(pi_corrals) = config.pi_corrals
+            && !from_command_line(matches, "no_pi_corrals")
+        {
+            self.no_pi_corrals = !pi_corrals;
+        }
+        if let Some(deadlock_max_nodes) = config.deadlock_max_nodes
+            && !from_command_line(matches, "deadlock_max_nodes")
+        {
+            self.deadlock_max_nodes = deadlock_max_nodes;
+        }
+        if let Some(conflict_deadlocks) = config.conflict_deadlocks
+            && !from_command_line(matches, "conflict_deadlocks")
+        {
+            self.conflict_deadlocks = conflict_deadlocks;
+        }
+        if let Some(conflict_max_nodes) = config.conflict_max_nodes
+            && !from_command_line(matches, "conflict_max_nodes")
+        {
+            self.conflict_max_nodes = conflict_max_nodes;
+        }
+        if let Some(corral_safety) = config.corral_safety
+            && !from_command_line(matches, "corral_safety")
+        {
+            self.corral_safety = corral_safety;
+        }
+        if let Some(pull_corral_pruning) = config.pull_corral_pruning
+            && !from_command_line(matches, "pull_corral_pruning")
+        {
+            self.pull_corral_pruning = pull_corral_pruning;
+        }
+        if let Some(optimal) = config.optimal
+            && !from_command_line(matches, "optimal")
+        {
+            self.optimal = optimal;
+        }
+        if let Some(cost) = &config.cost
+            && !from_command_line(matches, "cost")
+        {
+            self.cost = parse_value_enum(cost)?;
+        }
+        if let Some(weight) = config.weight
+            && !from_command_line(matches, "weight")
+        {
+            self.weight = check_weight(weight)?;
+        }
+        if let Some(algorithm) = &config.algorithm
+            && !from_command_line(matches, "algorithm")
+        {
+            self.algorithm = parse_value_enum(algorithm)?;
+        }
+        if let Some(anytime) = config.anytime
+            && !from_command_line(matches, "anytime")
+        {
+            self.anytime = anytime;
+        }
+        if let Some(reopen) = config.reopen
+            && !from_command_line(matches, "reopen")
+        {
+            self.reopen = reopen;
+        }
+        if let Some(adaptive) = config.adaptive
+            && !from_command_line(matches, "adaptive")
+        {
+            self.adaptive = adaptive;
+        }
+        if let Some(player_tie_break) = config.player_tie_break
+            && !from_command_line(matches, "player_tie_break")
+        {
+            self.player_tie_break = player_tie_break;
+        }
+        if let Some(tie_break) = &config.tie_break
+            && !from_command_line(matches, "tie_break")
+        {
+            self.tie_break = parse_value_enum(tie_break)?;
+        }
+        if let Some(penalties) = config.penalties
+            && !from_command_line(matches, "penalties")
+        {
+            self.penalties = penalties;
+        }
+        if let Some(portfolio) = &config.portfolio
+            && !from_command_line(matches, "portfolio")
+        {
+            self.portfolio = portfolio
+                .iter()
+                .map(|entry| parse_portfolio_entry(entry))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(threads) = config.threads
+            && !from_command_line(matches, "threads")
+        {
+            self.threads = threads;
+        }
+        if let Some(paranoid_hash) = config.paranoid_hash
+            && !from_command_line(matches, "paranoid_hash")
+        {
+            self.paranoid_hash = paranoid_hash;
+        }
+        if let Some(symmetry) = config.symmetry
+            && !from_command_line(matches, "no_symmetry")
+        {
+            self.no_symmetry = !symmetry;
+        }
+        if let Some(share_deadlocks) = config.share_deadlocks
+            && !from_command_line(matches, "no_share_deadlocks")
+        {
+            self.no_share_deadlocks = !share_deadlocks;
+        }
+        if let Some(zobrist_seed) = config.zobrist_seed
+            && !from_command_line(matches, "zobrist_seed")
+        {
+            self.zobrist_seed = Some(zobrist_seed);
+        }
+        if let Some(hash_diagnostics) = config.hash_diagnostics
+            && !from_command_line(matches, "hash_diagnostics")
+        {
+            self.hash_diagnostics = hash_diagnostics;
+        }
+        Ok(())
+    }
+
+    /// Check that the options can be used with parallel search, which only
+    /// supports greedy best-first search.
+    fn check_threads(&self) -> Result<(), String> {
+        if self.threads == 0 {
+            return Err("--threads must be at least 1".to_string());
+        }
+        if self.threads == 1 {
+            return Ok(());
+        }
+        let unsupported = [
+            (self.optimal, "--optimal"),
+            (matches!(self.cost, Cost::Moves), "--cost moves"),
+            (
+                !matches!(self.algorithm, Algorithm::BestFirst),
+                "--algorithm",
+            ),
+            (self.anytime, "--anytime"),
+            (self.reopen, "--reopen"),
+            (self.max_depth.is_some(), "--max-depth"),
+            (self.paranoid_hash, "--paranoid-hash"),
+            (
+                !matches!(self.tie_break, TieBreakOrder::Fifo),
+                "--tie-break",
+            ),
+        ];
+        match unsupported.iter().find(|(used, _)| *used) {
+            Some((_, option)) => Err(format!("{} is not supported with --threads", option)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the tie-breaking options can be used together, as both
+    /// split each priority into finer ones.
+    fn check_tie_break(&self) -> Result<(), String> {
+        let keyed = matches!(
+            self.tie_break,
+            TieBreakOrder::LowestCost | TieBreakOrder::MostOnGoals
+        );
+        if keyed && self.player_tie_break {
+            return Err(format!(
+                "--tie-break {} can't be combined with --player-tie-break",
+                value_name(self.tie_break)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Warn if optimal search was requested with a heuristic which can't
+    /// guarantee optimality.
+    fn warn_if_inadmissible(&self) {
+        let optimal = self.optimal
+            || matches!(self.cost, Cost::Moves)
+            || matches!(self.algorithm, Algorithm::IdaStar);
+        let is_greedy = |heuristic: HeuristicType| {
+            matches!(
+                heuristic,
+                HeuristicType::Greedy | HeuristicType::GreedyRefined
+            )
+        };
+        let greedy = if self.portfolio.is_empty() {
+            is_greedy(self.heuristic)
+        } else {
+            self.portfolio
+                .iter()
+                .any(|entry| is_greedy(entry.heuristic))
         };
-        let stats = solve_level(game, level_num, opts, args.heuristic, args.print_solution);
+        let greedy = greedy || self.max_with.iter().copied().any(is_greedy);
+        if optimal && greedy {
+            eprintln!(
+                "Warning: the greedy heuristic is not admissible, so solutions may not be optimal"
+            );
+        }
+    }
 
-        if stats.solved {
-            total_solved += 1;
+    fn opts(&self) -> SolverOpts {
+        SolverOpts {
+            search_type: self.direction.into(),
+            balance: self.balance.into(),
+            ratio: self.ratio,
+            perimeter: self.perimeter,
+            max_nodes_explored: self.max_nodes,
+            max_time: self.max_time,
+            max_memory: self.max_memory.map(megabytes),
+            freeze_deadlocks: !self.no_freeze_deadlocks,
+            deadlock_patterns: !self.no_deadlock_patterns,
+            dead_squares: !self.no_dead_squares,
+            matching_deadlocks: !self.no_matching_deadlocks,
+            conflict_deadlocks: self.conflict_deadlocks,
+            conflict_max_nodes: self.conflict_max_nodes,
+            pi_corrals: !self.no_pi_corrals,
+            deadlock_max_nodes: self.deadlock_max_nodes,
+            corral_safety: self.corral_safety,
+            pull_corral_pruning: self.pull_corral_pruning,
+            optimal: self.optimal,
+            cost: self.cost.into(),
+            weight: self.weight,
+            algorithm: self.algorithm.into(),
+            anytime: self.anytime,
+            reopen: self.reopen,
+            max_depth: self.max_depth,
+            adaptive: self.adaptive,
+            player_tie_break: self.player_tie_break,
+            tie_break: self.tie_break.into(),
+            penalties: self.penalties,
+            threads: self.threads,
+            paranoid_hash: self.paranoid_hash,
+            zobrist_seed: self.zobrist_seed,
+            symmetry: !self.no_symmetry,
+            share_deadlocks: !self.no_share_deadlocks,
+            hash_diagnostics: self.hash_diagnostics,
+            // Use 0..0 for no tracing
+            trace_range: self.trace_range.clone().unwrap_or(0..0),
+        }
+    }
+}
+
+#[derive(Args)]
+struct SolveArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Print the solution step-by-step
+    #[arg(short, long)]
+    print_solution: bool,
+
+    /// Format used when printing the solution
+    #[arg(long, value_enum, default_value = "boards")]
+    format: SolutionFormat,
+
+    /// Write the solution as an animated SVG to the given file
+    #[arg(long, value_name = "FILE")]
+    svg_out: Option<String>,
+
+    /// Write one SVG frame per push of the solution into the given directory
+    #[arg(long, value_name = "DIR")]
+    svg_frames: Option<String>,
+
+    /// Write an HTML page for stepping through the solution to the given file
+    #[arg(long, value_name = "FILE")]
+    html_out: Option<String>,
+
+    /// Stream search events as JSON Lines to the given file ("-" for stdout)
+    #[arg(long, value_name = "FILE")]
+    events: Option<String>,
+
+    /// Record each solved level (options, heuristic, nodes, time and result)
+    /// into the given SQLite database
+    #[arg(long, value_name = "FILE")]
+    stats_db: Option<String>,
+
+    /// Load corral deadlock search results saved for each level from the
+    /// given SQLite database, and save the new ones, so that re-running levels
+    /// doesn't redo the same deadlock proofs
+    #[arg(long, value_name = "FILE")]
+    deadlock_cache: Option<String>,
+
+    /// Write a certificate proving each unsolvable level unsolvable into the
+    /// given directory, as level_NNN.cert (check them with the certify
+    /// command)
+    #[arg(long, value_name = "DIR")]
+    certificates: Option<String>,
+
+    /// Maximum number of positions listed in a certificate, beyond which the
+    /// level is left uncertified
+    #[arg(long, value_name = "STATES", default_value = "100000")]
+    certificate_max_states: usize,
+
+    /// After solving the range, re-attempt levels which hit the node or time
+    /// limit for up to the given number of rounds, with larger limits each
+    /// round
+    #[arg(long, value_name = "ROUNDS", default_value = "0")]
+    escalate: usize,
+
+    /// Factor by which the node, corral deadlock and time limits grow on each
+    /// escalation round
+    #[arg(long, value_name = "FACTOR", default_value = "4")]
+    escalate_factor: usize,
+
+    /// Heuristic to use when re-attempting levels (defaults to --heuristic)
+    #[arg(long, value_enum, value_name = "HEURISTIC")]
+    escalate_heuristic: Option<HeuristicType>,
+
+    /// Number of levels to solve in parallel. Each level's output is still
+    /// printed in order, but timings are less reliable when running in
+    /// parallel.
+    #[arg(short, long, default_value = "1")]
+    jobs: usize,
+}
+
+impl SolveArgs {
+    /// Fill in the options not given on the command line from a config file.
+    fn apply_config(&mut self, config: &Config, matches: &ArgMatches) -> Result<(), String> {
+        self.solver.apply_config(&config.solver, matches)?;
+        let output = &config.output;
+        if let Some(print_solution) = output.print_solution
+            && !from_command_line(matches, "print_solution")
+        {
+            self.print_solution = print_solution;
+        }
+        if let Some(format) = &output.format
+            && !from_command_line(matches, "format")
+        {
+            self.format = parse_value_enum(format)?;
+        }
+        if output.events.is_some() && !from_command_line(matches, "events") {
+            self.events = output.events.clone();
+        }
+        if output.stats_db.is_some() && !from_command_line(matches, "stats_db") {
+            self.stats_db = output.stats_db.clone();
+        }
+        if output.deadlock_cache.is_some() && !from_command_line(matches, "deadlock_cache") {
+            self.deadlock_cache = output.deadlock_cache.clone();
+        }
+        if output.certificates.is_some() && !from_command_line(matches, "certificates") {
+            self.certificates = output.certificates.clone();
+        }
+        Ok(())
+    }
+}
+
+/// Whether the given argument was explicitly passed on the command line.
+fn from_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// Parse an enum value by its command-line name.
+fn parse_value_enum<T: ValueEnum>(value: &str) -> Result<T, String> {
+    T::from_str(value, false)
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+
+    /// Solutions file (one LURD or push list per level)
+    #[arg(short, long, value_name = "SOLUTION_FILE")]
+    solutions: String,
+}
+
+#[derive(Args)]
+struct CertifyArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+
+    /// Directory of certificates written by solve --certificates
+    #[arg(short, long, value_name = "DIR")]
+    certificates: String,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Number of times to solve each level
+    #[arg(short, long, default_value = "3")]
+    repeat: usize,
+}
+
+/// A grid of solver options; every combination of the given values is run.
+#[derive(Args)]
+struct ExperimentArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+
+    /// Heuristics to compare (comma-separated)
+    #[arg(
+        short = 'H',
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "hungarian"
+    )]
+    heuristic: Vec<HeuristicType>,
+
+    /// Search types to compare (comma-separated)
+    #[arg(
+        short = 'd',
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "bidirectional"
+    )]
+    direction: Vec<Direction>,
+
+    /// Bidirectional search balancing policies to compare (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "alternate")]
+    balance: Vec<Balance>,
+
+    /// Node limits to compare (comma-separated)
+    #[arg(short = 'n', long, value_delimiter = ',', default_value = "5000000")]
+    max_nodes: Vec<usize>,
+
+    /// Freeze deadlock detection settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "true")]
+    freeze_deadlocks: Vec<bool>,
+
+    /// Dead square pruning settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "true")]
+    dead_squares: Vec<bool>,
+
+    /// PI-corral pruning settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "true")]
+    pi_corrals: Vec<bool>,
+
+    /// Corral deadlock search node limits to compare (comma-separated)
+    #[arg(long, value_delimiter = ',', default_value = "20")]
+    deadlock_max_nodes: Vec<usize>,
+
+    /// PI-corral safety settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "false")]
+    corral_safety: Vec<bool>,
+
+    /// Optimal (A*) search settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "false")]
+    optimal: Vec<bool>,
+
+    /// Costs to minimise to compare (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "pushes")]
+    cost: Vec<Cost>,
+
+    /// Heuristic weights in optimal search to compare (comma-separated)
+    #[arg(long, value_delimiter = ',', default_value = "1", value_parser = parse_weight)]
+    weight: Vec<f64>,
+
+    /// Search algorithms to compare (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "best-first")]
+    algorithm: Vec<Algorithm>,
+
+    /// Anytime search settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "false")]
+    anytime: Vec<bool>,
+
+    /// Node reopening settings to compare (e.g., "true,false")
+    #[arg(long, value_delimiter = ',', default_value = "false")]
+    reopen: Vec<bool>,
+
+    /// Maximum wall-clock time to spend on each run, in seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    max_time: Option<Duration>,
+
+    /// Approximate memory limit for each run, in megabytes
+    #[arg(long, value_name = "MB")]
+    max_memory: Option<usize>,
+
+    /// Number of repetitions of each (level, configuration) cell
+    #[arg(short, long, default_value = "1")]
+    repeat: usize,
+
+    /// Number of cells to run in parallel. Note that timings are less
+    /// reliable when running in parallel.
+    #[arg(short, long, default_value = "1")]
+    jobs: usize,
+
+    /// Write the CSV to the given file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+}
+
+/// A single combination of solver options in an experiment.
+#[derive(Debug, Clone, Copy)]
+struct ExperimentConfig {
+    heuristic: HeuristicType,
+    direction: Direction,
+    balance: Balance,
+    max_nodes: usize,
+    max_time: Option<Duration>,
+    max_memory: Option<usize>,
+    freeze_deadlocks: bool,
+    dead_squares: bool,
+    pi_corrals: bool,
+    deadlock_max_nodes: usize,
+    corral_safety: bool,
+    optimal: bool,
+    cost: Cost,
+    weight: f64,
+    algorithm: Algorithm,
+    anytime: bool,
+    reopen: bool,
+}
+
+impl ExperimentConfig {
+    fn opts(&self) -> SolverOpts {
+        SolverOpts {
+            search_type: self.direction.into(),
+            balance: self.balance.into(),
+            ratio: (1, 1),
+            perimeter: None,
+            max_nodes_explored: self.max_nodes,
+            max_time: self.max_time,
+            max_memory: self.max_memory.map(megabytes),
+            freeze_deadlocks: self.freeze_deadlocks,
+            deadlock_patterns: true,
+            dead_squares: self.dead_squares,
+            matching_deadlocks: true,
+            conflict_deadlocks: false,
+            conflict_max_nodes: 50,
+            pi_corrals: self.pi_corrals,
+            deadlock_max_nodes: self.deadlock_max_nodes,
+            corral_safety: self.corral_safety,
+            pull_corral_pruning: false,
+            optimal: self.optimal,
+            cost: self.cost.into(),
+            weight: self.weight,
+            algorithm: self.algorithm.into(),
+            anytime: self.anytime,
+            reopen: self.reopen,
+            max_depth: None,
+            adaptive: false,
+            player_tie_break: false,
+            tie_break: TieBreak::Fifo,
+            penalties: false,
+            threads: 1,
+            paranoid_hash: false,
+            zobrist_seed: None,
+            symmetry: true,
+            share_deadlocks: true,
+            hash_diagnostics: false,
+            trace_range: 0..0,
+        }
+    }
+}
+
+impl ExperimentArgs {
+    /// Expand the option grid into the list of all configurations.
+    fn configs(&self) -> Vec<ExperimentConfig> {
+        let mut configs = Vec::new();
+        for &heuristic in &self.heuristic {
+            for &direction in &self.direction {
+                for &balance in &self.balance {
+                    for &max_nodes in &self.max_nodes {
+                        for &freeze_deadlocks in &self.freeze_deadlocks {
+                            for &dead_squares in &self.dead_squares {
+                                for &pi_corrals in &self.pi_corrals {
+                                    for &deadlock_max_nodes in &self.deadlock_max_nodes {
+                                        for &corral_safety in &self.corral_safety {
+                                            for &optimal in &self.optimal {
+                                                for &cost in &self.cost {
+                                                    for &weight in &self.weight {
+                                                        for &algorithm in &self.algorithm {
+                                                            for &anytime in &self.anytime {
+                                                                for &reopen in &self.reopen {
+                                                                    configs.push(
+                                                                        ExperimentConfig {
+                                                                            heuristic,
+                                                                            direction,
+                                                                            balance,
+                                                                            max_nodes,
+                                                                            max_time: self.max_time,
+                                                                            max_memory: self
+                                                                                .max_memory,
+                                                                            freeze_deadlocks,
+                                                                            dead_squares,
+                                                                            pi_corrals,
+                                                                            deadlock_max_nodes,
+                                                                            corral_safety,
+                                                                            optimal,
+                                                                            cost,
+                                                                            weight,
+                                                                            algorithm,
+                                                                            anytime,
+                                                                            reopen,
+                                                                        },
+                                                                    );
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+}
+
+#[derive(Args)]
+struct CorralsArgs {
+    #[command(flatten)]
+    levels: LevelArgs,
+
+    /// Maximum nodes to explore when searching each corral for deadlocks
+    #[arg(long, default_value = "20")]
+    deadlock_max_nodes: usize,
+}
+
+#[derive(Args)]
+struct PlayArgs {
+    /// Path to the levels file (XSB format)
+    #[arg(value_name = "FILE")]
+    levels_file: String,
+
+    /// Level number to play (1-indexed)
+    #[arg(value_name = "LEVEL")]
+    level: usize,
+}
+
+fn verify_levels(levels: &Levels, level_range: RangeInclusive<usize>, solutions_file: &str) {
+    let solutions = match fs::read_to_string(solutions_file)
+        .map_err(|e| e.to_string())
+        .and_then(|text| parse_solutions(&text))
+    {
+        Ok(solutions) => solutions,
+        Err(e) => {
+            eprintln!("Error loading solutions: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let num_levels = level_range.clone().count();
+    if solutions.len() < num_levels {
+        eprintln!(
+            "Error: {} levels selected but solution file contains {} solutions",
+            num_levels,
+            solutions.len()
+        );
+        std::process::exit(1);
+    }
+
+    let mut total_valid = 0;
+    for (level_num, solution) in level_range.zip(&solutions) {
+        let Some(game) = levels.get(level_num - 1) else {
+            print_skipped(levels, level_num);
+            continue;
+        };
+        match solution.verify(game) {
+            Ok(()) => {
+                total_valid += 1;
+                info!(
+                    "level: {:<3}  valid: Y  steps: {:<5}",
+                    level_num,
+                    solution.len()
+                );
+            }
+            Err(e) => {
+                info!("level: {:<3}  valid: N  {}", level_num, e);
+            }
         }
-        total_steps += stats.steps;
-        total_states += stats.states_explored;
-        total_time_ms += stats.elapsed_ms;
     }
 
-    // Print summary statistics if multiple levels were solved
     if num_levels > 1 {
         println!("---");
+        println!("valid: {:>3}/{:<3}", total_valid, num_levels);
+    }
+}
+
+fn certify_levels(levels: &Levels, level_range: RangeInclusive<usize>, certificates_dir: &str) {
+    let num_levels = level_range.clone().count();
+    let mut total_certified = 0;
+    for level_num in level_range {
+        let Some(game) = levels.get(level_num - 1) else {
+            print_skipped(levels, level_num);
+            continue;
+        };
+        let path = certificate_path(certificates_dir, level_num);
+        let result = fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+            .and_then(|text| Certificate::parse(&text))
+            .and_then(|certificate| {
+                check_certificate(game, &certificate).map(|()| certificate.kind())
+            });
+        match result {
+            Ok(kind) => {
+                total_certified += 1;
+                info!("level: {:<3}  certified: Y  proof: {}", level_num, kind);
+            }
+            Err(e) => info!("level: {:<3}  certified: N  {}", level_num, e),
+        }
+    }
+
+    if num_levels > 1 {
+        println!("---");
+        println!("certified: {:>3}/{:<3}", total_certified, num_levels);
+    }
+}
+
+/// The command-line name of an enum value.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+fn print_skipped(levels: &Levels, level_num: usize) {
+    info!(
+        "level: {:<3}  skipped: {}",
+        level_num,
+        levels.skip_reason(level_num - 1).unwrap_or("unknown")
+    );
+}
+
+/// The solver configuration used for a single run.
+struct SolverRun<'a> {
+    heuristic: HeuristicType,
+    direction: Direction,
+    opts: &'a SolverOpts,
+}
+
+/// Record the result of solving a level into the stats database.
+fn record_run(db: &StatsDb, levels_file: &str, run: &SolverRun, stats: &LevelStats) {
+    let record = RunRecord {
+        levels_file: levels_file.to_string(),
+        level: stats.level_num,
+        heuristic: value_name(run.heuristic),
+        direction: value_name(run.direction),
+        max_nodes: run.opts.max_nodes_explored,
+        freeze_deadlocks: run.opts.freeze_deadlocks,
+        dead_squares: run.opts.dead_squares,
+        pi_corrals: run.opts.pi_corrals,
+        deadlock_max_nodes: run.opts.deadlock_max_nodes,
+        result: stats.result.to_string(),
+        steps: stats.steps,
+        nodes_explored: stats.states_explored,
+        elapsed_ms: stats.elapsed_ms,
+    };
+    if let Err(e) = db.record(&record) {
+        eprintln!("Error writing to stats database: {}", e);
+    }
+}
+
+/// Settings for re-attempting levels which hit the node or time limit.
+struct Escalation {
+    rounds: usize,
+    factor: usize,
+    heuristic: HeuristicType,
+}
+
+/// Solve the levels of a range one after the other, stopping early if
+/// interrupted. Returns the levels solved along with their statistics.
+fn solve_range<'a>(
+    source: &'a LevelSource,
+    solver: &SolverArgs,
+    output: &OutputOpts,
+    stats_db: Option<&StatsDb>,
+    totals: &mut SolveTotals,
+) -> Vec<(&'a Game, LevelStats)> {
+    let mut results = Vec::new();
+    for level_num in source.range.clone() {
+        let Some(game) = source.levels.get(level_num - 1) else {
+            print_skipped(&source.levels, level_num);
+            totals.add_skipped(level_num);
+            continue;
+        };
+        let opts = solver.opts();
+        let stats = solve_level(
+            game,
+            level_num,
+            source.title(level_num),
+            opts.clone(),
+            solver.heuristic,
+            &solver.max_with,
+            &solver.portfolio,
+            output,
+        );
+        if interrupted() {
+            break;
+        }
+        if let Some(db) = stats_db {
+            let run = SolverRun {
+                heuristic: solver.heuristic,
+                direction: solver.direction,
+                opts: &opts,
+            };
+            record_run(db, &source.name, &run, &stats);
+        }
+        results.push((game, stats));
+    }
+    results
+}
+
+/// Solve the levels of a range on `jobs` worker threads, printing each
+/// level's output in order once it and every level before it are done.
+/// Returns the levels solved along with their statistics, as `solve_range`
+/// does, stopping early if interrupted.
+fn solve_range_parallel<'a>(
+    source: &'a LevelSource,
+    solver: &SolverArgs,
+    jobs: usize,
+    output: &OutputOpts,
+    stats_db: Option<&StatsDb>,
+    totals: &mut SolveTotals,
+) -> Vec<(&'a Game, LevelStats)> {
+    let levels: Vec<(usize, Option<&Game>)> = source
+        .range
+        .clone()
+        .map(|level_num| (level_num, source.levels.get(level_num - 1)))
+        .collect();
+    let opts = solver.opts();
+    let mut results = Vec::new();
+
+    let solve = |&(_, level): &(usize, Option<&Game>)| {
+        level.map(|game| {
+            run_level(
+                game,
+                opts.clone(),
+                solver.heuristic,
+                &solver.max_with,
+                &solver.portfolio,
+                None,
+                None,
+            )
+        })
+    };
+    for_each_ordered(&levels, jobs, solve, |i, outcome| {
+        let (level_num, level) = levels[i];
+        let (Some(game), Some(outcome)) = (level, outcome) else {
+            print_skipped(&source.levels, level_num);
+            totals.add_skipped(level_num);
+            return ControlFlow::Continue(());
+        };
+        let stats = report_level(
+            game,
+            level_num,
+            source.title(level_num),
+            opts.max_depth,
+            outcome,
+            output,
+        );
+        if interrupted() {
+            return ControlFlow::Break(());
+        }
+        if let Some(db) = stats_db {
+            let run = SolverRun {
+                heuristic: solver.heuristic,
+                direction: solver.direction,
+                opts: &opts,
+            };
+            record_run(db, &source.name, &run, &stats);
+        }
+        results.push((game, stats));
+        ControlFlow::Continue(())
+    });
+    results
+}
+
+/// Run `work` on each item on `jobs` worker threads, passing each result
+/// with its item's index to `report` in item order, as soon as it and every
+/// result before it are ready. Stops once `report` breaks, or when the
+/// workers stop early after being interrupted.
+fn for_each_ordered<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut report: impl FnMut(usize, R) -> ControlFlow<()>,
+) {
+    let next_item = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut pending: Vec<Option<R>> = items.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let sender = sender.clone();
+            let (work, next_item) = (&work, &next_item);
+            scope.spawn(move || {
+                while !interrupted() {
+                    let i = next_item.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    // The receiver outlives the scope, so sending can't fail
+                    let _ = sender.send((i, work(item)));
+                }
+            });
+        }
+        drop(sender);
+
+        // Report the items in order, waiting for each to finish
+        for i in 0..items.len() {
+            while pending[i].is_none() {
+                let Ok((j, result)) = receiver.recv() else {
+                    // Every worker stopped after being interrupted
+                    return;
+                };
+                pending[j] = Some(result);
+            }
+            if report(i, pending[i].take().unwrap()).is_break() {
+                return;
+            }
+        }
+    });
+}
+
+/// Re-attempt levels which hit the node or time limit, multiplying the limits
+/// by the escalation factor on each round. The stats of each level are
+/// replaced by those of its latest attempt, with elapsed time accumulated over
+/// all attempts.
+fn escalate_levels(
+    solver: &SolverArgs,
+    escalation: &Escalation,
+    results: &mut [(&Game, LevelStats)],
+    output: &OutputOpts,
+    source: &LevelSource,
+    stats_db: Option<&StatsDb>,
+) {
+    let mut opts = solver.opts();
+    let mut portfolio = solver.portfolio.clone();
+    let heuristic = escalation.heuristic;
+    let factor = escalation.factor.max(1);
+
+    for round in 1..=escalation.rounds {
+        let pending: Vec<usize> = (0..results.len())
+            .filter(|&i| matches!(results[i].1.result, "cutoff" | "timed_out"))
+            .collect();
+        if pending.is_empty() || interrupted() {
+            break;
+        }
+
+        opts.max_nodes_explored = opts.max_nodes_explored.saturating_mul(factor);
+        opts.deadlock_max_nodes = opts.deadlock_max_nodes.saturating_mul(factor);
+        opts.max_time = opts.max_time.map(|t| t.saturating_mul(factor as u32));
+        for entry in &mut portfolio {
+            entry.max_nodes = entry.max_nodes.map(|n| n.saturating_mul(factor));
+        }
+        info!(
+            "escalation round {}: levels: {}  max nodes: {}  deadlock max nodes: {}  heuristic: {}",
+            round,
+            pending.len(),
+            opts.max_nodes_explored,
+            opts.deadlock_max_nodes,
+            value_name(heuristic)
+        );
+
+        for i in pending {
+            let (game, previous) = &results[i];
+            let mut stats = solve_level(
+                game,
+                previous.level_num,
+                source.title(previous.level_num),
+                opts.clone(),
+                heuristic,
+                &solver.max_with,
+                &portfolio,
+                output,
+            );
+            if interrupted() {
+                return;
+            }
+            if let Some(db) = stats_db {
+                let run = SolverRun {
+                    heuristic,
+                    direction: solver.direction,
+                    opts: &opts,
+                };
+                record_run(db, &source.name, &run, &stats);
+            }
+            stats.elapsed_ms += previous.elapsed_ms;
+            results[i].1 = stats;
+        }
+    }
+}
+
+/// Running totals over the levels solved from one or more files.
+#[derive(Default)]
+struct SolveTotals {
+    levels: usize,
+    solved: usize,
+    skipped: usize,
+    /// The skipped levels of a single file, which are listed in its totals.
+    skipped_levels: Vec<usize>,
+    steps: usize,
+    states: usize,
+    unique_box_configs: usize,
+    time_ms: u128,
+}
+
+impl SolveTotals {
+    fn add(&mut self, stats: &LevelStats) {
+        self.levels += 1;
+        if stats.solved {
+            self.solved += 1;
+        }
+        self.steps += stats.steps;
+        self.states += stats.states_explored;
+        self.unique_box_configs += stats.unique_box_configs;
+        self.time_ms += stats.elapsed_ms;
+    }
+
+    fn add_skipped(&mut self, level_num: usize) {
+        self.levels += 1;
+        self.skipped += 1;
+        self.skipped_levels.push(level_num);
+    }
+
+    fn merge(&mut self, other: &SolveTotals) {
+        self.levels += other.levels;
+        self.solved += other.solved;
+        self.skipped += other.skipped;
+        self.steps += other.steps;
+        self.states += other.states;
+        self.unique_box_configs += other.unique_box_configs;
+        self.time_ms += other.time_ms;
+    }
+
+    fn print(&self) {
         println!(
             "solved: {:>3}/{:<3}        steps: {:<5}  states: {:<12}  elapsed: {} ms",
-            total_solved, num_levels, total_steps, total_states, total_time_ms
+            self.solved, self.levels, self.steps, self.states, self.time_ms
+        );
+        if self.skipped > 0 && self.skipped_levels.is_empty() {
+            println!("skipped: {}", self.skipped);
+        } else if self.skipped > 0 {
+            let levels: Vec<String> = self.skipped_levels.iter().map(usize::to_string).collect();
+            let plural = if levels.len() > 1 { "s" } else { "" };
+            println!(
+                "skipped: {} (level{} {})",
+                self.skipped,
+                plural,
+                levels.join(", ")
+            );
+        }
+        verbose!(
+            "  unique box configs: {} ({:.1}% of states)",
+            self.unique_box_configs,
+            100.0 * self.unique_box_configs as f64 / self.states.max(1) as f64
+        );
+    }
+}
+
+fn solve_levels(args: SolveArgs) {
+    let sources = args.levels.load_all();
+    args.solver.warn_if_inadmissible();
+    if let Err(e) = args
+        .solver
+        .check_threads()
+        .and_then(|()| args.solver.check_tie_break())
+    {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let num_levels: usize = sources.iter().map(|s| s.range.clone().count()).sum();
+
+    let output = OutputOpts {
+        print_solution: args.print_solution,
+        format: args.format,
+        svg_out: args.svg_out,
+        svg_frames_dir: args.svg_frames,
+        html_out: args.html_out,
+        events: args.events.as_deref().map(|path| {
+            EventsOutput::open(path).unwrap_or_else(|e| {
+                eprintln!("Error opening events file {}: {}", path, e);
+                std::process::exit(1);
+            })
+        }),
+        deadlock_cache: args.deadlock_cache.as_deref().map(|path| {
+            DeadlockDb::open(path).unwrap_or_else(|e| {
+                eprintln!("Error opening deadlock cache {}: {}", path, e);
+                std::process::exit(1);
+            })
+        }),
+        certificates_dir: args.certificates,
+        certificate_max_states: args.certificate_max_states,
+    };
+
+    let writes_solution = output.print_solution
+        || output.svg_out.is_some()
+        || output.svg_frames_dir.is_some()
+        || output.html_out.is_some();
+    if writes_solution && num_levels > 1 {
+        eprintln!("Error: solution output only supported when solving a single level");
+        std::process::exit(1);
+    }
+    // Events, run records and saved deadlock results describe a single
+    // configuration
+    if !args.solver.portfolio.is_empty()
+        && (output.events.is_some() || args.stats_db.is_some() || output.deadlock_cache.is_some())
+    {
+        eprintln!(
+            "Error: --events, --stats-db and --deadlock-cache are not supported with --portfolio"
         );
+        std::process::exit(1);
+    }
+    // Levels solved in parallel would interleave their events, and share
+    // the deadlock cache's connection
+    if args.jobs > 1 && (output.events.is_some() || output.deadlock_cache.is_some()) {
+        eprintln!("Error: --events and --deadlock-cache are not supported with --jobs");
+        std::process::exit(1);
+    }
+    // Parallel search doesn't record events
+    if args.solver.threads > 1 && output.events.is_some() {
+        eprintln!("Error: --events is not supported with --threads");
+        std::process::exit(1);
+    }
+
+    let stats_db = args.stats_db.as_deref().map(|path| {
+        StatsDb::open(path).unwrap_or_else(|e| {
+            eprintln!("Error opening stats database {}: {}", path, e);
+            std::process::exit(1);
+        })
+    });
+
+    let escalation = Escalation {
+        rounds: args.escalate,
+        factor: args.escalate_factor,
+        heuristic: args.escalate_heuristic.unwrap_or(args.solver.heuristic),
+    };
+
+    let multiple_files = sources.len() > 1;
+    let mut grand_totals = SolveTotals::default();
+    for source in &sources {
+        if interrupted() {
+            break;
+        }
+        if multiple_files {
+            info!("file: {}", source.name);
+        }
+
+        // Solve each level in the range, stopping early if interrupted
+        let mut totals = SolveTotals::default();
+        let mut results = if args.jobs > 1 {
+            solve_range_parallel(
+                source,
+                &args.solver,
+                args.jobs,
+                &output,
+                stats_db.as_ref(),
+                &mut totals,
+            )
+        } else {
+            solve_range(
+                source,
+                &args.solver,
+                &output,
+                stats_db.as_ref(),
+                &mut totals,
+            )
+        };
+
+        escalate_levels(
+            &args.solver,
+            &escalation,
+            &mut results,
+            &output,
+            source,
+            stats_db.as_ref(),
+        );
+        for (_, stats) in &results {
+            totals.add(stats);
+        }
+
+        // Print summary statistics if multiple levels were solved
+        if totals.levels > 1 || multiple_files {
+            println!("---");
+            if multiple_files {
+                println!("file: {}", source.name);
+            }
+            totals.print();
+        }
+        grand_totals.merge(&totals);
+    }
+
+    if multiple_files {
+        println!("===");
+        println!("files: {}", sources.len());
+        grand_totals.print();
+    }
+    if interrupted() {
+        println!("interrupted: summary covers completed levels only");
+    }
+}
+
+/// Running totals over the levels benchmarked from one or more files.
+#[derive(Default)]
+struct BenchTotals {
+    levels: usize,
+    solved: usize,
+    min_ms: u128,
+    mean_ms: f64,
+}
+
+impl BenchTotals {
+    fn merge(&mut self, other: &BenchTotals) {
+        self.levels += other.levels;
+        self.solved += other.solved;
+        self.min_ms += other.min_ms;
+        self.mean_ms += other.mean_ms;
+    }
+
+    fn print(&self, repeat: usize) {
+        println!(
+            "solved: {:>3}/{:<3}  runs: {}  min: {} ms  mean: {:.1} ms",
+            self.solved, self.levels, repeat, self.min_ms, self.mean_ms
+        );
+    }
+}
+
+fn bench_levels(args: BenchArgs) {
+    let sources = args.levels.load_all();
+    args.solver.warn_if_inadmissible();
+    if let Err(e) = args
+        .solver
+        .check_threads()
+        .and_then(|()| args.solver.check_tie_break())
+    {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let repeat = args.repeat.max(1);
+
+    let multiple_files = sources.len() > 1;
+    let mut grand_totals = BenchTotals::default();
+    for source in &sources {
+        if interrupted() {
+            break;
+        }
+        if multiple_files {
+            info!("file: {}", source.name);
+        }
+
+        let mut totals = BenchTotals::default();
+        for level_num in source.range.clone() {
+            let Some(game) = source.levels.get(level_num - 1) else {
+                print_skipped(&source.levels, level_num);
+                totals.levels += 1;
+                continue;
+            };
+
+            let mut times = Vec::with_capacity(repeat);
+            let mut solved = false;
+            let mut nodes_explored = 0;
+            for _ in 0..repeat {
+                let outcome = run_level(
+                    game,
+                    args.solver.opts(),
+                    args.solver.heuristic,
+                    &args.solver.max_with,
+                    &args.solver.portfolio,
+                    None,
+                    None,
+                );
+                if let SolveResult::Interrupted = outcome.result {
+                    print_interrupted(level_num, &outcome);
+                    break;
+                }
+                solved = matches!(outcome.result, SolveResult::Solved(_));
+                nodes_explored = outcome.stats.nodes_explored;
+                times.push(outcome.elapsed_ms);
+            }
+            if interrupted() {
+                break;
+            }
+
+            let min_ms = *times.iter().min().unwrap();
+            let mean_ms = times.iter().sum::<u128>() as f64 / repeat as f64;
+            info!(
+                "level: {:<3}  solved: {}  states: {:<12}  min: {} ms  mean: {:.1} ms",
+                level_num,
+                if solved { 'Y' } else { 'N' },
+                nodes_explored,
+                min_ms,
+                mean_ms
+            );
+
+            totals.levels += 1;
+            if solved {
+                totals.solved += 1;
+            }
+            totals.min_ms += min_ms;
+            totals.mean_ms += mean_ms;
+        }
+
+        if totals.levels > 1 || multiple_files {
+            println!("---");
+            if multiple_files {
+                println!("file: {}", source.name);
+            }
+            totals.print(repeat);
+        }
+        grand_totals.merge(&totals);
+    }
+
+    if multiple_files {
+        println!("===");
+        println!("files: {}", sources.len());
+        grand_totals.print(repeat);
+    }
+    if interrupted() {
+        println!("interrupted: summary covers completed levels only");
+    }
+}
+
+fn print_level_stats(args: StatsArgs) {
+    let sources = args.levels.load_all();
+    let multiple_files = sources.len() > 1;
+    // The first level seen with each fingerprint, counting mirror images and
+    // rotations as the same level
+    let mut seen = HashMap::new();
+
+    for source in &sources {
+        if multiple_files {
+            info!("file: {}", source.name);
+        }
+        for level_num in source.range.clone() {
+            let Some(game) = source.levels.get(level_num - 1) else {
+                print_skipped(&source.levels, level_num);
+                continue;
+            };
+            print_game_stats(game, level_num);
+            let label = if multiple_files {
+                format!("{} level {}", source.name, level_num)
+            } else {
+                format!("level {}", level_num)
+            };
+            match seen.entry(game.fingerprint(true)) {
+                Entry::Occupied(first) => info!("  duplicate of {}", first.get()),
+                Entry::Vacant(entry) => {
+                    entry.insert(label);
+                }
+            }
+        }
+    }
+}
+
+fn print_game_stats(game: &Game, level_num: usize) {
+    let interior = game.wall_region(game.player());
+    let mut floor = 0;
+    let mut dead = 0;
+    for y in 0..game.height() {
+        for x in 0..game.width() {
+            let pos = Position(x, y);
+            if interior.get(pos) {
+                floor += 1;
+                if game.is_push_dead_square(pos) {
+                    dead += 1;
+                }
+            }
+        }
+    }
+    let boxes_on_goals = game.box_count() - game.unsolved_boxes().len();
+
+    info!(
+        "level: {:<3}  size: {}x{:<5}  boxes: {:<3}  on goals: {:<3}  floor: {:<4}  dead: {:<4}  fingerprint: {:016x}",
+        level_num,
+        game.width(),
+        game.height(),
+        game.box_count(),
+        boxes_on_goals,
+        floor,
+        dead,
+        game.fingerprint(false)
+    );
+}
+
+fn print_level_corrals(args: CorralsArgs) {
+    let sources = args.levels.load_all();
+    let multiple_files = sources.len() > 1;
+
+    for source in &sources {
+        if multiple_files {
+            info!("file: {}", source.name);
+        }
+        for level_num in source.range.clone() {
+            let Some(game) = source.levels.get(level_num - 1) else {
+                print_skipped(&source.levels, level_num);
+                continue;
+            };
+            let reports = analyze_corrals(game, args.deadlock_max_nodes);
+            info!("level: {:<3}  corrals: {}", level_num, reports.len());
+            for (i, report) in reports.iter().enumerate() {
+                let yes_no = |condition| if condition { "yes" } else { "no" };
+                let deadlock = report
+                    .deadlock
+                    .map_or("not searched".to_string(), |result| result.to_string());
+                let pushes: Vec<_> = report.pushes.iter().map(|push| push.to_string()).collect();
+                info!(
+                    "corral {}: boxes: {}  edge boxes: {}  I: {}  P: {}  deadlock: {}",
+                    i + 1,
+                    report.boxes.len(),
+                    report.edge_boxes.len(),
+                    yes_no(report.i_condition),
+                    yes_no(report.p_condition),
+                    deadlock
+                );
+                info!("  pushes: {}", pushes.join(", "));
+                info!("{}", report.render(game).trim_end());
+            }
+        }
+    }
+}
+
+fn play_level(args: PlayArgs) {
+    if args.levels_file == "-" {
+        eprintln!("Error: play reads moves from stdin, so levels must be read from a file");
+        std::process::exit(1);
+    }
+    let levels = LevelArgs {
+        inputs: vec![args.levels_file, args.level.to_string()],
+        board: None,
+        strict: true,
+        skip_invalid: false,
+        unbalanced: false,
+        level_name: None,
+        snapshot: false,
+    }
+    .load()
+    .0;
+    let initial = levels.get(args.level - 1).unwrap().clone();
+
+    println!("Enter moves in LURD notation (e.g. \"llu\"), \"undo\", \"reset\" or \"quit\".");
+    // Previous game states, and whether the step from each was a push
+    let mut history: Vec<(Game, bool)> = Vec::new();
+    let mut game = initial.clone();
+    println!("{}", game);
+
+    for line in io::stdin().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        match line.trim() {
+            "" => continue,
+            "quit" | "q" => break,
+            "undo" | "z" => match history.pop() {
+                Some((prev, _)) => game = prev,
+                None => println!("Nothing to undo"),
+            },
+            "reset" => {
+                history.clear();
+                game = initial.clone();
+            }
+            moves => {
+                for ch in moves.chars() {
+                    let dir = match ch.to_ascii_lowercase() {
+                        'u' => GameDirection::Up,
+                        'd' => GameDirection::Down,
+                        'l' => GameDirection::Left,
+                        'r' => GameDirection::Right,
+                        _ => {
+                            println!("Unknown move '{}'", ch);
+                            break;
+                        }
+                    };
+                    let is_push = game
+                        .move_position(game.player(), dir)
+                        .is_some_and(|pos| game.box_index(pos).is_some());
+                    let mut next = game.clone();
+                    if let Err(e) = apply_step(&mut next, dir, is_push) {
+                        println!("Invalid move: {}", e);
+                        break;
+                    }
+                    history.push((std::mem::replace(&mut game, next), is_push));
+                }
+            }
+        }
+
+        println!("{}", game);
+        if game.is_solved() {
+            let pushes = history.iter().filter(|(_, is_push)| *is_push).count();
+            println!("Solved in {} moves ({} pushes)!", history.len(), pushes);
+            break;
+        }
+    }
+}
+
+const EXPERIMENT_CSV_HEADER: &str = "level,heuristic,direction,balance,max_nodes,freeze_deadlocks,\
+dead_squares,pi_corrals,deadlock_max_nodes,corral_safety,optimal,cost,weight,algorithm,anytime,reopen,repetition,result,steps,nodes_explored,\
+forward_nodes,reverse_nodes,forward_peak_open,reverse_peak_open,forward_peak_memory,reverse_peak_memory,meeting_direction,unique_box_configs,elapsed_ms";
+
+fn run_experiment(args: ExperimentArgs) {
+    let (levels, level_range) = args.levels.load();
+    let configs = args.configs();
+
+    // Enumerate all (level, configuration, repetition) cells
+    let mut cells = Vec::new();
+    for level_num in level_range {
+        let Some(game) = levels.get(level_num - 1) else {
+            if log::enabled(Level::Normal) {
+                eprintln!(
+                    "level {} skipped: {}",
+                    level_num,
+                    levels.skip_reason(level_num - 1).unwrap_or("unknown")
+                );
+            }
+            continue;
+        };
+        for config in &configs {
+            for repetition in 1..=args.repeat {
+                cells.push((level_num, game, *config, repetition));
+            }
+        }
+    }
+
+    // Run the cells on a pool of worker threads, each taking the next
+    // unclaimed cell until none remain
+    let next_cell = AtomicUsize::new(0);
+    let rows: Mutex<Vec<(usize, String)>> = Mutex::new(Vec::with_capacity(cells.len()));
+    thread::scope(|scope| {
+        for _ in 0..args.jobs.clamp(1, cells.len().max(1)) {
+            scope.spawn(|| {
+                while !interrupted() {
+                    let i = next_cell.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(level_num, game, config, repetition)) = cells.get(i) else {
+                        break;
+                    };
+                    let outcome = run_solver(
+                        game,
+                        config.opts(),
+                        config.heuristic,
+                        &[],
+                        None,
+                        None,
+                        INTERRUPT.clone(),
+                    );
+                    let (result, steps) = match &outcome.result {
+                        SolveResult::Solved(solution) => ("solved", solution.len()),
+                        SolveResult::Cutoff => ("cutoff", 0),
+                        SolveResult::TimedOut => ("timed_out", 0),
+                        SolveResult::Interrupted => break,
+                        SolveResult::Unsolvable => ("unsolvable", 0),
+                    };
+                    let meeting_direction = outcome
+                        .stats
+                        .meeting_direction
+                        .map(|direction| value_name(Direction::from(direction)))
+                        .unwrap_or_default();
+                    let row = format!(
+                        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                        level_num,
+                        value_name(config.heuristic),
+                        value_name(config.direction),
+                        value_name(config.balance),
+                        config.max_nodes,
+                        config.freeze_deadlocks,
+                        config.dead_squares,
+                        config.pi_corrals,
+                        config.deadlock_max_nodes,
+                        config.corral_safety,
+                        config.optimal,
+                        value_name(config.cost),
+                        config.weight,
+                        value_name(config.algorithm),
+                        config.anytime,
+                        config.reopen,
+                        repetition,
+                        result,
+                        steps,
+                        outcome.stats.nodes_explored,
+                        outcome.stats.forward.nodes_explored,
+                        outcome.stats.reverse.nodes_explored,
+                        outcome.stats.forward.peak_open,
+                        outcome.stats.reverse.peak_open,
+                        outcome.stats.forward.peak_memory,
+                        outcome.stats.reverse.peak_memory,
+                        meeting_direction,
+                        outcome.stats.unique_box_configs,
+                        outcome.elapsed_ms
+                    );
+                    if log::enabled(Level::Verbose) {
+                        eprintln!("cell {}/{}: {}", i + 1, cells.len(), row);
+                    }
+                    rows.lock().unwrap().push((i, row));
+                }
+            });
+        }
+    });
+
+    // Write rows in cell order, regardless of completion order
+    let mut rows = rows.into_inner().unwrap();
+    if interrupted() {
+        eprintln!(
+            "interrupted: CSV covers {} of {} runs",
+            rows.len(),
+            cells.len()
+        );
+    }
+    rows.sort_by_key(|(i, _)| *i);
+    let mut csv = String::new();
+    csv.push_str(EXPERIMENT_CSV_HEADER);
+    csv.push('\n');
+    for (_, row) in rows {
+        csv.push_str(&row);
+        csv.push('\n');
+    }
+
+    match &args.output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, csv) {
+                eprintln!("Error writing CSV to {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        None => print!("{}", csv),
+    }
+}
+
+fn main() {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    log::set_level(Level::from_flags(cli.verbose, cli.quiet));
+
+    if let Some(path) = &cli.config {
+        let config = Config::from_file(path).unwrap_or_else(|e| {
+            eprintln!("Error loading config {}: {}", path, e);
+            std::process::exit(1);
+        });
+        let (_, sub_matches) = matches.subcommand().unwrap();
+        let result = match &mut cli.command {
+            Command::Solve(args) => args.apply_config(&config, sub_matches),
+            Command::Bench(args) => args.solver.apply_config(&config.solver, sub_matches),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            eprintln!("Error in config {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    // Play reads moves from stdin, so Ctrl-C should exit as usual
+    if !matches!(cli.command, Command::Play(_)) {
+        install_interrupt_handler();
+    }
+
+    match cli.command {
+        Command::Solve(args) => solve_levels(args),
+        Command::Verify(args) => {
+            let (levels, level_range) = args.levels.load();
+            verify_levels(&levels, level_range, &args.solutions);
+        }
+        Command::Certify(args) => {
+            let (levels, level_range) = args.levels.load();
+            certify_levels(&levels, level_range, &args.certificates);
+        }
+        Command::Bench(args) => bench_levels(args),
+        Command::Stats(args) => print_level_stats(args),
+        Command::Corrals(args) => print_level_corrals(args),
+        Command::Play(args) => play_level(args),
+        Command::Experiment(args) => run_experiment(args),
+    }
+
+    if interrupted() {
+        std::process::exit(130);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_ordered() {
+        // Earlier items take longest, so they finish after the later ones
+        let items: Vec<u64> = (0..8).collect();
+        let work = |&n: &u64| {
+            thread::sleep(Duration::from_millis(5 * (8 - n)));
+            n * n
+        };
+        let mut reported = Vec::new();
+        for_each_ordered(&items, 4, work, |i, square| {
+            reported.push((i, square));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(
+            reported,
+            items
+                .iter()
+                .map(|&n| (n as usize, n * n))
+                .collect::<Vec<_>>()
+        );
+
+        // Breaking stops the reports
+        let mut reported = Vec::new();
+        for_each_ordered(&items, 3, work, |i, _| {
+            reported.push(i);
+            if i == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(reported, vec![0, 1, 2]);
     }
 }
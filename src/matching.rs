@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{
+    bits::{Bitvector, Index, Position, RawBitboard},
+    game::{Game, MAX_BOXES},
+    heuristic::{compute_pull_distances, compute_push_distances},
+    zobrist::Zobrist,
+};
+
+/// Marks a box which isn't matched to any goal.
+const NO_GOAL: u8 = u8::MAX;
+
+/// Detects matching deadlocks: positions in which the boxes can't all be
+/// placed on distinct goals, because some subset of the boxes can only reach
+/// fewer goals than it has boxes (i.e. Hall's condition fails). Summed
+/// distance heuristics only check that every box can reach some goal and that
+/// every goal can be reached by some box, which misses these. In a labelled
+/// game, boxes are only matched to goals they fit.
+///
+/// The matching found by the last check is kept, and since consecutive
+/// positions usually differ by a single push, only boxes which can no longer
+/// reach their matched goal are rematched, by augmenting paths.
+pub struct MatchingChecker {
+    zobrist: Rc<Zobrist>,
+    /// Whether boxes are moved by pulls (reverse search) rather than pushes.
+    pulls: bool,
+    /// Reachability of the goals for each set of frozen boxes, by the hash of
+    /// the frozen boxes.
+    reachability: HashMap<u64, Reachability>,
+    /// Goal matched to each box by the last check, or `NO_GOAL`.
+    matched_goal: [u8; MAX_BOXES],
+}
+
+/// The squares from which a box can reach each goal, with frozen boxes
+/// treated as walls.
+struct Reachability {
+    goals: Vec<RawBitboard>,
+}
+
+impl Reachability {
+    fn new(game: &Game, frozen_boxes: &Bitvector, pulls: bool) -> Self {
+        let distances = if pulls {
+            compute_pull_distances(game, frozen_boxes)
+        } else {
+            compute_push_distances(game, frozen_boxes)
+        };
+        let goals = distances
+            .iter()
+            .map(|goal_distances| {
+                let mut squares = RawBitboard::new();
+                for y in 0..game.height() {
+                    for x in 0..game.width() {
+                        let pos = Position(x, y);
+                        if goal_distances.get(game, pos) != u16::MAX {
+                            squares.set(pos);
+                        }
+                    }
+                }
+                squares
+            })
+            .collect();
+        Reachability { goals }
+    }
+}
+
+impl MatchingChecker {
+    /// Create a checker for boxes moved by pushes (forward search).
+    pub fn new_push(zobrist: Rc<Zobrist>) -> Self {
+        Self::new(zobrist, false)
+    }
+
+    /// Create a checker for boxes moved by pulls (reverse search).
+    pub fn new_pull(zobrist: Rc<Zobrist>) -> Self {
+        Self::new(zobrist, true)
+    }
+
+    fn new(zobrist: Rc<Zobrist>, pulls: bool) -> Self {
+        Self {
+            zobrist,
+            pulls,
+            reachability: HashMap::new(),
+            matched_goal: [NO_GOAL; MAX_BOXES],
+        }
+    }
+
+    /// Check whether every box can be matched to a distinct goal it can
+    /// reach, given the frozen boxes.
+    pub fn is_deadlocked(&mut self, game: &Game, frozen_boxes: &Bitvector) -> bool {
+        let frozen_hash = self.zobrist.compute_boxes_hash_subset(game, *frozen_boxes);
+        let pulls = self.pulls;
+        let reachability = self
+            .reachability
+            .entry(frozen_hash)
+            .or_insert_with(|| Reachability::new(game, frozen_boxes, pulls));
+
+        // Keep the previous matches which are still reachable
+        let goal_count = game.goal_positions().len();
+        let mut matched_box = [NO_GOAL; MAX_BOXES];
+        let mut unmatched = Vec::new();
+        for (box_idx, &pos) in game.box_positions().iter().enumerate() {
+            let goal = self.matched_goal[box_idx];
+            if goal != NO_GOAL
+                && matched_box[goal as usize] == NO_GOAL
+                && reachability.goals[goal as usize].get(pos)
+                && game.box_fits_goal(Index(box_idx as u8), goal as usize)
+            {
+                matched_box[goal as usize] = box_idx as u8;
+            } else {
+                self.matched_goal[box_idx] = NO_GOAL;
+                unmatched.push(box_idx);
+            }
+        }
+
+        // Rematch the other boxes
+        for box_idx in unmatched {
+            let mut visited = Bitvector::new();
+            if !augment(
+                game,
+                reachability,
+                box_idx,
+                goal_count,
+                &mut visited,
+                &mut self.matched_goal,
+                &mut matched_box,
+            ) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Find an augmenting path from the given unmatched box, rematching boxes
+/// along it, and return whether one was found.
+fn augment(
+    game: &Game,
+    reachability: &Reachability,
+    box_idx: usize,
+    goal_count: usize,
+    visited: &mut Bitvector,
+    matched_goal: &mut [u8; MAX_BOXES],
+    matched_box: &mut [u8; MAX_BOXES],
+) -> bool {
+    let pos = game.box_positions()[box_idx];
+    for goal in 0..goal_count {
+        if visited.contains(Index(goal as u8))
+            || !reachability.goals[goal].get(pos)
+            || !game.box_fits_goal(Index(box_idx as u8), goal)
+        {
+            continue;
+        }
+        visited.add(Index(goal as u8));
+        let other = matched_box[goal];
+        if other == NO_GOAL
+            || augment(
+                game,
+                reachability,
+                other as usize,
+                goal_count,
+                visited,
+                matched_goal,
+                matched_box,
+            )
+        {
+            matched_goal[box_idx] = goal as u8;
+            matched_box[goal] = box_idx as u8;
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Direction, Push};
+
+    fn new_checker() -> MatchingChecker {
+        MatchingChecker::new_push(Rc::new(Zobrist::new()))
+    }
+
+    #[test]
+    fn test_matching_deadlock() {
+        // Both boxes against the top wall can only reach the top left goal
+        let game = Game::from_text(
+            r#"
+#########
+#.$   $ #
+#       #
+#@  $  .#
+#      .#
+#########
+"#,
+        )
+        .unwrap();
+        let mut checker = new_checker();
+        assert!(checker.is_deadlocked(&game, &Bitvector::new()));
+    }
+
+    #[test]
+    fn test_no_matching_deadlock() {
+        let mut game = Game::from_text(
+            r#"
+#########
+#.$   $.#
+#       #
+#   $  .#
+#   @   #
+#       #
+#########
+"#,
+        )
+        .unwrap();
+        let mut checker = new_checker();
+        assert!(!checker.is_deadlocked(&game, &Bitvector::new()));
+
+        // The previous matching is repaired after a push
+        game.push(Push::new(Index(2), Direction::Up));
+        assert!(!checker.is_deadlocked(&game, &Bitvector::new()));
+
+        // Pushing a box against the top wall leaves three boxes which can only
+        // reach the two top goals
+        game.push(Push::new(Index(2), Direction::Up));
+        assert!(checker.is_deadlocked(&game, &Bitvector::new()));
+    }
+}
@@ -0,0 +1,539 @@
+use crate::bits::Bitvector;
+use crate::corral::DeadlockTables;
+use crate::game::{Game, Push, PushByPos};
+use crate::heuristic::{Cost, Heuristic};
+use crate::pqueue::PriorityQueue;
+use crate::solver::{
+    CHECK_SEED, ChildSink, DirectionStats, ExpandParts, ForwardSearchHelper, FrozenCache, Node,
+    Parent, PruningStats, SearchHelper, SearchType, SolveResult, SolverOpts, SolverStats,
+    TIME_CHECK_INTERVAL, TableEntry, apply_corral_pruning, combine_solution, generate_children,
+    hash_table_bytes, log_collision, state_hash, symmetry_class_hash, tie_break_priority,
+};
+use crate::symmetry::Symmetry;
+use crate::zobrist::{Zobrist, ZobristMap, ZobristSet};
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+/// Number of shards of the shared transposition table, each behind its own
+/// lock, so that workers rarely wait for each other.
+const TABLE_SHARDS: usize = 64;
+
+/// Shard holding the given hash. The middle bits are used, as the maps use
+/// the low and high bits of their keys as they are (see `ZobristHasher`).
+fn shard_index(hash: u64) -> usize {
+    (hash >> 32) as usize % TABLE_SHARDS
+}
+
+/// State shared by the workers of a parallel search.
+struct Shared {
+    /// Open list of each worker, holding nodes along with the order they
+    /// were queued in. Children are queued with the worker owning their hash
+    /// (after HDA*), which spreads the locking evenly, but every worker pops
+    /// the best node of all the queues.
+    queues: Vec<Mutex<PriorityQueue<(u64, Node)>>>,
+    /// Number of nodes queued so far, which orders nodes of equal priority
+    /// across the queues as a single queue would.
+    queued: AtomicU64,
+    /// Priority of the node each worker is expanding, if any. Nodes are only
+    /// popped under its lock.
+    expanding: Mutex<Vec<Option<usize>>>,
+    /// Transposition table, sharded by hash.
+    table: Vec<Mutex<ZobristMap<TableEntry>>>,
+    /// Boxes hashes of all expanded nodes, sharded by hash.
+    box_configs: Vec<Mutex<ZobristSet>>,
+    /// Classes of symmetric positions expanded, by the lowest canonical hash
+    /// in the class, sharded by hash.
+    symmetric: Vec<Mutex<ZobristSet>>,
+    /// Number of nodes queued or being expanded. Once it drops to zero, no
+    /// node is left to generate more, and the search is exhausted.
+    pending: AtomicUsize,
+    /// Largest number of nodes pending at once.
+    peak_open: AtomicUsize,
+    nodes_explored: AtomicUsize,
+    /// Lowest heuristic cost generated so far.
+    best_cost: AtomicUsize,
+    /// The result of the search, set by the first worker to finish it.
+    result: Mutex<Option<SolveResult>>,
+    done: AtomicBool,
+}
+
+impl Shared {
+    fn table_shard(&self, hash: u64) -> MutexGuard<'_, ZobristMap<TableEntry>> {
+        self.table[shard_index(hash)].lock().unwrap()
+    }
+
+    /// Queue a node with the worker owning the given hash.
+    fn push(&self, hash: u64, priority: usize, node: Node) {
+        let pending = self.pending.fetch_add(1, Ordering::AcqRel) + 1;
+        self.peak_open.fetch_max(pending, Ordering::Relaxed);
+        let mut queue = self.queues[hash as usize % self.queues.len()]
+            .lock()
+            .unwrap();
+        // Numbered under the lock, so that each queue stays in order
+        let order = self.queued.fetch_add(1, Ordering::Relaxed);
+        queue.push(priority, (order, node));
+    }
+
+    /// Pop the best node of all the workers' queues for the given worker, the
+    /// earliest queued on ties. Nothing is popped while another worker is
+    /// expanding a better node, whose children may come first, so that the
+    /// workers together expand nodes in much the order a single search would.
+    fn pop(&self, worker: usize) -> Option<Node> {
+        let mut expanding = self.expanding.lock().unwrap();
+        let (priority, _, best) = self
+            .queues
+            .iter()
+            .enumerate()
+            .filter_map(|(i, queue)| {
+                let (priority, &(order, _)) = queue.lock().unwrap().peek_min()?;
+                Some((priority, order, i))
+            })
+            .min()?;
+        if expanding.iter().flatten().any(|&other| other < priority) {
+            return None;
+        }
+        // Only popped under the lock, so the queue still holds the node
+        let (_, node) = self.queues[best].lock().unwrap().pop_min()?;
+        expanding[worker] = Some(priority);
+        Some(node)
+    }
+
+    /// Note that the given worker is done expanding its node, and has queued
+    /// its children.
+    fn expanded(&self, worker: usize) {
+        self.expanding.lock().unwrap()[worker] = None;
+    }
+
+    /// End the search with the given result, unless it has already ended.
+    fn finish(&self, result: SolveResult) {
+        let mut current = self.result.lock().unwrap();
+        if current.is_none() {
+            *current = Some(result);
+        }
+        self.done.store(true, Ordering::Release);
+    }
+}
+
+/// One thread of a parallel search, with its own copy of the game, helper and
+/// heuristics.
+struct Worker<'a, H: Heuristic> {
+    id: usize,
+    shared: &'a Shared,
+    opts: &'a SolverOpts,
+    /// The game as given to the solver, from which solutions are replayed.
+    start_game: &'a Game,
+    game: Game,
+    zobrist: Rc<Zobrist>,
+    check_zobrist: Zobrist,
+    heuristic: FrozenCache<H>,
+    /// Settings every heuristic is created with.
+    heuristic_params: &'a H::Params,
+    helper: ForwardSearchHelper,
+    pruning: PruningStats,
+    /// Mirror images and rotations of the board under which positions are
+    /// equally far from being solved.
+    symmetries: &'a [Symmetry],
+    /// Table hits on a different position than the one looked up, counted
+    /// with hash diagnostics.
+    hash_collisions: usize,
+    start: Instant,
+    interrupt: Option<&'a AtomicBool>,
+}
+
+impl<H: Heuristic> Worker<'_, H> {
+    fn run(&mut self) {
+        while !self.shared.done.load(Ordering::Acquire) {
+            let Some(node) = self.shared.pop(self.id) else {
+                // Nodes being expanded by other workers may still generate
+                // children
+                if self.shared.pending.load(Ordering::Acquire) == 0 {
+                    self.shared.finish(SolveResult::Unsolvable);
+                }
+                thread::yield_now();
+                continue;
+            };
+
+            let solution = self.expand_node(node);
+            self.shared.expanded(self.id);
+            self.shared.pending.fetch_sub(1, Ordering::AcqRel);
+            if let Some(solution) = solution {
+                self.shared.finish(SolveResult::Solved(solution));
+                break;
+            }
+
+            let nodes_explored = self.shared.nodes_explored.fetch_add(1, Ordering::Relaxed) + 1;
+            if nodes_explored >= self.opts.max_nodes_explored {
+                self.shared.finish(SolveResult::Cutoff);
+            }
+            // Every count is reached by exactly one worker, which checks the
+            // clock and interrupt flag for all of them
+            if nodes_explored.is_multiple_of(TIME_CHECK_INTERVAL) {
+                if self
+                    .opts
+                    .max_time
+                    .is_some_and(|max_time| self.start.elapsed() >= max_time)
+                {
+                    self.shared.finish(SolveResult::TimedOut);
+                }
+                if self
+                    .interrupt
+                    .is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+                {
+                    self.shared.finish(SolveResult::Interrupted);
+                }
+            }
+        }
+    }
+
+    /// Expand a node as the forward searcher does, queueing its children.
+    /// Returns the solution if the node is solved.
+    fn expand_node(&mut self, node: Node) -> Option<Vec<Push>> {
+        self.game.restore_packed(&node.checkpoint);
+        let reachable = match &node.last_move {
+            Some(last_move) => last_move.compute_moves(&self.helper, &self.game),
+            None => self.helper.compute_moves(&self.game),
+        };
+
+        // Compute hash
+        let boxes_hash = self.zobrist.compute_boxes_hash(&self.game);
+        let check_boxes_hash = self.check_zobrist.compute_boxes_hash(&self.game);
+        let uncanonical_hash = boxes_hash ^ self.zobrist.player_hash(self.game.player());
+
+        // Close the node, unless another worker got to it first
+        let (parent_hash, generating_move) = {
+            let mut shard = self.shared.table_shard(uncanonical_hash);
+            let entry = shard
+                .get_mut(&uncanonical_hash)
+                .filter(|entry| !entry.is_closed)?;
+            entry.is_closed = true;
+            (entry.parent_hash, entry.move_)
+        };
+
+        // Close the position under its canonical hash too, as other workers
+        // may reach it with the player elsewhere in the same region
+        let canonical_player_pos = reachable.squares.top_left().unwrap();
+        let canonical_hash = boxes_hash ^ self.zobrist.player_hash(canonical_player_pos);
+        let canonical_check =
+            check_boxes_hash ^ self.check_zobrist.player_hash(canonical_player_pos);
+        if canonical_hash != uncanonical_hash {
+            let mut shard = self.shared.table_shard(canonical_hash);
+            match shard.entry(canonical_hash) {
+                Entry::Occupied(mut e) => {
+                    let stored = e.get().check;
+                    if e.get().is_closed {
+                        drop(shard);
+                        self.check_collision(canonical_hash, stored, canonical_check);
+                        return None;
+                    }
+                    e.get_mut().is_closed = true;
+                }
+                Entry::Vacant(e) => {
+                    e.insert(TableEntry {
+                        parent_hash,
+                        move_: generating_move,
+                        check: canonical_check,
+                        is_closed: true,
+                        g: node.g,
+                        generation: 0,
+                    });
+                }
+            }
+        }
+
+        if self.game.is_solved() {
+            return Some(self.reconstruct_solution());
+        }
+
+        // Skip positions symmetric to one already expanded
+        if !self.symmetries.is_empty() {
+            let class_hash = symmetry_class_hash(
+                self.symmetries,
+                &self.zobrist,
+                &self.game,
+                &reachable.squares,
+                canonical_hash,
+            );
+            if !self.shared.symmetric[shard_index(class_hash)]
+                .lock()
+                .unwrap()
+                .insert(class_hash)
+            {
+                self.pruning.symmetry_pruned += 1;
+                return None;
+            }
+        }
+
+        // Record the box configuration
+        self.shared.box_configs[shard_index(boxes_hash)]
+            .lock()
+            .unwrap()
+            .insert(boxes_hash);
+
+        // Apply PI-corral pruning
+        let squares: Arc<[u64]> = reachable.squares.rows(self.game.height()).collect();
+        let moves = apply_corral_pruning(
+            &mut self.helper,
+            &mut self.game,
+            reachable,
+            &mut self.pruning,
+            &mut None,
+        )?;
+
+        // Try each move
+        let parent = Parent {
+            node: &node,
+            key: canonical_hash,
+            boxes_hash,
+            check_boxes_hash,
+            distances: None,
+            squares,
+        };
+        generate_children(self, &parent, &moves);
+
+        None
+    }
+
+    /// Note a collision if a table entry whose check hash is `stored` was hit
+    /// by a position whose check hash is `check`. Only checked with hash
+    /// diagnostics.
+    fn check_collision(&mut self, hash: u64, stored: u64, check: u64) {
+        if self.opts.hash_diagnostics && stored != check {
+            self.hash_collisions += 1;
+            log_collision(hash, stored, check);
+        }
+    }
+
+    /// Reconstruct the solution ending in the current (solved) game state.
+    fn reconstruct_solution(&self) -> Vec<Push> {
+        let mut solution = Vec::new();
+        let mut current_game = self.game.clone();
+        let mut current_hash = state_hash(&self.zobrist, &current_game, false);
+
+        // Work backwards by undoing each generating move until we reach an
+        // initial state
+        loop {
+            let (move_, parent_hash) = {
+                let shard = self.shared.table_shard(current_hash);
+                let entry = shard
+                    .get(&current_hash)
+                    .expect("Failed to reconstruct solution: state not in transposition table");
+                (entry.move_, entry.parent_hash)
+            };
+            let Some(move_) = move_ else {
+                break;
+            };
+
+            let unmove: Push = move_.to_move(&current_game);
+            self.helper.apply_unmove(&mut current_game, &unmove);
+            solution.push(self.helper.to_push_by_pos(&current_game, &unmove));
+            current_hash = parent_hash;
+        }
+
+        combine_solution(self.start_game, &solution, &[] as &[PushByPos])
+    }
+}
+
+impl<H: Heuristic> ChildSink<H, ForwardSearchHelper> for Worker<'_, H> {
+    fn parts(&mut self) -> ExpandParts<'_, H, ForwardSearchHelper> {
+        ExpandParts {
+            game: &mut self.game,
+            helper: &mut self.helper,
+            zobrist: &self.zobrist,
+            check_zobrist: &self.check_zobrist,
+            heuristic: &mut self.heuristic,
+            heuristic_params: self.heuristic_params,
+            pruning: &mut self.pruning,
+            events: None,
+        }
+    }
+
+    fn lookup(&mut self, hash: u64, check: u64, _g: u16) -> Option<u64> {
+        // Skip nodes already visited
+        let stored = self
+            .shared
+            .table_shard(hash)
+            .get(&hash)
+            .map(|entry| entry.check);
+        match stored {
+            Some(stored) => {
+                self.check_collision(hash, stored, check);
+                None
+            }
+            None => Some(hash),
+        }
+    }
+
+    fn record(&mut self, key: u64, entry: TableEntry) -> Option<u16> {
+        // Skip the child if another worker got to it first
+        match self.shared.table_shard(key).entry(key) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(e) => {
+                e.insert(entry);
+                Some(0)
+            }
+        }
+    }
+
+    fn queue(&mut self, cost: Cost, frozen_boxes: &Bitvector, node: Node) {
+        self.shared
+            .best_cost
+            .fetch_min(usize::from(cost), Ordering::Relaxed);
+
+        // Queue the child with the worker owning it
+        let mut priority = usize::from(cost);
+        if self.opts.player_tie_break {
+            priority = tie_break_priority(&self.helper, &self.game, frozen_boxes, priority);
+        }
+        self.shared.push(node.key, priority, node);
+    }
+}
+
+/// Run a greedy forward best-first search on `opts.threads` threads, starting
+/// from the given open nodes (with their priorities) and transposition table,
+/// and with the corral searches seeded from the given deadlock tables.
+/// `best_cost` is the lowest heuristic cost of the initial nodes, and
+/// positions symmetric under `symmetries` to one already expanded are
+/// skipped. Returns the result, the statistics and the deadlock tables
+/// learned by the workers.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn search<H: Heuristic>(
+    game: &Game,
+    opts: &SolverOpts,
+    heuristic_params: &H::Params,
+    initial: Vec<(usize, Node)>,
+    table: ZobristMap<TableEntry>,
+    best_cost: usize,
+    symmetries: &[Symmetry],
+    tables: &DeadlockTables,
+    interrupt: Option<&AtomicBool>,
+) -> (SolveResult, SolverStats, DeadlockTables) {
+    let threads = opts.threads.max(1);
+    let shared = Shared {
+        queues: (0..threads)
+            .map(|_| Mutex::new(PriorityQueue::new().with_saturation(usize::MAX)))
+            .collect(),
+        table: (0..TABLE_SHARDS)
+            .map(|_| Mutex::new(ZobristMap::default()))
+            .collect(),
+        box_configs: (0..TABLE_SHARDS)
+            .map(|_| Mutex::new(ZobristSet::default()))
+            .collect(),
+        symmetric: (0..TABLE_SHARDS)
+            .map(|_| Mutex::new(ZobristSet::default()))
+            .collect(),
+        queued: AtomicU64::new(0),
+        expanding: Mutex::new(vec![None; threads]),
+        pending: AtomicUsize::new(0),
+        peak_open: AtomicUsize::new(0),
+        nodes_explored: AtomicUsize::new(0),
+        best_cost: AtomicUsize::new(best_cost),
+        result: Mutex::new(None),
+        done: AtomicBool::new(false),
+    };
+    for (hash, entry) in table {
+        shared.table_shard(hash).insert(hash, entry);
+    }
+    for (i, (priority, node)) in initial.into_iter().enumerate() {
+        shared.push(i as u64, priority, node);
+    }
+
+    let start = Instant::now();
+    let outcomes: Vec<(PruningStats, DeadlockTables, usize, usize)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let shared = &shared;
+                scope.spawn(move || {
+                    let zobrist = Rc::new(
+                        opts.zobrist_seed
+                            .map_or_else(Zobrist::new, Zobrist::with_seed),
+                    );
+                    let check_zobrist = Zobrist::with_seed(zobrist.seed() ^ CHECK_SEED);
+                    let mut helper = ForwardSearchHelper::new(game, &zobrist, opts);
+                    helper.corral_searcher.load_tables(tables);
+                    let mut worker = Worker::<H> {
+                        id,
+                        shared,
+                        opts,
+                        start_game: game,
+                        game: game.clone(),
+                        zobrist,
+                        check_zobrist,
+                        heuristic: FrozenCache::new(),
+                        heuristic_params,
+                        helper,
+                        pruning: PruningStats::default(),
+                        symmetries,
+                        hash_collisions: 0,
+                        start,
+                        interrupt,
+                    };
+                    worker.run();
+                    (
+                        worker.pruning,
+                        worker.helper.corral_searcher.tables(),
+                        worker.heuristic.len(),
+                        worker.hash_collisions,
+                    )
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut pruning = PruningStats::default();
+    let mut learned = DeadlockTables::default();
+    let mut heuristic_entries = 0;
+    let mut hash_collisions = 0;
+    for (worker_pruning, worker_tables, worker_heuristics, worker_collisions) in &outcomes {
+        pruning = pruning.add(worker_pruning);
+        heuristic_entries += worker_heuristics;
+        hash_collisions += worker_collisions;
+        learned.corrals.extend(&worker_tables.corrals);
+        learned.learned.extend(&worker_tables.learned);
+    }
+
+    let result = shared.result.into_inner().unwrap().unwrap();
+    let nodes_explored = shared.nodes_explored.into_inner();
+    let peak_open = shared.peak_open.into_inner();
+    // Nothing is evicted, so the table is largest at the end
+    let table_entries: usize = shared
+        .table
+        .iter()
+        .map(|shard| shard.lock().unwrap().len())
+        .sum();
+    let unique_box_configs: usize = shared
+        .box_configs
+        .iter()
+        .map(|shard| shard.lock().unwrap().len())
+        .sum();
+    let node_size = size_of::<Node>() + game.packed_size();
+    let stats = SolverStats {
+        nodes_explored,
+        unique_box_configs,
+        pruning,
+        best_cost: shared.best_cost.into_inner(),
+        evicted_nodes: 0,
+        evicted_entries: 0,
+        hash_collisions,
+        disabled_direction: None,
+        forward: DirectionStats {
+            nodes_explored,
+            peak_open,
+            peak_table: table_entries,
+            heuristic_entries,
+            peak_memory: peak_open * node_size
+                + hash_table_bytes::<(u64, TableEntry)>(table_entries)
+                + hash_table_bytes::<u64>(unique_box_configs),
+        },
+        reverse: DirectionStats::default(),
+        meeting_direction: matches!(result, SolveResult::Solved(_)).then_some(SearchType::Forward),
+    };
+    (result, stats, learned)
+}
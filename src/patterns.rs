@@ -0,0 +1,175 @@
+use crate::game::{ALL_DIRECTIONS, Direction, Game, MAX_SIZE, Position, Tile};
+
+/// Small fixed deadlock patterns, checked in constant time around each pushed
+/// box before the more expensive freeze and corral analyses:
+///
+/// * a box off its goal in a corner,
+/// * two adjacent boxes against the same wall, not both on goals,
+/// * a 2x2 block of boxes and walls with some box off its goal.
+///
+/// All of these are freeze deadlocks, so the patterns only save time.
+pub struct DeadlockPatterns {
+    /// walls[y][x] = bitmask of the directions from (x, y) which lead into a
+    /// wall (or off the board)
+    walls: Box<[[u8; MAX_SIZE]; MAX_SIZE]>,
+}
+
+const VERTICAL: u8 = direction_bit(Direction::Up) | direction_bit(Direction::Down);
+const HORIZONTAL: u8 = direction_bit(Direction::Left) | direction_bit(Direction::Right);
+
+const fn direction_bit(dir: Direction) -> u8 {
+    match dir {
+        Direction::Up => 1,
+        Direction::Down => 2,
+        Direction::Left => 4,
+        Direction::Right => 8,
+    }
+}
+
+impl DeadlockPatterns {
+    pub fn new(game: &Game) -> Self {
+        let mut walls = Box::new([[0; MAX_SIZE]; MAX_SIZE]);
+        for y in 0..game.height() {
+            for x in 0..game.width() {
+                let pos = Position(x, y);
+                for dir in ALL_DIRECTIONS {
+                    if is_wall(game, game.move_position(pos, dir)) {
+                        walls[y as usize][x as usize] |= direction_bit(dir);
+                    }
+                }
+            }
+        }
+        Self { walls }
+    }
+
+    /// Check whether the box just pushed to the given position completes one
+    /// of the patterns.
+    pub fn is_deadlocked(&self, game: &Game, pos: Position) -> bool {
+        let walls = |pos: Position| self.walls[pos.1 as usize][pos.0 as usize];
+        let on_goal = |pos: Position| game.get_tile(pos) == Tile::Goal;
+
+        // Box in a corner
+        let pos_walls = walls(pos);
+        if !on_goal(pos) && pos_walls & VERTICAL != 0 && pos_walls & HORIZONTAL != 0 {
+            return true;
+        }
+
+        // Two adjacent boxes against the same wall
+        for dir in ALL_DIRECTIONS {
+            let Some(next_pos) = game.move_position(pos, dir) else {
+                continue;
+            };
+            if game.box_index(next_pos).is_none() || on_goal(pos) && on_goal(next_pos) {
+                continue;
+            }
+            let across = match dir {
+                Direction::Left | Direction::Right => VERTICAL,
+                Direction::Up | Direction::Down => HORIZONTAL,
+            };
+            if pos_walls & walls(next_pos) & across != 0 {
+                return true;
+            }
+        }
+
+        // 2x2 block of boxes and walls
+        for vertical in [Direction::Up, Direction::Down] {
+            for horizontal in [Direction::Left, Direction::Right] {
+                let side = game.move_position(pos, horizontal);
+                let block = [
+                    Some(pos),
+                    side,
+                    game.move_position(pos, vertical),
+                    side.and_then(|side| game.move_position(side, vertical)),
+                ];
+                let mut off_goal = false;
+                let all_blocked = block.iter().all(|&square| match square {
+                    Some(square) if game.box_index(square).is_some() => {
+                        off_goal |= !on_goal(square);
+                        true
+                    }
+                    square => is_wall(game, square),
+                });
+                if all_blocked && off_goal {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+fn is_wall(game: &Game, pos: Option<Position>) -> bool {
+    pos.is_none_or(|pos| game.get_tile(pos) == Tile::Wall)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_deadlocked(text: &str, pos: Position) -> bool {
+        let game = Game::from_text(text).unwrap();
+        DeadlockPatterns::new(&game).is_deadlocked(&game, pos)
+    }
+
+    #[test]
+    fn test_corner() {
+        let text = "#####\n\
+                    #$  #\n\
+                    #  .#\n\
+                    # @ #\n\
+                    #####";
+        assert!(is_deadlocked(text, Position(1, 1)));
+
+        // Not a deadlock on a goal
+        let text = "#####\n\
+                    #*  #\n\
+                    #   #\n\
+                    # @ #\n\
+                    #####";
+        assert!(!is_deadlocked(text, Position(1, 1)));
+    }
+
+    #[test]
+    fn test_wall_line() {
+        let text = "######\n\
+                    # $$ #\n\
+                    #    #\n\
+                    # .. #\n\
+                    #  @ #\n\
+                    ######";
+        assert!(is_deadlocked(text, Position(2, 1)));
+        assert!(is_deadlocked(text, Position(3, 1)));
+
+        // Away from the wall both boxes can still be pushed
+        let text = "######\n\
+                    #    #\n\
+                    # $$ #\n\
+                    # .. #\n\
+                    #  @ #\n\
+                    ######";
+        assert!(!is_deadlocked(text, Position(2, 2)));
+    }
+
+    #[test]
+    fn test_square() {
+        let text = "######\n\
+                    #    #\n\
+                    # $$ #\n\
+                    # $$ #\n\
+                    #....#\n\
+                    #  @ #\n\
+                    ######";
+        assert!(is_deadlocked(text, Position(2, 2)));
+
+        // A 2x2 block of boxes on goals is fine
+        let text = "######\n\
+                    #    #\n\
+                    # ** #\n\
+                    # ** #\n\
+                    #    #\n\
+                    #  @ #\n\
+                    ######";
+        assert!(!is_deadlocked(text, Position(2, 2)));
+    }
+}
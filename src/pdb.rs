@@ -0,0 +1,418 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use crate::bits::LazyBitboard;
+use crate::game::{ALL_DIRECTIONS, Game, Position, Tile};
+
+/// Largest room, in squares, to build a pattern database for.
+const MAX_ROOM_SQUARES: usize = 24;
+
+/// Largest number of box placements to enumerate for a single room.
+const MAX_PLACEMENTS: usize = 1 << 18;
+
+/// The shape of a goal room: a region of the board holding goals which can
+/// only be entered through a single entrance square.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RoomShape {
+    squares: Vec<Position>,
+    entrance: Position,
+    /// Bitmask of the squares which are goals.
+    goals: u32,
+    /// Directions from the entrance which lead out of the room.
+    exits: [bool; 4],
+}
+
+/// A goal room along with a pattern database holding, for every placement of
+/// up to one more box than it has goals, the exact number of pushes into the
+/// room needed to fill its goals.
+///
+/// The database is built over an abstraction of the board made of the room
+/// squares, the entrance, and a single node standing for everything outside
+/// the room. The outside is assumed to always have room for the player, and
+/// boxes may enter or leave through the entrance for free, so only pushes
+/// which end inside the room are counted. Pushes which end outside the room
+/// can be bounded separately and added on top.
+pub struct GoalRoom {
+    /// Squares of the room, followed by the entrance.
+    squares: Vec<Position>,
+    /// neighbors[node][dir] = the node next to the given node in the given
+    /// direction, if any. The node after the entrance is the outside.
+    neighbors: Vec<[Option<usize>; 4]>,
+    goals: u32,
+    costs: HashMap<u64, u16>,
+}
+
+impl GoalRoom {
+    /// The squares inside the room.
+    pub fn squares(&self) -> &[Position] {
+        &self.squares[..self.squares.len() - 1]
+    }
+
+    /// The square through which boxes enter and leave the room.
+    pub fn entrance(&self) -> Position {
+        self.squares[self.squares.len() - 1]
+    }
+
+    /// The exact number of pushes ending inside the room needed to fill its
+    /// goals from the placement of boxes in the room and on the entrance,
+    /// or None if the placement isn't in the database.
+    pub fn cost(&self, game: &Game) -> Option<u16> {
+        let mut boxes = 0;
+        for (node, &pos) in self.squares.iter().enumerate() {
+            if game.box_index(pos).is_some() {
+                boxes |= 1 << node;
+            }
+        }
+        let player = self
+            .squares
+            .iter()
+            .position(|&pos| pos == game.player())
+            .unwrap_or(self.outside());
+        self.costs.get(&self.key(boxes, player)).copied()
+    }
+
+    fn build(shape: &RoomShape) -> Self {
+        let mut squares = shape.squares.clone();
+        squares.push(shape.entrance);
+        let outside = squares.len();
+
+        let mut neighbors = vec![[None; 4]; outside + 1];
+        for (node, &pos) in squares.iter().enumerate() {
+            for dir in ALL_DIRECTIONS {
+                let next = pos.offset(dir);
+                neighbors[node][dir.index()] = squares.iter().position(|&pos| Some(pos) == next);
+            }
+        }
+        for dir in ALL_DIRECTIONS {
+            if shape.exits[dir.index()] {
+                neighbors[outside - 1][dir.index()] = Some(outside);
+                neighbors[outside][dir.reverse().index()] = Some(outside - 1);
+            }
+        }
+
+        let mut room = GoalRoom {
+            squares,
+            neighbors,
+            goals: shape.goals,
+            costs: HashMap::new(),
+        };
+        room.compute_costs();
+        room
+    }
+
+    /// The node standing for everything outside the room.
+    fn outside(&self) -> usize {
+        self.squares.len()
+    }
+
+    /// Bitmask of the nodes the player can reach from the given node.
+    fn region(&self, boxes: u32, player: usize) -> u64 {
+        let mut region = 1u64 << player;
+        let mut stack = vec![player];
+        while let Some(node) = stack.pop() {
+            for next in self.neighbors[node].into_iter().flatten() {
+                if boxes & (1 << next) == 0 && region & (1 << next) == 0 {
+                    region |= 1 << next;
+                    stack.push(next);
+                }
+            }
+        }
+        region
+    }
+
+    /// The database key for a placement of boxes, with the player normalized
+    /// to the first node of its region.
+    fn key(&self, boxes: u32, player: usize) -> u64 {
+        let player = self.region(boxes, player).trailing_zeros();
+        boxes as u64 | (player as u64) << 32
+    }
+
+    /// Fill in the database by searching backwards from the placements with
+    /// every goal filled, pulling boxes rather than pushing them.
+    fn compute_costs(&mut self) {
+        let entrance = self.outside() - 1;
+        let max_boxes = self.goals.count_ones() + 1;
+        let mut queue = VecDeque::new();
+        let mut predecessors: Vec<(u64, u16)> = Vec::new();
+
+        for player in 0..=self.outside() {
+            if self.goals & (1 << player) == 0 {
+                let key = self.key(self.goals, player);
+                if self.costs.insert(key, 0).is_none() {
+                    queue.push_back((key, 0));
+                }
+            }
+        }
+
+        while let Some((key, cost)) = queue.pop_front() {
+            if self.costs[&key] < cost {
+                continue;
+            }
+            let boxes = key as u32;
+            let region = self.region(boxes, (key >> 32) as usize);
+            let is_free = |node: usize| boxes & (1 << node) == 0;
+
+            for dir in ALL_DIRECTIONS {
+                let back = dir.reverse().index();
+                for node in (0..=entrance).filter(|&node| !is_free(node)) {
+                    // Undo pushing a box from `from` onto `node`, which only
+                    // counts if it ends inside the room
+                    let Some(from) = self.neighbors[node][back] else {
+                        continue;
+                    };
+                    let Some(player) = self.neighbors[from][back] else {
+                        continue;
+                    };
+                    if from != self.outside() && region & (1 << from) != 0 && is_free(player) {
+                        let boxes = boxes & !(1 << node) | 1 << from;
+                        let cost = if node == entrance { 0 } else { 1 };
+                        predecessors.push((self.key(boxes, player), cost));
+                    }
+                }
+
+                // Undo pushing a box from the entrance out of the room
+                if self.neighbors[entrance][dir.index()] == Some(self.outside())
+                    && region & (1 << entrance) != 0
+                    && boxes.count_ones() < max_boxes
+                    && let Some(player) = self.neighbors[entrance][back]
+                    && player != self.outside()
+                    && is_free(player)
+                {
+                    predecessors.push((self.key(boxes | 1 << entrance, player), 0));
+                }
+            }
+
+            // Undo pushing a box from outside the room onto the entrance
+            if !is_free(entrance) && region & (1 << self.outside()) != 0 {
+                predecessors.push((self.key(boxes & !(1 << entrance), self.outside()), 0));
+            }
+
+            for (key, step) in predecessors.drain(..) {
+                let new_cost = cost + step;
+                if self.costs.get(&key).is_none_or(|&old| new_cost < old) {
+                    self.costs.insert(key, new_cost);
+                    if step == 0 {
+                        queue.push_front((key, new_cost));
+                    } else {
+                        queue.push_back((key, new_cost));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The shapes of the goal rooms last found, and their databases.
+type CachedRooms = (Vec<RoomShape>, Arc<[GoalRoom]>);
+
+/// Find the goal rooms of the game and build their pattern databases. The
+/// rooms of the last game seen by the thread are kept, since a heuristic is
+/// created for every set of frozen boxes met during the search.
+pub fn find_goal_rooms(game: &Game) -> Arc<[GoalRoom]> {
+    thread_local! {
+        static LAST_ROOMS: RefCell<Option<CachedRooms>> = const { RefCell::new(None) };
+    }
+
+    let shapes = find_room_shapes(game);
+    LAST_ROOMS.with_borrow_mut(|last| {
+        if let Some((last_shapes, rooms)) = last
+            && *last_shapes == shapes
+        {
+            return rooms.clone();
+        }
+        let rooms: Arc<[GoalRoom]> = shapes.iter().map(GoalRoom::build).collect();
+        *last = Some((shapes, rooms.clone()));
+        rooms
+    })
+}
+
+/// Find disjoint regions holding at least two goals which are cut off from
+/// the rest of the board by a single doorway square, preferring those with
+/// the most goals and then the fewest squares.
+fn find_room_shapes(game: &Game) -> Vec<RoomShape> {
+    // The squares which boxes can ever reach
+    let mut interior = LazyBitboard::new();
+    for &pos in game.box_positions() {
+        interior.set_all(&game.wall_region(pos));
+    }
+    let interior_count = (0..game.height())
+        .flat_map(|y| (0..game.width()).map(move |x| Position(x, y)))
+        .filter(|&pos| interior.get(pos))
+        .count();
+
+    let mut shapes = Vec::new();
+    for y in 0..game.height() {
+        for x in 0..game.width() {
+            let entrance = Position(x, y);
+            if !interior.get(entrance)
+                || game.get_tile(entrance) == Tile::Goal
+                || entrance
+                    .neighbors(game.width(), game.height())
+                    .filter(|&pos| game.get_tile(pos) != Tile::Wall)
+                    .count()
+                    != 2
+            {
+                continue;
+            }
+
+            let mut visited = LazyBitboard::new();
+            visited.set(entrance);
+            for dir in ALL_DIRECTIONS {
+                let Some(start) = game.move_position(entrance, dir) else {
+                    continue;
+                };
+                if game.get_tile(start) == Tile::Wall || visited.get(start) {
+                    continue;
+                }
+                let squares = flood_fill(game, start, &mut visited);
+                if squares.len() + 1 == interior_count || squares.len() > MAX_ROOM_SQUARES {
+                    continue;
+                }
+
+                let goals = squares
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &pos)| game.get_tile(pos) == Tile::Goal)
+                    .fold(0u32, |goals, (node, _)| goals | 1 << node);
+                let exits = ALL_DIRECTIONS.map(|dir| {
+                    game.move_position(entrance, dir).is_some_and(|pos| {
+                        game.get_tile(pos) != Tile::Wall && !squares.contains(&pos)
+                    })
+                });
+                if goals.count_ones() >= 2
+                    && count_placements(squares.len() + 1, goals.count_ones() as usize + 1)
+                        <= MAX_PLACEMENTS
+                {
+                    shapes.push(RoomShape {
+                        squares,
+                        entrance,
+                        goals,
+                        exits,
+                    });
+                }
+            }
+        }
+    }
+
+    shapes.sort_by_key(|shape| (Reverse(shape.goals.count_ones()), shape.squares.len()));
+    // Rooms must not overlap, nor contain the entrance of another room
+    let mut taken = LazyBitboard::new();
+    let mut entrances = LazyBitboard::new();
+    shapes.retain(|shape| {
+        if taken.get(shape.entrance)
+            || shape
+                .squares
+                .iter()
+                .any(|&pos| taken.get(pos) || entrances.get(pos))
+        {
+            return false;
+        }
+        for &pos in &shape.squares {
+            taken.set(pos);
+        }
+        entrances.set(shape.entrance);
+        true
+    });
+    shapes
+}
+
+/// Collect the squares connected to the start without passing through walls
+/// or squares already visited.
+fn flood_fill(game: &Game, start: Position, visited: &mut LazyBitboard) -> Vec<Position> {
+    let mut squares = vec![start];
+    visited.set(start);
+    let mut i = 0;
+    while i < squares.len() {
+        for dir in ALL_DIRECTIONS {
+            if let Some(next) = game.move_position(squares[i], dir)
+                && game.get_tile(next) != Tile::Wall
+                && !visited.get(next)
+            {
+                visited.set(next);
+                squares.push(next);
+            }
+        }
+        i += 1;
+    }
+    squares
+}
+
+/// Number of ways to place up to `max_boxes` boxes on `squares` squares.
+fn count_placements(squares: usize, max_boxes: usize) -> usize {
+    let mut total = 0usize;
+    let mut ways = 1usize;
+    for boxes in 0..=max_boxes.min(squares) {
+        total = total.saturating_add(ways);
+        ways = ways.saturating_mul(squares - boxes) / (boxes + 1);
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOM: &str = "##########\n\
+                        #@       #\n\
+                        #  $$$   #\n\
+                        #        #\n\
+                        ##### ####\n  \
+                          #      #\n  \
+                          # ...  #\n  \
+                          #      #\n  \
+                          ########";
+
+    #[test]
+    fn test_find_goal_rooms() {
+        let game = Game::from_text(ROOM).unwrap();
+        let rooms = find_goal_rooms(&game);
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].entrance(), Position(5, 4));
+        assert_eq!(rooms[0].squares().len(), 18);
+        // Each outer goal can only be filled from the middle goal with the
+        // player standing on the other, so one box has to go around
+        assert_eq!(rooms[0].cost(&game), Some(10));
+    }
+
+    #[test]
+    fn test_goal_room_cost() {
+        // The last box only needs pushing straight down into the room
+        let game = Game::from_text(
+            "##########\n\
+             #    @   #\n\
+             #        #\n\
+             #    $   #\n\
+             ##### ####\n  \
+               #      #\n  \
+               # *.*  #\n  \
+               #      #\n  \
+               ########",
+        )
+        .unwrap();
+        assert_eq!(find_goal_rooms(&game)[0].cost(&game), Some(2));
+
+        // Once pushed into the room, the box can't be pushed sideways
+        let game = Game::from_text(
+            "##########\n\
+             #        #\n\
+             #    @   #\n\
+             #        #\n\
+             #####$####\n  \
+               #      #\n  \
+               # **.  #\n  \
+               #      #\n  \
+               ########",
+        )
+        .unwrap();
+        assert_eq!(find_goal_rooms(&game)[0].cost(&game), None);
+    }
+
+    #[test]
+    fn test_count_placements() {
+        assert_eq!(count_placements(4, 0), 1);
+        assert_eq!(count_placements(4, 2), 1 + 4 + 6);
+        assert_eq!(count_placements(3, 5), 8);
+    }
+}
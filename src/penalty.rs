@@ -0,0 +1,244 @@
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::{
+    bits::{Bitvector, Position},
+    game::{Game, Move},
+    heuristic::{GoalDistances, compute_min_push_distances},
+    zobrist::Zobrist,
+};
+
+/// Maximum number of boxes in a penalty pattern.
+const MAX_PATTERN_BOXES: usize = 8;
+
+/// Maximum number of positions searched to find the cost of a pattern.
+const MAX_PATTERN_NODES: usize = 200;
+
+/// Maximum number of box subsets examined, after which no more patterns are
+/// learned.
+const MAX_SUBSETS: usize = 10_000;
+
+/// Maximum number of penalty patterns kept, after which no more are learned.
+const MAX_PATTERNS: usize = 256;
+
+/// A set of box positions which needs more pushes than the sum of each box's
+/// distance to its nearest goal, even with every other box removed.
+struct Pattern {
+    positions: Box<[Position]>,
+    penalty: u16,
+}
+
+/// Penalty patterns learned during search, after Sokolution. Whenever a
+/// position contains the boxes of a pattern, the pushes the pattern needs on
+/// its own bound the heuristic cost from below. Patterns are learned from box subsets which the corral
+/// search had to examine, by searching for the fewest pushes needed to solve
+/// the subset on its own.
+pub struct PenaltyTable {
+    patterns: Vec<Pattern>,
+    /// Box hashes of the subsets already examined, whether or not they gave a
+    /// pattern.
+    seen: HashSet<u64>,
+    /// min_distances[square] = minimum pushes to get a box from the square to
+    /// any goal
+    min_distances: GoalDistances,
+    zobrist: Rc<Zobrist>,
+}
+
+impl PenaltyTable {
+    pub fn new(game: &Game, zobrist: Rc<Zobrist>) -> Self {
+        Self {
+            patterns: Vec::new(),
+            seen: HashSet::new(),
+            min_distances: compute_min_push_distances(game),
+            zobrist,
+        }
+    }
+
+    /// Number of patterns learned.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Learn a pattern from the given subset of boxes, if solving the subset
+    /// on its own needs more pushes than its boxes' distances to their
+    /// nearest goals. Returns the penalty of the new pattern, if any.
+    pub fn learn(&mut self, game: &Game, boxes: Bitvector) -> Option<u16> {
+        if boxes.len() < 2
+            || boxes.len() > MAX_PATTERN_BOXES
+            || self.len() >= MAX_PATTERNS
+            || self.seen.len() >= MAX_SUBSETS
+        {
+            return None;
+        }
+        let hash = self.zobrist.compute_boxes_hash_subset(game, boxes);
+        if !self.seen.insert(hash) {
+            return None;
+        }
+
+        let lower_bound: u16 = boxes
+            .iter()
+            .map(|box_idx| {
+                let pos = game.box_position(box_idx);
+                self.min_distances.get(game, pos)
+            })
+            .fold(0, u16::saturating_add);
+        let pushes = self.solve_subset(game, boxes)?;
+        let penalty = pushes.checked_sub(lower_bound).filter(|&p| p > 0)?;
+
+        // Keep the patterns ordered by decreasing penalty, so that the largest
+        // penalties are preferred when patterns overlap
+        let positions = boxes.iter().map(|idx| game.box_position(idx)).collect();
+        let idx = self.patterns.partition_point(|p| p.penalty >= penalty);
+        self.patterns.insert(idx, Pattern { positions, penalty });
+        Some(penalty)
+    }
+
+    /// Total penalty of the patterns matching the given position. Patterns
+    /// sharing a box are not added together.
+    pub fn penalty(&self, game: &Game) -> u16 {
+        let mut used = Bitvector::new();
+        let mut total = 0u16;
+        'patterns: for pattern in &self.patterns {
+            let mut boxes = Bitvector::new();
+            for &pos in &pattern.positions {
+                match game.box_index(pos) {
+                    Some(box_idx) if !used.contains(box_idx) => boxes.add(box_idx),
+                    _ => continue 'patterns,
+                }
+            }
+            used.add_all(&boxes);
+            total = total.saturating_add(pattern.penalty);
+        }
+        total
+    }
+
+    /// Lower bound on the pushes needed to solve the given position: the
+    /// pushes of each matching pattern on its own, plus the distance of every
+    /// other box to its nearest goal, or 0 if no pattern matches. Each box's
+    /// pushes only count once, so unlike adding the penalties to a matching
+    /// heuristic this is admissible.
+    pub fn lower_bound(&self, game: &Game) -> u16 {
+        let penalty = self.penalty(game);
+        if penalty == 0 {
+            return 0;
+        }
+        game.box_positions()
+            .iter()
+            .map(|&pos| self.min_distances.get(game, pos))
+            .fold(penalty, u16::saturating_add)
+    }
+
+    /// Fewest pushes needed to put the given boxes on goals with every other
+    /// box removed, from any player position, or `None` if the boxes can't
+    /// be solved within `MAX_PATTERN_NODES` positions.
+    fn solve_subset(&self, game: &Game, boxes: Bitvector) -> Option<u16> {
+        let mut game = game.clone();
+        game.project(boxes);
+
+        let mut queue = VecDeque::new();
+        for pos in game.all_possible_player_positions() {
+            game.set_player(pos);
+            queue.push_back((game.checkpoint(), 0u16));
+        }
+
+        let mut visited = HashSet::new();
+        while let Some((checkpoint, pushes)) = queue.pop_front() {
+            game.restore(&checkpoint);
+            if game.is_solved() {
+                return Some(pushes);
+            }
+
+            let reachable = game.compute_pushes();
+            let hash = self.zobrist.compute_boxes_hash(&game)
+                ^ self
+                    .zobrist
+                    .player_hash(reachable.squares.top_left().unwrap());
+            if !visited.insert(hash) {
+                continue;
+            }
+            if visited.len() > MAX_PATTERN_NODES {
+                return None;
+            }
+
+            for push in &reachable.moves {
+                let new_pos = game.box_position(push.box_index()) + push.direction();
+                if game.is_push_dead_square(new_pos) {
+                    continue;
+                }
+                game.push(push);
+                queue.push_back((game.checkpoint(), pushes + 1));
+                game.restore(&checkpoint);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bits::Index,
+        game::{Direction, Push},
+    };
+
+    fn new_table(game: &Game) -> PenaltyTable {
+        PenaltyTable::new(game, Rc::new(Zobrist::new()))
+    }
+
+    #[test]
+    fn test_learn_penalty() {
+        let game = Game::from_text(
+            r#"
+#######
+#     #
+#     #
+#@$$..#
+#     #
+#     #
+#######
+"#,
+        )
+        .unwrap();
+        let mut table = new_table(&game);
+
+        // One box must be moved out of the line and back to let the other
+        // past: 6 pushes, rather than the 3 pushes to the nearest goals
+        assert_eq!(table.learn(&game, Bitvector::full(2)), Some(3));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.penalty(&game), 3);
+        assert_eq!(table.lower_bound(&game), 6);
+
+        // Already examined
+        assert_eq!(table.learn(&game, Bitvector::full(2)), None);
+
+        // The pattern no longer matches once a box has moved
+        let mut moved = game.clone();
+        moved.push(Push::new(Index(1), Direction::Right));
+        assert_eq!(table.penalty(&moved), 0);
+        assert_eq!(table.lower_bound(&moved), 0);
+    }
+
+    #[test]
+    fn test_learn_no_penalty() {
+        let game = Game::from_text(
+            r#"
+#######
+#@$.$.#
+#######
+"#,
+        )
+        .unwrap();
+        let mut table = new_table(&game);
+
+        // Each box can be pushed onto its own nearest goal
+        assert_eq!(table.learn(&game, Bitvector::full(2)), None);
+        assert!(table.is_empty());
+        assert_eq!(table.penalty(&game), 0);
+    }
+}
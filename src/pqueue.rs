@@ -1,60 +1,187 @@
 use std::collections::VecDeque;
 
-const NUM_BUCKETS: usize = 4096;
-const NUM_WORDS: usize = NUM_BUCKETS / 64;
+/// Number of bits in each word of the bitmaps.
+const WORD_BITS: usize = 64;
 
-/// A bucketed priority queue implementation which supports O(1) pop-min.
-/// Priority values must lie within the range 0..4096
+/// A bucketed priority queue implementation which supports O(1) pop-min and
+/// pop-max. Buckets are allocated as higher priorities are pushed, up to
+/// `MAX_PRIORITY`, and the non-empty ones are found through three levels of
+/// bitmaps. Items of equal priority are popped first in, first out, unless
+/// the queue is LIFO.
 pub struct PriorityQueue<T> {
-    buckets: [VecDeque<T>; NUM_BUCKETS],
-    bitmap: [u64; NUM_WORDS],
-    summary: u64,
+    buckets: Vec<VecDeque<T>>,
+    /// A bit per bucket, set if it is non-empty.
+    bitmap: Vec<u64>,
+    /// A bit per word of `bitmap`, set if it is non-zero.
+    summary: Vec<u64>,
+    /// A bit per word of `summary`, set if it is non-zero.
+    top: u64,
+    len: usize,
+    lifo: bool,
+    /// Priority which higher priorities are lowered to, if the queue is
+    /// saturating.
+    saturate_at: Option<usize>,
 }
 
 impl<T> PriorityQueue<T> {
+    /// The largest supported priority, which the three bitmap levels cover.
+    pub const MAX_PRIORITY: usize = WORD_BITS * WORD_BITS * WORD_BITS - 1;
+
     pub fn new() -> Self {
         Self {
-            buckets: std::array::from_fn(|_| VecDeque::new()),
-            bitmap: [0; NUM_WORDS],
-            summary: 0,
+            buckets: Vec::new(),
+            bitmap: Vec::new(),
+            summary: Vec::new(),
+            top: 0,
+            len: 0,
+            lifo: false,
+            saturate_at: None,
+        }
+    }
+
+    /// Create a queue which pops the most recently pushed of the items with
+    /// the lowest priority first.
+    pub fn lifo() -> Self {
+        Self {
+            lifo: true,
+            ..Self::new()
+        }
+    }
+
+    /// Make the queue saturating: items pushed with a priority above
+    /// `max_priority` (at most `MAX_PRIORITY`) are given it instead, so the
+    /// queue never grows beyond it and never panics.
+    pub fn with_saturation(self, max_priority: usize) -> Self {
+        Self {
+            saturate_at: Some(max_priority.min(Self::MAX_PRIORITY)),
+            ..self
         }
     }
 
     pub fn push(&mut self, priority: usize, item: T) {
-        assert!(priority < NUM_BUCKETS, "priority must be < {}", NUM_BUCKETS);
+        let priority = match self.saturate_at {
+            Some(max_priority) => priority.min(max_priority),
+            None => priority,
+        };
+        assert!(
+            priority <= Self::MAX_PRIORITY,
+            "priority must be <= {}",
+            Self::MAX_PRIORITY
+        );
+        if priority >= self.buckets.len() {
+            self.grow(priority);
+        }
         self.buckets[priority].push_back(item);
+        self.len += 1;
 
-        // Update bitmap
-        let word_idx = priority / 64;
-        let bit_idx = priority % 64;
-        self.bitmap[word_idx] |= 1u64 << bit_idx;
-        self.summary |= 1u64 << word_idx;
+        // Update bitmaps
+        let word_idx = priority / WORD_BITS;
+        let summary_idx = word_idx / WORD_BITS;
+        self.bitmap[word_idx] |= 1u64 << (priority % WORD_BITS);
+        self.summary[summary_idx] |= 1u64 << (word_idx % WORD_BITS);
+        self.top |= 1u64 << summary_idx;
     }
 
     pub fn pop_min(&mut self) -> Option<T> {
-        // Find first non-empty word in summary
-        if self.summary == 0 {
+        let priority = self.min_priority()?;
+
+        // Pop item from bucket
+        let bucket = &mut self.buckets[priority];
+        let item = if self.lifo {
+            bucket.pop_back()?
+        } else {
+            bucket.pop_front()?
+        };
+        self.record_pop(priority);
+        Some(item)
+    }
+
+    /// Pop the item with the highest priority which `pop_min` would pop last
+    /// (the most recently pushed, unless the queue is LIFO), along with its
+    /// priority.
+    pub fn pop_max(&mut self) -> Option<(usize, T)> {
+        // Find last non-empty bucket, going down the bitmap levels
+        if self.top == 0 {
             return None;
         }
-        let word_idx = self.summary.trailing_zeros() as usize;
+        let summary_idx = 63 - self.top.leading_zeros() as usize;
+        let word_idx =
+            summary_idx * WORD_BITS + 63 - self.summary[summary_idx].leading_zeros() as usize;
+        let priority = word_idx * WORD_BITS + 63 - self.bitmap[word_idx].leading_zeros() as usize;
 
-        // Find first non-empty bucket in that word
-        let bit_idx = self.bitmap[word_idx].trailing_zeros() as usize;
-        let priority = word_idx * 64 + bit_idx;
+        // Pop item from bucket, releasing memory as the bucket shrinks since
+        // popping the maximum is used to free memory
+        let bucket = &mut self.buckets[priority];
+        let item = if self.lifo {
+            bucket.pop_front()?
+        } else {
+            bucket.pop_back()?
+        };
+        if bucket.len() < bucket.capacity() / 4 {
+            bucket.shrink_to(bucket.len() * 2);
+        }
+        self.record_pop(priority);
+        Some((priority, item))
+    }
 
-        // Pop item from bucket
-        let item = self.buckets[priority].pop_front()?;
+    /// The lowest priority of any item, if any.
+    pub fn min_priority(&self) -> Option<usize> {
+        // Find first non-empty bucket, going down the bitmap levels
+        if self.top == 0 {
+            return None;
+        }
+        let summary_idx = self.top.trailing_zeros() as usize;
+        let word_idx =
+            summary_idx * WORD_BITS + self.summary[summary_idx].trailing_zeros() as usize;
+        Some(word_idx * WORD_BITS + self.bitmap[word_idx].trailing_zeros() as usize)
+    }
+
+    /// The item `pop_min` would pop next, along with its priority.
+    pub fn peek_min(&self) -> Option<(usize, &T)> {
+        let priority = self.min_priority()?;
+        let bucket = &self.buckets[priority];
+        let item = if self.lifo {
+            bucket.back()?
+        } else {
+            bucket.front()?
+        };
+        Some((priority, item))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Allocate buckets up to at least the given priority, doubling the
+    /// number of buckets so that growing takes amortized O(1) time.
+    fn grow(&mut self, priority: usize) {
+        let len = (priority + 1).next_power_of_two().max(WORD_BITS);
+        self.buckets.resize_with(len, VecDeque::new);
+        self.bitmap.resize(len / WORD_BITS, 0);
+        self.summary
+            .resize(self.bitmap.len().div_ceil(WORD_BITS), 0);
+    }
 
-        // Update bitmap if bucket is now empty
+    /// Update the length and bitmaps after popping an item from the given
+    /// bucket.
+    fn record_pop(&mut self, priority: usize) {
+        self.len -= 1;
         if self.buckets[priority].is_empty() {
-            self.bitmap[word_idx] &= !(1u64 << bit_idx);
-            // Update summary if word is now empty
+            let word_idx = priority / WORD_BITS;
+            let summary_idx = word_idx / WORD_BITS;
+            self.bitmap[word_idx] &= !(1u64 << (priority % WORD_BITS));
+            // Update the levels above if the word is now empty
             if self.bitmap[word_idx] == 0 {
-                self.summary &= !(1u64 << word_idx);
+                self.summary[summary_idx] &= !(1u64 << (word_idx % WORD_BITS));
+                if self.summary[summary_idx] == 0 {
+                    self.top &= !(1u64 << summary_idx);
+                }
             }
         }
-
-        Some(item)
     }
 }
 
@@ -115,11 +242,71 @@ mod tests {
         assert_eq!(pq.pop_min(), Some("a"));
     }
 
+    #[test]
+    fn test_pop_max() {
+        let mut pq = PriorityQueue::new();
+        pq.push(10, "low");
+        pq.push(100, "first");
+        pq.push(100, "second");
+        pq.push(3000, "high");
+        assert_eq!(pq.len(), 4);
+
+        assert_eq!(pq.pop_max(), Some((3000, "high")));
+        assert_eq!(pq.pop_max(), Some((100, "second")));
+        assert_eq!(pq.pop_min(), Some("low"));
+        assert_eq!(pq.pop_max(), Some((100, "first")));
+        assert_eq!(pq.pop_max(), None);
+        assert!(pq.is_empty());
+    }
+
+    #[test]
+    fn test_lifo() {
+        let mut pq = PriorityQueue::lifo();
+        pq.push(10, "first");
+        pq.push(10, "second");
+        pq.push(10, "third");
+        pq.push(20, "high");
+        pq.push(5, "low");
+
+        assert_eq!(pq.pop_min(), Some("low"));
+        assert_eq!(pq.pop_min(), Some("third"));
+        assert_eq!(pq.pop_max(), Some((20, "high")));
+        assert_eq!(pq.pop_max(), Some((10, "first")));
+        assert_eq!(pq.pop_min(), Some("second"));
+        assert!(pq.is_empty());
+    }
+
+    #[test]
+    fn test_min_priority() {
+        let mut pq = PriorityQueue::new();
+        assert_eq!(pq.min_priority(), None);
+        pq.push(100, "high");
+        pq.push(70, "low");
+        assert_eq!(pq.min_priority(), Some(70));
+        pq.pop_min();
+        assert_eq!(pq.min_priority(), Some(100));
+    }
+
+    #[test]
+    fn test_peek_min() {
+        let mut pq = PriorityQueue::new();
+        assert_eq!(pq.peek_min(), None);
+        pq.push(10, "first");
+        pq.push(10, "second");
+        assert_eq!(pq.peek_min(), Some((10, &"first")));
+
+        let mut pq = PriorityQueue::lifo();
+        pq.push(10, "first");
+        pq.push(10, "second");
+        assert_eq!(pq.peek_min(), Some((10, &"second")));
+        assert_eq!(pq.pop_min(), Some("second"));
+    }
+
     #[test]
     fn test_boundary_priorities() {
         let mut pq = PriorityQueue::new();
         pq.push(0, "min");
-        pq.push(NUM_BUCKETS - 1, "max");
+        pq.push(PriorityQueue::<&str>::MAX_PRIORITY, "max");
         pq.push(2000, "mid");
 
         assert_eq!(pq.pop_min(), Some("min"));
@@ -131,7 +318,34 @@ mod tests {
     #[should_panic(expected = "priority must be <")]
     fn test_priority_too_large() {
         let mut pq = PriorityQueue::new();
-        pq.push(NUM_BUCKETS, "invalid");
+        pq.push(PriorityQueue::<&str>::MAX_PRIORITY + 1, "invalid");
+    }
+
+    #[test]
+    fn test_growth() {
+        let mut pq = PriorityQueue::new();
+        pq.push(5000, "beyond");
+        pq.push(100_000, "far beyond");
+        pq.push(3, "low");
+        assert_eq!(pq.min_priority(), Some(3));
+
+        assert_eq!(pq.pop_max(), Some((100_000, "far beyond")));
+        assert_eq!(pq.pop_min(), Some("low"));
+        assert_eq!(pq.pop_min(), Some("beyond"));
+        assert_eq!(pq.pop_min(), None);
+    }
+
+    #[test]
+    fn test_saturation() {
+        let mut pq = PriorityQueue::new().with_saturation(100);
+        pq.push(1_000_000, "huge");
+        pq.push(100, "max");
+        pq.push(99, "below");
+
+        assert_eq!(pq.pop_min(), Some("below"));
+        assert_eq!(pq.pop_max(), Some((100, "max")));
+        assert_eq!(pq.pop_max(), Some((100, "huge")));
+        assert!(pq.is_empty());
     }
 
     #[test]
@@ -143,15 +357,18 @@ mod tests {
     #[test]
     fn test_bitmap_word_boundaries() {
         let mut pq = PriorityQueue::new();
-        // Test across word boundaries (each word is 64 buckets)
+        // Test across word boundaries (each word is 64 buckets, and each
+        // summary word 4096)
         pq.push(63, "word0_last");
         pq.push(64, "word1_first");
         pq.push(128, "word2_first");
+        pq.push(4096, "summary1_first");
         pq.push(0, "word0_first");
 
         assert_eq!(pq.pop_min(), Some("word0_first"));
         assert_eq!(pq.pop_min(), Some("word0_last"));
         assert_eq!(pq.pop_min(), Some("word1_first"));
         assert_eq!(pq.pop_min(), Some("word2_first"));
+        assert_eq!(pq.pop_min(), Some("summary1_first"));
     }
 }
@@ -0,0 +1,354 @@
+use crate::game::{Game, Position, Push};
+use std::fmt::Write;
+
+/// Size of a single board square in SVG user units.
+const TILE_SIZE: u32 = 32;
+/// Time each frame is displayed in an animated SVG.
+const FRAME_DURATION_MS: u32 = 400;
+
+const WALL_COLOR: &str = "#5a5a5a";
+const FLOOR_COLOR: &str = "#eeeeee";
+const GOAL_COLOR: &str = "#d04040";
+const BOX_COLOR: &str = "#c8902c";
+const BOX_ON_GOAL_COLOR: &str = "#5a9a3c";
+const PLAYER_COLOR: &str = "#3060c0";
+const BOX_STROKE_COLOR: &str = "#000000";
+
+/// Render the current state of a game as a standalone SVG document.
+pub fn render_frame(game: &Game) -> String {
+    let mut svg = svg_header(game);
+    render_background(game, &mut svg);
+
+    for y in 0..game.height() {
+        for x in 0..game.width() {
+            let pos = Position(x, y);
+            match game.square_char(pos) {
+                '$' => render_box(pos, BOX_COLOR, &mut svg),
+                '*' => render_box(pos, BOX_ON_GOAL_COLOR, &mut svg),
+                '@' | '+' => render_player(pos, &mut svg),
+                _ => {}
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render one SVG frame for the starting position and one after each push.
+pub fn render_frames(game: &Game, solution: &[Push]) -> Vec<String> {
+    let mut game = game.clone();
+    let mut frames = vec![render_frame(&game)];
+    for &push in solution {
+        game.push(push);
+        frames.push(render_frame(&game));
+    }
+    frames
+}
+
+/// Render a solution as a single looping animated SVG.
+///
+/// The static parts of the board are drawn once and the boxes and player are
+/// animated with discrete SMIL animations, one step per push.
+pub fn render_animation(game: &Game, solution: &[Push]) -> String {
+    // Record box and player positions for each frame
+    let mut game = game.clone();
+    let mut box_frames = vec![Vec::new(); game.box_count()];
+    let mut player_frames = Vec::new();
+    let mut record = |game: &Game| {
+        for (frames, &pos) in box_frames.iter_mut().zip(game.box_positions()) {
+            frames.push(pos);
+        }
+        player_frames.push(game.player());
+    };
+    record(&game);
+    for &push in solution {
+        game.push(push);
+        record(&game);
+    }
+
+    let dur = format!("{}ms", FRAME_DURATION_MS as usize * player_frames.len());
+    let mut svg = svg_header(&game);
+    render_background(&game, &mut svg);
+
+    for frames in &box_frames {
+        let colors: Vec<&str> = frames
+            .iter()
+            .map(|&pos| {
+                if game.goal_positions().contains(&pos) {
+                    BOX_ON_GOAL_COLOR
+                } else {
+                    BOX_COLOR
+                }
+            })
+            .collect();
+        let (x, y) = box_origin(frames[0]);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{x}" y="{y}" width="{s}" height="{s}" rx="3" fill="{}" stroke="{BOX_STROKE_COLOR}" stroke-width="1">"#,
+            colors[0],
+            s = TILE_SIZE - 4,
+        );
+        let xs = join(frames.iter().map(|&pos| box_origin(pos).0));
+        let ys = join(frames.iter().map(|&pos| box_origin(pos).1));
+        write_animate(&mut svg, "x", &xs, &dur);
+        write_animate(&mut svg, "y", &ys, &dur);
+        write_animate(&mut svg, "fill", &colors.join(";"), &dur);
+        svg.push_str("</rect>\n");
+    }
+
+    let (cx, cy) = center(player_frames[0]);
+    let _ = writeln!(
+        svg,
+        r#"<circle cx="{cx}" cy="{cy}" r="{}" fill="{PLAYER_COLOR}">"#,
+        TILE_SIZE / 3
+    );
+    let cxs = join(player_frames.iter().map(|&pos| center(pos).0));
+    let cys = join(player_frames.iter().map(|&pos| center(pos).1));
+    write_animate(&mut svg, "cx", &cxs, &dur);
+    write_animate(&mut svg, "cy", &cys, &dur);
+    svg.push_str("</circle>\n");
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render a solution as a self-contained HTML page with a slider for stepping
+/// through the pushes and a table of search statistics.
+pub fn render_html(
+    title: &str,
+    game: &Game,
+    solution: &[Push],
+    stats: &[(&str, String)],
+) -> String {
+    let frames = render_frames(game, solution);
+    let title = escape_html(title);
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 1em; }}
+td {{ padding: 2px 12px 2px 0; }}
+.frame {{ display: none; }}
+.frame.active {{ display: block; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<table>
+"#
+    );
+    for (label, value) in stats {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            escape_html(label),
+            escape_html(value)
+        );
+    }
+    let _ = write!(
+        html,
+        r#"</table>
+<div>
+<input id="step" type="range" min="0" max="{max}" value="0" oninput="show(this.value)">
+<span id="label"></span>
+</div>
+"#,
+        max = frames.len() - 1
+    );
+    for (i, frame) in frames.iter().enumerate() {
+        let class = if i == 0 { "frame active" } else { "frame" };
+        let _ = write!(html, "<div class=\"{class}\">\n{frame}</div>\n");
+    }
+    let _ = write!(
+        html,
+        r#"<script>
+const frames = document.getElementsByClassName("frame");
+function show(step) {{
+  for (let i = 0; i < frames.length; i++) {{
+    frames[i].classList.toggle("active", i == step);
+  }}
+  document.getElementById("label").textContent = "push " + step + " / {total}";
+}}
+show(0);
+</script>
+</body>
+</html>
+"#,
+        total = solution.len()
+    );
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn svg_header(game: &Game) -> String {
+    let width = game.width() as u32 * TILE_SIZE;
+    let height = game.height() as u32 * TILE_SIZE;
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">
+"#
+    )
+}
+
+/// Render walls, floors and goals, which never change during a solution.
+fn render_background(game: &Game, svg: &mut String) {
+    let interior = game.wall_region(game.player());
+    for y in 0..game.height() {
+        for x in 0..game.width() {
+            let pos = Position(x, y);
+            let ch = game.square_char(pos);
+            let (px, py) = (x as u32 * TILE_SIZE, y as u32 * TILE_SIZE);
+            let fill = match ch {
+                '#' => WALL_COLOR,
+                ' ' if !interior.get(pos) => continue,
+                _ => FLOOR_COLOR,
+            };
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{px}" y="{py}" width="{TILE_SIZE}" height="{TILE_SIZE}" fill="{fill}"/>"#
+            );
+            if matches!(ch, '.' | '*' | '+') {
+                let (cx, cy) = center(pos);
+                let _ = writeln!(
+                    svg,
+                    r#"<circle cx="{cx}" cy="{cy}" r="{}" fill="{GOAL_COLOR}"/>"#,
+                    TILE_SIZE / 6
+                );
+            }
+        }
+    }
+}
+
+fn render_box(pos: Position, color: &str, svg: &mut String) {
+    let (x, y) = box_origin(pos);
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{x}" y="{y}" width="{s}" height="{s}" rx="3" fill="{color}" stroke="{BOX_STROKE_COLOR}" stroke-width="1"/>"#,
+        s = TILE_SIZE - 4,
+    );
+}
+
+fn render_player(pos: Position, svg: &mut String) {
+    let (cx, cy) = center(pos);
+    let _ = writeln!(
+        svg,
+        r#"<circle cx="{cx}" cy="{cy}" r="{}" fill="{PLAYER_COLOR}"/>"#,
+        TILE_SIZE / 3
+    );
+}
+
+fn write_animate(svg: &mut String, attribute: &str, values: &str, dur: &str) {
+    let _ = writeln!(
+        svg,
+        r#"<animate attributeName="{attribute}" values="{values}" dur="{dur}" calcMode="discrete" repeatCount="indefinite"/>"#
+    );
+}
+
+fn box_origin(pos: Position) -> (u32, u32) {
+    (pos.0 as u32 * TILE_SIZE + 2, pos.1 as u32 * TILE_SIZE + 2)
+}
+
+fn center(pos: Position) -> (u32, u32) {
+    (
+        pos.0 as u32 * TILE_SIZE + TILE_SIZE / 2,
+        pos.1 as u32 * TILE_SIZE + TILE_SIZE / 2,
+    )
+}
+
+fn join(values: impl Iterator<Item = u32>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Direction, Index};
+
+    #[test]
+    fn test_render_frame() {
+        let game = parse_game(
+            r#"
+#####
+#@$.#
+#####
+"#,
+        );
+        let svg = render_frame(&game);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"width="160" height="96""#));
+        assert!(svg.contains(BOX_COLOR));
+        assert!(svg.contains(PLAYER_COLOR));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_render_frames() {
+        let game = parse_game(
+            r#"
+#####
+#@$.#
+#####
+"#,
+        );
+        let solution = [Push::new(Index(0), Direction::Right)];
+        let frames = render_frames(&game, &solution);
+        assert_eq!(frames.len(), 2);
+        assert!(!frames[0].contains(BOX_ON_GOAL_COLOR));
+        assert!(frames[1].contains(BOX_ON_GOAL_COLOR));
+    }
+
+    #[test]
+    fn test_render_animation() {
+        let game = parse_game(
+            r#"
+######
+#@$ .#
+######
+"#,
+        );
+        let solution = [
+            Push::new(Index(0), Direction::Right),
+            Push::new(Index(0), Direction::Right),
+        ];
+        let svg = render_animation(&game, &solution);
+        assert!(svg.contains(r#"attributeName="x" values="66;98;130""#));
+        assert!(svg.contains(r#"attributeName="cx" values="48;80;112""#));
+        assert!(svg.contains(r#"dur="1200ms""#));
+    }
+
+    #[test]
+    fn test_render_html() {
+        let game = parse_game(
+            r#"
+#####
+#@$.#
+#####
+"#,
+        );
+        let solution = [Push::new(Index(0), Direction::Right)];
+        let stats = [("states", "2".to_string()), ("<b>", "&".to_string())];
+        let html = render_html("Level 1", &game, &solution, &stats);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Level 1</title>"));
+        assert!(html.contains(r#"max="1""#));
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert!(html.contains("<td>&lt;b&gt;</td><td>&amp;</td>"));
+    }
+
+    fn parse_game(text: &str) -> Game {
+        Game::from_text(text.trim_matches('\n')).unwrap()
+    }
+}
@@ -0,0 +1,415 @@
+use crate::game::{Direction, Game, Index, Move, Push, PushByPos};
+use std::fmt;
+
+/// A parsed solution for a single level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Solution {
+    /// Player moves in LURD notation. Each step records the direction and
+    /// whether the step pushes a box (uppercase letters).
+    Lurd(Vec<(Direction, bool)>),
+    /// A list of box pushes (e.g., `1R 1R 2U`). The player walks to each box
+    /// implicitly.
+    Pushes(Vec<Push>),
+}
+
+/// Error describing where a solution first diverges from a valid replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The 1-indexed step at which the replay failed. For solutions that are
+    /// valid but do not solve the level, this is the number of steps.
+    pub step: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {}: {}", self.step, self.reason)
+    }
+}
+
+impl Solution {
+    /// Parse a solution in either LURD notation or as a push list.
+    ///
+    /// Push lists are whitespace separated tokens consisting of a 1-indexed box
+    /// number followed by a direction letter (`U`, `D`, `L` or `R`).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if text.chars().all(|ch| "lurdLURD".contains(ch)) {
+            return Self::parse_lurd(text);
+        }
+
+        let mut pushes = Vec::new();
+        for token in text.split_whitespace() {
+            pushes.push(parse_push(token)?);
+        }
+        Ok(Solution::Pushes(pushes))
+    }
+
+    /// Parse a solution in LURD notation, skipping whitespace such as line
+    /// breaks. Errors give the 1-indexed step of the first invalid character.
+    pub fn parse_lurd(text: &str) -> Result<Self, String> {
+        text.chars()
+            .filter(|ch| !ch.is_whitespace())
+            .enumerate()
+            .map(|(i, ch)| match parse_direction(ch) {
+                Some(dir) => Ok((dir, ch.is_ascii_uppercase())),
+                None => Err(format!("step {}: invalid LURD character '{}'", i + 1, ch)),
+            })
+            .collect::<Result<_, _>>()
+            .map(Solution::Lurd)
+    }
+
+    /// Number of steps (moves or pushes) in the solution.
+    pub fn len(&self) -> usize {
+        match self {
+            Solution::Lurd(steps) => steps.len(),
+            Solution::Pushes(pushes) => pushes.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replay the solution against the given game, returning the final state.
+    pub fn replay(&self, game: &Game) -> Result<Game, VerifyError> {
+        let mut game = game.clone();
+        match self {
+            Solution::Lurd(steps) => {
+                for (i, &(dir, is_push)) in steps.iter().enumerate() {
+                    apply_step(&mut game, dir, is_push).map_err(|reason| VerifyError {
+                        step: i + 1,
+                        reason,
+                    })?;
+                }
+            }
+            Solution::Pushes(pushes) => {
+                for (i, &push) in pushes.iter().enumerate() {
+                    apply_push(&mut game, push).map_err(|reason| VerifyError {
+                        step: i + 1,
+                        reason,
+                    })?;
+                }
+            }
+        }
+        Ok(game)
+    }
+
+    /// Replay the solution and check that it solves the game.
+    pub fn verify(&self, game: &Game) -> Result<(), VerifyError> {
+        let game = self.replay(game)?;
+        if game.is_solved() {
+            Ok(())
+        } else {
+            Err(VerifyError {
+                step: self.len(),
+                reason: "puzzle is not solved".to_string(),
+            })
+        }
+    }
+}
+
+/// Parse a solutions file containing one solution per level.
+///
+/// Empty lines and lines starting with `;` are ignored.
+pub fn parse_solutions(text: &str) -> Result<Vec<Solution>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(Solution::parse)
+        .collect()
+}
+
+/// Format a push solution compactly, writing one line per run of consecutive
+/// pushes of the same box. Within a line, consecutive pushes in the same
+/// direction are run-length encoded (e.g., `3R 2U (box 5)`).
+pub fn format_compact(solution: &[Push]) -> String {
+    let mut result = String::new();
+    let mut i = 0;
+    while i < solution.len() {
+        let box_idx = solution[i].box_index();
+        let mut runs = Vec::new();
+        while i < solution.len() && solution[i].box_index() == box_idx {
+            let dir = solution[i].direction();
+            let mut count = 0;
+            while i < solution.len()
+                && solution[i].box_index() == box_idx
+                && solution[i].direction() == dir
+            {
+                count += 1;
+                i += 1;
+            }
+            let letter = direction_char(dir);
+            if count == 1 {
+                runs.push(letter.to_string());
+            } else {
+                runs.push(format!("{}{}", count, letter));
+            }
+        }
+        result.push_str(&format!("{} (box {})\n", runs.join(" "), box_idx.0 + 1));
+    }
+    result
+}
+
+/// Convert a push solution into pushes identified by box position, replaying
+/// it against the given game.
+pub fn to_push_positions(game: &Game, solution: &[Push]) -> Vec<PushByPos> {
+    let mut game = game.clone();
+    solution
+        .iter()
+        .map(|&push| {
+            let push_by_pos = PushByPos {
+                box_pos: game.box_position(push.box_index()),
+                direction: push.direction(),
+            };
+            game.push(push);
+            push_by_pos
+        })
+        .collect()
+}
+
+/// Format a push solution with one `(x,y) Direction` line per push, giving
+/// the board coordinates of the pushed box.
+pub fn format_push_positions(game: &Game, solution: &[Push]) -> String {
+    to_push_positions(game, solution)
+        .iter()
+        .map(|push| format!("{}\n", push))
+        .collect()
+}
+
+/// Convert a push solution into LURD notation, walking the player along a
+/// shortest path to each push. Pushes are written as uppercase letters.
+pub fn to_lurd(game: &Game, solution: &[Push]) -> String {
+    let mut game = game.clone();
+    let mut result = String::new();
+    for &push in solution {
+        let box_pos = game.box_position(push.box_index());
+        let push_pos = game
+            .move_position(box_pos, push.direction().reverse())
+            .expect("push position out of bounds");
+        let path = game
+            .player_path_to(push_pos)
+            .expect("push position not reachable");
+        result.extend(
+            path.into_iter()
+                .map(|dir| direction_char(dir).to_ascii_lowercase()),
+        );
+        result.push(direction_char(push.direction()));
+        game.push(push);
+    }
+    result
+}
+
+fn direction_char(dir: Direction) -> char {
+    match dir {
+        Direction::Up => 'U',
+        Direction::Down => 'D',
+        Direction::Left => 'L',
+        Direction::Right => 'R',
+    }
+}
+
+fn parse_direction(ch: char) -> Option<Direction> {
+    match ch.to_ascii_lowercase() {
+        'u' => Some(Direction::Up),
+        'd' => Some(Direction::Down),
+        'l' => Some(Direction::Left),
+        'r' => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+fn parse_push(token: &str) -> Result<Push, String> {
+    let invalid = || format!("invalid push '{}'", token);
+    let dir_ch = token.chars().last().ok_or_else(invalid)?;
+    let direction = parse_direction(dir_ch).ok_or_else(invalid)?;
+    let box_num: usize = token[..token.len() - dir_ch.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    if box_num == 0 || box_num > u8::MAX as usize {
+        return Err(invalid());
+    }
+    Ok(Push::new(Index((box_num - 1) as u8), direction))
+}
+
+/// Move the player one step in the given direction, pushing a box if
+/// `is_push` is set. Returns an error if the step is invalid.
+pub fn apply_step(game: &mut Game, dir: Direction, is_push: bool) -> Result<(), String> {
+    if is_push {
+        game.push_player(dir)
+    } else {
+        game.move_player(dir)
+    }
+}
+
+fn apply_push(game: &mut Game, push: Push) -> Result<(), String> {
+    if push.box_index().0 as usize >= game.box_count() {
+        return Err(format!("no box #{}", push.box_index().0 + 1));
+    }
+    if !game.compute_pushes().moves.contains(push) {
+        let box_pos = game.box_position(push.box_index());
+        return Err(format!(
+            "box #{} at {} cannot be pushed {}",
+            push.box_index().0 + 1,
+            box_pos,
+            push.direction()
+        ));
+    }
+    game.push(push);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Position;
+
+    #[test]
+    fn test_parse_lurd() {
+        let solution = Solution::parse("rRu").unwrap();
+        assert_eq!(
+            solution,
+            Solution::Lurd(vec![
+                (Direction::Right, false),
+                (Direction::Right, true),
+                (Direction::Up, false),
+            ])
+        );
+        assert_eq!(Solution::parse_lurd("rR\nu").unwrap(), solution);
+        assert_eq!(
+            Solution::parse_lurd("rR x").unwrap_err(),
+            "step 3: invalid LURD character 'x'"
+        );
+    }
+
+    #[test]
+    fn test_parse_pushes() {
+        let solution = Solution::parse("1R 12u").unwrap();
+        assert_eq!(
+            solution,
+            Solution::Pushes(vec![
+                Push::new(Index(0), Direction::Right),
+                Push::new(Index(11), Direction::Up),
+            ])
+        );
+        assert!(Solution::parse("0R").is_err());
+        assert!(Solution::parse("1X").is_err());
+    }
+
+    #[test]
+    fn test_verify_lurd() {
+        let game = parse_game(
+            r#"
+######
+#@ $.#
+######
+"#,
+        );
+        assert_eq!(Solution::parse("rR").unwrap().verify(&game), Ok(()));
+
+        let err = Solution::parse("rr").unwrap().verify(&game).unwrap_err();
+        assert_eq!(err.step, 2);
+
+        let err = Solution::parse("r").unwrap().verify(&game).unwrap_err();
+        assert_eq!(err.step, 1);
+        assert_eq!(err.reason, "puzzle is not solved");
+    }
+
+    #[test]
+    fn test_verify_pushes() {
+        let game = parse_game(
+            r#"
+#######
+#.$@$.#
+#######
+"#,
+        );
+        assert_eq!(Solution::parse("1L 2R").unwrap().verify(&game), Ok(()));
+
+        let err = Solution::parse("1L 1L").unwrap().verify(&game).unwrap_err();
+        assert_eq!(err.step, 2);
+
+        let err = Solution::parse("3L").unwrap().verify(&game).unwrap_err();
+        assert_eq!(err.step, 1);
+    }
+
+    #[test]
+    fn test_format_compact() {
+        let solution = [
+            Push::new(Index(4), Direction::Right),
+            Push::new(Index(4), Direction::Right),
+            Push::new(Index(4), Direction::Right),
+            Push::new(Index(4), Direction::Up),
+            Push::new(Index(4), Direction::Up),
+            Push::new(Index(0), Direction::Left),
+            Push::new(Index(4), Direction::Down),
+        ];
+        assert_eq!(
+            format_compact(&solution),
+            "3R 2U (box 5)\nL (box 1)\nD (box 5)\n"
+        );
+        assert_eq!(format_compact(&[]), "");
+    }
+
+    #[test]
+    fn test_format_push_positions() {
+        let game = parse_game(
+            r#"
+#######
+#.$@$.#
+#######
+"#,
+        );
+        let solution = [
+            Push::new(Index(0), Direction::Left),
+            Push::new(Index(1), Direction::Right),
+        ];
+        assert_eq!(
+            to_push_positions(&game, &solution),
+            vec![
+                PushByPos {
+                    box_pos: Position(2, 1),
+                    direction: Direction::Left,
+                },
+                PushByPos {
+                    box_pos: Position(4, 1),
+                    direction: Direction::Right,
+                },
+            ]
+        );
+        assert_eq!(
+            format_push_positions(&game, &solution),
+            "(2,1) Left\n(4,1) Right\n"
+        );
+    }
+
+    #[test]
+    fn test_to_lurd() {
+        let game = parse_game(
+            r#"
+#######
+#.$@$.#
+#######
+"#,
+        );
+        let solution = [
+            Push::new(Index(0), Direction::Left),
+            Push::new(Index(1), Direction::Right),
+        ];
+        let lurd = to_lurd(&game, &solution);
+        assert_eq!(lurd, "LrR");
+        assert_eq!(Solution::parse(&lurd).unwrap().verify(&game), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_solutions() {
+        let solutions = parse_solutions("; 1\nrR\n\n; 2\n1L 2R\n").unwrap();
+        assert_eq!(solutions.len(), 2);
+        assert!(matches!(solutions[0], Solution::Lurd(_)));
+        assert!(matches!(solutions[1], Solution::Pushes(_)));
+    }
+
+    fn parse_game(text: &str) -> Game {
+        Game::from_text(text.trim_matches('\n')).unwrap()
+    }
+}
@@ -1,14 +1,64 @@
-use crate::bits::{Bitvector, Index};
-use crate::corral::{CorralResult, CorralSearcher};
-use crate::frozen::{compute_frozen_boxes, compute_new_frozen_boxes};
-use crate::game::{Checkpoint, Direction, Game, Move, Moves, Position, Pull, Push, ReachableSet};
+use crate::bits::{Bitvector, Index, LazyBitboard, RawBitboard};
+use crate::conflict::ConflictProber;
+use crate::corral::{
+    CorralDowngrade, CorralResult, CorralSearcher, DeadlockTables, is_player_enclosed,
+    search_pull_corrals,
+};
+use crate::events::{Event, EventLog};
+use crate::fess::{FeatureQueue, Features};
+use crate::frozen::{
+    compute_frozen_boxes, compute_new_frozen_boxes, compute_new_pull_frozen_boxes,
+    compute_pull_frozen_boxes,
+};
+use crate::game::{
+    Checkpoint, Direction, Game, Move, Moves, PackedCheckpoint, PlayerDistances, Position, Pull,
+    Push, PushByPos, ReachableSet,
+};
 use crate::heuristic::{Cost, Heuristic};
+use crate::matching::MatchingChecker;
+use crate::parallel;
+use crate::patterns::DeadlockPatterns;
+use crate::penalty::PenaltyTable;
 use crate::pqueue::PriorityQueue;
-use crate::zobrist::Zobrist;
-use std::collections::HashMap;
+use crate::solution::to_lurd;
+use crate::symmetry::{Symmetry, find_symmetries};
+use crate::ttable::BoundedTable;
+use crate::zobrist::{Zobrist, ZobristMap, ZobristSet};
 use std::collections::hash_map::Entry;
 use std::ops::Range;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Number of nodes expanded between checks of the time limit and interrupt
+/// flag.
+pub(crate) const TIME_CHECK_INTERVAL: usize = 256;
+
+/// Mixed into the seed of the Zobrist hashes to seed the hashes used to
+/// double-check transposition table hits.
+pub(crate) const CHECK_SEED: u64 = 0x0fedcba987654321;
+
+/// Number of its own expansions without lowering its best heuristic cost
+/// after which a direction of adaptive bidirectional search is stalled.
+const STALL_EXPANSIONS: usize = 100_000;
+
+/// Factor by which a stalled direction's open list must outgrow the other
+/// direction's before adaptive bidirectional search stops expanding it.
+const STALL_GROWTH: usize = 8;
+
+/// Maximum number of heuristics (or dead square maps) kept by a search, one per
+/// set of frozen boxes, after which the least recently used is dropped.
+const MAX_FROZEN_SETS: usize = 1024;
+
+/// Number of player distances told apart by the player distance tie-break,
+/// which splits each priority into this many finer priorities.
+const TIE_BREAK_LEVELS: usize = 4;
+
+/// Number of keys told apart by the tie-breaking policies which order
+/// positions of equal priority by a key, which split each priority into this
+/// many finer priorities.
+const TIE_BREAK_KEYS: usize = 16;
 
 /// Result of solving a puzzle
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,22 +67,159 @@ pub enum SolveResult {
     Solved(Vec<Push>),
     /// Node limit exceeded before solution found
     Cutoff,
+    /// Time limit exceeded before solution found
+    TimedOut,
+    /// Search was interrupted before solution found
+    Interrupted,
     /// Puzzle is impossible to solve
     Unsolvable,
 }
 
+/// Statistics collected while solving a puzzle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolverStats {
+    /// Number of nodes explored across both search directions.
+    pub nodes_explored: usize,
+    /// Number of distinct box configurations expanded, ignoring the player
+    /// position, counting those expanded in both directions once. Comparing this against the number of nodes explored shows how
+    /// much effort is spent on player-position permutations. Not tracked by
+    /// IDA* search, as it would cost as much memory as a transposition table.
+    pub unique_box_configs: usize,
+    /// Breakdown of pruning across both search directions.
+    pub pruning: PruningStats,
+    /// Lowest heuristic cost reached in either search direction, i.e. the
+    /// closest the search got to meeting the other side.
+    pub best_cost: usize,
+    /// Number of open nodes evicted to stay within the memory limit.
+    pub evicted_nodes: usize,
+    /// Number of closed transposition table entries forgotten to stay within
+    /// the memory limit.
+    pub evicted_entries: usize,
+    /// Number of transposition table hits on a different position than the
+    /// one looked up, found with `paranoid_hash` or `hash_diagnostics`.
+    pub hash_collisions: usize,
+    /// Direction which adaptive bidirectional search stopped expanding, if
+    /// any.
+    pub disabled_direction: Option<SearchType>,
+    /// Statistics of the forward search (including IDA* search).
+    pub forward: DirectionStats,
+    /// Statistics of the reverse search.
+    pub reverse: DirectionStats,
+    /// Direction whose expansion reached a position the other direction had
+    /// reached, producing the solution, if one was found.
+    pub meeting_direction: Option<SearchType>,
+}
+
+/// Statistics collected for one direction of the search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirectionStats {
+    /// Number of nodes explored in this direction.
+    pub nodes_explored: usize,
+    /// Largest size the open list reached. Not tracked by IDA* search, which
+    /// has no open list.
+    pub peak_open: usize,
+    /// Largest number of transposition table entries. Not tracked by IDA*
+    /// search, whose table has a fixed size.
+    pub peak_table: usize,
+    /// Number of heuristics kept for sets of frozen boxes when the search
+    /// ended.
+    pub heuristic_entries: usize,
+    /// Largest estimated number of bytes used by the open list, transposition
+    /// table and box configurations (or by IDA*'s bounded table).
+    pub peak_memory: usize,
+}
+
+/// Counts of how often each pruning technique applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruningStats {
+    /// Nodes whose moves were restricted by PI-corral pruning.
+    pub corral_pruned: usize,
+    /// Nodes discarded due to corral deadlocks.
+    pub corral_deadlocks: usize,
+    /// Moves discarded because they push a box onto a dead square.
+    pub dead_square_pruned: usize,
+    /// Moves discarded because they complete a small fixed deadlock pattern.
+    pub pattern_pruned: usize,
+    /// Moves discarded because they create a freeze deadlock.
+    pub freeze_pruned: usize,
+    /// Moves discarded because they leave boxes which can't all be matched to
+    /// distinct goals.
+    pub matching_pruned: usize,
+    /// Moves discarded because the pushed box is deadlocked together with one
+    /// or two of the boxes around it.
+    pub conflict_pruned: usize,
+    /// Moves discarded by IDA* because the position was already searched in
+    /// the same iteration, no further from the root.
+    pub transposition_pruned: usize,
+    /// Nodes discarded because a mirror image or rotation of the position was
+    /// already expanded.
+    pub symmetry_pruned: usize,
+    /// Moves discarded because the position was already generated (or, in
+    /// IDA*, is on the current path), found before the deadlock checks and
+    /// heuristic evaluation which they were spared.
+    pub duplicates_pruned: usize,
+}
+
+impl PruningStats {
+    pub(crate) fn add(&self, other: &PruningStats) -> PruningStats {
+        PruningStats {
+            corral_pruned: self.corral_pruned + other.corral_pruned,
+            corral_deadlocks: self.corral_deadlocks + other.corral_deadlocks,
+            dead_square_pruned: self.dead_square_pruned + other.dead_square_pruned,
+            pattern_pruned: self.pattern_pruned + other.pattern_pruned,
+            freeze_pruned: self.freeze_pruned + other.freeze_pruned,
+            matching_pruned: self.matching_pruned + other.matching_pruned,
+            conflict_pruned: self.conflict_pruned + other.conflict_pruned,
+            transposition_pruned: self.transposition_pruned + other.transposition_pruned,
+            symmetry_pruned: self.symmetry_pruned + other.symmetry_pruned,
+            duplicates_pruned: self.duplicates_pruned + other.duplicates_pruned,
+        }
+    }
+}
+
 /// Internal trait containing search logic that is polymorphic depending on the
 /// direction of the search (forward vs reverse).
-trait SearchHelper {
+pub(crate) trait SearchHelper {
     type Move: Move;
 
     fn compute_moves(&self, game: &Game) -> ReachableSet<Self::Move>;
-    fn compute_unmoves(&self, game: &Game) -> Moves<Self::Move>;
+    /// Compute the moves after the box at `from` was moved to `to`, given the
+    /// squares the player could reach before the move.
+    fn compute_moves_after_move(
+        &self,
+        game: &Game,
+        squares: LazyBitboard,
+        from: Position,
+        to: Position,
+    ) -> ReachableSet<Self::Move>;
 
     fn apply_move(&self, game: &mut Game, move_: &Self::Move);
     fn apply_unmove(&self, game: &mut Game, move_: &Self::Move);
 
-    fn is_dead_square(&self, game: &Game, pos: Position) -> bool;
+    /// Whether a box moved to the given position can no longer reach a goal,
+    /// given the frozen boxes, if dead square pruning is enabled.
+    fn is_dead_square(&mut self, game: &Game, frozen_boxes: &Bitvector, pos: Position) -> bool;
+
+    /// Walking distance from the player to the nearest square from which it
+    /// can move a box which isn't frozen onto a square which isn't dead, or
+    /// `None` if there is no such move.
+    fn player_distance_to_move(&self, game: &Game, frozen_boxes: &Bitvector) -> Option<usize>;
+
+    /// Lower bound on the cost of the position from learned penalty
+    /// patterns, which the heuristic cost is raised to (0 if none match).
+    fn penalty_bound(&self, game: &Game) -> u16;
+
+    /// Whether the box just moved to the given position completes a deadlock
+    /// pattern, if deadlock patterns are enabled.
+    fn is_pattern_deadlock(&self, game: &Game, box_pos: Position) -> bool;
+
+    /// Whether the boxes can't all be matched to distinct goals they can
+    /// reach, if matching deadlock detection is enabled.
+    fn is_matching_deadlock(&mut self, game: &Game, frozen_boxes: &Bitvector) -> bool;
+
+    /// Whether the box just moved can't be put on a goal together with one or
+    /// two of the boxes around it, if conflict deadlock probing is enabled.
+    fn is_conflict_deadlock(&mut self, game: &Game, box_idx: Index) -> bool;
 
     fn search_corrals(
         &mut self,
@@ -40,6 +227,12 @@ trait SearchHelper {
         reachable: &ReachableSet<Self::Move>,
     ) -> CorralResult<Self::Move>;
 
+    fn take_corral_downgrade(&mut self) -> Option<CorralDowngrade>;
+
+    /// Takes the positions proven deadlocked by the corral searches, as
+    /// hashes and check hashes with the exact player position, if recorded.
+    fn take_dead_positions(&mut self) -> Vec<(u64, u64)>;
+
     fn compute_frozen_boxes(&self, game: &Game) -> Bitvector;
     fn compute_new_frozen_boxes(
         &self,
@@ -48,20 +241,48 @@ trait SearchHelper {
         box_idx: Index,
     ) -> Bitvector;
 
-    fn new_heuristic<H: Heuristic>(&self, game: &Game, frozen_boxes: Bitvector) -> H;
+    fn new_heuristic<H: Heuristic>(
+        &self,
+        game: &Game,
+        frozen_boxes: Bitvector,
+        params: &H::Params,
+    ) -> H;
 
     fn to_push_by_pos(&self, game: &Game, move_: &Self::Move) -> PushByPos;
 }
 
-struct ForwardSearchHelper {
-    corral_searcher: CorralSearcher,
+pub(crate) struct ForwardSearchHelper {
+    pub(crate) corral_searcher: CorralSearcher,
     freeze_deadlocks: bool,
-    dead_squares: bool,
+    dead_squares: Option<DeadSquares>,
     pi_corrals: bool,
+    /// Restrict moves to those into the PI-corral. When disabled, PI-corrals
+    /// are still used to detect deadlocks.
+    corral_move_pruning: bool,
+    /// Penalty patterns learned from the corrals searched, if enabled.
+    penalties: Option<PenaltyTable>,
+    patterns: Option<DeadlockPatterns>,
+    matching: Option<MatchingChecker>,
+    conflicts: Option<ConflictProber>,
 }
 
 struct ReverseSearchHelper {
-    dead_squares: bool,
+    freeze_deadlocks: bool,
+    dead_squares: Option<DeadSquares>,
+    pi_corrals: bool,
+    /// Restrict pulls to those out of a pull corral. When disabled, pull
+    /// corrals are still used to detect deadlocks.
+    corral_move_pruning: bool,
+    matching: Option<MatchingChecker>,
+}
+
+/// The cost minimised by optimal search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostType {
+    /// Number of box pushes.
+    Pushes,
+    /// Number of player moves, including both walking and pushing.
+    Moves,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -71,10 +292,75 @@ pub enum SearchType {
     Bidirectional,
 }
 
-#[derive(Debug, Copy, Clone)]
-struct PushByPos {
-    box_pos: Position,
-    direction: Direction,
+/// How bidirectional search chooses which direction to expand next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceType {
+    /// Alternate between the two directions.
+    Alternate,
+    /// Expand the direction with the smaller open list.
+    Frontier,
+    /// Expand the direction whose best open node has the lower cost.
+    Cost,
+}
+
+/// How the open list orders positions of equal priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    /// First in, first out.
+    Fifo,
+    /// Last in, first out, which favours the most recently generated (and so
+    /// often the deepest) positions.
+    Lifo,
+    /// Lowest heuristic cost first. Under optimal search, where priorities
+    /// are g + h, this favours the deepest positions; under greedy search,
+    /// where priorities are h, it changes nothing.
+    LowestCost,
+    /// Most boxes on goals first.
+    MostOnGoals,
+}
+
+/// The algorithm used to search for a solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmType {
+    /// Best-first search using an open list and a transposition table.
+    BestFirst,
+    /// Iterative-deepening A*, which only keeps the current path in memory.
+    /// This implies an optimal forward search.
+    IdaStar,
+    /// FESS-style search (after the Festival solver), which projects positions
+    /// onto a feature space (packed boxes, boxes on goals and player regions)
+    /// and takes turns expanding the best position of each feature cell.
+    Fess,
+}
+
+impl ForwardSearchHelper {
+    pub(crate) fn new(game: &Game, zobrist: &Rc<Zobrist>, opts: &SolverOpts) -> Self {
+        Self {
+            corral_searcher: CorralSearcher::new(
+                zobrist.clone(),
+                opts.deadlock_max_nodes,
+                opts.corral_safety,
+            ),
+            dead_squares: opts
+                .dead_squares
+                .then(|| DeadSquares::new(zobrist.clone(), false)),
+            pi_corrals: opts.pi_corrals,
+            freeze_deadlocks: opts.freeze_deadlocks,
+            // Restricting pushes to a corral can force longer walks, so it
+            // doesn't preserve move optimality
+            corral_move_pruning: opts.cost != CostType::Moves,
+            penalties: opts
+                .penalties
+                .then(|| PenaltyTable::new(game, zobrist.clone())),
+            patterns: opts.deadlock_patterns.then(|| DeadlockPatterns::new(game)),
+            matching: opts
+                .matching_deadlocks
+                .then(|| MatchingChecker::new_push(zobrist.clone())),
+            conflicts: opts
+                .conflict_deadlocks
+                .then(|| ConflictProber::new(zobrist.clone(), opts.conflict_max_nodes)),
+        }
+    }
 }
 
 impl SearchHelper for ForwardSearchHelper {
@@ -84,8 +370,14 @@ impl SearchHelper for ForwardSearchHelper {
         game.compute_pushes()
     }
 
-    fn compute_unmoves(&self, game: &Game) -> Moves<Push> {
-        game.compute_pulls().moves.to_pushes()
+    fn compute_moves_after_move(
+        &self,
+        game: &Game,
+        squares: LazyBitboard,
+        from: Position,
+        to: Position,
+    ) -> ReachableSet<Push> {
+        game.compute_pushes_after_move(squares, from, to)
     }
 
     fn apply_move(&self, game: &mut Game, push: &Push) {
@@ -96,12 +388,44 @@ impl SearchHelper for ForwardSearchHelper {
         game.pull(push.to_pull());
     }
 
-    fn is_dead_square(&self, game: &Game, pos: Position) -> bool {
-        if self.dead_squares {
-            game.is_push_dead_square(pos)
-        } else {
-            false
-        }
+    fn is_dead_square(&mut self, game: &Game, frozen_boxes: &Bitvector, pos: Position) -> bool {
+        self.dead_squares
+            .as_mut()
+            .is_some_and(|dead_squares| dead_squares.is_dead_with_frozen(game, frozen_boxes, pos))
+    }
+
+    fn player_distance_to_move(&self, game: &Game, frozen_boxes: &Bitvector) -> Option<usize> {
+        game.player_distance_to_push(|box_idx, dest| {
+            !frozen_boxes.contains(box_idx)
+                && !self
+                    .dead_squares
+                    .as_ref()
+                    .is_some_and(|dead_squares| dead_squares.is_dead(game, dest))
+        })
+    }
+
+    fn penalty_bound(&self, game: &Game) -> u16 {
+        self.penalties
+            .as_ref()
+            .map_or(0, |penalties| penalties.lower_bound(game))
+    }
+
+    fn is_pattern_deadlock(&self, game: &Game, box_pos: Position) -> bool {
+        self.patterns
+            .as_ref()
+            .is_some_and(|patterns| patterns.is_deadlocked(game, box_pos))
+    }
+
+    fn is_matching_deadlock(&mut self, game: &Game, frozen_boxes: &Bitvector) -> bool {
+        self.matching
+            .as_mut()
+            .is_some_and(|matching| matching.is_deadlocked(game, frozen_boxes))
+    }
+
+    fn is_conflict_deadlock(&mut self, game: &Game, box_idx: Index) -> bool {
+        self.conflicts
+            .as_mut()
+            .is_some_and(|conflicts| conflicts.is_deadlocked(game, box_idx))
     }
 
     fn search_corrals(
@@ -109,13 +433,29 @@ impl SearchHelper for ForwardSearchHelper {
         game: &mut Game,
         reachable: &ReachableSet<Self::Move>,
     ) -> CorralResult<Self::Move> {
-        if self.pi_corrals {
-            self.corral_searcher.search(game, reachable)
-        } else {
-            CorralResult::None
+        if !self.pi_corrals {
+            return CorralResult::None;
+        }
+        let result = self.corral_searcher.search(game, reachable);
+        if let Some(penalties) = &mut self.penalties {
+            for boxes in self.corral_searcher.take_patterns() {
+                penalties.learn(game, boxes);
+            }
+        }
+        match result {
+            CorralResult::Prune(_) if !self.corral_move_pruning => CorralResult::None,
+            result => result,
         }
     }
 
+    fn take_corral_downgrade(&mut self) -> Option<CorralDowngrade> {
+        self.corral_searcher.take_downgrade()
+    }
+
+    fn take_dead_positions(&mut self) -> Vec<(u64, u64)> {
+        self.corral_searcher.take_dead_positions()
+    }
+
     fn compute_frozen_boxes(&self, game: &Game) -> Bitvector {
         if self.freeze_deadlocks {
             compute_frozen_boxes(game)
@@ -137,8 +477,13 @@ impl SearchHelper for ForwardSearchHelper {
         }
     }
 
-    fn new_heuristic<H: Heuristic>(&self, game: &Game, frozen_boxes: Bitvector) -> H {
-        H::new_push(game, frozen_boxes)
+    fn new_heuristic<H: Heuristic>(
+        &self,
+        game: &Game,
+        frozen_boxes: Bitvector,
+        params: &H::Params,
+    ) -> H {
+        H::new_push(game, frozen_boxes, params)
     }
 
     fn to_push_by_pos(&self, game: &Game, push: &Push) -> PushByPos {
@@ -156,8 +501,14 @@ impl SearchHelper for ReverseSearchHelper {
         game.compute_pulls()
     }
 
-    fn compute_unmoves(&self, game: &Game) -> Moves<Pull> {
-        game.compute_pushes().moves.to_pulls()
+    fn compute_moves_after_move(
+        &self,
+        game: &Game,
+        squares: LazyBitboard,
+        from: Position,
+        to: Position,
+    ) -> ReachableSet<Pull> {
+        game.compute_pulls_after_move(squares, from, to)
     }
 
     fn apply_move(&self, game: &mut Game, pull: &Pull) {
@@ -168,42 +519,96 @@ impl SearchHelper for ReverseSearchHelper {
         game.push(pull.to_push())
     }
 
-    fn is_dead_square(&self, game: &Game, pos: Position) -> bool {
-        if self.dead_squares {
-            game.is_pull_dead_square(pos)
-        } else {
-            false
-        }
+    fn is_dead_square(&mut self, game: &Game, frozen_boxes: &Bitvector, pos: Position) -> bool {
+        self.dead_squares
+            .as_mut()
+            .is_some_and(|dead_squares| dead_squares.is_dead_with_frozen(game, frozen_boxes, pos))
+    }
+
+    fn player_distance_to_move(&self, game: &Game, frozen_boxes: &Bitvector) -> Option<usize> {
+        game.player_distance_to_pull(|box_idx, dest| {
+            !frozen_boxes.contains(box_idx)
+                && !self
+                    .dead_squares
+                    .as_ref()
+                    .is_some_and(|dead_squares| dead_squares.is_dead(game, dest))
+        })
+    }
+
+    fn penalty_bound(&self, _game: &Game) -> u16 {
+        0
+    }
+
+    fn is_pattern_deadlock(&self, _game: &Game, _box_pos: Position) -> bool {
+        // The patterns are all for pushes
+        false
+    }
+
+    fn is_matching_deadlock(&mut self, game: &Game, frozen_boxes: &Bitvector) -> bool {
+        self.matching
+            .as_mut()
+            .is_some_and(|matching| matching.is_deadlocked(game, frozen_boxes))
+    }
+
+    fn is_conflict_deadlock(&mut self, _game: &Game, _box_idx: Index) -> bool {
+        false
     }
 
     fn search_corrals(
         &mut self,
-        _game: &mut Game,
-        _reachable: &ReachableSet<Self::Move>,
+        game: &mut Game,
+        reachable: &ReachableSet<Self::Move>,
     ) -> CorralResult<Self::Move> {
-        CorralResult::None
+        if !self.pi_corrals {
+            return CorralResult::None;
+        }
+        match search_pull_corrals(game, reachable) {
+            CorralResult::Prune(_) if !self.corral_move_pruning => CorralResult::None,
+            result => result,
+        }
+    }
+
+    fn take_corral_downgrade(&mut self) -> Option<CorralDowngrade> {
+        None
+    }
+
+    fn take_dead_positions(&mut self) -> Vec<(u64, u64)> {
+        Vec::new()
     }
 
-    fn compute_frozen_boxes(&self, _game: &Game) -> Bitvector {
-        Bitvector::new()
+    fn compute_frozen_boxes(&self, game: &Game) -> Bitvector {
+        if self.freeze_deadlocks {
+            compute_pull_frozen_boxes(game)
+        } else {
+            Bitvector::new()
+        }
     }
 
     fn compute_new_frozen_boxes(
         &self,
-        _frozen: &Bitvector,
-        _game: &Game,
-        _box_idx: Index,
+        frozen: &Bitvector,
+        game: &Game,
+        box_idx: Index,
     ) -> Bitvector {
-        Bitvector::new()
+        if self.freeze_deadlocks {
+            compute_new_pull_frozen_boxes(*frozen, game, box_idx)
+        } else {
+            Bitvector::new()
+        }
     }
 
-    fn new_heuristic<H: Heuristic>(&self, game: &Game, frozen_boxes: Bitvector) -> H {
-        H::new_pull(game, frozen_boxes)
+    fn new_heuristic<H: Heuristic>(
+        &self,
+        game: &Game,
+        frozen_boxes: Bitvector,
+        params: &H::Params,
+    ) -> H {
+        H::new_pull(game, frozen_boxes, params)
     }
 
     fn to_push_by_pos(&self, game: &Game, pull: &Pull) -> PushByPos {
         let new_box_pos = game.box_position(pull.box_index());
-        let old_box_pos = game.move_position(new_box_pos, pull.direction()).unwrap();
+        let old_box_pos = new_box_pos + pull.direction();
         PushByPos {
             box_pos: old_box_pos,
             direction: pull.direction().reverse(),
@@ -212,26 +617,354 @@ impl SearchHelper for ReverseSearchHelper {
 }
 
 /// An open-list node.
-struct Node {
-    checkpoint: Checkpoint,
-    frozen_boxes: Bitvector,
+pub(crate) struct Node {
+    pub(crate) checkpoint: PackedCheckpoint,
+    /// Frozen boxes, numbered as restoring the checkpoint numbers them.
+    pub(crate) frozen_boxes: Bitvector,
+    /// Number of moves from the root.
+    pub(crate) g: u16,
+    /// Transposition table key of the node's position, with the player where
+    /// it is.
+    pub(crate) key: u64,
+    /// The generation of the table entry when the node was pushed. Nodes
+    /// pushed again (when reopened) leave stale copies in the open list, which
+    /// are told apart by their older generation and skipped when popped.
+    pub(crate) generation: u16,
+    /// Whether the node was reopened to regenerate evicted children. Such
+    /// nodes are never evicted, as their remaining children refer to them.
+    pub(crate) reopened: bool,
+    /// The move which led to the node, if any, from which its moves are
+    /// computed without searching the whole player region again.
+    pub(crate) last_move: Option<LastMove>,
+}
+
+impl Node {
+    /// A node for the current state of the game, whose table entry has the
+    /// given key.
+    pub(crate) fn new(game: &Game, frozen_boxes: &Bitvector, g: u16, key: u64) -> Self {
+        Self {
+            checkpoint: game.pack(),
+            frozen_boxes: game.packed_box_indices(frozen_boxes),
+            g,
+            key,
+            generation: 0,
+            reopened: false,
+            last_move: None,
+        }
+    }
+}
+
+/// A box move together with the squares the player could reach before it,
+/// shared by all the children of a node.
+#[derive(Clone)]
+pub(crate) struct LastMove {
+    pub(crate) squares: Arc<[u64]>,
+    pub(crate) from: Position,
+    pub(crate) to: Position,
+}
+
+impl LastMove {
+    /// Compute the moves of the node the move led to, currently in `game`.
+    pub(crate) fn compute_moves<H: SearchHelper>(
+        &self,
+        helper: &H,
+        game: &Game,
+    ) -> ReachableSet<H::Move> {
+        helper.compute_moves_after_move(
+            game,
+            LazyBitboard::from_rows(&self.squares),
+            self.from,
+            self.to,
+        )
+    }
+}
+
+/// The open list, ordered by priority or, for FESS, split into feature-space
+/// cells which take turns.
+enum OpenList {
+    Single(PriorityQueue<Node>),
+    Features(FeatureQueue<Node>),
+}
+
+impl OpenList {
+    /// Push a node, given the game in the node's state.
+    fn push(&mut self, game: &Game, priority: usize, node: Node) {
+        match self {
+            OpenList::Single(queue) => queue.push(priority, node),
+            OpenList::Features(queue) => {
+                queue.push(Features::compute(game, &node.frozen_boxes), priority, node)
+            }
+        }
+    }
+
+    fn pop_min(&mut self) -> Option<Node> {
+        match self {
+            OpenList::Single(queue) => queue.pop_min(),
+            OpenList::Features(queue) => queue.pop_min(),
+        }
+    }
+
+    fn pop_max(&mut self) -> Option<(usize, Node)> {
+        match self {
+            OpenList::Single(queue) => queue.pop_max(),
+            OpenList::Features(queue) => queue.pop_max(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            OpenList::Single(queue) => queue.len(),
+            OpenList::Features(queue) => queue.len(),
+        }
+    }
+
+    fn min_priority(&self) -> Option<usize> {
+        match self {
+            OpenList::Single(queue) => queue.min_priority(),
+            OpenList::Features(queue) => queue.min_priority(),
+        }
+    }
+}
+
+/// The move which generated a position from its parent. The box is identified
+/// by its position after the move, as boxes are numbered differently in each
+/// search direction.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TableMove {
+    pub(crate) box_pos: Position,
+    pub(crate) direction: Direction,
+}
+
+impl TableMove {
+    /// Find the move in the given game, which is in the generated position.
+    pub(crate) fn to_move<M: Move>(self, game: &Game) -> M {
+        let box_index = game
+            .box_index(self.box_pos)
+            .expect("no box where the generating move left it");
+        M::new(box_index, self.direction)
+    }
 }
 
 /// A transpotion table entry.
-struct TableEntry {
-    parent_hash: u64,
-    is_closed: bool,
+pub(crate) struct TableEntry {
+    pub(crate) parent_hash: u64,
+    /// The move from the parent which generated this position, or `None` for
+    /// initial positions.
+    pub(crate) move_: Option<TableMove>,
+    /// Independent hash of the same position, confirming that positions with
+    /// the same key really are the same.
+    pub(crate) check: u64,
+    pub(crate) is_closed: bool,
+    /// Lowest known number of moves from the root.
+    pub(crate) g: u16,
+    /// Number of times the position was pushed onto the open list again
+    /// (wrapping), so that only its latest copy there is expanded.
+    pub(crate) generation: u16,
+}
+
+impl TableEntry {
+    /// An entry for a position proven deadlocked. It is closed at g = 0, so
+    /// the position is never expanded, nor reopened by a shorter path. The
+    /// other direction never meets it, as it can't lead to a solution.
+    pub(crate) fn dead(check: u64) -> Self {
+        Self {
+            parent_hash: 0,
+            move_: None,
+            check,
+            is_closed: true,
+            g: 0,
+            generation: 0,
+        }
+    }
 }
 
 /// Searcher which searches in a single direction (either forward/pushes or
 /// reverse/pulls).
-struct Searcher<H, S> {
+struct Searcher<H: Heuristic, S> {
     game: Game,
-    open_list: PriorityQueue<Node>,
-    table: HashMap<u64, TableEntry>,
+    open_list: OpenList,
+    table: ZobristMap<TableEntry>,
     zobrist: Rc<Zobrist>,
-    heuristic: HashMap<u64, H>,
+    /// Zobrist hashes for the check hashes of transposition table entries.
+    check_zobrist: Zobrist,
+    heuristic: FrozenCache<H>,
+    /// Settings every heuristic is created with.
+    heuristic_params: H::Params,
     helper: S,
+    /// Boxes hashes of all expanded nodes.
+    box_configs: ZobristSet,
+    /// Lowest heuristic cost generated so far.
+    best_cost: usize,
+    pruning: PruningStats,
+    /// Buffered search events, or `None` if events are not being recorded.
+    events: Option<Vec<Event>>,
+    /// Order the open list by f = g + h rather than by h alone.
+    optimal: bool,
+    /// Reopen nodes when a shorter path to them is found. Required by optimal
+    /// search, and by anytime search once it has found a solution.
+    reopen: bool,
+    /// Weight of the heuristic when ordering by f = g + weight * h.
+    weight: f64,
+    /// Break ties in priority by the player's distance to the nearest box it
+    /// can usefully move.
+    tie_break: bool,
+    /// How the open list orders positions of equal priority.
+    order: TieBreak,
+    /// Maximum cost of the solutions searched for, from the depth limit or
+    /// the best solution found so far by anytime search. Children whose
+    /// g + h exceeds it are pruned.
+    max_cost: Option<usize>,
+    /// Count player moves rather than pushes in g. Positions are then
+    /// identified by the exact player position, as the cost of the next push
+    /// depends on it. Only supported for forward search.
+    moves: bool,
+    /// Never let positions with different check hashes share a table entry
+    /// (see `table_key`).
+    paranoid_hash: bool,
+    /// Compare check hashes on every table hit, logging those which don't
+    /// match.
+    hash_diagnostics: bool,
+    /// Table hits on a different position than the one looked up.
+    hash_collisions: usize,
+    /// Mirror images and rotations of the board under which positions are
+    /// equally far from the end of the search, used to skip positions
+    /// symmetric to one already expanded.
+    symmetries: Vec<Symmetry>,
+    /// Lowest g at which a position of each class of symmetric positions was
+    /// expanded, by the lowest canonical hash in the class.
+    symmetric_g: ZobristMap<u16>,
+    /// Symmetries of the forward game, under which the search meets the other
+    /// direction if the image of a position is in the other's table.
+    meeting_symmetries: Vec<Symmetry>,
+    /// The symmetry mapping the position which last met the other direction
+    /// onto the position in the other's table, unless it was the same.
+    meeting_symmetry: Option<Symmetry>,
+}
+
+/// Values (heuristics or dead squares) built for each set of frozen boxes, by
+/// the hash of the frozen boxes. At most `MAX_FROZEN_SETS` are kept, dropping
+/// the least recently used (which is rebuilt if its frozen boxes come up
+/// again).
+pub(crate) struct FrozenCache<T> {
+    entries: ZobristMap<(T, u64)>,
+    /// Incremented on every lookup, to record when each entry was last used.
+    clock: u64,
+}
+
+impl<T> FrozenCache<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: ZobristMap::default(),
+            clock: 0,
+        }
+    }
+
+    /// Get the value for the given frozen boxes hash, building it with `new`
+    /// if it isn't kept.
+    pub(crate) fn get_or_insert_with(&mut self, hash: u64, new: impl FnOnce() -> T) -> &T {
+        self.clock += 1;
+        if !self.entries.contains_key(&hash) && self.entries.len() >= MAX_FROZEN_SETS {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&hash, _)| hash);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        let entry = self.entries.entry(hash).or_insert_with(|| (new(), 0));
+        entry.1 = self.clock;
+        &entry.0
+    }
+
+    /// Number of values kept.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Dead squares for each set of frozen boxes. Boxes frozen on goals act as
+/// walls and take their goals, so once boxes freeze more squares can be dead
+/// than those found on the empty board.
+struct DeadSquares {
+    zobrist: Rc<Zobrist>,
+    /// Whether boxes are moved by pulls (reverse search) rather than pushes.
+    pulls: bool,
+    frozen: FrozenCache<RawBitboard>,
+}
+
+impl DeadSquares {
+    fn new(zobrist: Rc<Zobrist>, pulls: bool) -> Self {
+        Self {
+            zobrist,
+            pulls,
+            frozen: FrozenCache::new(),
+        }
+    }
+
+    /// Whether a box at the given position can't reach a goal on the empty
+    /// board.
+    fn is_dead(&self, game: &Game, pos: Position) -> bool {
+        if self.pulls {
+            game.is_pull_dead_square(pos)
+        } else {
+            game.is_push_dead_square(pos)
+        }
+    }
+
+    /// Whether a box at the given position can't reach a goal, given the
+    /// frozen boxes.
+    fn is_dead_with_frozen(
+        &mut self,
+        game: &Game,
+        frozen_boxes: &Bitvector,
+        pos: Position,
+    ) -> bool {
+        if frozen_boxes.is_empty() {
+            return self.is_dead(game, pos);
+        }
+        let hash = self.zobrist.compute_boxes_hash_subset(game, *frozen_boxes);
+        let pulls = self.pulls;
+        self.frozen
+            .get_or_insert_with(hash, || {
+                if pulls {
+                    game.compute_pull_dead_squares(frozen_boxes)
+                } else {
+                    game.compute_push_dead_squares(frozen_boxes)
+                }
+            })
+            .get(pos)
+    }
+}
+
+/// Refine an open list priority by the player's walking distance to the
+/// nearest box it can usefully move, so that among positions of equal priority
+/// those where the player is stranded far from any such box come last.
+pub(crate) fn tie_break_priority<S: SearchHelper>(
+    helper: &S,
+    game: &Game,
+    frozen_boxes: &Bitvector,
+    priority: usize,
+) -> usize {
+    let distance = helper
+        .player_distance_to_move(game, frozen_boxes)
+        .unwrap_or(usize::MAX);
+    let max_priority = PriorityQueue::<Node>::MAX_PRIORITY / TIE_BREAK_LEVELS;
+    priority.min(max_priority) * TIE_BREAK_LEVELS + distance.min(TIE_BREAK_LEVELS - 1)
+}
+
+/// Refine an open list priority by the key of a tie-breaking policy, so that
+/// among positions of equal priority those with lower keys come first.
+/// Policies ordering by insertion alone leave the priority as it is.
+fn order_priority(order: TieBreak, game: &Game, cost: Cost, priority: usize) -> usize {
+    let key = match order {
+        TieBreak::Fifo | TieBreak::Lifo => return priority,
+        TieBreak::LowestCost => usize::from(cost),
+        TieBreak::MostOnGoals => game.unsolved_boxes().len(),
+    };
+    let max_priority = PriorityQueue::<Node>::MAX_PRIORITY / TIE_BREAK_KEYS;
+    priority.min(max_priority) * TIE_BREAK_KEYS + key.min(TIE_BREAK_KEYS - 1)
 }
 
 /// Result of expanding a node.
@@ -245,50 +978,76 @@ enum ExpandNode {
 }
 
 impl<H: Heuristic, S: SearchHelper> Searcher<H, S> {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         game: &Game,
         zobrist: Rc<Zobrist>,
         initial_player_positions: &[Position],
-        helper: S,
+        mut helper: S,
+        heuristic_params: H::Params,
+        optimal: bool,
+        weight: f64,
+        moves: bool,
+        fess: bool,
+        tie_break: bool,
+        order: TieBreak,
     ) -> Self {
-        let mut open_list = PriorityQueue::new();
-        let mut table = HashMap::new();
-        let mut heuristic: HashMap<u64, H> = HashMap::new();
+        let lifo = order == TieBreak::Lifo;
+        let mut open_list = match (fess, lifo) {
+            (true, false) => OpenList::Features(FeatureQueue::new()),
+            (true, true) => OpenList::Features(FeatureQueue::lifo()),
+            (false, false) => OpenList::Single(PriorityQueue::new().with_saturation(usize::MAX)),
+            (false, true) => OpenList::Single(PriorityQueue::lifo().with_saturation(usize::MAX)),
+        };
+        let mut table = ZobristMap::default();
+        let mut heuristic: FrozenCache<H> = FrozenCache::new();
         let mut game = game.clone();
+        let mut best_cost = usize::MAX;
+        let check_zobrist = Zobrist::with_seed(zobrist.seed() ^ CHECK_SEED);
 
         // Loop through initial positions
         for &pos in initial_player_positions {
             // Set initial position
             game.set_player(pos);
 
-            // Compute frozen boxes
+            // Compute frozen boxes, skipping positions which start frozen
             let frozen_boxes = helper.compute_frozen_boxes(&game);
+            if game.unsolved_boxes().contains_any(&frozen_boxes) {
+                continue;
+            }
 
             // Compute initial cost
             let frozen_boxes_hash = zobrist.compute_boxes_hash_subset(&game, frozen_boxes);
             let cost = heuristic
-                .entry(frozen_boxes_hash)
-                .or_insert_with(|| helper.new_heuristic(&game, frozen_boxes))
-                .compute(&game);
-            if cost == Cost::INFINITE {
+                .get_or_insert_with(frozen_boxes_hash, || {
+                    helper.new_heuristic(&game, frozen_boxes, &heuristic_params)
+                })
+                .compute(&game)
+                .at_least(helper.penalty_bound(&game));
+            if cost == Cost::INFINITE || helper.is_matching_deadlock(&game, &frozen_boxes) {
                 continue;
             }
+            best_cost = best_cost.min(usize::from(cost));
 
             // Insert into open_list
-            open_list.push(
-                usize::from(cost),
-                Node {
-                    checkpoint: game.checkpoint(),
-                    frozen_boxes,
-                },
-            );
+            let mut priority = usize::from(cost);
+            if tie_break {
+                priority = tie_break_priority(&helper, &game, &frozen_boxes, priority);
+            }
+            priority = order_priority(order, &game, cost, priority);
+            let key = state_hash(&zobrist, &game, moves);
+            open_list.push(&game, priority, Node::new(&game, &frozen_boxes, 0, key));
 
             // Insert into transposition table
             table.insert(
-                zobrist.compute_hash(&game),
+                key,
                 TableEntry {
                     parent_hash: 0,
+                    move_: None,
+                    check: state_hash(&check_zobrist, &game, moves),
                     is_closed: false,
+                    g: 0,
+                    generation: 0,
                 },
             );
         }
@@ -298,12 +1057,38 @@ impl<H: Heuristic, S: SearchHelper> Searcher<H, S> {
             open_list,
             table,
             zobrist,
+            check_zobrist,
             heuristic,
+            heuristic_params,
             helper,
+            box_configs: ZobristSet::default(),
+            best_cost,
+            pruning: PruningStats::default(),
+            events: None,
+            optimal,
+            reopen: optimal,
+            weight,
+            tie_break,
+            order,
+            max_cost: None,
+            moves,
+            paranoid_hash: false,
+            hash_diagnostics: false,
+            hash_collisions: 0,
+            symmetries: Vec::new(),
+            symmetric_g: ZobristMap::default(),
+            meeting_symmetries: Vec::new(),
+            meeting_symmetry: None,
         }
     }
 
-    fn expand_node<H2, S2>(&mut self, other_searcher: &Searcher<H2, S2>) -> ExpandNode {
+    fn emit(&mut self, event: Event) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
+        }
+    }
+
+    fn expand_node<H2: Heuristic, S2>(&mut self, other_searcher: &Searcher<H2, S2>) -> ExpandNode {
         // Pop next node from open list
         let node = self.open_list.pop_min();
         if node.is_none() {
@@ -312,39 +1097,64 @@ impl<H: Heuristic, S: SearchHelper> Searcher<H, S> {
         }
         let node = node.unwrap();
 
+        // Check the transposition table before doing any work on the node
+        let Some(entry) = self.table.get_mut(&node.key) else {
+            // The node has since been evicted, and its parent reopened
+            return ExpandNode::NotDone;
+        };
+        if entry.is_closed || entry.generation != node.generation {
+            // Someone else closed this node, or it has since been pushed again
+            // (along a shorter path, or to regenerate evicted children)
+            return ExpandNode::NotDone;
+        }
+        entry.is_closed = true;
+        let parent_hash = entry.parent_hash;
+        let generating_move = entry.move_;
+
         // Restore the node's checkpoint
-        self.game.restore(&node.checkpoint);
+        self.game.restore_packed(&node.checkpoint);
 
-        // Compute reachable set
-        let reachable = self.helper.compute_moves(&self.game);
+        // Compute reachable set, from the parent's where possible
+        let reachable = match &node.last_move {
+            Some(last_move) => last_move.compute_moves(&self.helper, &self.game),
+            None => self.helper.compute_moves(&self.game),
+        };
 
         // Compute hash
         let boxes_hash = self.zobrist.compute_boxes_hash(&self.game);
+        let check_boxes_hash = self.check_zobrist.compute_boxes_hash(&self.game);
         let player_hash = self.zobrist.player_hash(self.game.player());
         let uncanonical_hash = boxes_hash ^ player_hash;
 
-        // Check tranposition table for uncanonical hash
-        let entry = self.table.get_mut(&uncanonical_hash).unwrap();
-        if entry.is_closed {
-            // Someone else closed this node
-            return ExpandNode::NotDone;
-        } else {
-            // Mark node as closed
-            entry.is_closed = true;
-        }
-        let parent_hash = entry.parent_hash;
-
         // Compute canonical hash
         let canonical_player_pos = reachable.squares.top_left().unwrap();
         let canonical_player_hash = self.zobrist.player_hash(canonical_player_pos);
         let canonical_hash = boxes_hash ^ canonical_player_hash;
+        let canonical_check =
+            check_boxes_hash ^ self.check_zobrist.player_hash(canonical_player_pos);
+        let canonical_key = self.table_key(canonical_hash, canonical_check);
+
+        // Key identifying this node as the parent of its children
+        let node_hash = if self.moves { node.key } else { canonical_key };
 
         // Check transposition table for canonical hash
-        if canonical_hash != uncanonical_hash {
-            match self.table.entry(canonical_hash) {
+        if canonical_hash != uncanonical_hash && !self.moves {
+            self.check_collision(canonical_hash, canonical_check);
+            match self.table.entry(canonical_key) {
                 Entry::Occupied(mut e) => {
                     let e = e.get_mut();
-                    if e.is_closed {
+                    if self.reopen {
+                        if e.g < node.g || (e.is_closed && e.g == node.g) {
+                            // The same position has a shorter or equally
+                            // short path which is expanded instead
+                            return ExpandNode::NotDone;
+                        }
+                        // Reopen the position along the shorter path
+                        e.g = node.g;
+                        e.parent_hash = parent_hash;
+                        e.move_ = generating_move;
+                        e.is_closed = true;
+                    } else if e.is_closed {
                         // Someone else closed this node
                         return ExpandNode::NotDone;
                     } else {
@@ -356,350 +1166,3282 @@ impl<H: Heuristic, S: SearchHelper> Searcher<H, S> {
                     // Otherwise, insert a closed node
                     e.insert(TableEntry {
                         parent_hash,
+                        move_: generating_move,
+                        check: canonical_check,
                         is_closed: true,
+                        g: node.g,
+                        generation: 0,
                     });
                 }
             }
         }
 
-        // Check if we've hit the other side
-        if other_searcher.table.contains_key(&canonical_hash) {
-            return ExpandNode::Solved;
-        }
-
-        // Apply PI-corral pruning
-        let moves = match self.helper.search_corrals(&mut self.game, &reachable) {
-            CorralResult::Prune(pruned_moves) => pruned_moves,
-            CorralResult::None => reachable.moves,
-            CorralResult::Deadlocked => return ExpandNode::NotDone,
-        };
-
-        // Try each move
-        for move_ in &moves {
-            // Make sure we're not trying to push a frozen box
-            if node.frozen_boxes.contains(move_.box_index()) {
-                continue;
+        // Check if we've hit the other side. A mismatched check hash means
+        // that a different position merely shares the hash, so keep searching.
+        // The node's children are still generated, as the joined solution
+        // may be rejected (for exceeding the depth limit, or by anytime
+        // search for being no better), and the search then carries on
+        // through them.
+        let mut outcome = ExpandNode::NotDone;
+        let other_key = table_key(
+            &other_searcher.table,
+            canonical_hash,
+            canonical_check,
+            self.paranoid_hash,
+        );
+        if let Some(other) = other_searcher.table.get(&other_key) {
+            if other.check == canonical_check {
+                self.meeting_symmetry = None;
+                outcome = ExpandNode::Solved;
+            } else if self.hash_diagnostics {
+                self.note_collision(other_key, other.check, canonical_check);
             }
+        }
 
-            let old_box_pos = self.game.box_position(move_.box_index());
-            let new_box_pos = self
-                .game
-                .move_position(old_box_pos, move_.direction())
-                .unwrap();
-
-            // Apply dead square pruning
-            if self.helper.is_dead_square(&self.game, new_box_pos) {
-                continue;
-            }
+        // The other side may have reached a mirror image or rotation instead
+        if matches!(outcome, ExpandNode::NotDone)
+            && let Some(&symmetry) = self.meeting_symmetries.iter().find(|symmetry| {
+                let hash = symmetry.hash(&self.zobrist, &self.game, &reachable.squares);
+                let check = symmetry.hash(&self.check_zobrist, &self.game, &reachable.squares);
+                let key = table_key(&other_searcher.table, hash, check, self.paranoid_hash);
+                other_searcher
+                    .table
+                    .get(&key)
+                    .is_some_and(|other| other.check == check)
+            })
+        {
+            self.meeting_symmetry = Some(symmetry);
+            outcome = ExpandNode::Solved;
+        }
 
-            // Apply move
-            self.helper.apply_move(&mut self.game, &move_);
+        // The reverse search of a game with surplus goals starts from just
+        // one of its solved positions, so the forward search checks for the
+        // others itself (the reverse game has surplus boxes and is never
+        // solved), as it does for labelled games
+        if matches!(outcome, ExpandNode::NotDone)
+            && !self.game.has_unique_solved_boxes()
+            && self.game.is_solved()
+        {
+            self.meeting_symmetry = None;
+            return ExpandNode::Solved;
+        }
 
-            // Compute newly frozen boxes
-            let new_frozen = self.helper.compute_new_frozen_boxes(
-                &node.frozen_boxes,
+        // Skip positions symmetric to one already expanded, unless they were
+        // reached by a shorter path
+        if !self.symmetries.is_empty() {
+            let class_hash = symmetry_class_hash(
+                &self.symmetries,
+                &self.zobrist,
                 &self.game,
-                move_.box_index(),
+                &reachable.squares,
+                canonical_hash,
             );
-            let child_frozen_boxes = node.frozen_boxes.union(&new_frozen);
-
-            // Apply frozen box deadlock pruning
-            if self.game.unsolved_boxes().contains_any(&child_frozen_boxes) {
-                self.helper.apply_unmove(&mut self.game, &move_);
-                continue;
-            }
-
-            // Compute child hash
-            let child_boxes_hash = boxes_hash
-                ^ self.zobrist.box_hash(old_box_pos)
-                ^ self.zobrist.box_hash(new_box_pos);
-            let child_hash = child_boxes_hash ^ self.zobrist.player_hash(self.game.player());
-
-            // Check the transposition table
-            match self.table.entry(child_hash) {
-                Entry::Occupied(_) => {
-                    // This node was already visited before, skip
-                    self.helper.apply_unmove(&mut self.game, &move_);
-                    continue;
+            match self.symmetric_g.entry(class_hash) {
+                Entry::Occupied(mut e) => {
+                    if !self.reopen || *e.get() <= node.g {
+                        self.pruning.symmetry_pruned += 1;
+                        return outcome;
+                    }
+                    *e.get_mut() = node.g;
                 }
                 Entry::Vacant(e) => {
-                    // Insert an open node
-                    e.insert(TableEntry {
-                        parent_hash: canonical_hash,
-                        is_closed: false,
-                    });
+                    e.insert(node.g);
                 }
-            };
+            }
+        }
 
-            // Compute child cost using appropriate heuristic
-            let frozen_hash = self
-                .zobrist
-                .compute_boxes_hash_subset(&self.game, child_frozen_boxes);
-            let child_cost = self
-                .heuristic
-                .entry(frozen_hash)
-                .or_insert_with(|| {
-                    self.helper
-                        .new_heuristic::<H>(&self.game, child_frozen_boxes)
-                })
-                .compute(&self.game);
+        // Record the box configuration
+        self.box_configs.insert(boxes_hash);
+        self.emit(Event::Expanded {
+            hash: canonical_hash,
+        });
 
-            // If unsolvable, skip
-            if child_cost == Cost::INFINITE {
-                self.helper.apply_unmove(&mut self.game, &move_);
-                continue;
-            }
+        // Compute walking distances, which determine the cost of each push
+        let distances = self.moves.then(|| self.game.player_distances());
+        let squares: Arc<[u64]> = reachable.squares.rows(self.game.height()).collect();
 
-            // Insert into open list
-            self.open_list.push(
-                usize::from(child_cost),
-                Node {
-                    checkpoint: self.game.checkpoint(),
-                    frozen_boxes: child_frozen_boxes,
-                },
-            );
+        // Apply PI-corral pruning
+        let moves = apply_corral_pruning(
+            &mut self.helper,
+            &mut self.game,
+            reachable,
+            &mut self.pruning,
+            &mut self.events,
+        );
+        self.mark_dead_positions();
+        let Some(moves) = moves else {
+            return outcome;
+        };
+
+        // Try each move
+        let parent = Parent {
+            node: &node,
+            key: node_hash,
+            boxes_hash,
+            check_boxes_hash,
+            distances,
+            squares,
+        };
+        generate_children(self, &parent, &moves);
+
+        // Undoing the pushes leaves the player behind the last box pushed, so
+        // put it back where the solution is reconstructed from
+        if matches!(outcome, ExpandNode::Solved) {
+            self.game.restore_packed(&node.checkpoint);
+        }
+        outcome
+    }
 
-            // Unapply move
-            self.helper.apply_unmove(&mut self.game, &move_);
+    /// Mark the positions proven deadlocked by the corral searches dead in
+    /// the transposition table, so that they are skipped as soon as they are
+    /// generated. Positions already in the table are left alone.
+    fn mark_dead_positions(&mut self) {
+        for (hash, check) in self.helper.take_dead_positions() {
+            let key = self.table_key(hash, check);
+            self.table
+                .entry(key)
+                .or_insert_with(|| TableEntry::dead(check));
         }
+    }
 
-        ExpandNode::NotDone
+    /// Estimated number of bytes used by the open list, transposition table
+    /// and box configurations.
+    fn memory_usage(&self) -> usize {
+        let table_size = hash_table_bytes::<(u64, TableEntry)>(self.table.len());
+        let box_configs_size = hash_table_bytes::<u64>(self.box_configs.len());
+        let symmetric_size = hash_table_bytes::<(u64, u16)>(self.symmetric_g.len());
+        let node_size = size_of::<Node>() + self.game.packed_size();
+        self.open_list.len() * node_size + table_size + box_configs_size + symmetric_size
+    }
+
+    /// Record the sizes of the open list and transposition table, and the
+    /// estimated memory used, where larger than any before.
+    fn record_memory(&self, stats: &mut DirectionStats) {
+        stats.peak_open = stats.peak_open.max(self.open_list.len());
+        stats.peak_table = stats.peak_table.max(self.table.len());
+        stats.peak_memory = stats.peak_memory.max(self.memory_usage());
+    }
+
+    /// Evict the worst open nodes until at most `target` bytes are used, or
+    /// half of the open list has been evicted (closed nodes are left to
+    /// `evict_closed_entries()`). Evicted
+    /// nodes are forgotten, and their parents are reopened with the evicted
+    /// node's priority so that they are regenerated if needed. Returns the
+    /// number of nodes evicted.
+    fn evict_nodes(&mut self, target: usize) -> usize {
+        let mut kept = Vec::new();
+        let mut evicted = 0;
+        let max_evicted = self.open_list.len() / 2;
+        while self.memory_usage() > target && evicted < max_evicted {
+            let Some((priority, node)) = self.open_list.pop_max() else {
+                break;
+            };
+
+            let Some(entry) = self.table.get(&node.key) else {
+                // Already evicted
+                continue;
+            };
+            if entry.is_closed || entry.generation != node.generation {
+                // Stale node which would be skipped anyway
+                continue;
+            }
+            let Some(move_) = entry.move_.filter(|_| !node.reopened) else {
+                // Initial nodes can't be regenerated
+                kept.push((priority, node));
+                continue;
+            };
+
+            let parent_hash = entry.parent_hash;
+            self.table.remove(&node.key);
+            self.game.restore_packed(&node.checkpoint);
+            self.reopen_parent(parent_hash, move_, priority);
+            evicted += 1;
+        }
+
+        for (priority, node) in kept {
+            self.game.restore_packed(&node.checkpoint);
+            self.open_list.push(&self.game, priority, node);
+        }
+        evicted
+    }
+
+    /// Forget closed transposition table entries until at most `target`
+    /// bytes are used. Only entries which aren't the ancestor of an open
+    /// entry are forgotten, so that any solution found can still be
+    /// reconstructed. As they have all been expanded, and the positions they
+    /// generated are still open or were evicted (reopening their parents),
+    /// the search stays complete, though it may expand them again if it
+    /// comes back to them. Initial positions and those marked dead are kept.
+    /// Returns the number of entries forgotten.
+    fn evict_closed_entries(&mut self, target: usize) -> usize {
+        if self.memory_usage() <= target {
+            return 0;
+        }
+
+        let mut ancestors = ZobristSet::default();
+        for entry in self.table.values().filter(|entry| !entry.is_closed) {
+            let mut entry = entry;
+            while entry.move_.is_some() && ancestors.insert(entry.parent_hash) {
+                let Some(parent) = self.table.get(&entry.parent_hash) else {
+                    break;
+                };
+                entry = parent;
+            }
+        }
+        let evictable: Vec<u64> = self
+            .table
+            .iter()
+            .filter(|(key, entry)| {
+                entry.is_closed && entry.move_.is_some() && !ancestors.contains(*key)
+            })
+            .map(|(&key, _)| key)
+            .collect();
+
+        let mut evicted = 0;
+        for key in evictable {
+            if self.memory_usage() <= target {
+                break;
+            }
+            self.table.remove(&key);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Reopen the parent of the node in the current game state, given the
+    /// parent's hash and the move which generated the node.
+    fn reopen_parent(&mut self, parent_hash: u64, move_: TableMove, priority: usize) {
+        let Some(parent) = self.table.get_mut(&parent_hash) else {
+            return;
+        };
+        let g = parent.g;
+        parent.is_closed = false;
+        parent.generation = parent.generation.wrapping_add(1);
+        let generation = parent.generation;
+
+        // Undo the generating move, placing the player as they were when the
+        // parent was expanded so that the reopened entry is found when it is
+        // popped
+        let unmove = move_.to_move(&self.game);
+        self.helper.apply_unmove(&mut self.game, &unmove);
+        if self.moves {
+            if let Some(pos) = self.find_player_position(&self.game, parent_hash) {
+                self.game.set_player(pos);
+            }
+        } else {
+            self.game.set_player(self.game.canonical_player_pos());
+        }
+        let frozen_boxes = self.helper.compute_frozen_boxes(&self.game);
+        self.open_list.push(
+            &self.game,
+            priority,
+            Node {
+                generation,
+                reopened: true,
+                ..Node::new(&self.game, &frozen_boxes, g, parent_hash)
+            },
+        );
     }
 
     fn reconstruct_solution(&self) -> Vec<PushByPos> {
         let mut solution = Vec::new();
         let mut current_game = self.game.clone();
-        let mut current_hash = self.zobrist.compute_hash(&current_game);
+        let mut current_hash = self.table_key(
+            state_hash(&self.zobrist, &current_game, self.moves),
+            state_hash(&self.check_zobrist, &current_game, self.moves),
+        );
 
-        // Work backwards until we reach an initial state (parent_hash == 0)
+        // Work backwards by undoing each generating move until we reach an
+        // initial state
         loop {
             let entry = self
                 .table
                 .get(&current_hash)
                 .expect("Failed to reconstruct solution: state not in transposition table");
-
-            if entry.parent_hash == 0 {
-                // Reached an initial state
+            let Some(move_) = entry.move_ else {
                 break;
+            };
+
+            let unmove = move_.to_move(&current_game);
+            self.helper.apply_unmove(&mut current_game, &unmove);
+            solution.push(self.helper.to_push_by_pos(&current_game, &unmove));
+            current_hash = entry.parent_hash;
+        }
+
+        solution
+    }
+
+    /// Remove every node from the open list, along with its priority.
+    fn drain_open_list(&mut self) -> Vec<(usize, Node)> {
+        let mut nodes = Vec::new();
+        while let Some(priority) = self.open_list.min_priority() {
+            nodes.push((priority, self.open_list.pop_min().unwrap()));
+        }
+        nodes
+    }
+
+    /// Find the position the player can walk to in which the game has the
+    /// given (exact player position) table key.
+    fn find_player_position(&self, game: &Game, key: u64) -> Option<Position> {
+        let boxes_hash = self.zobrist.compute_boxes_hash(game);
+        let check_boxes_hash = self.check_zobrist.compute_boxes_hash(game);
+        let reachable = game.player_distances();
+        (0..game.height())
+            .flat_map(|y| (0..game.width()).map(move |x| Position(x, y)))
+            .find(|&pos| {
+                reachable.get(pos).is_some()
+                    && self.table_key(
+                        boxes_hash ^ self.zobrist.player_hash(pos),
+                        check_boxes_hash ^ self.check_zobrist.player_hash(pos),
+                    ) == key
+            })
+    }
+
+    /// The transposition table key of the position with the given hash and
+    /// check hash.
+    fn table_key(&self, hash: u64, check: u64) -> u64 {
+        table_key(&self.table, hash, check, self.paranoid_hash)
+    }
+
+    /// Note a collision if the table has a different position under the
+    /// hash of the position with the given check hash. Only checked with
+    /// paranoid hashing or hash diagnostics.
+    fn check_collision(&mut self, hash: u64, check: u64) {
+        if (self.paranoid_hash || self.hash_diagnostics)
+            && let Some(entry) = self.table.get(&hash)
+            && entry.check != check
+        {
+            self.note_collision(hash, entry.check, check);
+        }
+    }
+
+    /// Record a table hit on an entry whose check hash is `stored` by a
+    /// position whose check hash is `check`, logging it with hash
+    /// diagnostics.
+    fn note_collision(&mut self, hash: u64, stored: u64, check: u64) {
+        self.hash_collisions += 1;
+        self.emit(Event::HashCollision { hash });
+        if self.hash_diagnostics {
+            log_collision(hash, stored, check);
+        }
+    }
+}
+
+impl<H: Heuristic, S: SearchHelper> ChildSink<H, S> for Searcher<H, S> {
+    fn parts(&mut self) -> ExpandParts<'_, H, S> {
+        ExpandParts {
+            game: &mut self.game,
+            helper: &mut self.helper,
+            zobrist: &self.zobrist,
+            check_zobrist: &self.check_zobrist,
+            heuristic: &mut self.heuristic,
+            heuristic_params: &self.heuristic_params,
+            pruning: &mut self.pruning,
+            events: self.events.as_mut(),
+        }
+    }
+
+    fn lookup(&mut self, hash: u64, check: u64, g: u16) -> Option<u64> {
+        self.check_collision(hash, check);
+        let key = self.table_key(hash, check);
+        // Skip nodes already visited, unless along a longer path
+        if self
+            .table
+            .get(&key)
+            .is_some_and(|e| !self.reopen || g >= e.g)
+        {
+            return None;
+        }
+        Some(key)
+    }
+
+    fn record(&mut self, key: u64, entry: TableEntry) -> Option<u16> {
+        match self.table.entry(key) {
+            Entry::Occupied(mut e) => {
+                // Found a shorter path to a visited node, so reopen it
+                let e = e.get_mut();
+                e.parent_hash = entry.parent_hash;
+                e.move_ = entry.move_;
+                e.is_closed = false;
+                e.g = entry.g;
+                e.generation = e.generation.wrapping_add(1);
+                Some(e.generation)
+            }
+            Entry::Vacant(e) => {
+                // Insert an open node
+                e.insert(entry);
+                Some(0)
             }
+        }
+    }
+
+    fn queue(&mut self, cost: Cost, frozen_boxes: &Bitvector, node: Node) {
+        // Skip children which can't lead to a cheap enough solution
+        if let Some(max_cost) = self.max_cost
+            && usize::from(node.g) + usize::from(cost) > max_cost
+        {
+            return;
+        }
 
-            // Compute all possible unmoves from current state
-            let unmoves = self.helper.compute_unmoves(&current_game);
+        // Record improvements to the best heuristic cost
+        if usize::from(cost) < self.best_cost {
+            self.best_cost = usize::from(cost);
+            self.emit(Event::ImprovedBound {
+                cost: self.best_cost,
+            });
+        }
 
-            // Try each unmove to find which one leads to parent state
-            let mut found = false;
-            for unmove in &unmoves {
-                self.helper.apply_unmove(&mut current_game, &unmove);
+        // Insert into open list
+        let mut priority = if self.optimal {
+            usize::from(node.g).saturating_add(weighted_cost(cost, self.weight))
+        } else {
+            usize::from(cost)
+        };
+        if self.tie_break {
+            priority = tie_break_priority(&self.helper, &self.game, frozen_boxes, priority);
+        }
+        priority = order_priority(self.order, &self.game, cost, priority);
+        self.open_list.push(&self.game, priority, node);
+    }
+}
 
-                // Compute hash of this previous state
-                let prev_hash = self.zobrist.compute_hash(&current_game);
+/// The transposition table key of the position with the given hash and check
+/// hash, which is just the hash unless `paranoid`. Otherwise, a position whose
+/// hash is taken by an entry with a different check hash is keyed by the
+/// first key in a sequence stepping by its check hash which is free or has a
+/// matching entry, as in open addressing. Two positions then only share an
+/// entry if both of their 64-bit hashes collide.
+pub(crate) fn table_key(
+    table: &ZobristMap<TableEntry>,
+    hash: u64,
+    check: u64,
+    paranoid: bool,
+) -> u64 {
+    let mut key = hash;
+    if paranoid {
+        while let Some(entry) = table.get(&key)
+            && entry.check != check
+        {
+            key = key.wrapping_add(check | 1);
+        }
+    }
+    key
+}
 
-                // Check if this matches the parent we're looking for
-                if prev_hash == entry.parent_hash {
-                    solution.push(self.helper.to_push_by_pos(&current_game, &unmove));
-                    current_hash = prev_hash;
-                    found = true;
-                    break;
+/// A node on the current path of an IDA* search.
+struct IdaFrame {
+    checkpoint: Checkpoint,
+    frozen_boxes: Bitvector,
+    /// Cost of reaching the node from the root.
+    g: usize,
+    /// Hash of the node, used to detect cycles along the path.
+    hash: u64,
+    /// The push leading to this node from its parent, or `None` for the root.
+    push: Option<Push>,
+    /// Children yet to be tried, along with the cost of the push to each.
+    children: Vec<(Push, usize)>,
+}
+
+/// Searcher which performs iterative-deepening A* forwards from the initial
+/// state. Each iteration is a depth-first search which skips nodes whose
+/// f = g + h exceeds a bound, and the bound is raised to the lowest f which
+/// exceeded it for the next iteration. Only the current path is kept in
+/// memory, at the cost of re-expanding nodes in every iteration.
+struct IdaSearcher<H: Heuristic> {
+    game: Game,
+    root: Checkpoint,
+    zobrist: Rc<Zobrist>,
+    heuristic: FrozenCache<H>,
+    /// Settings every heuristic is created with.
+    heuristic_params: H::Params,
+    helper: ForwardSearchHelper,
+    path: Vec<IdaFrame>,
+    /// Hashes of all nodes on the current path.
+    path_hashes: ZobristSet,
+    /// Positions already searched in the current iteration, if a memory
+    /// limit was given.
+    table: Option<BoundedTable>,
+    /// Bound on f for the current iteration.
+    bound: usize,
+    /// Bound for the next iteration, or `None` if no node has exceeded the
+    /// current bound.
+    next_bound: Option<usize>,
+    /// Lowest heuristic cost generated so far.
+    best_cost: usize,
+    pruning: PruningStats,
+    /// Buffered search events, or `None` if events are not being recorded.
+    events: Option<Vec<Event>>,
+    /// Weight of the heuristic in f = g + weight * h.
+    weight: f64,
+    /// Maximum cost of the solutions searched for.
+    max_cost: Option<usize>,
+    /// Count player moves rather than pushes in g, identifying nodes by the
+    /// exact player position.
+    moves: bool,
+}
+
+impl<H: Heuristic> IdaSearcher<H> {
+    fn new(
+        game: &Game,
+        zobrist: Rc<Zobrist>,
+        helper: ForwardSearchHelper,
+        heuristic_params: H::Params,
+        weight: f64,
+        moves: bool,
+        max_memory: Option<usize>,
+    ) -> Self {
+        let mut searcher = Self {
+            game: game.clone(),
+            root: game.checkpoint(),
+            zobrist,
+            heuristic: FrozenCache::new(),
+            heuristic_params,
+            helper,
+            path: Vec::new(),
+            path_hashes: ZobristSet::default(),
+            table: max_memory.map(BoundedTable::with_max_bytes),
+            bound: 0,
+            next_bound: None,
+            best_cost: usize::MAX,
+            pruning: PruningStats::default(),
+            events: None,
+            weight,
+            max_cost: None,
+            moves,
+        };
+
+        // The first iteration is bounded by the heuristic cost of the root
+        let frozen_boxes = searcher.helper.compute_frozen_boxes(&searcher.game);
+        if searcher.game.unsolved_boxes().contains_any(&frozen_boxes) {
+            return searcher;
+        }
+        let cost = searcher.compute_heuristic(frozen_boxes);
+        if cost != Cost::INFINITE {
+            searcher.best_cost = usize::from(cost);
+            searcher.next_bound = Some(weighted_cost(cost, weight));
+        }
+        searcher
+    }
+
+    fn emit(&mut self, event: Event) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
+        }
+    }
+
+    fn compute_heuristic(&mut self, frozen_boxes: Bitvector) -> Cost {
+        let frozen_hash = self
+            .zobrist
+            .compute_boxes_hash_subset(&self.game, frozen_boxes);
+        self.heuristic
+            .get_or_insert_with(frozen_hash, || {
+                self.helper
+                    .new_heuristic::<H>(&self.game, frozen_boxes, &self.heuristic_params)
+            })
+            .compute(&self.game)
+            .at_least(self.helper.penalty_bound(&self.game))
+    }
+
+    /// Expand the next node within the bound, starting a new iteration once
+    /// the current one is exhausted.
+    fn expand_node(&mut self) -> ExpandNode {
+        loop {
+            let Some(frame) = self.path.last_mut() else {
+                // Start the next iteration from the root
+                let Some(bound) = self.next_bound.take() else {
+                    // No node exceeded the bound, so every node was searched
+                    return ExpandNode::Unsolvable;
+                };
+                self.bound = bound;
+                if let Some(table) = &mut self.table {
+                    table.next_iteration();
+                }
+                self.game.restore(&self.root);
+                let frozen_boxes = self.helper.compute_frozen_boxes(&self.game);
+                let hash = state_hash(&self.zobrist, &self.game, self.moves);
+                if self.game.is_solved() {
+                    self.path.push(self.new_frame(None, 0, frozen_boxes, hash));
+                    return ExpandNode::Solved;
                 }
+                return self.expand(None, 0, frozen_boxes, hash);
+            };
+
+            // Backtrack once all children have been tried
+            let Some((push, step_cost)) = frame.children.pop() else {
+                self.path_hashes.remove(&frame.hash);
+                self.path.pop();
+                continue;
+            };
+            let parent_frozen_boxes = frame.frozen_boxes;
+            let child_g = frame.g + step_cost;
+            self.game.restore(&frame.checkpoint);
 
-                // Redo the unmove if it wasn't correct
-                self.helper.apply_move(&mut current_game, &unmove);
+            // Apply push
+            let new_box_pos = self.game.box_position(push.box_index()) + push.direction();
+            self.game.push(push);
+
+            // Skip children which would form a cycle, before the deadlock
+            // checks and heuristic below
+            let child_hash = state_hash(&self.zobrist, &self.game, self.moves);
+            if self.path_hashes.contains(&child_hash) {
+                self.pruning.duplicates_pruned += 1;
+                continue;
+            }
+
+            // Skip children already searched this iteration with at least as
+            // much of the bound left
+            if self
+                .table
+                .as_ref()
+                .is_some_and(|table| table.contains(child_hash, child_g))
+            {
+                self.pruning.transposition_pruned += 1;
+                continue;
+            }
+
+            // Apply deadlock pattern pruning
+            if self.helper.is_pattern_deadlock(&self.game, new_box_pos) {
+                self.pruning.pattern_pruned += 1;
+                continue;
             }
 
-            assert!(
-                found,
-                "Failed to reconstruct solution: no unmove leads to parent state"
+            // Apply frozen box deadlock pruning
+            let new_frozen = self.helper.compute_new_frozen_boxes(
+                &parent_frozen_boxes,
+                &self.game,
+                push.box_index(),
             );
+            let child_frozen_boxes = parent_frozen_boxes.union(&new_frozen);
+            if self.game.unsolved_boxes().contains_any(&child_frozen_boxes) {
+                self.emit(Event::FreezePruned {
+                    box_pos: new_box_pos,
+                });
+                self.pruning.freeze_pruned += 1;
+                continue;
+            }
+
+            // Newly frozen boxes may shut the player in for good
+            if !new_frozen.is_empty() && is_player_enclosed(&self.game, &child_frozen_boxes) {
+                self.emit(Event::CorralDeadlock);
+                self.pruning.corral_deadlocks += 1;
+                continue;
+            }
+
+            // Apply matching deadlock pruning
+            if self
+                .helper
+                .is_matching_deadlock(&self.game, &child_frozen_boxes)
+            {
+                self.pruning.matching_pruned += 1;
+                continue;
+            }
+
+            // Apply conflict deadlock pruning
+            if self
+                .helper
+                .is_conflict_deadlock(&self.game, push.box_index())
+            {
+                self.pruning.conflict_pruned += 1;
+                continue;
+            }
+
+            // Compute child cost, skipping unsolvable children
+            let child_cost = self.compute_heuristic(child_frozen_boxes);
+            if child_cost == Cost::INFINITE {
+                continue;
+            }
+            if let Some(max_cost) = self.max_cost
+                && child_g + usize::from(child_cost) > max_cost
+            {
+                continue;
+            }
+            if usize::from(child_cost) < self.best_cost {
+                self.best_cost = usize::from(child_cost);
+                self.emit(Event::ImprovedBound {
+                    cost: self.best_cost,
+                });
+            }
+
+            // Skip children beyond the bound, remembering the lowest f seen
+            let f = child_g.saturating_add(weighted_cost(child_cost, self.weight));
+            if f > self.bound {
+                self.next_bound = Some(self.next_bound.map_or(f, |b| b.min(f)));
+                continue;
+            }
+
+            if self.game.is_solved() {
+                let frame = self.new_frame(Some(push), child_g, child_frozen_boxes, child_hash);
+                self.path.push(frame);
+                return ExpandNode::Solved;
+            }
+            return self.expand(Some(push), child_g, child_frozen_boxes, child_hash);
         }
+    }
 
-        solution
+    /// Expand the node in the current game state, pushing it onto the path.
+    fn expand(
+        &mut self,
+        push: Option<Push>,
+        g: usize,
+        frozen_boxes: Bitvector,
+        hash: u64,
+    ) -> ExpandNode {
+        let mut frame = self.new_frame(push, g, frozen_boxes, hash);
+        self.emit(Event::Expanded { hash });
+
+        // Compute walking distances, which determine the cost of each push
+        let reachable = self.helper.compute_moves(&self.game);
+        let distances = self.moves.then(|| self.game.player_distances());
+
+        // Apply PI-corral pruning
+        let Some(moves) = apply_corral_pruning(
+            &mut self.helper,
+            &mut self.game,
+            reachable,
+            &mut self.pruning,
+            &mut self.events,
+        ) else {
+            return ExpandNode::NotDone;
+        };
+
+        for child in &moves {
+            // Make sure we're not trying to push a frozen box
+            if frozen_boxes.contains(child.box_index()) {
+                continue;
+            }
+
+            // Apply dead square pruning
+            let old_box_pos = self.game.box_position(child.box_index());
+            if self.helper.is_dead_square(
+                &self.game,
+                &frozen_boxes,
+                old_box_pos + child.direction(),
+            ) {
+                self.pruning.dead_square_pruned += 1;
+                continue;
+            }
+
+            let step_cost = match &distances {
+                Some(distances) => {
+                    let push_pos = old_box_pos + child.direction().reverse();
+                    distances.get(push_pos).unwrap() + 1
+                }
+                None => 1,
+            };
+            frame.children.push((child, step_cost));
+        }
+
+        self.path_hashes.insert(hash);
+        if let Some(table) = &mut self.table {
+            table.insert(hash, g);
+        }
+        self.path.push(frame);
+        ExpandNode::NotDone
+    }
+
+    fn new_frame(
+        &self,
+        push: Option<Push>,
+        g: usize,
+        frozen_boxes: Bitvector,
+        hash: u64,
+    ) -> IdaFrame {
+        IdaFrame {
+            checkpoint: self.game.checkpoint(),
+            frozen_boxes,
+            g,
+            hash,
+            push,
+            children: Vec::new(),
+        }
+    }
+
+    /// The pushes along the current path, which solve the game once search
+    /// has succeeded.
+    fn solution(&self) -> Vec<Push> {
+        self.path.iter().filter_map(|frame| frame.push).collect()
     }
 }
 
-/// Solver which performs bidirectional search.
-pub struct Solver<H> {
-    forward: Searcher<H, ForwardSearchHelper>,
-    reverse: Searcher<H, ReverseSearchHelper>,
-    game: Game,
-    opts: SolverOpts,
+/// Restrict the moves of an expanded node using PI-corral pruning, recording
+/// pruning statistics and events. Returns `None` if the node is deadlocked.
+pub(crate) fn apply_corral_pruning<S: SearchHelper>(
+    helper: &mut S,
+    game: &mut Game,
+    reachable: ReachableSet<S::Move>,
+    pruning: &mut PruningStats,
+    events: &mut Option<Vec<Event>>,
+) -> Option<Moves<S::Move>> {
+    let mut emit = |event| {
+        if let Some(events) = events {
+            events.push(event);
+        }
+    };
+    let corral_result = helper.search_corrals(game, &reachable);
+    if let Some(downgrade) = helper.take_corral_downgrade() {
+        emit(match downgrade {
+            CorralDowngrade::NextBest { candidates, chosen } => Event::CorralDowngraded {
+                candidates,
+                chosen: Some(chosen),
+            },
+            CorralDowngrade::Disabled { candidates } => Event::CorralDowngraded {
+                candidates,
+                chosen: None,
+            },
+        });
+    }
+    match corral_result {
+        CorralResult::Prune(pruned_moves) => {
+            emit(Event::CorralPruned {
+                moves: reachable.moves.len(),
+                pruned_moves: pruned_moves.len(),
+            });
+            pruning.corral_pruned += 1;
+            Some(pruned_moves)
+        }
+        CorralResult::None => Some(reachable.moves),
+        CorralResult::Deadlocked => {
+            emit(Event::CorralDeadlock);
+            pruning.corral_deadlocks += 1;
+            None
+        }
+    }
 }
 
-pub struct SolverOpts {
-    pub search_type: SearchType,
-    pub max_nodes_explored: usize,
-    pub freeze_deadlocks: bool,
-    pub dead_squares: bool,
-    pub pi_corrals: bool,
-    pub deadlock_max_nodes: usize,
-    pub trace_range: Range<usize>,
+/// The parts of a best-first searcher which generate the children of its
+/// nodes, borrowed apart from its transposition table and open list.
+pub(crate) struct ExpandParts<'a, H: Heuristic, S: SearchHelper> {
+    pub(crate) game: &'a mut Game,
+    pub(crate) helper: &'a mut S,
+    pub(crate) zobrist: &'a Zobrist,
+    pub(crate) check_zobrist: &'a Zobrist,
+    pub(crate) heuristic: &'a mut FrozenCache<H>,
+    pub(crate) heuristic_params: &'a H::Params,
+    pub(crate) pruning: &'a mut PruningStats,
+    /// Buffered search events, or `None` if events are not being recorded.
+    pub(crate) events: Option<&'a mut Vec<Event>>,
+}
+
+impl<H: Heuristic, S: SearchHelper> ExpandParts<'_, H, S> {
+    fn emit(&mut self, event: Event) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
+        }
+    }
+}
+
+/// A best-first search whose transposition table and open list children are
+/// recorded in by `generate_children`. Implemented by the searchers of a
+/// serial search and the workers of a parallel one, so that both generate
+/// and prune children alike.
+pub(crate) trait ChildSink<H: Heuristic, S: SearchHelper> {
+    fn parts(&mut self) -> ExpandParts<'_, H, S>;
+
+    /// The key to record the child with the given hash and check hash under,
+    /// or `None` if it was already visited along a path no longer than `g`.
+    fn lookup(&mut self, hash: u64, check: u64, g: u16) -> Option<u64>;
+
+    /// Record the child in the transposition table under the given key,
+    /// returning the generation to queue it with, or `None` if it is to be
+    /// skipped after all.
+    fn record(&mut self, key: u64, entry: TableEntry) -> Option<u16>;
+
+    /// Queue the child, whose position is in the game, given its heuristic
+    /// cost and frozen boxes.
+    fn queue(&mut self, cost: Cost, frozen_boxes: &Bitvector, node: Node);
+}
+
+/// A node whose children are being generated.
+pub(crate) struct Parent<'a> {
+    pub(crate) node: &'a Node,
+    /// Key which the node's children refer to it by.
+    pub(crate) key: u64,
+    /// Hash and check hash of the node's boxes.
+    pub(crate) boxes_hash: u64,
+    pub(crate) check_boxes_hash: u64,
+    /// The player's walking distances, which determine the cost of each push
+    /// when counting moves.
+    pub(crate) distances: Option<PlayerDistances>,
+    /// The squares of the player's region.
+    pub(crate) squares: Arc<[u64]>,
+}
+
+/// Try each of the given moves of a node, whose position is in the game,
+/// recording the children which aren't deadlocked or already visited in the
+/// sink's table and open list.
+pub(crate) fn generate_children<H: Heuristic, S: SearchHelper>(
+    sink: &mut impl ChildSink<H, S>,
+    parent: &Parent,
+    moves: &Moves<S::Move>,
+) {
+    let node = parent.node;
+    for move_ in moves {
+        // Make sure we're not trying to push a frozen box
+        if node.frozen_boxes.contains(move_.box_index()) {
+            continue;
+        }
+
+        let parts = sink.parts();
+        let old_box_pos = parts.game.box_position(move_.box_index());
+        let new_box_pos = old_box_pos + move_.direction();
+
+        // Apply dead square pruning
+        if parts
+            .helper
+            .is_dead_square(parts.game, &node.frozen_boxes, new_box_pos)
+        {
+            parts.pruning.dead_square_pruned += 1;
+            continue;
+        }
+
+        // When counting moves, the player walks to the square behind the box
+        // before pushing it
+        let step_cost = match &parent.distances {
+            Some(distances) => {
+                let push_pos = old_box_pos + move_.direction().reverse();
+                distances.get(push_pos).unwrap() + 1
+            }
+            None => 1,
+        };
+        let child_g = node.g.saturating_add(step_cost as u16);
+
+        parts.helper.apply_move(parts.game, &move_);
+        generate_child(sink, parent, &move_, old_box_pos, child_g);
+        let parts = sink.parts();
+        parts.helper.apply_unmove(parts.game, &move_);
+    }
 }
 
-impl<H: Heuristic> Solver<H> {
-    pub fn new(game: &Game, opts: SolverOpts) -> Self {
-        let zobrist = Rc::new(Zobrist::new());
-        let reverse_game = game.swap_boxes_and_goals();
-        let forward_player_positions = [game.canonical_player_pos()];
-        let reverse_player_positions = reverse_game.all_possible_player_positions();
+/// Record the child reached by the given move, which has been applied to the
+/// game, unless it is deadlocked or already visited.
+fn generate_child<H: Heuristic, S: SearchHelper>(
+    sink: &mut impl ChildSink<H, S>,
+    parent: &Parent,
+    move_: &S::Move,
+    old_box_pos: Position,
+    child_g: u16,
+) {
+    let node = parent.node;
+    let new_box_pos = old_box_pos + move_.direction();
+
+    // Compute child hash
+    let parts = sink.parts();
+    let label = parts.game.box_label(move_.box_index());
+    let child_boxes_hash = parent.boxes_hash
+        ^ parts.zobrist.labelled_box_hash(old_box_pos, label)
+        ^ parts.zobrist.labelled_box_hash(new_box_pos, label);
+    let child_hash = child_boxes_hash ^ parts.zobrist.player_hash(parts.game.player());
+    let child_check = parent.check_boxes_hash
+        ^ parts.check_zobrist.labelled_box_hash(old_box_pos, label)
+        ^ parts.check_zobrist.labelled_box_hash(new_box_pos, label)
+        ^ parts.check_zobrist.player_hash(parts.game.player());
+
+    // Check the transposition table first, as it is much cheaper than the
+    // deadlock checks and heuristic below
+    let Some(child_key) = sink.lookup(child_hash, child_check, child_g) else {
+        sink.parts().pruning.duplicates_pruned += 1;
+        return;
+    };
+
+    // Apply deadlock pattern pruning
+    let mut parts = sink.parts();
+    if parts.helper.is_pattern_deadlock(parts.game, new_box_pos) {
+        parts.pruning.pattern_pruned += 1;
+        return;
+    }
+
+    // Compute newly frozen boxes
+    let new_frozen =
+        parts
+            .helper
+            .compute_new_frozen_boxes(&node.frozen_boxes, parts.game, move_.box_index());
+    let child_frozen_boxes = node.frozen_boxes.union(&new_frozen);
+
+    // Apply frozen box deadlock pruning
+    if parts
+        .game
+        .unsolved_boxes()
+        .contains_any(&child_frozen_boxes)
+    {
+        parts.emit(Event::FreezePruned {
+            box_pos: new_box_pos,
+        });
+        parts.pruning.freeze_pruned += 1;
+        return;
+    }
+
+    // Newly frozen boxes may shut the player in for good
+    if !new_frozen.is_empty() && is_player_enclosed(parts.game, &child_frozen_boxes) {
+        parts.emit(Event::CorralDeadlock);
+        parts.pruning.corral_deadlocks += 1;
+        return;
+    }
+
+    // Apply matching deadlock pruning
+    if parts
+        .helper
+        .is_matching_deadlock(parts.game, &child_frozen_boxes)
+    {
+        parts.pruning.matching_pruned += 1;
+        return;
+    }
+
+    // Apply conflict deadlock pruning
+    if parts
+        .helper
+        .is_conflict_deadlock(parts.game, move_.box_index())
+    {
+        parts.pruning.conflict_pruned += 1;
+        return;
+    }
+
+    // Record the child in the transposition table
+    let entry = TableEntry {
+        parent_hash: parent.key,
+        move_: Some(TableMove {
+            box_pos: new_box_pos,
+            direction: move_.direction(),
+        }),
+        check: child_check,
+        is_closed: false,
+        g: child_g,
+        generation: 0,
+    };
+    let Some(generation) = sink.record(child_key, entry) else {
+        return;
+    };
+
+    // Compute child cost using appropriate heuristic
+    let parts = sink.parts();
+    let frozen_hash = parts
+        .zobrist
+        .compute_boxes_hash_subset(parts.game, child_frozen_boxes);
+    let child_cost = parts
+        .heuristic
+        .get_or_insert_with(frozen_hash, || {
+            parts
+                .helper
+                .new_heuristic::<H>(parts.game, child_frozen_boxes, parts.heuristic_params)
+        })
+        .compute(parts.game)
+        .at_least(parts.helper.penalty_bound(parts.game));
+
+    // If unsolvable, skip
+    if child_cost == Cost::INFINITE {
+        return;
+    }
+
+    let child = Node {
+        generation,
+        last_move: Some(LastMove {
+            squares: parent.squares.clone(),
+            from: old_box_pos,
+            to: new_box_pos,
+        }),
+        ..Node::new(parts.game, &child_frozen_boxes, child_g, child_key)
+    };
+    sink.queue(child_cost, &child_frozen_boxes, child);
+}
+
+/// The lowest of the hashes of a position's mirror images and rotations
+/// under the given symmetries and its own canonical hash, which identifies
+/// its class of symmetric positions.
+pub(crate) fn symmetry_class_hash(
+    symmetries: &[Symmetry],
+    zobrist: &Zobrist,
+    game: &Game,
+    region: &LazyBitboard,
+    canonical_hash: u64,
+) -> u64 {
+    symmetries
+        .iter()
+        .map(|symmetry| symmetry.hash(zobrist, game, region))
+        .fold(canonical_hash, u64::min)
+}
+
+/// Log a table hit on an entry whose check hash is `stored` by a position
+/// whose check hash is `check`, found with hash diagnostics.
+pub(crate) fn log_collision(hash: u64, stored: u64, check: u64) {
+    crate::info!(
+        "hash collision: entry {:016x} has check hash {:016x}, but the position has {:016x}",
+        hash,
+        stored,
+        check
+    );
+}
+
+/// Heuristic cost scaled by the given weight, for use in f = g + weight * h.
+/// Capped at the open list's largest priority, so that large weights can't
+/// overflow f.
+fn weighted_cost(cost: Cost, weight: f64) -> usize {
+    ((usize::from(cost) as f64 * weight).round() as usize).min(PriorityQueue::<Node>::MAX_PRIORITY)
+}
+
+/// Hash of a game state, using either the exact or the canonical player
+/// position.
+pub(crate) fn state_hash(zobrist: &Zobrist, game: &Game, exact_player: bool) -> u64 {
+    if exact_player {
+        zobrist.compute_boxes_hash(game) ^ zobrist.player_hash(game.player())
+    } else {
+        zobrist.compute_hash(game)
+    }
+}
+
+/// Estimated number of bytes used by a hash table of `len` entries of type
+/// `T`. Hash tables store a control byte per bucket, and are at most 7/8 full.
+pub(crate) fn hash_table_bytes<T>(len: usize) -> usize {
+    len * (size_of::<T>() + 1) * 8 / 7
+}
+
+/// The search direction of the forward or reverse searcher.
+fn direction_of(is_forward: bool) -> SearchType {
+    if is_forward {
+        SearchType::Forward
+    } else {
+        SearchType::Reverse
+    }
+}
+
+/// Solver which performs bidirectional search.
+pub struct Solver<H: Heuristic> {
+    forward: Searcher<H, ForwardSearchHelper>,
+    reverse: Searcher<H, ReverseSearchHelper>,
+    /// IDA* searcher, used instead of the forward and reverse searchers when
+    /// the IDA* algorithm is selected.
+    ida: Option<IdaSearcher<H>>,
+    game: Game,
+    opts: SolverOpts,
+    event_log: Option<EventLog>,
+    interrupt: Option<Arc<AtomicBool>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SolverOpts {
+    pub search_type: SearchType,
+    /// How bidirectional search chooses the direction to expand next.
+    pub balance: BalanceType,
+    /// Forward and reverse effort in bidirectional search: the number of
+    /// forward and reverse expansions in each round when alternating, or the
+    /// ratio of the open list sizes when balancing the frontiers. Both must be
+    /// positive.
+    pub ratio: (usize, usize),
+    /// Perimeter search: in bidirectional search, first expand this many
+    /// reverse nodes, then search only forwards until reaching a position the
+    /// reverse search reached.
+    pub perimeter: Option<usize>,
+    pub max_nodes_explored: usize,
+    /// Wall-clock time limit for the search, if any.
+    pub max_time: Option<Duration>,
+    /// Approximate limit in bytes on the memory used by the open lists and
+    /// transposition tables. Once exceeded, the worst open nodes are evicted
+    /// and regenerated later if needed, then closed positions no open one
+    /// was reached from are forgotten. IDA* search instead fills a
+    /// fixed-size transposition table of this size.
+    pub max_memory: Option<usize>,
+    /// Prune positions with boxes which are frozen off their goals: boxes
+    /// which can never be pushed again, or never pulled again in reverse
+    /// search.
+    pub freeze_deadlocks: bool,
+    /// Prune pushes completing a small fixed deadlock pattern (a box in a
+    /// corner, two boxes against a wall or a 2x2 block) before the freeze
+    /// analysis.
+    pub deadlock_patterns: bool,
+    pub dead_squares: bool,
+    /// Prune positions whose boxes can't all be matched to distinct goals
+    /// they can reach.
+    pub matching_deadlocks: bool,
+    /// Prune pushes after which the pushed box can't be put on a goal
+    /// together with one or two of the boxes around it, with every other box
+    /// removed. Only used by forward search.
+    pub conflict_deadlocks: bool,
+    /// Maximum positions searched for each group of boxes probed for conflict
+    /// deadlocks.
+    pub conflict_max_nodes: usize,
+    pub pi_corrals: bool,
+    pub deadlock_max_nodes: usize,
+    /// Downgrade PI-corral pruning when the chosen corral would exclude every
+    /// push onto a goal while empty goals remain outside it.
+    pub corral_safety: bool,
+    /// Restrict the pulls of bidirectional search's reverse direction to
+    /// those out of a pull corral, when they can all be made (see
+    /// `search_pull_corrals`). This may prune every path to positions the
+    /// forward search would meet, and every way back to the start, so it is
+    /// off by default; pull corrals are used to detect deadlocks either way.
+    pub pull_corral_pruning: bool,
+    /// Search for a push-optimal solution using A* (ordering by pushes so far
+    /// plus the heuristic). This implies a forward search, and is only
+    /// guaranteed to be optimal with an admissible heuristic.
+    pub optimal: bool,
+    /// The cost to minimise. Counting moves implies optimal search.
+    pub cost: CostType,
+    /// Weight of the heuristic in optimal search, which orders nodes by
+    /// f = g + weight * h. Weights above 1 trade optimality for speed, finding
+    /// solutions at most `weight` times longer than optimal.
+    pub weight: f64,
+    pub algorithm: AlgorithmType,
+    /// Keep searching after a solution is found, pruning nodes which can't
+    /// lead to a cheaper one, and return the best solution found within the
+    /// node and time limits.
+    pub anytime: bool,
+    /// Reopen positions when a shorter path to them is found, so that greedy
+    /// search finds shorter solutions at the cost of re-expanding positions.
+    /// Always done by optimal search.
+    pub reopen: bool,
+    /// Only search for solutions costing at most this many pushes (or moves),
+    /// pruning nodes whose g + h exceeds it. If no such solution exists, the
+    /// result is `SolveResult::Unsolvable`.
+    pub max_depth: Option<usize>,
+    /// In bidirectional search, stop expanding a direction once it has
+    /// stalled, i.e. it has stopped getting closer to the other side while its
+    /// open list outgrew the other's, and expand only the other direction.
+    pub adaptive: bool,
+    /// Break ties between positions of equal priority by the player's walking
+    /// distance to the nearest box it can usefully move, expanding positions
+    /// where the player is stranded far from useful work later. Never changes
+    /// the order of positions of different priority, so optimal search stays
+    /// optimal, but only priorities up to a quarter of the usual range are
+    /// told apart. Not used by IDA* search.
+    pub player_tie_break: bool,
+    /// How the open list orders positions of equal priority. Like the player
+    /// distance tie-break, policies ordering by a key never change the order
+    /// of positions of different priority, but only tell apart priorities up
+    /// to a sixteenth of the usual range, and can't be combined with it. Not
+    /// used by IDA* search.
+    pub tie_break: TieBreak,
+    /// Learn penalty patterns from the corrals searched for PI-corral pruning
+    /// (after Sokolution): box subsets which need more pushes on their own
+    /// than their distances to the nearest goals. The heuristic cost of every
+    /// position containing them is raised to at least their pushes plus the
+    /// other boxes' distances to the nearest goals, which tightens it on
+    /// congested levels while keeping it admissible. Only used by forward
+    /// search, and requires `pi_corrals`.
+    pub penalties: bool,
+    /// Number of threads to search with. With more than one, a greedy forward
+    /// best-first search is run by that many workers sharing their open lists
+    /// and transposition table (see `parallel`). The search direction, memory
+    /// limit and event log are then ignored, and optimal, anytime,
+    /// depth-limited, reopening, IDA* and FESS searches aren't supported.
+    pub threads: usize,
+    /// Never let positions with different check hashes (a second,
+    /// independent Zobrist hash) share a transposition table entry in
+    /// best-first search, rather than trusting the 64-bit hash alone
+    /// everywhere but where the two directions meet. Not supported with
+    /// more than one thread.
+    pub paranoid_hash: bool,
+    /// Seed of the Zobrist hashes identifying positions, or `None` for the
+    /// default. Searching again with another seed rules out a hash
+    /// collision behind a suspect result. Other seeds hash slightly slower,
+    /// as games only keep the boxes hash of the default seed up to date.
+    pub zobrist_seed: Option<u64>,
+    /// Skip positions which are mirror images or rotations of one already
+    /// expanded, on levels whose walls and goals are symmetric, and let the
+    /// two directions meet at such images. Not used when counting moves, or by
+    /// IDA* search.
+    pub symmetry: bool,
+    /// Mark the positions visited by corral deadlock searches which find a
+    /// deadlock dead in the forward search's transposition table, so that
+    /// they are skipped when generated. Not used by IDA* search, or with more
+    /// than one thread.
+    pub share_deadlocks: bool,
+    /// Compare the check hashes on every transposition table hit in
+    /// best-first search, logging each one made by a different position
+    /// (which paranoid hashing would have keyed apart).
+    pub hash_diagnostics: bool,
+    pub trace_range: Range<usize>,
+}
+
+/// Progress of one direction of bidirectional search, used to tell when it
+/// has stalled.
+struct DirectionProgress {
+    expansions: usize,
+    best_cost: usize,
+    /// Number of expansions when the best cost was last lowered.
+    last_improved: usize,
+}
+
+impl DirectionProgress {
+    fn new() -> Self {
+        Self {
+            expansions: 0,
+            best_cost: usize::MAX,
+            last_improved: 0,
+        }
+    }
+
+    /// Record an expansion, given the direction's best heuristic cost after
+    /// it.
+    fn record(&mut self, best_cost: usize) {
+        self.expansions += 1;
+        if best_cost < self.best_cost {
+            self.best_cost = best_cost;
+            self.last_improved = self.expansions;
+        }
+    }
+
+    fn is_stalled(&self) -> bool {
+        self.expansions - self.last_improved >= STALL_EXPANSIONS
+    }
+}
+
+impl<H: Heuristic> Solver<H> {
+    pub fn new(game: &Game, opts: SolverOpts) -> Self
+    where
+        H: Heuristic<Params = ()>,
+    {
+        Self::with_params(game, opts, ())
+    }
+
+    /// Create a solver whose heuristics are all created with the given
+    /// settings.
+    pub fn with_params(game: &Game, opts: SolverOpts, params: H::Params) -> Self {
+        // When counting moves, the player's exact position matters
+        let forward_player_position = if opts.cost == CostType::Moves {
+            game.player()
+        } else {
+            game.canonical_player_pos()
+        };
+        Self::with_player_positions(game, opts, &[forward_player_position], params)
+    }
+
+    /// Create a solver for a level whose player position is unknown (see
+    /// `Game::from_text_unknown_player`), which searches forwards from every
+    /// region from which the player can reach a box. The player's position in
+    /// the game is ignored, except by IDA* search which only searches from it.
+    /// Use `Game::set_player_for_push` with the first push of a solution to
+    /// place the player in the region it starts from.
+    pub fn new_unknown_player(game: &Game, opts: SolverOpts) -> Self
+    where
+        H: Heuristic<Params = ()>,
+    {
+        Self::with_player_positions(game, opts, &game.all_possible_player_positions(), ())
+    }
+
+    fn with_player_positions(
+        game: &Game,
+        mut opts: SolverOpts,
+        forward_player_positions: &[Position],
+        params: H::Params,
+    ) -> Self {
+        // A game with surplus goals can be solved with any of them left
+        // empty, so there is no single position to search backwards from.
+        // Labelled games are only searched forwards too
+        if !game.has_unique_solved_boxes() {
+            opts.search_type = SearchType::Forward;
+        }
+        let zobrist = Rc::new(
+            opts.zobrist_seed
+                .map_or_else(Zobrist::new, Zobrist::with_seed),
+        );
+        let reverse_game = game.swap_boxes_and_goals();
+        let moves = opts.cost == CostType::Moves;
+        let optimal = opts.optimal || moves;
+        let fess = opts.algorithm == AlgorithmType::Fess;
+
+        // The player can never cross walls, so reverse start regions outside
+        // the forward start regions can never meet the forward search
+        let forward_regions: Vec<LazyBitboard> = forward_player_positions
+            .iter()
+            .map(|&pos| game.wall_region(pos))
+            .collect();
+        let reverse_player_positions: Vec<Position> = reverse_game
+            .all_possible_player_positions()
+            .into_iter()
+            .filter(|&pos| forward_regions.iter().any(|region| region.get(pos)))
+            .collect();
+
+        let reverse_helper = ReverseSearchHelper {
+            freeze_deadlocks: opts.freeze_deadlocks,
+            dead_squares: opts
+                .dead_squares
+                .then(|| DeadSquares::new(zobrist.clone(), true)),
+            pi_corrals: opts.pi_corrals,
+            // Pruning pulls can cut every way back to the start, after which
+            // only the forward search can carry on, so a search in reverse
+            // alone keeps every pull to stay complete
+            corral_move_pruning: opts.pull_corral_pruning
+                && opts.search_type != SearchType::Reverse,
+            matching: opts
+                .matching_deadlocks
+                .then(|| MatchingChecker::new_pull(zobrist.clone())),
+        };
+
+        let forward_searcher = Searcher::new(
+            game,
+            zobrist.clone(),
+            forward_player_positions,
+            ForwardSearchHelper::new(game, &zobrist, &opts),
+            params.clone(),
+            optimal,
+            opts.weight,
+            moves,
+            fess,
+            opts.player_tie_break,
+            opts.tie_break,
+        );
+        let reverse_searcher = Searcher::new(
+            &reverse_game,
+            zobrist.clone(),
+            &reverse_player_positions,
+            reverse_helper,
+            params.clone(),
+            optimal,
+            opts.weight,
+            false,
+            fess,
+            opts.player_tie_break,
+            opts.tie_break,
+        );
+        let ida = (opts.algorithm == AlgorithmType::IdaStar).then(|| {
+            IdaSearcher::new(
+                game,
+                zobrist.clone(),
+                ForwardSearchHelper::new(game, &zobrist, &opts),
+                params,
+                opts.weight,
+                moves,
+                opts.max_memory,
+            )
+        });
+
+        let mut solver = Self {
+            forward: forward_searcher,
+            reverse: reverse_searcher,
+            ida,
+            game: game.clone(),
+            opts,
+            event_log: None,
+            interrupt: None,
+        };
+        if solver.opts.reopen {
+            solver.forward.reopen = true;
+            solver.reverse.reopen = true;
+        }
+        if solver.opts.paranoid_hash {
+            solver.forward.paranoid_hash = true;
+            solver.reverse.paranoid_hash = true;
+        }
+        if solver.opts.hash_diagnostics {
+            solver.forward.hash_diagnostics = true;
+            solver.reverse.hash_diagnostics = true;
+        }
+        if solver.opts.symmetry && !moves {
+            let symmetries = find_symmetries(game);
+            solver.forward.symmetries = symmetries.clone();
+            solver.forward.meeting_symmetries = symmetries.clone();
+
+            // The reverse search ends at the initial boxes with the player in
+            // a start region, so its symmetries must keep the player there
+            let start_regions: Vec<LazyBitboard> = forward_player_positions
+                .iter()
+                .map(|&pos| {
+                    let mut start = game.clone();
+                    start.set_player(pos);
+                    start.compute_pushes().squares
+                })
+                .collect();
+            solver.reverse.symmetries = find_symmetries(&reverse_game)
+                .into_iter()
+                .filter(|symmetry| {
+                    forward_player_positions.iter().all(|&pos| {
+                        let pos = symmetry.apply(pos);
+                        start_regions.iter().any(|region| region.get(pos))
+                    })
+                })
+                .collect();
+            solver.reverse.meeting_symmetries = symmetries;
+        }
+        if solver.opts.share_deadlocks {
+            let check_zobrist = Zobrist::with_seed(zobrist.seed() ^ CHECK_SEED);
+            solver
+                .forward
+                .helper
+                .corral_searcher
+                .record_dead_positions(check_zobrist);
+        }
+        if let Some(max_depth) = solver.opts.max_depth {
+            solver.set_max_cost(max_depth);
+        }
+        solver
+    }
+
+    /// Prune nodes which can't lead to a solution costing at most `max_cost`.
+    fn set_max_cost(&mut self, max_cost: usize) {
+        // Shorter paths to positions already searched may now lead to cheap
+        // enough solutions, so they need to be reopened
+        self.forward.max_cost = Some(max_cost);
+        self.forward.reopen = true;
+        self.reverse.max_cost = Some(max_cost);
+        self.reverse.reopen = true;
+        if let Some(ida) = &mut self.ida {
+            ida.max_cost = Some(max_cost);
+        }
+    }
+
+    /// Stop the search (with `SolveResult::Interrupted`) once the given flag
+    /// is set.
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) {
+        self.interrupt = Some(interrupt);
+    }
+
+    /// Seed the corral deadlock searches with results saved from earlier
+    /// searches of the same level.
+    pub fn load_deadlock_tables(&mut self, tables: &DeadlockTables) {
+        self.forward.helper.corral_searcher.load_tables(tables);
+        if let Some(ida) = &mut self.ida {
+            ida.helper.corral_searcher.load_tables(tables);
+        }
+    }
+
+    /// The corral deadlock search results so far, to be saved for later
+    /// searches of the same level.
+    pub fn deadlock_tables(&self) -> DeadlockTables {
+        match &self.ida {
+            Some(ida) => ida.helper.corral_searcher.tables(),
+            None => self.forward.helper.corral_searcher.tables(),
+        }
+    }
+
+    /// Stream structured search events to the given log.
+    pub fn set_event_log(&mut self, event_log: EventLog) {
+        self.forward.events = Some(Vec::new());
+        self.reverse.events = Some(Vec::new());
+        if let Some(ida) = &mut self.ida {
+            ida.events = Some(Vec::new());
+        }
+        self.event_log = Some(event_log);
+    }
+
+    /// Write out events buffered by a searcher.
+    fn flush_events(&mut self, is_forward: bool, nodes_explored: usize) {
+        let Some(event_log) = &mut self.event_log else {
+            return;
+        };
+        let (direction, events) = if let Some(ida) = &mut self.ida {
+            ("forward", &mut ida.events)
+        } else if is_forward {
+            ("forward", &mut self.forward.events)
+        } else {
+            ("reverse", &mut self.reverse.events)
+        };
+        if let Some(events) = events {
+            for event in events.drain(..) {
+                event_log.write(nodes_explored, Some(direction), &event);
+            }
+        }
+    }
+
+    pub fn solve(&mut self) -> (SolveResult, SolverStats) {
+        if self.opts.threads > 1 {
+            return self.solve_parallel();
+        }
+
+        let mut nodes_explored = 0;
+        let mut evicted_nodes = 0;
+        let mut evicted_entries = 0;
+        let mut result;
+        let start = Instant::now();
+        // Cost and pushes of the best solution found so far by anytime search
+        let mut best: Option<(usize, Vec<Push>)> = None;
+        let mut forward_progress = DirectionProgress::new();
+        let mut reverse_progress = DirectionProgress::new();
+        let mut disabled_direction = None;
+        let mut forward_stats = DirectionStats::default();
+        let mut reverse_stats = DirectionStats::default();
+        if self.ida.is_none() {
+            self.forward.record_memory(&mut forward_stats);
+            self.reverse.record_memory(&mut reverse_stats);
+        }
+        let mut meeting_direction = None;
+
+        loop {
+            let is_forward = match self.opts.search_type {
+                _ if self.ida.is_some() => true,
+                _ if self.opts.optimal || self.opts.cost == CostType::Moves => true,
+                // The forward search carries on alone once the reverse search
+                // has been disabled, whatever the search type
+                _ if disabled_direction == Some(SearchType::Reverse) => true,
+                SearchType::Forward => true,
+                SearchType::Reverse => false,
+                // Perimeter search expands the reverse search first, then
+                // searches forwards towards the positions it reached
+                SearchType::Bidirectional if self.opts.perimeter.is_some() => self
+                    .opts
+                    .perimeter
+                    .is_some_and(|perimeter| nodes_explored >= perimeter),
+                // The disabled direction's table is still used to detect
+                // meetings with it
+                SearchType::Bidirectional if disabled_direction.is_some() => {
+                    disabled_direction == Some(SearchType::Reverse)
+                }
+                SearchType::Bidirectional => match self.opts.balance {
+                    BalanceType::Alternate => {
+                        let (forward, reverse) = self.opts.ratio;
+                        nodes_explored % (forward + reverse) < forward
+                    }
+                    BalanceType::Frontier => {
+                        let (forward, reverse) = self.opts.ratio;
+                        self.forward.open_list.len() * reverse
+                            <= self.reverse.open_list.len() * forward
+                    }
+                    // An exhausted direction is picked, proving unsolvability
+                    BalanceType::Cost => {
+                        self.forward.open_list.min_priority()
+                            <= self.reverse.open_list.min_priority()
+                    }
+                },
+            };
+
+            let expand_node = if let Some(ida) = &mut self.ida {
+                ida.expand_node()
+            } else if is_forward {
+                self.forward.expand_node(&self.reverse)
+            } else {
+                self.reverse.expand_node(&self.forward)
+            };
+            self.flush_events(is_forward, nodes_explored);
+
+            if self.ida.is_none() {
+                if is_forward {
+                    self.forward.record_memory(&mut forward_stats);
+                } else {
+                    self.reverse.record_memory(&mut reverse_stats);
+                }
+            }
+
+            match expand_node {
+                ExpandNode::NotDone => {
+                    nodes_explored += 1;
+                    if is_forward {
+                        forward_stats.nodes_explored += 1;
+                    } else {
+                        reverse_stats.nodes_explored += 1;
+                    }
+                    if nodes_explored >= self.opts.max_nodes_explored {
+                        result = SolveResult::Cutoff;
+                        break;
+                    }
+                    // Only check the clock periodically, as it is relatively
+                    // expensive compared to expanding a node
+                    if nodes_explored % TIME_CHECK_INTERVAL == 0
+                        && let Some(max_time) = self.opts.max_time
+                        && start.elapsed() >= max_time
+                    {
+                        result = SolveResult::TimedOut;
+                        break;
+                    }
+                    if nodes_explored % TIME_CHECK_INTERVAL == 0
+                        && let Some(max_memory) = self.opts.max_memory
+                    {
+                        let (nodes, entries) = self.enforce_memory_limit(max_memory);
+                        evicted_nodes += nodes;
+                        evicted_entries += entries;
+                    }
+                    if self.opts.adaptive {
+                        if is_forward {
+                            forward_progress.record(self.forward.best_cost);
+                        } else {
+                            reverse_progress.record(self.reverse.best_cost);
+                        }
+                        if nodes_explored % TIME_CHECK_INTERVAL == 0 && disabled_direction.is_none()
+                        {
+                            disabled_direction =
+                                self.stalled_direction(&forward_progress, &reverse_progress);
+                            if let Some(direction) = disabled_direction
+                                && let Some(event_log) = &mut self.event_log
+                            {
+                                let direction = match direction {
+                                    SearchType::Forward => "forward",
+                                    _ => "reverse",
+                                };
+                                event_log.write(
+                                    nodes_explored,
+                                    Some(direction),
+                                    &Event::DirectionDisabled,
+                                );
+                            }
+                        }
+                    }
+                    if nodes_explored % TIME_CHECK_INTERVAL == 0
+                        && let Some(interrupt) = &self.interrupt
+                        && interrupt.load(Ordering::Relaxed)
+                    {
+                        result = SolveResult::Interrupted;
+                        break;
+                    }
+                }
+                ExpandNode::Solved => {
+                    // Best-first nodes meeting the other direction are
+                    // expanded too, in case the meeting is rejected below
+                    if self.ida.is_none() {
+                        nodes_explored += 1;
+                        if is_forward {
+                            forward_stats.nodes_explored += 1;
+                        } else {
+                            reverse_stats.nodes_explored += 1;
+                        }
+                    }
+                    let soln =
+                        if let Some(ida) = &self.ida {
+                            ida.solution()
+                        } else {
+                            let symmetry = if is_forward {
+                                self.forward.meeting_symmetry
+                            } else {
+                                self.reverse.meeting_symmetry
+                            };
+                            match (is_forward, symmetry) {
+                                (true, None) => {
+                                    self.reverse.game.restore(&self.forward.game.checkpoint())
+                                }
+                                (false, None) => {
+                                    self.forward.game.restore(&self.reverse.game.checkpoint())
+                                }
+                                (true, Some(symmetry)) => symmetry
+                                    .apply_to_game(&self.forward.game, &mut self.reverse.game),
+                                (false, Some(symmetry)) => symmetry
+                                    .apply_to_game(&self.reverse.game, &mut self.forward.game),
+                            }
+
+                            // Map the reverse half onto the positions the forward
+                            // half leads to
+                            let reverse_symmetry = match symmetry {
+                                Some(symmetry) if is_forward => Some(symmetry.inverse()),
+                                symmetry => symmetry,
+                            };
+                            self.reconstruct_solution(reverse_symmetry)
+                        };
+                    let cost = match self.opts.cost {
+                        CostType::Pushes => soln.len(),
+                        CostType::Moves => to_lurd(&self.game, &soln).len(),
+                    };
+                    if self
+                        .opts
+                        .max_depth
+                        .is_some_and(|max_depth| cost > max_depth)
+                    {
+                        // Both directions were within the depth limit, but
+                        // not together, so keep searching
+                    } else if self.opts.anytime {
+                        if self.improve_solution(soln, cost, nodes_explored, &mut best) {
+                            meeting_direction = Some(direction_of(is_forward));
+                        }
+                    } else {
+                        meeting_direction = Some(direction_of(is_forward));
+                        result = SolveResult::Solved(soln);
+                        break;
+                    }
+                }
+                // Pull corral pruning may discard every way back to the
+                // start, so an exhausted reverse search proves nothing once
+                // it has pruned (which only bidirectional search does, and
+                // only with `pull_corral_pruning`)
+                ExpandNode::Unsolvable if !is_forward && self.reverse.pruning.corral_pruned > 0 => {
+                    disabled_direction = Some(SearchType::Reverse);
+                    if let Some(event_log) = &mut self.event_log {
+                        event_log.write(nodes_explored, Some("reverse"), &Event::DirectionDisabled);
+                    }
+                }
+                ExpandNode::Unsolvable => {
+                    result = SolveResult::Unsolvable;
+                    break;
+                }
+            }
+
+            if self.opts.trace_range.contains(&nodes_explored) {
+                let (dir, game) = if let Some(ida) = &self.ida {
+                    ("forward", &ida.game)
+                } else if is_forward {
+                    ("forward", &self.forward.game)
+                } else {
+                    ("reverse", &self.reverse.game)
+                };
+                crate::trace!("direction={} count={}:\n{}", dir, nodes_explored, game);
+            }
+        }
+
+        // Anytime search ends when its limits are hit or the open list is
+        // exhausted, returning the best solution found until then
+        if let Some((_, soln)) = best {
+            result = SolveResult::Solved(soln);
+        }
+
+        forward_stats.heuristic_entries = self.forward.heuristic.len();
+        reverse_stats.heuristic_entries = self.reverse.heuristic.len();
+        if let Some(ida) = &self.ida {
+            forward_stats.heuristic_entries = ida.heuristic.len();
+            forward_stats.peak_memory = ida.table.as_ref().map_or(0, BoundedTable::memory_usage);
+        }
+
+        if let Some(event_log) = &mut self.event_log {
+            for (direction, stats) in [("forward", &forward_stats), ("reverse", &reverse_stats)] {
+                if stats.nodes_explored > 0 {
+                    event_log.write(nodes_explored, Some(direction), &Event::memory(stats));
+                }
+            }
+            let event = match &result {
+                SolveResult::Solved(soln) => Event::Solved { length: soln.len() },
+                SolveResult::Cutoff => Event::Cutoff,
+                SolveResult::TimedOut => Event::TimedOut,
+                SolveResult::Interrupted => Event::Interrupted,
+                SolveResult::Unsolvable => Event::Unsolvable,
+            };
+            event_log.write(nodes_explored, None, &event);
+            event_log.flush();
+        }
+
+        let mut stats = SolverStats {
+            nodes_explored,
+            unique_box_configs: self
+                .forward
+                .box_configs
+                .union(&self.reverse.box_configs)
+                .count(),
+            pruning: self.forward.pruning.add(&self.reverse.pruning),
+            best_cost: self.forward.best_cost.min(self.reverse.best_cost),
+            evicted_nodes,
+            evicted_entries,
+            hash_collisions: self.forward.hash_collisions + self.reverse.hash_collisions,
+            disabled_direction,
+            forward: forward_stats,
+            reverse: reverse_stats,
+            meeting_direction,
+        };
+        if let Some(ida) = &self.ida {
+            stats.pruning = ida.pruning;
+            stats.best_cost = ida.best_cost;
+        }
+        (result, stats)
+    }
+
+    /// Search forwards on several threads, starting from the forward
+    /// searcher's initial nodes.
+    fn solve_parallel(&mut self) -> (SolveResult, SolverStats) {
+        assert!(
+            self.ida.is_none()
+                && matches!(self.forward.open_list, OpenList::Single(_))
+                && !self.forward.optimal
+                && !self.forward.reopen
+                && self.forward.max_cost.is_none()
+                && !self.opts.anytime,
+            "parallel search only supports greedy best-first search"
+        );
+        let initial = self.forward.drain_open_list();
+        let table = std::mem::take(&mut self.forward.table);
+        let (result, stats, tables) = parallel::search::<H>(
+            &self.game,
+            &self.opts,
+            &self.forward.heuristic_params,
+            initial,
+            table,
+            self.forward.best_cost,
+            &self.forward.symmetries,
+            &self.forward.helper.corral_searcher.tables(),
+            self.interrupt.as_deref(),
+        );
+        self.forward.helper.corral_searcher.load_tables(&tables);
+        (result, stats)
+    }
+
+    /// The direction of bidirectional search to stop expanding, if one has
+    /// stalled while its open list outgrew the other's.
+    fn stalled_direction(
+        &self,
+        forward_progress: &DirectionProgress,
+        reverse_progress: &DirectionProgress,
+    ) -> Option<SearchType> {
+        let bidirectional = self.opts.search_type == SearchType::Bidirectional
+            && self.opts.perimeter.is_none()
+            && self.ida.is_none()
+            && !self.opts.optimal
+            && self.opts.cost == CostType::Pushes;
+        if !bidirectional {
+            return None;
+        }
+        // A stalled direction must also be further from the other side than
+        // the other direction got, so that its progress isn't thrown away
+        let forward_len = self.forward.open_list.len();
+        let reverse_len = self.reverse.open_list.len();
+        if forward_progress.is_stalled()
+            && forward_progress.best_cost > reverse_progress.best_cost
+            && forward_len >= reverse_len * STALL_GROWTH
+        {
+            Some(SearchType::Forward)
+        } else if reverse_progress.is_stalled()
+            && reverse_progress.best_cost > forward_progress.best_cost
+            && reverse_len >= forward_len * STALL_GROWTH
+        {
+            Some(SearchType::Reverse)
+        } else {
+            None
+        }
+    }
+
+    /// Evict open nodes, then closed table entries, once the memory limit is
+    /// exceeded, returning the numbers of nodes and entries evicted.
+    fn enforce_memory_limit(&mut self, max_memory: usize) -> (usize, usize) {
+        let usage = self.forward.memory_usage() + self.reverse.memory_usage();
+        if usage <= max_memory {
+            return (0, 0);
+        }
+        // Evict a quarter of each searcher's memory, so that eviction isn't
+        // needed again immediately
+        let forward_target = self.forward.memory_usage() / 4 * 3;
+        let reverse_target = self.reverse.memory_usage() / 4 * 3;
+        let nodes =
+            self.forward.evict_nodes(forward_target) + self.reverse.evict_nodes(reverse_target);
+        let entries = self.forward.evict_closed_entries(forward_target)
+            + self.reverse.evict_closed_entries(reverse_target);
+        (nodes, entries)
+    }
+
+    /// Record a solution found by anytime search if it is cheaper than the
+    /// best so far, tightening the bound used to prune nodes. Returns whether
+    /// it was cheaper.
+    fn improve_solution(
+        &mut self,
+        soln: Vec<Push>,
+        cost: usize,
+        nodes_explored: usize,
+        best: &mut Option<(usize, Vec<Push>)>,
+    ) -> bool {
+        if best
+            .as_ref()
+            .is_some_and(|(best_cost, _)| cost >= *best_cost)
+        {
+            return false;
+        }
+
+        crate::verbose!(
+            "  found solution: steps: {:<5}  cost: {:<5}  states: {}",
+            soln.len(),
+            cost,
+            nodes_explored
+        );
+        if let Some(event_log) = &mut self.event_log {
+            let event = Event::ImprovedSolution {
+                length: soln.len(),
+                cost,
+            };
+            event_log.write(nodes_explored, None, &event);
+        }
+
+        self.set_max_cost(cost.saturating_sub(1));
+        *best = Some((cost, soln));
+        true
+    }
+
+    /// Reconstruct the solution through the positions the two directions
+    /// met at, mapping the reverse half by the given symmetry if they met at
+    /// mirror images or rotations of each other.
+    fn reconstruct_solution(&self, reverse_symmetry: Option<Symmetry>) -> Vec<Push> {
+        let forward_soln = self.forward.reconstruct_solution();
+        // Solved positions of unbalanced or labelled games aren't
+        // necessarily met in the reverse table, so the forward half is the
+        // whole solution
+        let mut reverse_soln = if self.game.has_unique_solved_boxes() {
+            self.reverse.reconstruct_solution()
+        } else {
+            Vec::new()
+        };
+        if let Some(symmetry) = reverse_symmetry {
+            for push in &mut reverse_soln {
+                *push = symmetry.apply_push(*push);
+            }
+        }
+        combine_solution(&self.game, &forward_soln, &reverse_soln)
+    }
+}
+
+/// Join the forward half of a solution (in reverse order, as reconstructed)
+/// with the reverse half, verifying each push against the game.
+pub(crate) fn combine_solution(
+    game: &Game,
+    forward_soln: &[PushByPos],
+    reverse_soln: &[PushByPos],
+) -> Vec<Push> {
+    let mut game = game.clone();
+    let mut soln = Vec::new();
+    let chained = forward_soln.iter().rev().chain(reverse_soln.iter());
+
+    for (i, push_by_pos) in chained.enumerate() {
+        // The forward search may have started from a different region than
+        // the game's player position
+        if i == 0 {
+            game.set_player(
+                game.move_position(push_by_pos.box_pos, push_by_pos.direction.reverse())
+                    .expect("Push player position out of bounds"),
+            );
+        }
+
+        // Get box index at this position
+        let box_index = game.box_index(push_by_pos.box_pos).unwrap_or_else(|| {
+            panic!(
+                "Solution verification failed: no box at position {} for push {}",
+                push_by_pos.box_pos,
+                i + 1
+            )
+        });
+
+        // Compute valid pushes at this state
+        let valid_pushes = game.compute_pushes().moves;
+
+        // Verify that this push is among the valid pushes
+        let push = Push::new(box_index, push_by_pos.direction);
+        assert!(
+            valid_pushes.contains(push),
+            "Solution verification failed: push {} (box at {}, direction {:?}) is not valid",
+            i + 1,
+            push_by_pos.box_pos,
+            push_by_pos.direction
+        );
+
+        // Apply the push
+        game.push(push);
+        soln.push(push);
+    }
+
+    // Verify final state is solved
+    assert!(
+        game.is_solved(),
+        "Solution verification failed: puzzle is not solved"
+    );
+
+    soln
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heuristic::{HeuristicKind, HungarianHeuristic, MaxListHeuristic, SimpleHeuristic};
+    use crate::solution::{Solution, to_lurd};
+
+    use super::*;
+
+    #[test]
+    fn test_solve_simple() {
+        let game = parse_game(
+            r#"
+#####
+#@$.#
+#####
+"#,
+        );
+        let mut solver = new_solver(game.clone());
+        let result = solver.solve();
+
+        if let (SolveResult::Solved(soln), _) = result {
+            assert_eq!(soln.len(), 1);
+
+            // Verify solution works
+            let mut test_game = game.clone();
+            for push in soln {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn test_solve_already_solved() {
+        let game = parse_game(
+            r#"
+####
+#@*#
+####
+"#,
+        );
+        let mut solver = new_solver(game);
+        let result = solver.solve();
+
+        if let (SolveResult::Solved(moves), _) = result {
+            assert_eq!(moves.len(), 0);
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn test_solve_two_moves() {
+        let game = parse_game(
+            r#"
+######
+#@$ .#
+######
+"#,
+        );
+        let mut solver = new_solver(game.clone());
+        let result = solver.solve();
+
+        if let (SolveResult::Solved(soln), _) = result {
+            assert_eq!(soln.len(), 2);
+
+            // Verify solution works
+            let mut test_game = game.clone();
+            for push in soln {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn test_solve_impossible() {
+        let game = parse_game(
+            r#"
+#######
+#@$ #.#
+#######
+"#,
+        );
+        let mut solver = new_solver(game);
+        let result = solver.solve();
+        assert_eq!(result.0, SolveResult::Unsolvable);
+    }
+
+    #[test]
+    fn test_solve_frozen_at_start() {
+        // The 2x2 block of boxes can never move, which the Hungarian heuristic
+        // would otherwise reject on creation
+        let game = parse_game(
+            r#"
+######
+#    #
+# $$ #
+# $$ #
+# ...#
+#  .@#
+######
+"#,
+        );
+        for search_type in [SearchType::Forward, SearchType::Reverse] {
+            let opts = SolverOpts {
+                search_type,
+                ..new_opts()
+            };
+            let (result, _) = Solver::<HungarianHeuristic>::new(&game, opts).solve();
+            assert_eq!(result, SolveResult::Unsolvable);
+        }
+        let opts = SolverOpts {
+            algorithm: AlgorithmType::IdaStar,
+            ..new_opts()
+        };
+        let (result, _) = Solver::<HungarianHeuristic>::new(&game, opts).solve();
+        assert_eq!(result, SolveResult::Unsolvable);
+    }
+
+    #[test]
+    fn test_matching_deadlocks() {
+        // Both boxes against the top wall can only reach the top left goal,
+        // which the simple heuristic doesn't notice
+        let game = parse_game(
+            r#"
+#########
+#.$   $ #
+#       #
+#@  $  .#
+#      .#
+#########
+"#,
+        );
+        let solve = |matching_deadlocks| {
+            Solver::<SimpleHeuristic>::new(
+                &game,
+                SolverOpts {
+                    matching_deadlocks,
+                    ..new_opts()
+                },
+            )
+            .solve()
+        };
+
+        let (result, stats) = solve(true);
+        assert_eq!(result, SolveResult::Unsolvable);
+        assert_eq!(stats.nodes_explored, 0);
+
+        let (result, stats) = solve(false);
+        assert_eq!(result, SolveResult::Unsolvable);
+        assert!(stats.nodes_explored > 0);
+    }
+
+    #[test]
+    fn test_deadlock_patterns() {
+        let game = parse_game(
+            r#"
+#######
+#  .. #
+# $$  #
+#  @  #
+#######
+"#,
+        );
+        let solve = |deadlock_patterns| {
+            Solver::<SimpleHeuristic>::new(
+                &game,
+                SolverOpts {
+                    deadlock_patterns,
+                    optimal: true,
+                    ..new_opts()
+                },
+            )
+            .solve()
+        };
+
+        // The patterns only catch freeze deadlocks earlier
+        let (result, stats) = solve(true);
+        let SolveResult::Solved(pushes) = result else {
+            panic!("expected a solution");
+        };
+        assert!(stats.pruning.pattern_pruned > 0);
+
+        let (result, stats) = solve(false);
+        assert_eq!(result, SolveResult::Solved(pushes));
+        assert_eq!(stats.pruning.pattern_pruned, 0);
+        assert!(stats.pruning.freeze_pruned > 0);
+    }
+
+    #[test]
+    fn test_conflict_deadlocks() {
+        let game = parse_game(
+            r#"
+####
+# .#
+#  ###
+#*@  #
+#  $ #
+#  ###
+####
+"#,
+        );
+        let solve = |conflict_deadlocks| {
+            Solver::<SimpleHeuristic>::new(
+                &game,
+                SolverOpts {
+                    conflict_deadlocks,
+                    optimal: true,
+                    ..new_opts()
+                },
+            )
+            .solve()
+        };
+
+        let (result, stats) = solve(true);
+        let SolveResult::Solved(pushes) = result else {
+            panic!("expected a solution");
+        };
+        assert!(stats.pruning.conflict_pruned > 0);
+
+        let (result, stats) = solve(false);
+        assert_eq!(result, SolveResult::Solved(pushes));
+        assert_eq!(stats.pruning.conflict_pruned, 0);
+    }
+
+    #[test]
+    fn test_unique_box_configs() {
+        let game = parse_game(
+            r#"
+#######
+#     #
+# $@ .#
+#     #
+#######
+"#,
+        );
+        let mut solver = new_solver(game.clone());
+        let (result, stats) = solver.solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        assert!(stats.unique_box_configs > 0);
+        assert!(stats.unique_box_configs <= stats.nodes_explored);
+
+        // Box configurations expanded in both directions are counted once
+        let opts = SolverOpts {
+            search_type: SearchType::Bidirectional,
+            ..new_opts()
+        };
+        let mut solver = Solver::<SimpleHeuristic>::new(&game, opts);
+        let (result, stats) = solver.solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        let forward = &solver.forward.box_configs;
+        let reverse = &solver.reverse.box_configs;
+        assert!(forward.iter().any(|hash| reverse.contains(hash)));
+        assert_eq!(
+            stats.unique_box_configs,
+            forward.len() + reverse.len() - forward.intersection(reverse).count()
+        );
+    }
+
+    #[test]
+    fn test_surplus_goals() {
+        // The near goals are filled, leaving the far ones empty
+        let game = Game::from_text_unbalanced(
+            "########\n\
+             #.  $ .#\n\
+             #  @$ .#\n\
+             #.     #\n\
+             ########",
+        )
+        .unwrap();
+        for search_type in [SearchType::Bidirectional, SearchType::Reverse] {
+            for optimal in [false, true] {
+                let opts = SolverOpts {
+                    search_type,
+                    optimal,
+                    ..new_opts()
+                };
+                let (result, stats) = Solver::<HungarianHeuristic>::new(&game, opts).solve();
+                let SolveResult::Solved(soln) = result else {
+                    panic!("expected a solution, got {:?}", result);
+                };
+                assert_eq!(soln.len(), 4);
+                assert_eq!(stats.reverse.nodes_explored, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_labels() {
+        // Each box has to cross over to the far goal
+        let mut game = Game::from_text(
+            "#########\n\
+             #       #\n\
+             #       #\n\
+             # .$@$. #\n\
+             #       #\n\
+             #       #\n\
+             #########",
+        )
+        .unwrap();
+        game.set_labels(&[1, 2], &[2, 1]).unwrap();
+        for search_type in [SearchType::Bidirectional, SearchType::Reverse] {
+            let opts = SolverOpts {
+                search_type,
+                optimal: true,
+                ..new_opts()
+            };
+            let (result, stats) = Solver::<HungarianHeuristic>::new(&game, opts).solve();
+            let SolveResult::Solved(soln) = result else {
+                panic!("expected a solution, got {:?}", result);
+            };
+            assert_eq!(soln.len(), 8);
+            assert_eq!(stats.reverse.nodes_explored, 0);
+            let mut test_game = game.clone();
+            for push in soln {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+        }
+    }
+
+    #[test]
+    fn test_labels_learned_deadlocks() {
+        // A corral deadlock learned for the boxes under one labelling doesn't
+        // hold for the same squares with the labels swapped
+        let mut game = Game::from_text(
+            "#######\n\
+             # .   #\n\
+             #   $ #\n\
+             #  @###\n\
+             ### $ #\n\
+             #.    #\n\
+             #######",
+        )
+        .unwrap();
+        game.set_labels(&[1, 2], &[2, 1]).unwrap();
+        for optimal in [false, true] {
+            let opts = SolverOpts {
+                optimal,
+                ..new_opts()
+            };
+            let (result, _) = Solver::<HungarianHeuristic>::new(&game, opts).solve();
+            let SolveResult::Solved(soln) = result else {
+                panic!("expected a solution, got {:?}", result);
+            };
+            let mut test_game = game.clone();
+            for push in &soln {
+                test_game.push(*push);
+            }
+            assert!(test_game.is_solved());
+            if optimal {
+                assert_eq!(soln.len(), 13);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reverse_seeds_restricted_to_forward_region() {
+        let game = parse_game(
+            r#"
+##########
+#@$ .#*  #
+##########
+"#,
+        );
+        let mut solver = new_solver(game);
+
+        // Only the region containing the player should be seeded
+        assert_eq!(solver.reverse.table.len(), 1);
+
+        let result = solver.solve();
+        assert!(matches!(result.0, SolveResult::Solved(_)));
+    }
+
+    #[test]
+    fn test_reverse_only_keeps_pulls() {
+        let game = parse_game(
+            r#"
+  ####
+###  ####
+#     $ #
+# #  #$ #
+# . .#@ #
+#########
+"#,
+        );
+
+        // Pull corral pruning cuts every way back to the start here, so a
+        // search in reverse alone keeps every pull rather than handing over
+        // to the forward search, even when asked to prune
+        let opts = SolverOpts {
+            search_type: SearchType::Reverse,
+            pull_corral_pruning: true,
+            ..new_opts()
+        };
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        let SolveResult::Solved(soln) = result else {
+            panic!("expected a solution, got {:?}", result);
+        };
+        let mut test_game = game.clone();
+        for push in soln {
+            test_game.push(push);
+        }
+        assert!(test_game.is_solved());
+        assert_eq!(stats.disabled_direction, None);
+        assert_eq!(stats.meeting_direction, Some(SearchType::Reverse));
+        assert_eq!(stats.forward.nodes_explored, 0);
+
+        // Bidirectional search keeps every pull too unless asked to prune, so
+        // its reverse direction isn't cut off from the start and disabled
+        let opts = SolverOpts {
+            search_type: SearchType::Bidirectional,
+            ..new_opts()
+        };
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        assert_eq!(stats.disabled_direction, None);
+        let opts = SolverOpts {
+            search_type: SearchType::Bidirectional,
+            pull_corral_pruning: true,
+            ..new_opts()
+        };
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        assert_eq!(stats.disabled_direction, Some(SearchType::Reverse));
+    }
+
+    #[test]
+    fn test_meeting_hash_collision() {
+        let game = parse_game(
+            r#"
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
+"#,
+        );
+        let opts = SolverOpts {
+            search_type: SearchType::Bidirectional,
+            ..new_opts()
+        };
+        let mut solver = Solver::<SimpleHeuristic>::new(&game, opts);
+
+        // Plant a different position with the same hash as the initial
+        // position in the reverse table, so the first expansion appears to
+        // meet the other side
+        let hash = solver.forward.zobrist.compute_hash(&game);
+        solver.reverse.table.insert(
+            hash,
+            TableEntry {
+                parent_hash: 1,
+                move_: None,
+                check: 0,
+                is_closed: true,
+                g: 0,
+                generation: 0,
+            },
+        );
+
+        let (result, _) = solver.solve();
+        let SolveResult::Solved(soln) = result else {
+            panic!("unexpected result {:?}", result);
+        };
+        let mut test_game = game.clone();
+        for push in soln {
+            test_game.push(push);
+        }
+        assert!(test_game.is_solved());
+    }
+
+    #[test]
+    fn test_paranoid_hash() {
+        let game = parse_game(
+            r#"
+########
+#@$   .#
+########
+"#,
+        );
+        let solve = |paranoid_hash, hash_diagnostics| {
+            let opts = SolverOpts {
+                search_type: SearchType::Forward,
+                paranoid_hash,
+                hash_diagnostics,
+                ..new_opts()
+            };
+            let mut solver = Solver::<SimpleHeuristic>::new(&game, opts);
+
+            // Plant a different position with the same hash as the position
+            // after the only first push
+            let mut child = game.clone();
+            child.push(game.compute_pushes().moves.iter().next().unwrap());
+            solver.forward.table.insert(
+                state_hash(&solver.forward.zobrist, &child, true),
+                TableEntry {
+                    parent_hash: 1,
+                    move_: None,
+                    check: 0,
+                    is_closed: true,
+                    g: 0,
+                    generation: 0,
+                },
+            );
+            solver.solve()
+        };
+
+        // Trusting the hash alone, the position is taken to be searched
+        // already, which diagnostics notice
+        let (result, stats) = solve(false, false);
+        assert_eq!(result, SolveResult::Unsolvable);
+        assert_eq!(stats.hash_collisions, 0);
+        let (result, stats) = solve(false, true);
+        assert_eq!(result, SolveResult::Unsolvable);
+        assert_eq!(stats.hash_collisions, 1);
+
+        // Otherwise, it gets a key of its own
+        let (result, stats) = solve(true, false);
+        let SolveResult::Solved(soln) = result else {
+            panic!("unexpected result {:?}", result);
+        };
+        assert_eq!(soln.len(), 4);
+        assert_eq!(stats.hash_collisions, 1);
+    }
+
+    #[test]
+    fn test_stale_nodes() {
+        let game = parse_game(
+            r#"
+########
+#@$   .#
+########
+"#,
+        );
+        let opts = SolverOpts {
+            search_type: SearchType::Forward,
+            ..new_opts()
+        };
+        let mut solver = Solver::<SimpleHeuristic>::new(&game, opts);
+
+        // Push the initial position again, leaving a stale copy in the open
+        // list which is skipped without expanding it
+        let key = state_hash(&solver.forward.zobrist, &game, false);
+        solver.forward.table.get_mut(&key).unwrap().generation = 1;
+        assert!(matches!(
+            solver.forward.expand_node(&solver.reverse),
+            ExpandNode::NotDone
+        ));
+        assert!(!solver.forward.table[&key].is_closed);
+        assert_eq!(solver.forward.table.len(), 1);
+        assert_eq!(solver.forward.open_list.len(), 0);
+    }
+
+    #[test]
+    fn test_duplicates_pruned() {
+        let game = parse_game(
+            r#"
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
+"#,
+        );
+        for algorithm in [AlgorithmType::BestFirst, AlgorithmType::IdaStar] {
+            let opts = SolverOpts {
+                search_type: SearchType::Forward,
+                optimal: true,
+                algorithm,
+                ..new_opts()
+            };
+            let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+            let SolveResult::Solved(soln) = result else {
+                panic!("unexpected result {:?}", result);
+            };
+            let mut test_game = game.clone();
+            for push in soln {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+
+            // Pushing boxes in another order leads to positions already seen
+            assert!(stats.pruning.duplicates_pruned > 0);
+        }
+    }
+
+    #[test]
+    fn test_zobrist_seed() {
+        let game = parse_game(
+            r#"
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
+"#,
+        );
+        let solve = |zobrist_seed| {
+            let opts = SolverOpts {
+                search_type: SearchType::Bidirectional,
+                optimal: true,
+                zobrist_seed,
+                ..new_opts()
+            };
+            match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+                (SolveResult::Solved(soln), _) => soln,
+                (result, _) => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        // Other hashes find a solution of the same length
+        let soln = solve(None);
+        for seed in [0, 1, 12345] {
+            let other = solve(Some(seed));
+            assert_eq!(other.len(), soln.len());
+            let mut test_game = game.clone();
+            for push in other {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+        }
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let game = parse_game(
+            r#"
+#######
+#.   .#
+# $ $ #
+#  @  #
+# $ $ #
+#.   .#
+#######
+"#,
+        );
+        let solve = |search_type, symmetry| {
+            let opts = SolverOpts {
+                search_type,
+                optimal: true,
+                symmetry,
+                ..new_opts()
+            };
+            match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+                (SolveResult::Solved(soln), stats) => (soln, stats),
+                (result, _) => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        for search_type in [
+            SearchType::Forward,
+            SearchType::Reverse,
+            SearchType::Bidirectional,
+        ] {
+            // Skipping mirror images finds a solution just as short, with
+            // fewer nodes
+            let (soln, _) = solve(search_type, false);
+            let (symmetric_soln, stats) = solve(search_type, true);
+            assert_eq!(symmetric_soln.len(), soln.len());
+            assert!(stats.pruning.symmetry_pruned > 0);
+            let mut test_game = game.clone();
+            for push in symmetric_soln {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+        }
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let game = parse_game(
+            r#"
+#######
+# @#  #
+#.$   #
+#. # $##
+#.$#   #
+#. # $ #
+#  #   #
+########
+"#,
+        );
+        let interrupt = Arc::new(AtomicBool::new(true));
+        let mut solver = new_solver(game);
+        solver.set_interrupt(interrupt);
+        let (result, stats) = solver.solve();
+        assert_eq!(result, SolveResult::Interrupted);
+        assert_eq!(stats.nodes_explored, TIME_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn test_optimal() {
+        let game = parse_game(
+            r#"
+#######
+# @#  #
+#.$   #
+#. # $##
+#.$#   #
+#. # $ #
+#  #   #
+########
+"#,
+        );
+        let solve = |opts: SolverOpts| match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), _) => soln.len(),
+            (result, _) => panic!("unexpected result {:?}", result),
+        };
+
+        let greedy = solve(new_opts());
+        let optimal = solve(SolverOpts {
+            optimal: true,
+            ..new_opts()
+        });
+        // Uniform-cost search over all positions is trivially optimal
+        let uniform = solve(SolverOpts {
+            optimal: true,
+            pi_corrals: false,
+            freeze_deadlocks: false,
+            dead_squares: false,
+            ..new_opts()
+        });
+        assert_eq!(optimal, uniform);
+        assert!(optimal <= greedy);
+
+        // Tie-breaking by player distance doesn't affect optimality
+        let tie_break = solve(SolverOpts {
+            optimal: true,
+            player_tie_break: true,
+            ..new_opts()
+        });
+        assert_eq!(tie_break, optimal);
+        solve(SolverOpts {
+            player_tie_break: true,
+            ..new_opts()
+        });
+
+        // Nor does any order of positions of equal priority
+        for order in [TieBreak::Lifo, TieBreak::LowestCost, TieBreak::MostOnGoals] {
+            let ordered = solve(SolverOpts {
+                optimal: true,
+                tie_break: order,
+                ..new_opts()
+            });
+            assert_eq!(ordered, optimal);
+            solve(SolverOpts {
+                tie_break: order,
+                ..new_opts()
+            });
+        }
+    }
+
+    #[test]
+    fn test_weighted() {
+        let game = parse_game(
+            r#"
+#######
+# @#  #
+#.$   #
+#. # $##
+#.$#   #
+#. # $ #
+#  #   #
+########
+"#,
+        );
+        let solve = |weight| {
+            let opts = SolverOpts {
+                optimal: true,
+                weight,
+                ..new_opts()
+            };
+            match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+                (SolveResult::Solved(soln), _) => soln.len(),
+                (result, _) => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        // Weighted solutions are at most `weight` times longer than optimal
+        let optimal = solve(1.0);
+        let weighted = solve(3.0);
+        assert!(weighted >= optimal);
+        assert!(weighted <= 3 * optimal);
+
+        // Huge weights saturate rather than overflowing f
+        let game = parse_game("#######\n#@ $ .#\n#######");
+        for algorithm in [AlgorithmType::BestFirst, AlgorithmType::IdaStar] {
+            let opts = SolverOpts {
+                optimal: true,
+                weight: 1e300,
+                algorithm,
+                ..new_opts()
+            };
+            let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+            assert!(matches!(result, SolveResult::Solved(_)));
+        }
+    }
+
+    #[test]
+    fn test_anytime() {
+        let game = parse_game(
+            r#"
+#######
+#     #
+# .$. #
+# $.$ #
+# .$. #
+# $.$ #
+#  @  #
+#######
+"#,
+        );
+        let solve = |opts: SolverOpts| match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), _) => soln.len(),
+            (result, _) => panic!("unexpected result {:?}", result),
+        };
+
+        let greedy = solve(new_opts());
+        let anytime = solve(SolverOpts {
+            anytime: true,
+            ..new_opts()
+        });
+        let optimal = solve(SolverOpts {
+            optimal: true,
+            ..new_opts()
+        });
+        assert_eq!(greedy, 12);
+        // Searching until the open list is exhausted finds an optimal solution
+        assert_eq!(anytime, optimal);
+
+        // Microban level 26, whose optimal solution of 10 pushes is only found
+        // by searching on below the positions the two directions first met at
+        let game = parse_game(
+            r#"
+ #####
+ # @ #
+ #   #
+###$ #
+# ...#
+# $$ #
+###  #
+  ####
+"#,
+        );
+        let opts = SolverOpts {
+            search_type: SearchType::Bidirectional,
+            anytime: true,
+            ..new_opts()
+        };
+        match Solver::<HungarianHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), _) => assert_eq!(soln.len(), 10),
+            (result, _) => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_reopen() {
+        let game = parse_game(
+            r#"
+#######
+#     ###
+#  @$$..#
+#### ## #
+  #     #
+  #  ####
+  #  #
+  ####
+"#,
+        );
+        let solve = |opts: SolverOpts| match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), _) => soln.len(),
+            (result, _) => panic!("unexpected result {:?}", result),
+        };
+
+        // Greedy search finds a shorter solution when positions reached by
+        // shorter paths are reopened
+        let greedy = solve(new_opts());
+        let reopen = solve(SolverOpts {
+            reopen: true,
+            ..new_opts()
+        });
+        assert_eq!(greedy, 18);
+        assert_eq!(reopen, 16);
+    }
+
+    #[test]
+    fn test_penalties() {
+        let game = parse_game(
+            r#"
+#######
+# @#  #
+#.$   #
+#. # $##
+#.$#   #
+#. # $ #
+#  #   #
+########
+"#,
+        );
+        let solve = |opts: SolverOpts| match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), stats) => {
+                let mut test_game = game.clone();
+                for push in soln {
+                    test_game.push(push);
+                }
+                assert!(test_game.is_solved());
+                stats.nodes_explored
+            }
+            (result, _) => panic!("unexpected result {:?}", result),
+        };
+
+        let plain = solve(SolverOpts {
+            search_type: SearchType::Forward,
+            ..new_opts()
+        });
+        let penalties = solve(SolverOpts {
+            search_type: SearchType::Forward,
+            penalties: true,
+            ..new_opts()
+        });
+        // Learned penalties steer the search away from congested positions
+        assert!(penalties < plain);
 
-        let forward_helper = ForwardSearchHelper {
-            corral_searcher: CorralSearcher::new(zobrist.clone(), opts.deadlock_max_nodes),
-            dead_squares: opts.dead_squares,
-            pi_corrals: opts.pi_corrals,
-            freeze_deadlocks: opts.freeze_deadlocks,
-        };
-        let reverse_helper = ReverseSearchHelper {
-            dead_squares: opts.dead_squares,
+        // They only raise the heuristic to a lower bound on the pushes
+        // needed, so optimal solutions stay optimal
+        let optimal = |penalties| {
+            let opts = SolverOpts {
+                search_type: SearchType::Forward,
+                optimal: true,
+                penalties,
+                ..new_opts()
+            };
+            match Solver::<HungarianHeuristic>::new(&game, opts).solve() {
+                (SolveResult::Solved(soln), _) => soln.len(),
+                (result, _) => panic!("unexpected result {:?}", result),
+            }
         };
+        assert_eq!(optimal(true), optimal(false));
+    }
 
-        let forward_searcher = Searcher::new(
-            game,
-            zobrist.clone(),
-            &forward_player_positions,
-            forward_helper,
-        );
-        let reverse_searcher = Searcher::new(
-            &reverse_game,
-            zobrist,
-            &reverse_player_positions,
-            reverse_helper,
-        );
-
-        Self {
-            forward: forward_searcher,
-            reverse: reverse_searcher,
-            game: game.clone(),
-            opts,
+    #[test]
+    fn test_heuristic_cache() {
+        let mut cache = FrozenCache::new();
+        let mut built = 0;
+        for hash in 0..MAX_FROZEN_SETS as u64 {
+            cache.get_or_insert_with(hash, || {
+                built += 1;
+                hash
+            });
         }
+        assert_eq!(built, MAX_FROZEN_SETS);
+
+        // Using the first entry makes the second the least recently used
+        assert_eq!(*cache.get_or_insert_with(0, || unreachable!()), 0);
+        cache.get_or_insert_with(u64::MAX, || u64::MAX);
+        assert_eq!(cache.entries.len(), MAX_FROZEN_SETS);
+        assert_eq!(*cache.get_or_insert_with(0, || unreachable!()), 0);
+        assert_eq!(*cache.get_or_insert_with(1, || 100), 100);
     }
 
-    pub fn solve(&mut self) -> (SolveResult, usize) {
-        let mut nodes_explored = 0;
-        let result;
+    #[test]
+    fn test_direction_progress() {
+        let mut progress = DirectionProgress::new();
+        progress.record(10);
+        for _ in 1..STALL_EXPANSIONS {
+            progress.record(10);
+        }
+        assert!(!progress.is_stalled());
+        progress.record(10);
+        assert!(progress.is_stalled());
+        // Getting closer to the other side resets the count
+        progress.record(9);
+        assert!(!progress.is_stalled());
+    }
 
-        loop {
-            let is_forward = match self.opts.search_type {
-                SearchType::Forward => true,
-                SearchType::Reverse => false,
-                // TODO: try being greedy between the two sides
-                SearchType::Bidirectional => nodes_explored % 2 == 0,
+    #[test]
+    fn test_max_depth() {
+        let game = parse_game(
+            r#"
+#######
+#     #
+# .$. #
+# $.$ #
+# .$. #
+# $.$ #
+#  @  #
+#######
+"#,
+        );
+        let solve = |max_depth| {
+            let opts = SolverOpts {
+                max_depth: Some(max_depth),
+                ..new_opts()
             };
+            Solver::<SimpleHeuristic>::new(&game, opts).solve().0
+        };
 
-            let expand_node = if is_forward {
-                self.forward.expand_node(&self.reverse)
-            } else {
-                self.reverse.expand_node(&self.forward)
-            };
+        // The optimal solution has 6 pushes, while greedy search finds 12
+        assert_eq!(solve(5), SolveResult::Unsolvable);
+        let SolveResult::Solved(soln) = solve(6) else {
+            panic!("expected a solution within 6 pushes");
+        };
+        assert_eq!(soln.len(), 6);
+    }
 
-            match expand_node {
-                ExpandNode::NotDone => {
-                    nodes_explored += 1;
-                    if nodes_explored >= self.opts.max_nodes_explored {
-                        result = SolveResult::Cutoff;
-                        break;
-                    }
-                }
-                ExpandNode::Solved => {
-                    if is_forward {
-                        self.reverse.game.restore(&self.forward.game.checkpoint());
-                    } else {
-                        self.forward.game.restore(&self.reverse.game.checkpoint());
-                    }
-                    let soln = self.reconstruct_solution();
-                    result = SolveResult::Solved(soln);
-                    break;
-                }
-                ExpandNode::Unsolvable => {
-                    result = SolveResult::Unsolvable;
-                    break;
-                }
+    #[test]
+    fn test_max_depth_meeting() {
+        // Microban level 136, whose optimal solution has 25 pushes. The
+        // directions first meet along a longer path, which must not cut the
+        // search off below the position they met at.
+        let game = parse_game(
+            r#"
+ #######
+ #     #
+## ###$##
+#.$   @ #
+# .. #$ #
+#.##  $ #
+#    ####
+######
+"#,
+        );
+        for search_type in [
+            SearchType::Forward,
+            SearchType::Reverse,
+            SearchType::Bidirectional,
+        ] {
+            let opts = SolverOpts {
+                search_type,
+                max_depth: Some(25),
+                ..new_opts()
+            };
+            let result = Solver::<HungarianHeuristic>::new(&game, opts).solve().0;
+            let SolveResult::Solved(soln) = result else {
+                panic!("expected a solution within 25 pushes, got {:?}", result);
+            };
+            assert_eq!(soln.len(), 25);
+            let mut test_game = game.clone();
+            for push in soln {
+                test_game.push(push);
             }
+            assert!(test_game.is_solved());
+        }
+    }
 
-            if self.opts.trace_range.contains(&nodes_explored) {
-                let (dir, game) = if is_forward {
-                    ("forward", &self.forward.game)
-                } else {
-                    ("reverse", &self.reverse.game)
-                };
-                println!("direction={} count={}:\n{}", dir, nodes_explored, game);
-            }
+    #[test]
+    fn test_max_memory() {
+        let game = parse_game(
+            r#"
+#######
+# @#  #
+#.$   #
+#. # $##
+#.$#   #
+#. # $ #
+#  #   #
+########
+"#,
+        );
+        let solve = |opts: SolverOpts| match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), stats) => (soln, stats),
+            (result, _) => panic!("unexpected result {:?}", result),
+        };
+
+        let (soln, stats) = solve(SolverOpts {
+            max_memory: Some(4096),
+            ..new_opts()
+        });
+        assert!(stats.evicted_nodes > 0);
+        let mut test_game = game.clone();
+        for push in soln {
+            test_game.push(push);
         }
+        assert!(test_game.is_solved());
 
-        (result, nodes_explored)
-    }
+        // Evicted nodes are regenerated, so optimal search is still optimal
+        let (optimal, unbounded) = solve(SolverOpts {
+            optimal: true,
+            ..new_opts()
+        });
+        let (bounded, stats) = solve(SolverOpts {
+            optimal: true,
+            max_memory: Some(4096),
+            ..new_opts()
+        });
+        assert!(stats.evicted_nodes > 0);
+        assert_eq!(bounded.len(), optimal.len());
 
-    fn reconstruct_solution(&self) -> Vec<Push> {
-        let forward_soln = self.forward.reconstruct_solution();
-        let reverse_soln = self.reverse.reconstruct_solution();
-        self.combine_solution(&forward_soln, &reverse_soln)
+        // Closed positions are forgotten too, keeping the table smaller
+        assert!(stats.evicted_entries > 0);
+        assert!(stats.forward.peak_table < unbounded.forward.peak_table);
     }
 
-    fn combine_solution(
-        &self,
-        forward_soln: &[PushByPos],
-        reverse_soln: &[PushByPos],
-    ) -> Vec<Push> {
-        let mut game = self.game.clone();
-        let mut soln = Vec::new();
-        let chained = forward_soln.iter().rev().chain(reverse_soln.iter());
-
-        for (i, push_by_pos) in chained.enumerate() {
-            // Get box index at this position
-            let box_index = game.box_index(push_by_pos.box_pos).unwrap_or_else(|| {
-                panic!(
-                    "Solution verification failed: no box at position {} for push {}",
-                    push_by_pos.box_pos,
-                    i + 1
-                )
-            });
+    #[test]
+    fn test_optimal_moves() {
+        let game = parse_game(
+            r#"
+####
+# .#
+#  ###
+#*@  #
+#  $ #
+#  ###
+####
+"#,
+        );
+        let opts = SolverOpts {
+            cost: CostType::Moves,
+            ..new_opts()
+        };
+        let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        let SolveResult::Solved(soln) = result else {
+            panic!("unexpected result {:?}", result);
+        };
+        // Optimal number of moves, found by breadth-first search over player
+        // moves
+        let lurd = to_lurd(&game, &soln);
+        assert_eq!(lurd.len(), 33);
+        assert_eq!(Solution::parse(&lurd).unwrap().verify(&game), Ok(()));
+    }
 
-            // Compute valid pushes at this state
-            let valid_pushes = game.compute_pushes().moves;
+    #[test]
+    fn test_ida_star() {
+        let game = parse_game(
+            r#"
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
+"#,
+        );
+        let solve = |opts: SolverOpts| match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+            (SolveResult::Solved(soln), _) => soln,
+            (result, _) => panic!("unexpected result {:?}", result),
+        };
 
-            // Verify that this push is among the valid pushes
-            let push = Push::new(box_index, push_by_pos.direction);
-            assert!(
-                valid_pushes.contains(push),
-                "Solution verification failed: push {} (box at {}, direction {:?}) is not valid",
-                i + 1,
-                push_by_pos.box_pos,
-                push_by_pos.direction
-            );
+        let optimal = solve(SolverOpts {
+            optimal: true,
+            ..new_opts()
+        });
+        let ida = solve(SolverOpts {
+            algorithm: AlgorithmType::IdaStar,
+            ..new_opts()
+        });
+        assert_eq!(ida.len(), optimal.len());
 
-            // Apply the push
-            game.push(push);
-            soln.push(push);
+        // Verify solution works
+        let mut test_game = game.clone();
+        for push in ida {
+            test_game.push(push);
         }
+        assert!(test_game.is_solved());
+    }
 
-        // Verify final state is solved
-        assert!(
-            game.is_solved(),
-            "Solution verification failed: puzzle is not solved"
+    #[test]
+    fn test_ida_star_bounded_table() {
+        let game = parse_game(
+            r#"
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
+"#,
         );
+        let solve = |max_memory| {
+            let opts = SolverOpts {
+                algorithm: AlgorithmType::IdaStar,
+                max_memory,
+                ..new_opts()
+            };
+            match Solver::<SimpleHeuristic>::new(&game, opts).solve() {
+                (SolveResult::Solved(soln), stats) => (soln, stats),
+                (result, _) => panic!("unexpected result {:?}", result),
+            }
+        };
 
-        soln
+        // Skipping positions already searched keeps the solution optimal,
+        // even with a table of a single bucket
+        let (soln, stats) = solve(None);
+        assert_eq!(stats.pruning.transposition_pruned, 0);
+        let (bounded_soln, bounded_stats) = solve(Some(1 << 20));
+        assert_eq!(bounded_soln.len(), soln.len());
+        assert!(bounded_stats.pruning.transposition_pruned > 0);
+        assert!(bounded_stats.nodes_explored < stats.nodes_explored);
+        let (tiny_soln, _) = solve(Some(1));
+        assert_eq!(tiny_soln.len(), soln.len());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::heuristic::SimpleHeuristic;
-
-    use super::*;
 
     #[test]
-    fn test_solve_simple() {
+    fn test_fess() {
         let game = parse_game(
             r#"
-#####
-#@$.#
-#####
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
 "#,
         );
-        let mut solver = new_solver(game.clone());
-        let result = solver.solve();
-
-        if let (SolveResult::Solved(soln), _) = result {
-            assert_eq!(soln.len(), 1);
+        for search_type in [SearchType::Forward, SearchType::Bidirectional] {
+            let opts = SolverOpts {
+                search_type,
+                algorithm: AlgorithmType::Fess,
+                ..new_opts()
+            };
+            let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+            let SolveResult::Solved(soln) = result else {
+                panic!("unexpected result {:?}", result);
+            };
 
             // Verify solution works
             let mut test_game = game.clone();
@@ -707,58 +4449,180 @@ mod tests {
                 test_game.push(push);
             }
             assert!(test_game.is_solved());
-        } else {
-            panic!();
         }
     }
 
     #[test]
-    fn test_solve_already_solved() {
+    fn test_balance() {
         let game = parse_game(
             r#"
-####
-#@*#
-####
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
 "#,
         );
-        let mut solver = new_solver(game);
-        let result = solver.solve();
+        let balances = [
+            BalanceType::Alternate,
+            BalanceType::Frontier,
+            BalanceType::Cost,
+        ];
+        for balance in balances {
+            for ratio in [(1, 1), (3, 1), (1, 3)] {
+                let opts = SolverOpts {
+                    search_type: SearchType::Bidirectional,
+                    balance,
+                    ratio,
+                    ..new_opts()
+                };
+                let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+                let SolveResult::Solved(soln) = result else {
+                    panic!(
+                        "unexpected result {:?} with {:?} {:?}",
+                        result, balance, ratio
+                    );
+                };
 
-        if let (SolveResult::Solved(moves), _) = result {
-            assert_eq!(moves.len(), 0);
-        } else {
-            panic!();
+                // Verify solution works
+                let mut test_game = game.clone();
+                for push in soln {
+                    test_game.push(push);
+                }
+                assert!(test_game.is_solved());
+            }
         }
     }
 
     #[test]
-    fn test_solve_two_moves() {
+    fn test_perimeter() {
         let game = parse_game(
             r#"
-######
-#@$ .#
-######
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
 "#,
         );
-        let mut solver = new_solver(game.clone());
-        let result = solver.solve();
+        // The forward search meets the perimeter however large it is, or the
+        // reverse search finds the start position itself
+        for perimeter in [0, 1, 10, 100] {
+            let opts = SolverOpts {
+                search_type: SearchType::Bidirectional,
+                perimeter: Some(perimeter),
+                ..new_opts()
+            };
+            let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+            let SolveResult::Solved(soln) = result else {
+                panic!(
+                    "unexpected result {:?} with perimeter {}",
+                    result, perimeter
+                );
+            };
 
-        if let (SolveResult::Solved(soln), _) = result {
+            // Verify solution works
+            let mut test_game = game.clone();
+            for push in soln {
+                test_game.push(push);
+            }
+            assert!(test_game.is_solved());
+        }
+    }
+
+    #[test]
+    fn test_unknown_player() {
+        let game = Game::from_text_unknown_player("#######\n#.$ $.#\n#######").unwrap();
+        // The player is placed on the left, from where the level can't be
+        // solved, but it can from the middle
+        let (result, _) = Solver::<SimpleHeuristic>::new(&game, new_opts()).solve();
+        assert_eq!(result, SolveResult::Unsolvable);
+
+        for search_type in [
+            SearchType::Forward,
+            SearchType::Reverse,
+            SearchType::Bidirectional,
+        ] {
+            let opts = SolverOpts {
+                search_type,
+                ..new_opts()
+            };
+            let (result, _) = Solver::<SimpleHeuristic>::new_unknown_player(&game, opts).solve();
+            let SolveResult::Solved(soln) = result else {
+                panic!("unexpected result {:?} with {:?}", result, search_type);
+            };
             assert_eq!(soln.len(), 2);
 
-            // Verify solution works
+            // Verify solution works from the region it starts in
             let mut test_game = game.clone();
+            test_game.set_player_for_push(soln[0]);
             for push in soln {
                 test_game.push(push);
             }
             assert!(test_game.is_solved());
-        } else {
-            panic!();
         }
     }
 
     #[test]
-    fn test_solve_impossible() {
+    fn test_direction_stats() {
+        let game = parse_game(
+            r#"
+      #####
+      #.  #
+      #.# #
+#######.# #
+# @ $ $ $ #
+# # # # ###
+#       #
+#########
+"#,
+        );
+        let opts = SolverOpts {
+            search_type: SearchType::Bidirectional,
+            ..new_opts()
+        };
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        assert_eq!(
+            stats.forward.nodes_explored + stats.reverse.nodes_explored,
+            stats.nodes_explored
+        );
+        assert!(stats.forward.peak_open > 0);
+        assert!(stats.reverse.peak_open > 0);
+        assert!(stats.meeting_direction.is_some());
+        for direction in [stats.forward, stats.reverse] {
+            assert!(direction.peak_table >= direction.nodes_explored);
+            assert!(direction.heuristic_entries > 0);
+            assert!(direction.peak_memory > 0);
+        }
+
+        // Forward search meets the goal positions seeded in the reverse table
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, new_opts()).solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        assert_eq!(stats.forward.nodes_explored, stats.nodes_explored);
+        assert_eq!(stats.reverse.nodes_explored, 0);
+        assert_eq!(stats.meeting_direction, Some(SearchType::Forward));
+
+        // IDA* reports the size of its bounded table
+        let opts = SolverOpts {
+            algorithm: AlgorithmType::IdaStar,
+            max_memory: Some(4096),
+            ..new_opts()
+        };
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        assert!(matches!(result, SolveResult::Solved(_)));
+        assert_eq!(stats.forward.peak_table, 0);
+        assert_eq!(stats.forward.peak_memory, 4096);
+    }
+
+    #[test]
+    fn test_ida_star_impossible() {
         let game = parse_game(
             r#"
 #######
@@ -766,9 +4630,137 @@ mod tests {
 #######
 "#,
         );
-        let mut solver = new_solver(game);
-        let result = solver.solve();
-        assert_eq!(result.0, SolveResult::Unsolvable);
+        let opts = SolverOpts {
+            algorithm: AlgorithmType::IdaStar,
+            ..new_opts()
+        };
+        let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        assert_eq!(result, SolveResult::Unsolvable);
+    }
+
+    #[test]
+    fn test_threads() {
+        let game = parse_game(
+            r#"
+####
+# .#
+#  ###
+#*@  #
+#  $ #
+#  ###
+####
+"#,
+        );
+        let opts = SolverOpts {
+            threads: 2,
+            ..new_opts()
+        };
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&game, opts.clone()).solve();
+        let SolveResult::Solved(soln) = result else {
+            panic!("expected a solution, got {:?}", result);
+        };
+        let mut test_game = game.clone();
+        for push in soln {
+            test_game.push(push);
+        }
+        assert!(test_game.is_solved());
+        assert!(stats.nodes_explored > 0);
+        assert_eq!(stats.forward.nodes_explored, stats.nodes_explored);
+        assert_eq!(stats.meeting_direction, Some(SearchType::Forward));
+
+        // Every worker creates its heuristics with the solver's settings
+        let kinds = vec![HeuristicKind::Hungarian, HeuristicKind::Simple];
+        let (result, _) =
+            Solver::<MaxListHeuristic>::with_params(&game, opts.clone(), kinds).solve();
+        let SolveResult::Solved(soln) = result else {
+            panic!("expected a solution, got {:?}", result);
+        };
+        let mut test_game = game.clone();
+        for push in soln {
+            test_game.push(push);
+        }
+        assert!(test_game.is_solved());
+
+        // Workers skip mirror images of positions already expanded (microban
+        // level 52)
+        let symmetric_game = parse_game(
+            r#"
+  ####
+### @#
+#  $ #
+#  *.#
+#  *.#
+#  $ #
+###  #
+  ####
+"#,
+        );
+        let (result, stats) = Solver::<SimpleHeuristic>::new(&symmetric_game, opts.clone()).solve();
+        let SolveResult::Solved(soln) = result else {
+            panic!("expected a solution, got {:?}", result);
+        };
+        let mut test_game = symmetric_game.clone();
+        for push in soln {
+            test_game.push(push);
+        }
+        assert!(test_game.is_solved());
+        assert!(stats.pruning.symmetry_pruned > 0);
+
+        // Every worker runs out of nodes to expand
+        let game = parse_game(
+            r#"
+#######
+#@$ #.#
+#######
+"#,
+        );
+        let (result, _) = Solver::<SimpleHeuristic>::new(&game, opts).solve();
+        assert_eq!(result, SolveResult::Unsolvable);
+    }
+
+    #[test]
+    fn test_threads_order() {
+        // Microban level 145, which greedy search solves almost straight
+        // away. Workers which only expanded the best nodes of their own
+        // queues explored tens of thousands of positions.
+        let game = parse_game(
+            r#"
+   #####
+   # @ #
+  ##   ##
+###.$$$.###
+#  $...$  #
+#  $.#.$  #
+#  $...$  #
+###.$$$.###
+  ##   ##
+   #   #
+   #####
+"#,
+        );
+        let solve = |threads| {
+            let opts = SolverOpts {
+                threads,
+                max_nodes_explored: 100_000,
+                ..new_opts()
+            };
+            match Solver::<HungarianHeuristic>::new(&game, opts).solve() {
+                (SolveResult::Solved(_), stats) => stats.nodes_explored,
+                (result, _) => panic!("unexpected result {:?}", result),
+            }
+        };
+
+        // Workers expand the best node of all the queues, and wait on better
+        // nodes being expanded, so the search stays close to the serial one
+        // (allowing for nodes of equal priority expanded at once)
+        let serial = solve(1);
+        let parallel = solve(4);
+        assert!(
+            parallel <= 2 * serial,
+            "{} nodes explored with 4 threads, {} with 1",
+            parallel,
+            serial
+        );
     }
 
     fn parse_game(text: &str) -> Game {
@@ -776,17 +4768,46 @@ mod tests {
     }
 
     fn new_solver(game: Game) -> Solver<SimpleHeuristic> {
-        Solver::new(
-            &game,
-            SolverOpts {
-                search_type: SearchType::Forward,
-                max_nodes_explored: 10000,
-                freeze_deadlocks: true,
-                dead_squares: true,
-                pi_corrals: true,
-                deadlock_max_nodes: 1000,
-                trace_range: 0..0,
-            },
-        )
+        Solver::new(&game, new_opts())
+    }
+
+    fn new_opts() -> SolverOpts {
+        SolverOpts {
+            search_type: SearchType::Forward,
+            balance: BalanceType::Alternate,
+            ratio: (1, 1),
+            perimeter: None,
+            max_nodes_explored: 10000,
+            max_time: None,
+            max_memory: None,
+            freeze_deadlocks: true,
+            deadlock_patterns: true,
+            dead_squares: true,
+            matching_deadlocks: true,
+            conflict_deadlocks: false,
+            conflict_max_nodes: 50,
+            pi_corrals: true,
+            deadlock_max_nodes: 1000,
+            corral_safety: false,
+            pull_corral_pruning: false,
+            optimal: false,
+            cost: CostType::Pushes,
+            weight: 1.0,
+            algorithm: AlgorithmType::BestFirst,
+            anytime: false,
+            reopen: false,
+            adaptive: false,
+            player_tie_break: false,
+            tie_break: TieBreak::Fifo,
+            penalties: false,
+            max_depth: None,
+            threads: 1,
+            paranoid_hash: false,
+            zobrist_seed: None,
+            symmetry: true,
+            share_deadlocks: true,
+            hash_diagnostics: false,
+            trace_range: 0..0,
+        }
     }
 }
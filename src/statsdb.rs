@@ -0,0 +1,143 @@
+use rusqlite::{Connection, params};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The result of solving a single level with a given configuration.
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub levels_file: String,
+    pub level: usize,
+    pub heuristic: String,
+    pub direction: String,
+    pub max_nodes: usize,
+    pub freeze_deadlocks: bool,
+    pub dead_squares: bool,
+    pub pi_corrals: bool,
+    pub deadlock_max_nodes: usize,
+    /// One of "solved", "cutoff" or "unsolvable".
+    pub result: String,
+    pub steps: usize,
+    pub nodes_explored: usize,
+    pub elapsed_ms: u128,
+}
+
+/// A SQLite database of solver runs, used to track solver performance over
+/// time. Each solved level is recorded as a row in the `runs` table.
+pub struct StatsDb {
+    conn: Connection,
+    started_at: i64,
+}
+
+impl StatsDb {
+    /// Open (or create) the database at the given path.
+    pub fn open(path: &str) -> Result<Self, String> {
+        Self::init(Connection::open(path).map_err(|e| e.to_string())?)
+    }
+
+    /// Open a temporary in-memory database.
+    pub fn open_in_memory() -> Result<Self, String> {
+        Self::init(Connection::open_in_memory().map_err(|e| e.to_string())?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, String> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY,
+                started_at INTEGER NOT NULL,
+                version TEXT NOT NULL,
+                levels_file TEXT NOT NULL,
+                level INTEGER NOT NULL,
+                heuristic TEXT NOT NULL,
+                direction TEXT NOT NULL,
+                max_nodes INTEGER NOT NULL,
+                freeze_deadlocks INTEGER NOT NULL,
+                dead_squares INTEGER NOT NULL,
+                pi_corrals INTEGER NOT NULL,
+                deadlock_max_nodes INTEGER NOT NULL,
+                result TEXT NOT NULL,
+                steps INTEGER NOT NULL,
+                nodes_explored INTEGER NOT NULL,
+                elapsed_ms INTEGER NOT NULL
+            );",
+        )
+        .map_err(|e| e.to_string())?;
+
+        // All records written through this connection share a start time, so
+        // that the levels of a single invocation can be grouped together.
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        Ok(Self { conn, started_at })
+    }
+
+    /// Record the result of solving a single level.
+    pub fn record(&self, run: &RunRecord) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO runs (
+                    started_at, version, levels_file, level, heuristic, direction,
+                    max_nodes, freeze_deadlocks, dead_squares, pi_corrals,
+                    deadlock_max_nodes, result, steps, nodes_explored, elapsed_ms
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                params![
+                    self.started_at,
+                    env!("CARGO_PKG_VERSION"),
+                    run.levels_file,
+                    run.level as i64,
+                    run.heuristic,
+                    run.direction,
+                    run.max_nodes as i64,
+                    run.freeze_deadlocks,
+                    run.dead_squares,
+                    run.pi_corrals,
+                    run.deadlock_max_nodes as i64,
+                    run.result,
+                    run.steps as i64,
+                    run.nodes_explored as i64,
+                    run.elapsed_ms as i64,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let db = StatsDb::open_in_memory().unwrap();
+        let run = RunRecord {
+            levels_file: "levels/microban.txt".to_string(),
+            level: 3,
+            heuristic: "greedy".to_string(),
+            direction: "bidirectional".to_string(),
+            max_nodes: 5000000,
+            freeze_deadlocks: true,
+            dead_squares: true,
+            pi_corrals: false,
+            deadlock_max_nodes: 20,
+            result: "solved".to_string(),
+            steps: 13,
+            nodes_explored: 20,
+            elapsed_ms: 1,
+        };
+        db.record(&run).unwrap();
+        db.record(&RunRecord { level: 4, ..run }).unwrap();
+
+        let (count, total_steps, pi_corrals): (i64, i64, bool) = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*), SUM(steps), MAX(pi_corrals) FROM runs",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(total_steps, 26);
+        assert!(!pi_corrals);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{
+    bits::LazyBitboard,
+    game::{Direction, Game, Position, PushByPos},
+    zobrist::Zobrist,
+};
+
+/// A mirror image or rotation of the board: an optional transpose (swapping
+/// x and y, for square boards), followed by optional flips of either axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symmetry {
+    transpose: bool,
+    flip_x: bool,
+    flip_y: bool,
+    width: u8,
+    height: u8,
+}
+
+impl Symmetry {
+    /// The position the given one is mapped to.
+    pub fn apply(&self, pos: Position) -> Position {
+        let Position(mut x, mut y) = pos;
+        if self.transpose {
+            (x, y) = (y, x);
+        }
+        if self.flip_x {
+            x = self.width - 1 - x;
+        }
+        if self.flip_y {
+            y = self.height - 1 - y;
+        }
+        Position(x, y)
+    }
+
+    /// The direction the given one is mapped to.
+    pub fn apply_direction(&self, dir: Direction) -> Direction {
+        let mut dir = dir;
+        if self.transpose {
+            dir = match dir {
+                Direction::Up => Direction::Left,
+                Direction::Left => Direction::Up,
+                Direction::Down => Direction::Right,
+                Direction::Right => Direction::Down,
+            };
+        }
+        if (self.flip_x && matches!(dir, Direction::Left | Direction::Right))
+            || (self.flip_y && matches!(dir, Direction::Up | Direction::Down))
+        {
+            dir = dir.reverse();
+        }
+        dir
+    }
+
+    /// The push the given one is mapped to.
+    pub fn apply_push(&self, push: PushByPos) -> PushByPos {
+        PushByPos {
+            box_pos: self.apply(push.box_pos),
+            direction: self.apply_direction(push.direction),
+        }
+    }
+
+    /// The symmetry undoing this one.
+    pub fn inverse(&self) -> Symmetry {
+        if self.transpose {
+            // Flipping after transposing is transposing after flipping the
+            // other axis
+            Symmetry {
+                flip_x: self.flip_y,
+                flip_y: self.flip_x,
+                ..*self
+            }
+        } else {
+            *self
+        }
+    }
+
+    /// Map the boxes and player of `from` onto `game`, a game with the same
+    /// walls.
+    pub fn apply_to_game(&self, from: &Game, game: &mut Game) {
+        let boxes: Vec<Position> = from
+            .box_positions()
+            .iter()
+            .map(|&pos| self.apply(pos))
+            .collect();
+        game.set_boxes(&boxes)
+            .expect("symmetry maps boxes onto the floor");
+        game.set_player(self.apply(from.player()));
+    }
+
+    /// The Zobrist hash of the mirrored or rotated game, with the player at
+    /// the top-left square of its region, given the squares of the player's
+    /// region in the game itself.
+    pub fn hash(&self, zobrist: &Zobrist, game: &Game, region: &LazyBitboard) -> u64 {
+        let boxes_hash = game
+            .box_positions()
+            .iter()
+            .fold(0, |hash, &pos| hash ^ zobrist.box_hash(self.apply(pos)));
+        boxes_hash ^ zobrist.player_hash(self.top_left(game, region))
+    }
+
+    /// The top-left square of the mirrored or rotated region.
+    fn top_left(&self, game: &Game, region: &LazyBitboard) -> Position {
+        let mut top_left = Position(u8::MAX, u8::MAX);
+        for (y, mut row) in region.rows(game.height()).enumerate() {
+            while row != 0 {
+                let x = row.trailing_zeros() as u8;
+                row &= row - 1;
+                let pos = self.apply(Position(x, y as u8));
+                if (pos.1, pos.0) < (top_left.1, top_left.0) {
+                    top_left = pos;
+                }
+            }
+        }
+        top_left
+    }
+}
+
+/// Find the mirror images and rotations (other than the identity) mapping the
+/// walls and goals of the board onto themselves. Positions mapped onto each
+/// other by one of them are equally far from being solved. Labelled games
+/// have none, as the mapped positions would have to keep the labels too.
+pub fn find_symmetries(game: &Game) -> Vec<Symmetry> {
+    let (width, height) = (game.width(), game.height());
+    let mut symmetries = Vec::new();
+    if game.is_labelled() {
+        return symmetries;
+    }
+    for transpose in [false, true] {
+        if transpose && width != height {
+            continue;
+        }
+        for flip_x in [false, true] {
+            for flip_y in [false, true] {
+                if !transpose && !flip_x && !flip_y {
+                    continue;
+                }
+                let symmetry = Symmetry {
+                    transpose,
+                    flip_x,
+                    flip_y,
+                    width,
+                    height,
+                };
+                let preserves_tiles = (0..height).all(|y| {
+                    (0..width).all(|x| {
+                        let pos = Position(x, y);
+                        game.get_tile(pos) == game.get_tile(symmetry.apply(pos))
+                    })
+                });
+                if preserves_tiles {
+                    symmetries.push(symmetry);
+                }
+            }
+        }
+    }
+    symmetries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ALL_DIRECTIONS;
+
+    #[test]
+    fn test_find_symmetries() {
+        // Mirrored left to right
+        let game = Game::from_text(
+            "########\n\
+             #.    .#\n\
+             # $  $ #\n\
+             #   @  #\n\
+             ########",
+        )
+        .unwrap();
+        let symmetries = find_symmetries(&game);
+        assert_eq!(symmetries.len(), 1);
+        assert_eq!(symmetries[0].apply(Position(1, 1)), Position(6, 1));
+
+        // Every rotation and reflection of a square
+        let game = Game::from_text(
+            "#######\n\
+             #.   .#\n\
+             # $ $ #\n\
+             #  @  #\n\
+             # $ $ #\n\
+             #.   .#\n\
+             #######",
+        )
+        .unwrap();
+        assert_eq!(find_symmetries(&game).len(), 7);
+
+        // No symmetry
+        let game = Game::from_text("#####\n#@$.#\n# ###\n#####").unwrap();
+        assert!(find_symmetries(&game).is_empty());
+    }
+
+    #[test]
+    fn test_symmetry_maps() {
+        let game = Game::from_text(
+            "#######\n\
+             #.   .#\n\
+             # $ $ #\n\
+             #  @  #\n\
+             # $ $ #\n\
+             #.   .#\n\
+             #######",
+        )
+        .unwrap();
+        for symmetry in find_symmetries(&game) {
+            let inverse = symmetry.inverse();
+            for y in 0..game.height() {
+                for x in 0..game.width() {
+                    let pos = Position(x, y);
+                    assert_eq!(inverse.apply(symmetry.apply(pos)), pos);
+
+                    // Directions are mapped as the squares they lead to
+                    for dir in ALL_DIRECTIONS {
+                        if let Some(next) = game.move_position(pos, dir) {
+                            assert_eq!(
+                                game.move_position(
+                                    symmetry.apply(pos),
+                                    symmetry.apply_direction(dir)
+                                ),
+                                Some(symmetry.apply(next))
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::mem::size_of;
+
+/// An entry of the table. Empty slots belong to iteration zero, which is
+/// never current.
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    key: u64,
+    g: u32,
+    iteration: u32,
+}
+
+/// Two slots sharing a bucket: the first keeps the entry with the lowest g,
+/// and the second always takes the most recent entry.
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    preferred: Entry,
+    recent: Entry,
+}
+
+/// A fixed-capacity transposition table for depth-first search, recording
+/// the lowest g at which each position was searched in the current
+/// iteration. Once full, entries are replaced rather than the table growing:
+/// each bucket keeps one entry searched closest to the root, which covers the
+/// largest subtree, and one entry which is always replaced.
+pub struct BoundedTable {
+    buckets: Vec<Bucket>,
+    mask: usize,
+    /// Entries from earlier iterations are stale, so the table never needs to
+    /// be cleared.
+    iteration: u32,
+}
+
+impl BoundedTable {
+    /// Create a table using at most about `max_bytes` bytes, with a power of
+    /// two number of buckets.
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
+        let len = (max_bytes / size_of::<Bucket>()).max(1);
+        let len = 1 << len.ilog2();
+        Self {
+            buckets: vec![Bucket::default(); len],
+            mask: len - 1,
+            iteration: 1,
+        }
+    }
+
+    /// Bytes used by the table.
+    pub fn memory_usage(&self) -> usize {
+        self.buckets.len() * size_of::<Bucket>()
+    }
+
+    /// Start a new iteration, forgetting all entries.
+    pub fn next_iteration(&mut self) {
+        self.iteration += 1;
+    }
+
+    /// Whether the position was already searched in this iteration at a g no
+    /// higher than the given one.
+    pub fn contains(&self, key: u64, g: usize) -> bool {
+        let bucket = &self.buckets[key as usize & self.mask];
+        [bucket.preferred, bucket.recent].iter().any(|entry| {
+            entry.key == key && entry.iteration == self.iteration && entry.g as usize <= g
+        })
+    }
+
+    /// Record that the position is searched at the given g in this
+    /// iteration.
+    pub fn insert(&mut self, key: u64, g: usize) {
+        let entry = Entry {
+            key,
+            g: g.min(u32::MAX as usize) as u32,
+            iteration: self.iteration,
+        };
+        let bucket = &mut self.buckets[key as usize & self.mask];
+        let preferred = bucket.preferred;
+        if preferred.iteration != self.iteration {
+            bucket.preferred = entry;
+        } else if preferred.key == key {
+            bucket.preferred.g = preferred.g.min(entry.g);
+        } else if entry.g <= preferred.g {
+            // Demote the old entry to the always-replace slot
+            bucket.recent = preferred;
+            bucket.preferred = entry;
+        } else {
+            bucket.recent = entry;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_table() {
+        // A single bucket
+        let mut table = BoundedTable::with_max_bytes(size_of::<Bucket>());
+        assert_eq!(table.memory_usage(), size_of::<Bucket>());
+
+        table.insert(1, 5);
+        assert!(table.contains(1, 5));
+        assert!(table.contains(1, 6));
+        assert!(!table.contains(1, 4));
+        assert!(!table.contains(2, 5));
+
+        // Entries further from the root go to the always-replace slot
+        table.insert(2, 7);
+        table.insert(3, 8);
+        assert!(table.contains(1, 5));
+        assert!(!table.contains(2, 7));
+        assert!(table.contains(3, 8));
+
+        // An entry closer to the root takes the preferred slot, demoting the
+        // old one
+        table.insert(4, 2);
+        assert!(table.contains(4, 2));
+        assert!(table.contains(1, 5));
+        assert!(!table.contains(3, 8));
+
+        // Searching a position again at a lower g updates its entry
+        table.insert(4, 1);
+        assert!(table.contains(4, 1));
+
+        // A new iteration forgets everything
+        table.next_iteration();
+        assert!(!table.contains(4, 1));
+        table.insert(5, 9);
+        assert!(table.contains(5, 9));
+    }
+}
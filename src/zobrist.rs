@@ -4,17 +4,48 @@ use crate::{
 };
 use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::OnceLock;
+
+/// Seed of `Zobrist::new`, whose boxes hash every `Game` keeps up to date.
+const DEFAULT_SEED: u64 = 0x123456789abcdef0;
 
 /// Zobrist hash for game states
 pub struct Zobrist {
+    seed: u64,
     box_hashes: [[u64; MAX_SIZE]; MAX_SIZE],
     player_hashes: [[u64; MAX_SIZE]; MAX_SIZE],
 }
 
+impl Default for Zobrist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Zobrist {
     pub fn new() -> Self {
+        Self::with_seed(DEFAULT_SEED)
+    }
+
+    /// The hashes of `Zobrist::new`, shared by every game to keep its boxes
+    /// hash up to date (see `Game::boxes_hash`).
+    pub fn shared() -> &'static Zobrist {
+        static SHARED: OnceLock<Zobrist> = OnceLock::new();
+        SHARED.get_or_init(Zobrist::new)
+    }
+
+    /// The seed the hashes were created from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Create Zobrist hashes from the given seed. Hashes from different seeds
+    /// are independent, so they can be used to double-check each other.
+    pub fn with_seed(seed: u64) -> Self {
         // Use a seeded PRNG for reproducible Zobrist hashes
-        let mut rng = ChaCha8Rng::seed_from_u64(0x123456789abcdef0);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
 
         let mut box_hashes = [[0u64; MAX_SIZE]; MAX_SIZE];
         for row in box_hashes.iter_mut() {
@@ -31,6 +62,7 @@ impl Zobrist {
         }
 
         Zobrist {
+            seed,
             box_hashes,
             player_hashes,
         }
@@ -41,18 +73,25 @@ impl Zobrist {
         self.box_hashes[pos.1 as usize][pos.0 as usize]
     }
 
+    /// Get hash value for a box with the given Sokoban+ label (see
+    /// `Game::box_label`) at a specific position. Unlabelled boxes hash as
+    /// `box_hash`, and distinct labels swapping squares change the hash.
+    pub fn labelled_box_hash(&self, pos: Position, label: u8) -> u64 {
+        self.box_hash(pos).wrapping_mul(2 * u64::from(label) + 1)
+    }
+
     /// Get hash value for player position
     pub fn player_hash(&self, pos: Position) -> u64 {
         self.player_hashes[pos.1 as usize][pos.0 as usize]
     }
 
-    /// Compute hash for all boxes in a game state
+    /// Compute hash for all boxes in a game state. The game keeps this hash
+    /// up to date for the default seed, so it only needs computing for others.
     pub fn compute_boxes_hash(&self, game: &Game) -> u64 {
-        let mut boxes_hash = 0u64;
-        for &pos in game.box_positions() {
-            boxes_hash ^= self.box_hash(pos);
+        if self.seed == DEFAULT_SEED && !game.is_labelled() {
+            return game.boxes_hash();
         }
-        boxes_hash
+        self.compute_boxes_hash_subset(game, Bitvector::full(game.box_count() as u8))
     }
 
     /// Compute hash for a subset of boxes in a game state
@@ -60,7 +99,7 @@ impl Zobrist {
         let mut boxes_hash = 0u64;
         for box_idx in subset.iter() {
             let pos = game.box_position(box_idx);
-            boxes_hash ^= self.box_hash(pos);
+            boxes_hash ^= self.labelled_box_hash(pos, game.box_label(box_idx));
         }
         boxes_hash
     }
@@ -72,3 +111,33 @@ impl Zobrist {
         boxes_hash ^ self.player_hash(canonical_pos)
     }
 }
+
+/// Hash map keyed by Zobrist hashes. See `ZobristHasher`.
+pub type ZobristMap<V> = HashMap<u64, V, BuildHasherDefault<ZobristHasher>>;
+
+/// Hash set of Zobrist hashes. See `ZobristHasher`.
+pub type ZobristSet = HashSet<u64, BuildHasherDefault<ZobristHasher>>;
+
+/// Hasher for keys which are already Zobrist hashes, whose bits are random
+/// enough to use as they are. Much faster than the default SipHash, but keys
+/// must not share their low bits (which pick the bucket) or high bits (which
+/// the table compares first).
+#[derive(Default)]
+pub struct ZobristHasher(u64);
+
+impl Hasher for ZobristHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Only u64 keys are expected, but fold anything else in FNV-1a style
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n;
+    }
+}